@@ -0,0 +1,34 @@
+// 比较重复子表达式很多时普通求值和记忆化求值的耗时：cargo bench --bench memoize
+// 记忆化的语法树只建立一次编号表，之后每次求值都复用
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use expression_parsing_calculation::{eval, parse, Memoized};
+
+// 每一层都把上一层重复两次，展开后有 2^depth 个叶子，但只有 depth 个不同的子树
+fn doubling(depth: usize) -> String {
+    (0..depth).fold("sqrt(pi + 1)".to_string(), |term, _| {
+        format!("({} + {}) / 2", term, term)
+    })
+}
+
+// 同一个子项重复出现很多次的长求和
+fn repeated_sum(count: usize) -> String {
+    vec!["(sin(pi / 7) * exp(0.5) + sqrt(2 ^ 3 + 1) * ln(10))"; count].join(" + ")
+}
+
+fn bench_memoize(c: &mut Criterion) {
+    for (name, input) in [
+        ("doubling_14", doubling(14)),
+        ("repeated_sum_1000", repeated_sum(1000)),
+    ] {
+        let ast = parse(&input).unwrap();
+        let memoized = Memoized::new(&ast);
+        assert_eq!(eval(&ast).unwrap(), memoized.eval().unwrap());
+        let mut group = c.benchmark_group(name);
+        group.bench_function("eval", |b| b.iter(|| eval(black_box(&ast))));
+        group.bench_function("memoized", |b| b.iter(|| black_box(&memoized).eval()));
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_memoize);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+// 开启 `ffi` feature 时用 cbindgen 根据 src/ffi.rs 生成 C 头文件 calc.h，写入 OUT_DIR
+// 设置环境变量 CALC_HEADER_DIR 时同时写入该目录，构建默认不修改源码目录
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        println!("cargo:rerun-if-env-changed=CALC_HEADER_DIR");
+        let config = cbindgen::Config::from_file("cbindgen.toml").expect("invalid cbindgen.toml");
+        let bindings = cbindgen::Builder::new()
+            .with_config(config)
+            .with_src("src/ffi.rs")
+            .generate()
+            .expect("cannot generate C header");
+        let out_dir = std::env::var_os("OUT_DIR").expect("OUT_DIR is not set");
+        bindings.write_to_file(std::path::Path::new(&out_dir).join("calc.h"));
+        if let Some(dir) = std::env::var_os("CALC_HEADER_DIR") {
+            bindings.write_to_file(std::path::Path::new(&dir).join("calc.h"));
+        }
+    }
+    #[cfg(not(feature = "ffi"))]
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,40 @@
+#ifndef EXPRESSION_CALC_H
+#define EXPRESSION_CALC_H
+
+/* 由 cbindgen 根据 src/ffi.rs 生成，不要手动修改 */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// calc_eval 的返回值，CALC_STATUS_OK 以外的值表示出错，详细信息由 calc_last_error 获取
+typedef enum CalcStatus {
+  CALC_STATUS_OK = 0,
+  CALC_STATUS_NULL_POINTER = 1,
+  CALC_STATUS_INVALID_UTF8 = 2,
+  CALC_STATUS_SYNTAX_ERROR = 3,
+  CALC_STATUS_PARSE_ERROR = 4,
+  CALC_STATUS_OVERFLOW = 5,
+  CALC_STATUS_MATH_ERROR = 6,
+  CALC_STATUS_NOT_INTEGER = 7,
+  CALC_STATUS_DIMENSION_ERROR = 8,
+  CALC_STATUS_MISMATCH = 9,
+  CALC_STATUS_SHAPE_ERROR = 10,
+  CALC_STATUS_SHUNTING_YARD_ERROR = 11,
+  CALC_STATUS_PANIC = 12,
+} CalcStatus;
+
+// 求值以 NUL 结尾的 UTF-8 表达式，成功时把结果写入 out
+//
+// # Safety
+//
+// expression 必须是空指针或指向以 NUL 结尾的字符串，out 必须是空指针或指向可写的 double
+enum CalcStatus calc_eval(const char *expression,
+                          double *out);
+
+// 当前线程最近一次 calc_eval 的错误信息，成功时为空字符串
+// 返回的指针在同一线程下一次调用 calc_eval 之前有效，调用方不需要释放
+const char *calc_last_error(void);
+
+#endif  /* EXPRESSION_CALC_H */
@@ -0,0 +1,18 @@
+// 调度场算法的示例程序，解析策略本身在库（expression_parsing_algorithm.rs）中
+use std::process::ExitCode;
+
+use expression_parsing_calculation::expression_parsing_algorithm;
+
+fn main() -> ExitCode {
+    let expr = "92 + 5 + 5 * 27 - (92 - 12) / 4 + 26";
+    match expression_parsing_algorithm(expr) {
+        Ok(result) => {
+            println!("Result: {}", result);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
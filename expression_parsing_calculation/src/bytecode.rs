@@ -0,0 +1,346 @@
+// 字节码编译器和栈式虚拟机：同一个表达式需要用不同的变量值反复求值时（如绘图、模拟），
+// 先用 compile 编译一次，之后每次 Program::run 只执行扁平的指令序列，不再解析，也不分配内存
+// 大量的变量取值（如蒙特卡洛模拟）可以用 eval_batch 一次求值，开启 `parallel` feature 时多线程执行
+use std::cell::RefCell;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use super::*;
+
+// 虚拟机指令，操作数都在栈顶
+#[derive(Debug, Clone)]
+enum Instr {
+    Const(f64),             // 压入常量
+    Load(usize),            // 压入第 n 个变量的值
+    Binary(Token),          // 弹出两个值，压入二元运算的结果
+    Negate,                 // 栈顶取负
+    Factorial,              // 栈顶求阶乘
+    Call(BuiltinFn, usize), // 弹出 n 个参数调用内置函数，压入结果
+    JumpIfZero(usize),      // 弹出栈顶，为 0 时跳转到指定位置
+    Jump(usize),            // 无条件跳转
+}
+
+// 编译好的表达式
+#[derive(Debug)]
+pub struct Program {
+    code: Vec<Instr>,
+    variables: Vec<String>,   // 变量名，run 的参数按这个顺序传入变量值
+    max_depth: usize,         // 操作数栈的最大深度
+    stack: RefCell<Vec<f64>>, // 运行时的操作数栈，编译时按最大深度预先分配
+}
+
+// eval_batch 的一组变量值，顺序与 Program::variables() 一致
+pub type VarSet = Vec<f64>;
+
+// 把表达式编译为字节码程序
+// 内置常量（pi、e 等）直接编译为常量，其余名字都是变量；不支持赋值、函数定义和多条语句，
+// 位运算只能在整数模式下使用，这里同样在编译时报错
+pub fn compile(input: &str) -> Result<Program> {
+    let ast = Expr::new(strip_formula_prefix(input)?).parse()?;
+    let mut compiler = Compiler {
+        code: Vec::new(),
+        variables: Vec::new(),
+        functions: builtin_functions(),
+        depth: 0,
+        max_depth: 0,
+    };
+    compiler.compile(&ast)?;
+    Ok(Program {
+        code: compiler.code,
+        variables: compiler.variables,
+        max_depth: compiler.max_depth,
+        stack: RefCell::new(Vec::with_capacity(compiler.max_depth)),
+    })
+}
+
+struct Compiler {
+    code: Vec<Instr>,
+    variables: Vec<String>,
+    functions: HashMap<&'static str, BuiltinFn>,
+    depth: usize,     // 执行到当前位置时栈中值的个数
+    max_depth: usize, // 栈的最大深度
+}
+
+impl Compiler {
+    // 生成一条指令，并记录它对栈深度的影响
+    fn emit(&mut self, instr: Instr) {
+        match &instr {
+            Instr::Const(_) | Instr::Load(_) => self.depth += 1,
+            Instr::Binary(_) | Instr::JumpIfZero(_) => self.depth -= 1,
+            Instr::Call(_, argc) => self.depth = self.depth + 1 - argc,
+            Instr::Negate | Instr::Factorial | Instr::Jump(_) => {}
+        }
+        self.max_depth = self.max_depth.max(self.depth);
+        self.code.push(instr);
+    }
+
+    // 变量的编号，第一次出现时分配
+    fn variable(&mut self, name: &str) -> usize {
+        match self.variables.iter().position(|v| v == name) {
+            Some(index) => index,
+            None => {
+                self.variables.push(name.to_string());
+                self.variables.len() - 1
+            }
+        }
+    }
+
+    fn compile(&mut self, ast: &Ast) -> Result<()> {
+        match ast {
+            Ast::Num(n) => self.emit(Instr::Const(*n)),
+            Ast::Var(name) => match builtin_constant(name) {
+                Some(value) => self.emit(Instr::Const(value)),
+                None => {
+                    let index = self.variable(name);
+                    self.emit(Instr::Load(index));
+                }
+            },
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                // 从外到内检查，报告最外层的位运算符
+                if let Some((op, _)) = spine.iter().rev().find(|(op, _)| op.is_bitwise()) {
+                    return Err(bitwise_requires_integer_mode(op));
+                }
+                self.compile(node)?;
+                for (op, rhs) in spine {
+                    self.compile(rhs)?;
+                    self.emit(Instr::Binary(op.clone()));
+                }
+            }
+            Ast::UnaryOp(Token::Minus, operand) => {
+                self.compile(operand)?;
+                self.emit(Instr::Negate);
+            }
+            Ast::UnaryOp(Token::Factorial, operand) => {
+                self.compile(operand)?;
+                self.emit(Instr::Factorial);
+            }
+            Ast::UnaryOp(Token::BitNot, _) => {
+                return Err(bitwise_requires_integer_mode(&Token::BitNot))
+            }
+            Ast::UnaryOp(_, operand) => self.compile(operand)?,
+            Ast::Call(name, args) if series_notation(name, args).is_some() => {
+                return Err(ExpError::ParseError(format!(
+                    "{}() notation is not supported in compiled programs",
+                    name
+                )))
+            }
+            Ast::Call(name, args) => {
+                let function = *self
+                    .functions
+                    .get(name.as_str())
+                    .ok_or_else(|| ExpError::ParseError(format!("Unknown function: {}", name)))?;
+                for arg in args {
+                    self.compile(arg)?;
+                }
+                self.emit(Instr::Call(function, args.len()));
+            }
+            // 条件表达式编译为跳转，只执行被选中的分支
+            Ast::Cond(cond, then, otherwise) => {
+                self.compile(cond)?;
+                let jump_if_zero = self.code.len();
+                self.emit(Instr::JumpIfZero(0));
+                self.compile(then)?;
+                let jump = self.code.len();
+                self.emit(Instr::Jump(0));
+                // 两个分支只有一个会执行，else 分支开始时栈深度与 then 分支开始时相同
+                self.depth -= 1;
+                self.code[jump_if_zero] = Instr::JumpIfZero(self.code.len());
+                self.compile(otherwise)?;
+                self.code[jump] = Instr::Jump(self.code.len());
+            }
+            Ast::Assign(..) | Ast::Define(..) | Ast::Seq(_) => {
+                return Err(ExpError::ParseError(
+                    "Only single expressions can be compiled".to_string(),
+                ))
+            }
+            // 字符串参数只能传给 FunctionProvider，字节码不支持
+            Ast::Str(_) => {
+                return Err(ExpError::ParseError(
+                    "String arguments cannot be compiled".to_string(),
+                ))
+            }
+            // 字节码只计算数
+            Ast::List(_) => {
+                return Err(ExpError::ParseError(
+                    "Vectors cannot be compiled".to_string(),
+                ))
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Program {
+    // 变量名，run 的参数按这个顺序传入
+    pub fn variables(&self) -> &[String] {
+        &self.variables
+    }
+
+    // 使用给定的变量值执行程序，vars 的顺序与 variables() 一致
+    pub fn run(&self, vars: &[f64]) -> Result<f64> {
+        if vars.len() != self.variables.len() {
+            return Err(arity_error(self.variables.len(), vars.len()));
+        }
+        execute(&self.code, vars, &mut self.stack.borrow_mut())
+    }
+}
+
+// 传入的变量值个数与程序的变量个数不一致
+fn arity_error(expected: usize, got: usize) -> ExpError {
+    ExpError::ParseError(format!(
+        "Program takes {} variable(s), got {}",
+        expected, got
+    ))
+}
+
+// 执行指令序列，stack 是可以复用的操作数栈
+fn execute(code: &[Instr], vars: &[f64], stack: &mut Vec<f64>) -> Result<f64> {
+    stack.clear();
+    let mut pc = 0;
+    while let Some(instr) = code.get(pc) {
+        pc += 1;
+        match instr {
+            Instr::Const(value) => stack.push(*value),
+            Instr::Load(index) => stack.push(vars[*index]),
+            Instr::Binary(op) => {
+                let right = stack.pop().unwrap();
+                let left = stack.pop().unwrap();
+                let value = op
+                    .compute(left, right)
+                    .ok_or_else(|| ExpError::ParseError("Unexpected expr".into()))?;
+                stack.push(value);
+            }
+            Instr::Negate => {
+                let value = stack.pop().unwrap();
+                stack.push(-value);
+            }
+            Instr::Factorial => {
+                let value = stack.pop().unwrap();
+                stack.push(factorial(value)?);
+            }
+            Instr::Call(function, argc) => {
+                let start = stack.len() - argc;
+                let value = function(&stack[start..])?;
+                stack.truncate(start);
+                stack.push(value);
+            }
+            Instr::JumpIfZero(target) => {
+                if stack.pop().unwrap() == 0.0 {
+                    pc = *target;
+                }
+            }
+            Instr::Jump(target) => pc = *target,
+        }
+    }
+    Ok(stack.pop().unwrap())
+}
+
+// 用多组变量值执行同一个程序，结果与 sets 一一对应，某一组出错不影响其他组
+// 开启 `parallel` feature 时用 rayon 多线程执行，每个线程使用自己的操作数栈
+pub fn eval_batch(program: &Program, sets: &[VarSet]) -> Vec<Result<f64>> {
+    // Program 中的 RefCell 不能跨线程共享，只借用指令序列
+    let (code, arity, max_depth) = (&program.code, program.variables.len(), program.max_depth);
+    let run = |stack: &mut Vec<f64>, vars: &VarSet| {
+        if vars.len() != arity {
+            return Err(arity_error(arity, vars.len()));
+        }
+        execute(code, vars, stack)
+    };
+    let new_stack = || Vec::with_capacity(max_depth);
+    #[cfg(feature = "parallel")]
+    {
+        sets.par_iter().map_init(new_stack, run).collect()
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        let mut stack = new_stack();
+        sets.iter().map(|vars| run(&mut stack, vars)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compiled_program_matches_evaluator() {
+        let program = compile("x^2 + 2*x*y - sqrt(abs(y)) / 3 + pi").unwrap();
+        assert_eq!(program.variables(), ["x", "y"]);
+        for (x, y) in [(0.0, 0.0), (1.5, -2.0), (-3.0, 9.0), (10.0, 0.25)] {
+            let mut context = EvalContext::default();
+            context.set("x", x);
+            context.set("y", y);
+            let expected =
+                evaluate_with_context("x^2 + 2*x*y - sqrt(abs(y)) / 3 + pi", &mut context);
+            assert_eq!(program.run(&[x, y]).unwrap(), expected.unwrap());
+        }
+    }
+
+    #[test]
+    fn test_compiled_conditional_and_calls() {
+        let program = compile("x > 0 ? ln(x) : max(x, -1, 3!)").unwrap();
+        assert_eq!(program.run(&[1.0]).unwrap(), 0.0);
+        assert_eq!(program.run(&[-5.0]).unwrap(), 6.0);
+        // 未选中的分支不会执行
+        let program = compile("x == 0 ? 0 : 1 / x").unwrap();
+        assert_eq!(program.run(&[0.0]).unwrap(), 0.0);
+        assert_eq!(program.run(&[4.0]).unwrap(), 0.25);
+    }
+
+    #[test]
+    fn test_run_reuses_stack() {
+        let program = compile("(a + b) * (c - (d + (f * 2)))").unwrap();
+        let capacity = program.stack.borrow().capacity();
+        for i in 0..1000 {
+            let v = i as f64;
+            program.run(&[v, v, v, v, v]).unwrap();
+        }
+        assert_eq!(program.stack.borrow().capacity(), capacity);
+        let program = compile(&std::format!("1{}", "+x".repeat(100_000))).unwrap();
+        let capacity = program.stack.borrow().capacity();
+        assert_eq!(program.run(&[1.0]).unwrap(), 100_001.0);
+        // 很长的左结合链只需要两个栈位置
+        assert_eq!(program.stack.borrow().capacity(), capacity);
+        assert!(capacity < 16);
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        assert!(
+            matches!(compile("foo(1)"), Err(ExpError::ParseError(msg)) if msg == "Unknown function: foo")
+        );
+        assert!(compile("x = 3").is_err());
+        assert!(compile("1 & 2").is_err());
+        assert!(compile("1 +").is_err());
+        let program = compile("x + y").unwrap();
+        assert_eq!(
+            program.run(&[1.0]).unwrap_err().to_string(),
+            "ParseError: Program takes 2 variable(s), got 1"
+        );
+        // 运行时错误与求值器一致
+        assert!(compile("(-1)!").unwrap().run(&[]).is_err());
+    }
+
+    #[test]
+    fn test_eval_batch() {
+        let program = compile("x > 0 ? sqrt(x) * y : (-x)!").unwrap();
+        let sets: Vec<VarSet> = (0..1000).map(|i| vec![i as f64, 2.0]).collect();
+        let results = eval_batch(&program, &sets);
+        assert_eq!(results.len(), 1000);
+        for (vars, result) in sets.iter().zip(&results) {
+            assert_eq!(result.as_ref().unwrap(), &program.run(vars).unwrap());
+        }
+        assert_eq!(results[0].as_ref().unwrap(), &1.0);
+        assert_eq!(results[16].as_ref().unwrap(), &8.0);
+        // 出错的一组不影响其他组
+        let results = eval_batch(&program, &[vec![-0.5, 1.0], vec![1.0], vec![4.0, 1.0]]);
+        assert!(results[0].is_err());
+        assert!(
+            matches!(&results[1], Err(ExpError::ParseError(msg)) if msg == "Program takes 2 variable(s), got 1")
+        );
+        assert_eq!(results[2].as_ref().unwrap(), &2.0);
+        assert!(eval_batch(&program, &[]).is_empty());
+    }
+}
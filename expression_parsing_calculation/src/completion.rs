@@ -0,0 +1,240 @@
+// REPL 的补全和行内提示
+// 补全光标前的标识符：内置函数、常量、会话中定义的变量和函数；提示输入当前的计算结果
+// 两者都只读取 ReplState，由 run_repl 通过 rustyline 的 Completer、Hinter 接入
+use super::*;
+
+// 光标前正在输入的标识符的起始位置（字节）
+fn word_start(line: &str, pos: usize) -> usize {
+    line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map_or(pos, |(start, _)| start)
+}
+
+// 补全光标前的标识符，函数名后面带上 `(`
+// 返回被替换部分的起始位置（字节）和按字母排序的候选项；命令名和数字不补全
+pub fn repl_completions(line: &str, pos: usize, state: &ReplState) -> (usize, Vec<String>) {
+    let start = word_start(line, pos);
+    let prefix = &line[start..pos];
+    if prefix.is_empty()
+        || prefix.starts_with(|c: char| c.is_ascii_digit())
+        || line[..start].ends_with(':')
+    {
+        return (pos, Vec::new());
+    }
+    let context = &state.context;
+    // 求积记号 prod(k, 1, n, 通项) 不在内置函数表中
+    let functions = builtin_functions()
+        .into_keys()
+        .chain(random::RANDOM_FUNCTIONS)
+        .chain(datetime::DATE_FUNCTIONS)
+        .chain(tensor::TENSOR_FUNCTIONS)
+        .chain(["prod"])
+        .map(str::to_string)
+        .chain(context.functions.keys().cloned())
+        .map(|name| format!("{}(", name));
+    let values = BUILTIN_CONSTANTS
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .chain(
+            context
+                .sorted_variables()
+                .into_iter()
+                .map(|(name, _)| name.to_string()),
+        );
+    let mut candidates: Vec<String> = functions
+        .chain(values)
+        .filter(|candidate| candidate.starts_with(prefix))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+// 提示在每次按键时计算，调用求和与求积记号、向量和矩阵函数或会话中定义的函数时可能很慢，不提示
+fn costly_call(ast: &Ast, context: &EvalContext) -> bool {
+    struct Costly<'a> {
+        context: &'a EvalContext,
+        found: bool,
+    }
+    impl visit::Visitor for Costly<'_> {
+        fn visit_call(&mut self, name: &str, args: &[Ast]) -> bool {
+            self.found |= series_notation(name, args).is_some()
+                || tensor::TENSOR_FUNCTIONS.contains(&name)
+                || self.context.function(name).is_some();
+            !self.found
+        }
+    }
+    let mut costly = Costly {
+        context,
+        found: false,
+    };
+    ast.walk(&mut costly);
+    costly.found
+}
+
+// 输入可以求值时提示当前的结果，如输入 `2 * 3` 时提示 ` = 6`
+// 在上下文的副本上求值，赋值和随机数不会影响会话；命令、函数定义、单独的数字和耗时的调用不提示
+pub fn repl_hint(line: &str, state: &ReplState) -> Option<String> {
+    let input = line.trim();
+    if input.is_empty() || input.starts_with(':') {
+        return None;
+    }
+    match parse(input) {
+        Ok(Ast::Num(_) | Ast::Define(..)) => return None,
+        Ok(ast) if costly_call(&ast, &state.context) => return None,
+        _ => {}
+    }
+    let output = if state.units {
+        units::evaluate_with_units(input).ok()?
+    } else if state.integer_mode {
+        let value = evaluate_integer_with_context(input, &mut state.context.clone()).ok()?;
+        state.format.format(value)
+    } else {
+        let value = evaluate_value_with_context(input, &mut state.context.clone()).ok()?;
+        state.format.format_value(&value)
+    };
+    Some(format!(" = {}", output))
+}
+
+// 交互模式的 rustyline 辅助对象，持有 REPL 的状态，补全和提示才能看到会话中定义的变量
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct ReplHelper {
+    pub(crate) state: ReplState,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustyline::completion::Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &rustyline::Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(repl_completions(line, pos, &self.state))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustyline::hint::Hinter for ReplHelper {
+    type Hint = String;
+
+    // 只在光标位于行尾时提示，避免提示覆盖后面的输入
+    fn hint(&self, line: &str, pos: usize, _ctx: &rustyline::Context<'_>) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        repl_hint(line, &self.state)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustyline::highlight::Highlighter for ReplHelper {
+    // 提示用暗色显示，与输入区分开
+    fn highlight_hint<'h>(&self, hint: &'h str) -> std::borrow::Cow<'h, str> {
+        std::borrow::Cow::Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustyline::validate::Validator for ReplHelper {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl rustyline::Helper for ReplHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completions(line: &str, state: &ReplState) -> (usize, Vec<String>) {
+        repl_completions(line, line.len(), state)
+    }
+
+    #[test]
+    fn test_complete_builtins() {
+        let state = ReplState::default();
+        assert_eq!(
+            completions("1 + sq", &state),
+            (4, vec!["sqrt(".to_string()])
+        );
+        assert_eq!(
+            completions("ta", &state),
+            (0, vec!["tan(".to_string(), "tau".to_string()])
+        );
+        assert_eq!(
+            completions("2 * p", &state).1,
+            ["percentile(", "pi", "prod("]
+        );
+        assert_eq!(completions("ran", &state).1, ["rand(", "randint("]);
+        // 光标在中间时只补全光标前的部分
+        assert_eq!(
+            repl_completions("mea + 1", 3, &state),
+            (0, vec!["mean(".to_string()])
+        );
+        // 空白、数字和命令名不补全
+        assert!(completions("1 + ", &state).1.is_empty());
+        assert!(completions("12", &state).1.is_empty());
+        assert!(completions(":fm", &state).1.is_empty());
+        assert_eq!(completions(":fmt co", &state).1, ["cos("]);
+    }
+
+    #[test]
+    fn test_complete_session_names() {
+        let mut state = ReplState::default();
+        repl_line("rate = 0.05", &mut state);
+        repl_line("area(r) = pi * r^2", &mut state);
+        repl_line("2 + 2", &mut state);
+        assert_eq!(completions("ra", &state).1, ["rand(", "randint(", "rate"]);
+        assert_eq!(completions("1 + ar", &state).1, ["area("]);
+        assert_eq!(completions("an", &state).1, ["ans"]);
+        repl_line(":clear", &mut state);
+        assert!(completions("ar", &state).1.is_empty());
+    }
+
+    #[test]
+    fn test_hint_partial_result() {
+        let mut state = ReplState::default();
+        assert_eq!(repl_hint("2 * 3", &state), Some(" = 6".to_string()));
+        assert_eq!(
+            repl_hint("[1, 2] * 2", &state),
+            Some(" = [2, 4]".to_string())
+        );
+        // 输入不完整、命令、函数定义和单独的数字没有提示
+        assert_eq!(repl_hint("2 * ", &state), None);
+        assert_eq!(repl_hint("42", &state), None);
+        assert_eq!(repl_hint(":vars", &state), None);
+        assert_eq!(repl_hint("f(x) = x + 1", &state), None);
+        assert_eq!(repl_hint("y + 1", &state), None);
+        // 提示使用会话的变量和输出格式，但不会修改会话
+        repl_line("x = 10", &mut state);
+        repl_line(":digits fixed 2", &mut state);
+        assert_eq!(repl_hint("x / 4", &state), Some(" = 2.50".to_string()));
+        assert_eq!(repl_hint("x = 1", &state), Some(" = 1.00".to_string()));
+        assert_eq!(state.context.get("x"), Some(10.0));
+        repl_line(":int", &mut state);
+        assert_eq!(repl_hint("7 / 2", &state), Some(" = 3.00".to_string()));
+    }
+
+    #[test]
+    fn test_hint_skips_costly_calls() {
+        let mut state = ReplState::default();
+        repl_line("f(n) = n + 1", &mut state);
+        for line in [
+            "sum(i, 1, 1000000, i)",
+            "1 + prod(k, 1, 10, k)",
+            "dot([1, 2], [3, 4])",
+            "dot(",
+            "dot()",
+            "2 * f(1)",
+        ] {
+            assert_eq!(repl_hint(line, &state), None, "{:?}", line);
+        }
+        // 普通的内置函数仍然提示
+        assert_eq!(repl_hint("sum(1, 2, 3)", &state), Some(" = 6".to_string()));
+        assert_eq!(repl_hint("sqrt(16)", &state), Some(" = 4".to_string()));
+    }
+}
@@ -0,0 +1,362 @@
+// 日期和时长：date("2024-01-01") 是日期，today() 是今天的日期，
+// 数字后面紧跟 w、d、h、m、s 是时长字面量（周、天、小时、分钟、秒），如 `3d + 12h`，解析为 duration(3, "d")
+// 日期加减时长得到日期，两个日期相减得到时长，时长可以相加减、乘除以数，两个时长相除得到数，同类的值可以比较大小
+// days_between(a, b) 是从 a 到 b 的天数；日期和时长与向量一样由 evaluate_value 求值，时长精确到微秒
+use std::cmp::Ordering;
+use std::fmt;
+
+use chrono::{Local, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+
+use super::*;
+
+// 参数或结果是日期、时长的函数，会话中定义的和宿主注册的同名函数优先
+pub const DATE_FUNCTIONS: [&str; 4] = ["date", "today", "days_between", "duration"];
+
+// 时长字面量的单位及其秒数
+const DURATION_UNITS: [(&str, f64); 5] = [
+    ("w", 604_800.0),
+    ("d", 86_400.0),
+    ("h", 3_600.0),
+    ("m", 60.0),
+    ("s", 1.0),
+];
+
+// date() 接受的日期时间格式，只有日期时为当天零点
+const DATETIME_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+pub fn is_date_function(name: &str) -> bool {
+    DATE_FUNCTIONS.contains(&name)
+}
+
+fn unit_seconds(unit: &str) -> Option<f64> {
+    DURATION_UNITS
+        .iter()
+        .find(|(name, _)| *name == unit)
+        .map(|(_, seconds)| *seconds)
+}
+
+// 时长字面量 duration(n, "d") 的数和单位，格式化时写回 `nd`
+pub fn duration_literal<'a>(name: &str, args: &'a [Ast]) -> Option<(&'a Ast, &'a str)> {
+    match (name, args) {
+        ("duration", [number @ Ast::Num(n), Ast::Str(unit)])
+            if *n >= 0.0 && unit_seconds(unit).is_some() =>
+        {
+            Some((number, unit))
+        }
+        _ => None,
+    }
+}
+
+// 秒数转换为时长，舍入到微秒
+fn from_seconds(seconds: f64) -> Result<TimeDelta> {
+    let micros = (seconds * 1e6).round();
+    if !micros.is_finite() || micros.abs() >= i64::MAX as f64 {
+        return Err(ExpError::Overflow);
+    }
+    Ok(TimeDelta::microseconds(micros as i64))
+}
+
+fn seconds(duration: &TimeDelta) -> f64 {
+    duration.num_seconds() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn shift(date: &NaiveDateTime, duration: TimeDelta) -> Result<Value<'static>> {
+    date.checked_add_signed(duration)
+        .map(Value::Date)
+        .ok_or(ExpError::Overflow)
+}
+
+// 比较运算的结果，与数的比较一样成立时为 1
+fn compare(op: &Token, ordering: Ordering) -> Option<f64> {
+    let result = match op {
+        Token::Less => ordering.is_lt(),
+        Token::LessEqual => ordering.is_le(),
+        Token::Greater => ordering.is_gt(),
+        Token::GreaterEqual => ordering.is_ge(),
+        Token::Equal => ordering.is_eq(),
+        Token::NotEqual => ordering.is_ne(),
+        _ => return None,
+    };
+    Some(if result { 1.0 } else { 0.0 })
+}
+
+// 至少有一个操作数是日期或时长的二元运算，两个操作数都不是时返回 None
+pub fn binary(op: &Token, a: &Value, b: &Value) -> Option<Result<Value<'static>>> {
+    use Value::{Date, Duration, Number};
+    let result = match (op, a, b) {
+        (Token::Plus, Date(date), Duration(d)) | (Token::Plus, Duration(d), Date(date)) => {
+            shift(date, *d)
+        }
+        (Token::Minus, Date(date), Duration(d)) => shift(date, -*d),
+        (Token::Minus, Date(a), Date(b)) => Ok(Duration(a.signed_duration_since(*b))),
+        (Token::Plus, Duration(a), Duration(b)) => {
+            a.checked_add(b).map(Duration).ok_or(ExpError::Overflow)
+        }
+        (Token::Minus, Duration(a), Duration(b)) => {
+            a.checked_sub(b).map(Duration).ok_or(ExpError::Overflow)
+        }
+        (Token::Multiply, Duration(d), Number(n)) | (Token::Multiply, Number(n), Duration(d)) => {
+            from_seconds(seconds(d) * n).map(Duration)
+        }
+        (Token::Divide, Duration(_), Number(n)) if *n == 0.0 => {
+            Err(ExpError::MathError(MathError::DivisionByZero))
+        }
+        (Token::Divide, Duration(d), Number(n)) => from_seconds(seconds(d) / n).map(Duration),
+        (Token::Divide, Duration(_), Duration(b)) if b.is_zero() => {
+            Err(ExpError::MathError(MathError::DivisionByZero))
+        }
+        (Token::Divide, Duration(a), Duration(b)) => Ok(Number(seconds(a) / seconds(b))),
+        (op, Date(a), Date(b)) if compare(op, a.cmp(b)).is_some() => {
+            Ok(Number(compare(op, a.cmp(b))?))
+        }
+        (op, Duration(a), Duration(b)) if compare(op, a.cmp(b)).is_some() => {
+            Ok(Number(compare(op, a.cmp(b))?))
+        }
+        (_, Date(_) | Duration(_), _) | (_, _, Date(_) | Duration(_)) => {
+            Err(ExpError::ShapeError(std::format!(
+                "cannot apply {} to {} and {}",
+                op,
+                tensor::describe(a),
+                tensor::describe(b)
+            )))
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+// 时长可以取正负号，日期不能参与一元运算
+pub fn unary(op: &Token, value: &Value) -> Option<Result<Value<'static>>> {
+    match (op, value) {
+        (Token::Plus, Value::Duration(d)) => Some(Ok(Value::Duration(*d))),
+        (Token::Minus, Value::Duration(d)) => Some(Ok(Value::Duration(-*d))),
+        (_, Value::Date(_) | Value::Duration(_)) => Some(Err(ExpError::ShapeError(std::format!(
+            "cannot apply {} to {}",
+            op,
+            tensor::describe(value)
+        )))),
+        _ => None,
+    }
+}
+
+fn parse_date(text: &str) -> Result<NaiveDateTime> {
+    DATETIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .map(|date| date.and_time(NaiveTime::MIN))
+        })
+        .ok_or_else(|| {
+            ExpError::ParseError(std::format!(
+                "Invalid date: \"{}\", expected YYYY-MM-DD or YYYY-MM-DD HH:MM[:SS]",
+                text
+            ))
+        })
+}
+
+// 调用日期函数
+pub fn call(name: &str, args: &[Value]) -> Result<Value<'static>> {
+    match (name, args) {
+        ("date", [Value::Text(text)]) => parse_date(text).map(Value::Date),
+        ("today", []) => Ok(Value::Date(
+            Local::now().date_naive().and_time(NaiveTime::MIN),
+        )),
+        ("days_between", [Value::Date(a), Value::Date(b)]) => Ok(Value::Number(
+            seconds(&b.signed_duration_since(*a)) / 86_400.0,
+        )),
+        ("duration", [Value::Number(n), Value::Text(unit)]) => match unit_seconds(unit) {
+            Some(unit) => from_seconds(n * unit).map(Value::Duration),
+            None => Err(ExpError::ParseError(std::format!(
+                "Unknown duration unit: \"{}\", expected one of w, d, h, m, s",
+                unit
+            ))),
+        },
+        _ => {
+            let expected = match name {
+                "date" => "a string like \"2024-01-01\"",
+                "today" => "no arguments",
+                "days_between" => "two dates",
+                _ => "a number and a unit",
+            };
+            Err(ExpError::ParseError(std::format!(
+                "{}() expects {}, got {}",
+                name,
+                expected,
+                match args {
+                    [] => "no arguments".to_string(),
+                    args => args
+                        .iter()
+                        .map(tensor::describe)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                }
+            )))
+        }
+    }
+}
+
+// 零点的日期只显示日期部分
+pub fn write_date(f: &mut fmt::Formatter, date: &NaiveDateTime) -> fmt::Result {
+    if date.time() == NaiveTime::MIN {
+        write!(f, "{}", date.format("%Y-%m-%d"))
+    } else {
+        write!(f, "{}", date.format("%Y-%m-%d %H:%M:%S%.f"))
+    }
+}
+
+// 时长按天、小时、分钟、秒显示，如 `3d 12h`，省略为 0 的部分
+pub fn write_duration(f: &mut fmt::Formatter, duration: &TimeDelta) -> fmt::Result {
+    if duration.is_zero() {
+        return write!(f, "0s");
+    }
+    if *duration < TimeDelta::zero() {
+        write!(f, "-")?;
+    }
+    let duration = duration.abs();
+    let total = duration.num_seconds();
+    let parts = [
+        (total / 86_400, "d"),
+        (total % 86_400 / 3_600, "h"),
+        (total % 3_600 / 60, "m"),
+    ];
+    let mut first = true;
+    for (amount, unit) in parts.into_iter().filter(|(amount, _)| *amount != 0) {
+        if !first {
+            write!(f, " ")?;
+        }
+        write!(f, "{}{}", amount, unit)?;
+        first = false;
+    }
+    let seconds = (total % 60) as f64 + duration.subsec_nanos() as f64 / 1e9;
+    if seconds != 0.0 {
+        if !first {
+            write!(f, " ")?;
+        }
+        write!(f, "{}s", seconds)?;
+    }
+    Ok(())
+}
+
+impl Expr<'_> {
+    // 数字后面紧跟时长单位时是时长字面量，如 3d、12h；中间有空格时不是
+    pub fn parse_number(&mut self, n: f64) -> Ast {
+        let end = self.last_span.offset + self.last_span.len;
+        let adjacent = self.peek_span().offset == end;
+        match self.peek_token() {
+            Some(&Lexeme::Ident(unit)) if adjacent && unit_seconds(unit).is_some() => {
+                self.next_token();
+                Ast::Call(
+                    "duration".to_string(),
+                    vec![Ast::Num(n), Ast::Str(unit.to_string())],
+                )
+            }
+            _ => Ast::Num(n),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(input: &str) -> String {
+        evaluate_value(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_duration_literals() {
+        assert_eq!(value("3d + 12h"), "3d 12h");
+        assert_eq!(value("1w - 1s"), "6d 23h 59m 59s");
+        assert_eq!(value("90m"), "1h 30m");
+        assert_eq!(value("1.5s * 3"), "4.5s");
+        assert_eq!(value("-2h / 4"), "-30m");
+        assert_eq!(value("1d - 24h"), "0s");
+        assert_eq!(value("duration(2, \"h\")"), "2h");
+        assert_eq!(evaluate("3d / 12h").unwrap(), 6.0);
+        assert_eq!(evaluate("1h > 59m").unwrap(), 1.0);
+        assert_eq!(evaluate("2 * 30m == 1h").unwrap(), 1.0);
+        // 作为宿主程序的值取出
+        assert_eq!(
+            evaluate_value("2d").unwrap(),
+            Value::Duration(TimeDelta::days(2))
+        );
+        // 中间有空格、或者单位后面还有字母时不是时长
+        assert!(evaluate_value("3 d").is_err());
+        assert!(evaluate_value("2day").is_err());
+        // 格式化时写回字面量
+        assert_eq!(
+            format::format_expression("3d+12h - 1.5w").unwrap(),
+            "3d + 12h - 1.5w"
+        );
+    }
+
+    #[test]
+    fn test_date_arithmetic() {
+        assert_eq!(value("date(\"2024-01-01\")"), "2024-01-01");
+        assert_eq!(value("date(\"2024-02-28\") + 2d"), "2024-03-01");
+        assert_eq!(value("date(\"2024-01-01\") + 36h"), "2024-01-02 12:00:00");
+        assert_eq!(
+            value("date(\"2024-03-10 08:30\") - 45m"),
+            "2024-03-10 07:45:00"
+        );
+        assert_eq!(value("date(\"2024-03-01\") - date(\"2024-01-01\")"), "60d");
+        assert_eq!(
+            evaluate("days_between(date(\"2024-01-01\"), date(\"2025-01-01\"))").unwrap(),
+            366.0
+        );
+        assert_eq!(
+            evaluate("days_between(date(\"2024-01-02\"), date(\"2024-01-01T12:00:00\"))").unwrap(),
+            -0.5
+        );
+        assert_eq!(
+            evaluate("date(\"2024-01-01\") < date(\"2024-01-02\")").unwrap(),
+            1.0
+        );
+        assert_eq!(
+            evaluate("days_between(today(), today() + 1w)").unwrap(),
+            7.0
+        );
+        assert_eq!(value("today() - today()"), "0s");
+
+        let mut context = EvalContext::new();
+        evaluate_value_with_context("start = date(\"2024-06-01\"); span = 10d", &mut context)
+            .unwrap();
+        assert_eq!(
+            evaluate_value_with_context("start + span * 2", &mut context)
+                .unwrap()
+                .to_string(),
+            "2024-06-21"
+        );
+    }
+
+    #[test]
+    fn test_date_errors() {
+        for input in [
+            "date(\"2024-13-01\")",
+            "date(\"yesterday\")",
+            "date(2024)",
+            "today(1)",
+            "days_between(1, 2)",
+            "duration(1, \"y\")",
+            "date(\"2024-01-01\") + date(\"2024-01-02\")",
+            "date(\"2024-01-01\") * 2",
+            "-date(\"2024-01-01\")",
+            "1d + 1",
+            "1d / 0",
+            "1d / (1d - 24h)",
+            "1e300d",
+            "date(\"2024-01-01\") ? 1 : 0",
+        ] {
+            assert!(evaluate_value(input).is_err(), "{}", input);
+        }
+        // 结果必须是数的地方不能是日期
+        assert!(evaluate("date(\"2024-01-01\")").is_err());
+    }
+}
@@ -0,0 +1,210 @@
+// 任意精度十进制求值后端，需要开启 `arbitrary-precision` feature
+// 加减乘除、取余和整数次幂按十进制精确计算，所以 0.1 + 0.2 == 0.3、3^100 都是精确结果；
+// 函数调用、阶乘、位运算等仍然按 f64 计算后再转换为十进制
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, One, ToPrimitive, Zero};
+
+use super::*;
+
+// 除法结果保留的有效数字位数
+const DIVISION_PRECISION: u64 = 50;
+
+// 使用精确计算的最大整数指数，更大的指数按 f64 计算
+const MAX_EXACT_EXPONENT: u64 = 10_000;
+
+// f64 转换为十进制：使用最短的往返表示，所以字面量 0.1 转换后正好是 0.1
+pub fn from_f64(value: f64) -> Result<BigDecimal> {
+    if !value.is_finite() {
+        return Err(ExpError::Overflow);
+    }
+    BigDecimal::from_str(&value.to_string())
+        .map_err(|e| ExpError::ParseError(format!("cannot convert {} to decimal: {}", value, e)))
+}
+
+// 十进制转换为 f64，超出范围时返回 ExpError::Overflow
+pub fn to_f64(value: &BigDecimal) -> Result<f64> {
+    value
+        .to_f64()
+        .filter(|v| v.is_finite())
+        .ok_or(ExpError::Overflow)
+}
+
+impl Evaluator<'_> {
+    // 按十进制计算语法树的值，不支持精确计算的节点退回到 f64 求值
+    pub fn eval_decimal(&mut self, ast: &Ast) -> Result<BigDecimal> {
+        match ast {
+            Ast::Num(n) if !self.integer_mode => from_f64(*n),
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                let mut value = self.eval_decimal(node)?;
+                for (op, rhs) in spine {
+                    let rhs = self.eval_decimal(rhs)?;
+                    value = self.compute_decimal(op, value, rhs)?;
+                }
+                Ok(value)
+            }
+            Ast::UnaryOp(Token::Minus, operand) => Ok(-self.eval_decimal(operand)?),
+            Ast::UnaryOp(Token::Plus, operand) => self.eval_decimal(operand),
+            Ast::Cond(cond, then, otherwise) => {
+                if !self.eval_decimal(cond)?.is_zero() {
+                    self.eval_decimal(then)
+                } else {
+                    self.eval_decimal(otherwise)
+                }
+            }
+            Ast::Assign(name, value) => {
+                let value = self.eval_decimal(value)?;
+                match self.context.as_deref_mut() {
+                    // 上下文中的变量仍然保存为 f64
+                    Some(context) => context.set(name, to_f64(&value)?),
+                    None => {
+                        return Err(ExpError::ParseError(
+                            "Assignment requires an EvalContext".to_string(),
+                        ))
+                    }
+                }
+                Ok(value)
+            }
+            Ast::Seq(statements) => {
+                let mut value = Err(ExpError::ParseError("Empty expression".to_string()));
+                for statement in statements {
+                    value = Ok(self.eval_decimal(statement)?);
+                }
+                value
+            }
+            _ => from_f64(self.eval(ast)?),
+        }
+    }
+
+    // 十进制的二元运算，整数模式和其他运算符按 f64 计算
+    fn compute_decimal(
+        &self,
+        token: &Token,
+        left: BigDecimal,
+        right: BigDecimal,
+    ) -> Result<BigDecimal> {
+        if self.integer_mode {
+            return from_f64(self.compute_binary(token, to_f64(&left)?, to_f64(&right)?)?);
+        }
+        let division_by_zero = || ExpError::MathError(MathError::DivisionByZero);
+        let result = match token {
+            Token::Plus => left + right,
+            Token::Minus => left - right,
+            Token::Multiply => left * right,
+            Token::Divide if right.is_zero() => return Err(division_by_zero()),
+            Token::Divide => (left / right).with_prec(DIVISION_PRECISION),
+            Token::Modulo if right.is_zero() => return Err(division_by_zero()),
+            Token::Modulo => left % right,
+            Token::Power => match power(&left, &right) {
+                Some(result) => result,
+                None => {
+                    return from_f64(self.compute_binary(token, to_f64(&left)?, to_f64(&right)?)?)
+                }
+            },
+            Token::Less => bool_decimal(left < right),
+            Token::LessEqual => bool_decimal(left <= right),
+            Token::Greater => bool_decimal(left > right),
+            Token::GreaterEqual => bool_decimal(left >= right),
+            // 十进制下相等比较是精确的，不需要容差
+            Token::Equal => bool_decimal(left == right),
+            Token::NotEqual => bool_decimal(left != right),
+            Token::And => bool_decimal(!left.is_zero() && !right.is_zero()),
+            Token::Or => bool_decimal(!left.is_zero() || !right.is_zero()),
+            _ => return from_f64(self.compute_binary(token, to_f64(&left)?, to_f64(&right)?)?),
+        };
+        Ok(result.normalized())
+    }
+}
+
+fn bool_decimal(b: bool) -> BigDecimal {
+    if b {
+        BigDecimal::one()
+    } else {
+        BigDecimal::zero()
+    }
+}
+
+// 整数次幂的精确计算（快速幂），指数不是整数或过大时返回 None
+fn power(base: &BigDecimal, exponent: &BigDecimal) -> Option<BigDecimal> {
+    if !exponent.is_integer() {
+        return None;
+    }
+    let exponent = exponent.to_i64()?;
+    let mut remaining = exponent.unsigned_abs();
+    if remaining > MAX_EXACT_EXPONENT {
+        return None;
+    }
+    let mut result = BigDecimal::one();
+    let mut square = base.clone();
+    while remaining > 0 {
+        if remaining & 1 == 1 {
+            result *= &square;
+        }
+        square = &square * &square;
+        remaining >>= 1;
+    }
+    if exponent < 0 {
+        if result.is_zero() {
+            return None;
+        }
+        result = (BigDecimal::one() / result).with_prec(DIVISION_PRECISION);
+    }
+    Some(result)
+}
+
+impl Expr<'_> {
+    // 解析并按十进制计算表达式的值
+    pub fn eval_decimal(&mut self) -> Result<BigDecimal> {
+        let ast = self.parse()?;
+        self.evaluator.eval_decimal(&ast)
+    }
+}
+
+// 按十进制求值，返回精确结果，例如 `0.1 + 0.2` 等于 0.3
+pub fn evaluate_decimal(input: &str) -> Result<BigDecimal> {
+    let mut context = EvalContext::default();
+    Expr::new(strip_formula_prefix(input)?)
+        .with_context(&mut context)
+        .eval_decimal()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn test_exact_decimal_arithmetic() {
+        assert_eq!(evaluate_decimal("0.1 + 0.2").unwrap(), decimal("0.3"));
+        assert_eq!(evaluate_decimal("0.1 + 0.2 == 0.3").unwrap(), decimal("1"));
+        assert_eq!(evaluate_decimal("1.1 * 1.1").unwrap(), decimal("1.21"));
+        assert_eq!(evaluate_decimal("10 / 4").unwrap(), decimal("2.5"));
+        assert_eq!(evaluate_decimal("-7.5 % 2").unwrap(), decimal("-1.5"));
+        assert_eq!(
+            evaluate_decimal("3^40").unwrap(),
+            decimal("12157665459056928801")
+        );
+        assert_eq!(evaluate_decimal("2^-2").unwrap(), decimal("0.25"));
+        assert_eq!(evaluate_decimal("x = 0.1; x * 3").unwrap(), decimal("0.3"));
+        assert!(evaluate_decimal("1 / 0").is_err());
+    }
+
+    #[test]
+    fn test_decimal_falls_back_to_float() {
+        // 函数调用和非整数次幂按 f64 计算
+        assert_eq!(evaluate_decimal("sqrt(16) + 0.1").unwrap(), decimal("4.1"));
+        assert_eq!(evaluate_decimal("4^0.5").unwrap(), decimal("2"));
+        assert_eq!(
+            Expr::new("0.1 + 0.2")
+                .with_precision(Precision::Arbitrary)
+                .eval()
+                .unwrap(),
+            0.3
+        );
+        assert_eq!(Expr::new("0.1 + 0.2").eval().unwrap(), 0.1 + 0.2);
+    }
+}
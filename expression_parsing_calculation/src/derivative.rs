@@ -0,0 +1,404 @@
+// 符号求导：对多项式、三角函数、指数和对数表达式求导，返回化简后的语法树
+// 如 x^2 + 3x 对 x 求导得到 2 * x + 3
+use super::*;
+
+// 对变量 var 求导，结果已经过 Ast::simplify 化简
+// 阶乘、比较、位运算等不可导的运算依赖 var 时返回错误
+pub fn derivative(ast: &Ast, var: &str) -> Result<Ast> {
+    Ok(differentiate(ast, var)?.simplify())
+}
+
+// 解析表达式并对 var 求导，允许省略乘号（如 3x）；var 必须是一个标识符
+pub fn derivative_expression(input: &str, var: &str) -> Result<Ast> {
+    let mut lexemes = Tokenizer::new(var).lexemes();
+    if !matches!(
+        (lexemes.next(), lexemes.next()),
+        (Some((Lexeme::Ident(name), _)), None) if name == var
+    ) {
+        return Err(ExpError::ParseError(format!(
+            "Invalid variable name: {:?}",
+            var
+        )));
+    }
+    let ast = Expr::new(strip_formula_prefix(input)?)
+        .with_implicit_multiplication(true)
+        .parse()?;
+    derivative(&ast, var)
+}
+
+// 表达式中是否出现变量 var
+pub fn contains_var(ast: &Ast, var: &str) -> bool {
+    // 用显式的栈遍历，避免很长的运算链导致递归过深
+    let mut pending = vec![ast];
+    while let Some(node) = pending.pop() {
+        match node {
+            Ast::Var(name) if name == var => return true,
+            Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => {}
+            Ast::BinOp(_, lhs, rhs) => pending.extend([lhs.as_ref(), rhs.as_ref()]),
+            Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) => pending.push(operand),
+            Ast::Call(_, args) | Ast::Seq(args) | Ast::List(args) => pending.extend(args.iter()),
+            Ast::Cond(cond, then, otherwise) => {
+                pending.extend([cond.as_ref(), then.as_ref(), otherwise.as_ref()])
+            }
+            // 函数体中的变量是参数，与外面的变量无关
+            Ast::Define(..) => {}
+        }
+    }
+    false
+}
+
+fn num(n: f64) -> Ast {
+    Ast::Num(n)
+}
+
+fn binary(op: Token, lhs: Ast, rhs: Ast) -> Ast {
+    Ast::BinOp(op, Box::new(lhs), Box::new(rhs))
+}
+
+fn call(name: &str, arg: Ast) -> Ast {
+    Ast::Call(name.to_string(), vec![arg])
+}
+
+fn neg(operand: Ast) -> Ast {
+    Ast::UnaryOp(Token::Minus, Box::new(operand))
+}
+
+fn cannot_differentiate(what: &str) -> ExpError {
+    ExpError::ParseError(format!("Cannot differentiate {}", what))
+}
+
+// 乘除和乘方链上的一环 lhs op rhs 的导数，dlhs 和 drhs 是两边的导数
+fn differentiate_link(op: &Token, lhs: &Ast, dlhs: Ast, rhs: &Ast, drhs: Ast, var: &str) -> Ast {
+    match (contains_var(lhs, var), contains_var(rhs, var)) {
+        (false, false) => num(0.0),
+        // (f^n)' = n f^(n-1) f'
+        (true, false) if *op == Token::Power => binary(
+            Token::Multiply,
+            binary(
+                Token::Multiply,
+                rhs.clone(),
+                binary(
+                    Token::Power,
+                    lhs.clone(),
+                    binary(Token::Minus, rhs.clone(), num(1.0)),
+                ),
+            ),
+            dlhs,
+        ),
+        // (a^g)' = a^g ln(a) g'
+        (false, true) if *op == Token::Power => binary(
+            Token::Multiply,
+            binary(
+                Token::Multiply,
+                binary(Token::Power, lhs.clone(), rhs.clone()),
+                call("ln", lhs.clone()),
+            ),
+            drhs,
+        ),
+        // (f^g)' = f^g (g' ln(f) + g f' / f)
+        (true, true) if *op == Token::Power => binary(
+            Token::Multiply,
+            binary(Token::Power, lhs.clone(), rhs.clone()),
+            binary(
+                Token::Plus,
+                binary(Token::Multiply, drhs, call("ln", lhs.clone())),
+                binary(
+                    Token::Divide,
+                    binary(Token::Multiply, rhs.clone(), dlhs),
+                    lhs.clone(),
+                ),
+            ),
+        ),
+        // 一侧是常量时直接提出来，否则使用乘积法则
+        (false, true) if *op == Token::Multiply => binary(Token::Multiply, lhs.clone(), drhs),
+        (_, false) => binary(op.clone(), dlhs, rhs.clone()),
+        (true, true) if *op == Token::Multiply => binary(
+            Token::Plus,
+            binary(Token::Multiply, dlhs, rhs.clone()),
+            binary(Token::Multiply, lhs.clone(), drhs),
+        ),
+        // (c/g)' = -c g' / g^2
+        (false, true) => binary(
+            Token::Divide,
+            neg(binary(Token::Multiply, lhs.clone(), drhs)),
+            binary(Token::Power, rhs.clone(), num(2.0)),
+        ),
+        // 商的法则：(f/g)' = (f' g - f g') / g^2
+        (true, true) => binary(
+            Token::Divide,
+            binary(
+                Token::Minus,
+                binary(Token::Multiply, dlhs, rhs.clone()),
+                binary(Token::Multiply, lhs.clone(), drhs),
+            ),
+            binary(Token::Power, rhs.clone(), num(2.0)),
+        ),
+    }
+}
+
+// 求导，不做化简
+fn differentiate(ast: &Ast, var: &str) -> Result<Ast> {
+    // 不含 var 的子表达式是常量
+    if !contains_var(ast, var) {
+        return Ok(num(0.0));
+    }
+    let d = |ast: &Ast| differentiate(ast, var);
+    let result = match ast {
+        Ast::Var(_) => num(1.0),
+        Ast::BinOp(Token::Plus | Token::Minus, ..) => {
+            let (node, spine) =
+                ast.left_spine_while(|op| matches!(op, Token::Plus | Token::Minus), |_, _| true);
+            let mut result = d(node)?;
+            for (op, rhs) in spine {
+                result = binary(op.clone(), result, d(rhs)?);
+            }
+            result
+        }
+        // 右结合的乘方塔 a^b^...^z 从最右边的指数开始向外迭代
+        Ast::BinOp(Token::Power, _, exponent)
+            if matches!(**exponent, Ast::BinOp(Token::Power, ..)) =>
+        {
+            let mut bases = Vec::new();
+            let mut value = ast;
+            while let Ast::BinOp(Token::Power, base, exponent) = value {
+                bases.push(base.as_ref());
+                value = exponent;
+            }
+            let mut result = d(value)?;
+            let mut value = value.clone();
+            for base in bases.into_iter().rev() {
+                result = differentiate_link(&Token::Power, base, d(base)?, &value, result, var);
+                value = binary(Token::Power, base.clone(), value);
+            }
+            result
+        }
+        Ast::BinOp(Token::Multiply | Token::Divide | Token::Power, ..) => {
+            // 沿乘除和乘方链迭代，value 是链上已经处理过的左边部分，result 是它的导数
+            // 避免 x*x*...*x 这样很长的链递归过深
+            let (node, spine) = ast.left_spine_while(
+                |op| matches!(op, Token::Multiply | Token::Divide | Token::Power),
+                |_, _| true,
+            );
+            let mut value = node.clone();
+            let mut result = d(node)?;
+            for (op, rhs) in spine {
+                result = differentiate_link(op, &value, result, rhs, d(rhs)?, var);
+                value = binary(op.clone(), value, rhs.clone());
+            }
+            result
+        }
+        Ast::UnaryOp(Token::Minus, operand) => neg(d(operand)?),
+        Ast::UnaryOp(Token::Plus, operand) => d(operand)?,
+        // 求和逐项求导，下标变量与 var 同名时 var 是求和内部的局部变量
+        Ast::Call(name, args) if series_notation(name, args).is_some() => {
+            let (index, from, to, body) = series_notation(name, args).unwrap();
+            if index == var {
+                num(0.0)
+            } else if name != "sum" {
+                return Err(cannot_differentiate(&format!("{}()", name)));
+            } else if contains_var(from, var) || contains_var(to, var) {
+                return Err(cannot_differentiate("sum() with variable bounds"));
+            } else {
+                let term = d(body)?.simplify();
+                if contains_var(&term, index) {
+                    Ast::Call(
+                        name.clone(),
+                        vec![args[0].clone(), from.clone(), to.clone(), term],
+                    )
+                } else {
+                    // 通项的导数与下标无关时就是项数乘以该导数
+                    let count = binary(
+                        Token::Plus,
+                        binary(Token::Minus, to.clone(), from.clone()),
+                        num(1.0),
+                    );
+                    let count = Ast::Call("max".to_string(), vec![count, num(0.0)]);
+                    binary(Token::Multiply, count, term)
+                }
+            }
+        }
+        Ast::Call(name, args) => match args.as_slice() {
+            // 链式法则：f(u)' = f'(u) u'
+            [u] => {
+                let outer = match name.as_str() {
+                    "sin" => call("cos", u.clone()),
+                    "cos" => neg(call("sin", u.clone())),
+                    "tan" => binary(
+                        Token::Divide,
+                        num(1.0),
+                        binary(Token::Power, call("cos", u.clone()), num(2.0)),
+                    ),
+                    "exp" => call("exp", u.clone()),
+                    "ln" => binary(Token::Divide, num(1.0), u.clone()),
+                    "log" => binary(
+                        Token::Divide,
+                        num(1.0),
+                        binary(Token::Multiply, u.clone(), call("ln", num(10.0))),
+                    ),
+                    "sqrt" => binary(
+                        Token::Divide,
+                        num(1.0),
+                        binary(Token::Multiply, num(2.0), call("sqrt", u.clone())),
+                    ),
+                    "asin" | "acos" => {
+                        let slope = binary(
+                            Token::Divide,
+                            num(1.0),
+                            call(
+                                "sqrt",
+                                binary(
+                                    Token::Minus,
+                                    num(1.0),
+                                    binary(Token::Power, u.clone(), num(2.0)),
+                                ),
+                            ),
+                        );
+                        if name == "acos" {
+                            neg(slope)
+                        } else {
+                            slope
+                        }
+                    }
+                    "atan" => binary(
+                        Token::Divide,
+                        num(1.0),
+                        binary(
+                            Token::Plus,
+                            num(1.0),
+                            binary(Token::Power, u.clone(), num(2.0)),
+                        ),
+                    ),
+                    _ => return Err(cannot_differentiate(&format!("{}()", name))),
+                };
+                binary(Token::Multiply, outer, d(u)?)
+            }
+            _ => return Err(cannot_differentiate(&format!("{}()", name))),
+        },
+        // 分段求导：条件不变，两个分支分别求导
+        Ast::Cond(cond, then, otherwise) => {
+            Ast::Cond(cond.clone(), Box::new(d(then)?), Box::new(d(otherwise)?))
+        }
+        // 向量和矩阵逐个元素求导，不含 var 的元素导数为 0
+        Ast::List(items) => Ast::List(items.iter().map(d).collect::<Result<_>>()?),
+        Ast::BinOp(op, ..) => return Err(cannot_differentiate(&format!("operator {}", op))),
+        Ast::UnaryOp(op, _) => return Err(cannot_differentiate(&format!("operator {}", op))),
+        Ast::Num(_) | Ast::Str(_) | Ast::Assign(..) | Ast::Seq(_) | Ast::Define(..) => {
+            return Err(cannot_differentiate("statements"))
+        }
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn derived(input: &str) -> String {
+        format::format(&derivative_expression(input, "x").unwrap())
+    }
+
+    #[test]
+    fn test_polynomial_derivative() {
+        assert_eq!(derived("x^2 + 3x"), "2 * x + 3");
+        assert_eq!(derived("x^3 - 2x + 7"), "3 * x ^ 2 - 2");
+        assert_eq!(derived("5"), "0");
+        assert_eq!(derived("y * x"), "y");
+        assert_eq!(derived("-x"), "-1");
+        assert_eq!(derived("x * x"), "x + x");
+        assert_eq!(derived("1 / x"), "-1 / x ^ 2");
+    }
+
+    #[test]
+    fn test_function_derivative() {
+        assert_eq!(derived("sin(x)"), "cos(x)");
+        assert_eq!(derived("cos(2x)"), "-sin(2 * x) * 2");
+        assert_eq!(derived("exp(x^2)"), "exp(x ^ 2) * (2 * x)");
+        assert_eq!(derived("ln(x)"), "1 / x");
+        assert_eq!(derived("2^x"), "2 ^ x * ln(2)");
+        assert_eq!(derived("x > 0 ? x^2 : -x"), "x > 0 ? 2 * x : -1");
+        assert_eq!(derived("sum(i, 1, 3, i * x)"), "sum(i, 1, 3, i)");
+        assert_eq!(derived("sum(x, 1, 3, x^2)"), "0");
+        assert_eq!(derived("[x^2, 3, 2x]"), "[2 * x, 0, 2]");
+    }
+
+    #[test]
+    fn test_derivative_matches_numeric_slope() {
+        for input in [
+            "x^2 + 3x",
+            "sin(x) * exp(x)",
+            "x^x",
+            "sqrt(x) / (1 + x)",
+            "atan(x) - ln(x)",
+            "sum(i, 1, 4, x^i / i)",
+            "sum(k, 0, 2, x + k)",
+        ] {
+            let derivative = format::format(&derivative_expression(input, "x").unwrap());
+            for x in [0.5, 1.0, 2.5] {
+                let at = |input: &str, x: f64| {
+                    let mut context = EvalContext::default();
+                    context.set("x", x);
+                    Expr::new(input)
+                        .with_implicit_multiplication(true)
+                        .with_context(&mut context)
+                        .eval()
+                        .unwrap()
+                };
+                let h = 1e-6;
+                let slope = (at(input, x + h) - at(input, x - h)) / (2.0 * h);
+                assert!(
+                    (at(&derivative, x) - slope).abs() < 1e-4,
+                    "d/dx {} = {} at {}",
+                    input,
+                    derivative,
+                    x
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_chain_derivative() {
+        // 很长的乘除链和乘方塔迭代求导，不会因为递归过深而栈溢出
+        let product = vec!["x"; 600].join(" * ");
+        assert!(derivative_expression(&product, "x").is_ok());
+        assert!(derivative_expression(&vec!["x"; 600].join(" / "), "x").is_ok());
+        assert!(derivative_expression(&vec!["x"; 250].join("^"), "x").is_ok());
+        let nested = format!("{}x{}", "(".repeat(250), ")^2".repeat(250));
+        assert!(derivative_expression(&nested, "x").is_ok());
+        let output = repl_line(&format!(":d/dx {}", product), &mut ReplState::default());
+        assert!(matches!(output, ReplOutput::Print(text) if !text.starts_with("Error")));
+        // 迭代求导得到的结果与逐个运算符求导相同
+        assert_eq!(derived("x * x * x"), "(x + x) * x + x * x");
+        assert_eq!(derived("2^x^2"), "2 ^ x ^ 2 * ln(2) * (2 * x)");
+        assert_eq!(
+            derived("x^2/(x*3)/x"),
+            "((2 * x * (x * 3) - x ^ 2 * 3) / (x * 3) ^ 2 * x - x ^ 2 / (x * 3)) / x ^ 2"
+        );
+    }
+
+    #[test]
+    fn test_derivative_errors() {
+        assert!(derivative_expression("x!", "x").is_err());
+        assert!(derivative_expression("max(x, 1)", "x").is_err());
+        assert!(derivative_expression("x < 1", "x").is_err());
+        assert!(derivative_expression("prod(k, 1, 3, x * k)", "x").is_err());
+        assert!(derivative_expression("sum(i, 1, x, i)", "x").is_err());
+        // 求导变量必须是标识符
+        for var in ["", " x", "2", "x y", "x+1"] {
+            assert_eq!(
+                derivative_expression("x^2", var).unwrap_err().to_string(),
+                format!("ParseError: Invalid variable name: {:?}", var)
+            );
+        }
+        assert_eq!(
+            repl_line(":d/d  x^2", &mut ReplState::default()),
+            ReplOutput::Print("Error: ParseError: Invalid variable name: \"\"".to_string())
+        );
+        assert_eq!(
+            repl_line(":d/dt t^2", &mut ReplState::default()),
+            ReplOutput::Print("2 * t".to_string())
+        );
+        // 不依赖 x 的部分即使不可导也是常量
+        assert_eq!(derived("3! + x"), "1");
+    }
+}
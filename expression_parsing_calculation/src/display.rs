@@ -0,0 +1,480 @@
+// 结果的输出格式：有效数字、固定小数位数或科学计数法，舍入方式可以是四舍五入或四舍六入五成双，
+// 可选插入千位分隔符，例如 1/3 可以输出为 0.333333、0.33 或 3.3333e-1
+// 舍入按 f64 精确的十进制展开计算，所以 0.125 保留两位时两种方式的结果不同，而 2.675（实际略小于 2.675）都是 2.67
+use std::fmt;
+
+use super::*;
+
+// 有效数字的最大位数，17 位足以精确还原任意 f64
+pub const MAX_SIGNIFICANT_DIGITS: usize = 17;
+
+// 定点表示的最大小数位数，最小的非规格化数 5e-324 也能显示出有效数字
+pub const MAX_DECIMAL_PLACES: usize = 340;
+
+// 保留的位数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Digits {
+    #[default]
+    Shortest, // 能精确还原 f64 的最短表示，与 f64 的 Display 相同
+    Decimals(usize),    // 固定的小数位数，如 0.33，最多 MAX_DECIMAL_PLACES 位
+    Significant(usize), // 有效数字，去掉末尾的 0，数太大或太小时改用科学计数法，如 0.333333，最多 17 位
+    Scientific(usize),  // 科学计数法，尾数保留的小数位数，如 3.3333e-1，最多 16 位
+}
+
+// 舍入方式，只影响恰好在两个候选值正中间的情况
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    #[default]
+    HalfEven, // 四舍六入五成双，与 Rust 的格式化相同
+    HalfUp, // 四舍五入，正中间时远离 0
+}
+
+// 数的输出格式，默认与 f64 的 Display 相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DisplayFormat {
+    pub digits: Digits,
+    pub rounding: Rounding,
+    pub group: bool, // 整数部分插入千位分隔符，科学计数法不插入
+}
+
+// 绝对值的十进制数字（首位不为 0）和首位数字的指数，值为 d0.d1d2... × 10^exponent
+struct Decimal {
+    digits: Vec<u8>,
+    exponent: i32,
+}
+
+impl Decimal {
+    // f64 的小数部分最多 1074 位，按 1100 位格式化得到的是精确值
+    fn exact(x: f64) -> Option<Decimal> {
+        let text = std::format!("{:.1100}", x.abs());
+        let (int_part, frac_part) = text.split_once('.').unwrap_or((&text, ""));
+        let all: Vec<u8> = int_part
+            .bytes()
+            .chain(frac_part.bytes())
+            .map(|b| b - b'0')
+            .collect();
+        let leading = all.iter().position(|&d| d != 0)?;
+        let mut digits = all[leading..].to_vec();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Some(Decimal {
+            digits,
+            exponent: places(int_part.len()) - places(leading) - 1,
+        })
+    }
+
+    // 只保留前 keep 位数字，其余按舍入方式进位或舍去；进位后可能多出一位，指数加 1
+    fn round(&self, keep: i32, rounding: Rounding) -> Decimal {
+        let keep = usize::try_from(keep).unwrap_or(0);
+        let mut digits: Vec<u8> = self.digits.iter().copied().take(keep).collect();
+        digits.resize(keep, 0);
+        let rest = self.digits.get(keep..).unwrap_or(&[]);
+        let up = match rest.first() {
+            Some(&first) if first > 5 => true,
+            Some(5) => {
+                let odd = keep > 0 && digits[keep - 1] % 2 == 1;
+                rest[1..].iter().any(|&d| d != 0) || rounding == Rounding::HalfUp || odd
+            }
+            _ => false,
+        };
+        let mut exponent = self.exponent;
+        if up {
+            let mut i = keep;
+            loop {
+                if i == 0 {
+                    digits.insert(0, 1);
+                    exponent += 1;
+                    break;
+                }
+                i -= 1;
+                if digits[i] == 9 {
+                    digits[i] = 0;
+                } else {
+                    digits[i] += 1;
+                    break;
+                }
+            }
+        }
+        Decimal { digits, exponent }
+    }
+
+    fn digit(&self, exponent: i32) -> u8 {
+        usize::try_from(self.exponent - exponent)
+            .ok()
+            .and_then(|index| self.digits.get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // 定点表示，保留 decimals 位小数
+    fn positional(&self, decimals: usize) -> String {
+        let mut text = String::new();
+        for exponent in (0..=self.exponent.max(0)).rev() {
+            text.push((b'0' + self.digit(exponent)) as char);
+        }
+        if decimals > 0 {
+            text.push('.');
+            for exponent in 1..=places(decimals) {
+                text.push((b'0' + self.digit(-exponent)) as char);
+            }
+        }
+        text
+    }
+
+    // 科学计数法，尾数保留 decimals 位小数
+    fn scientific(&self, decimals: usize) -> String {
+        let mut text = ((b'0' + self.digit(self.exponent)) as char).to_string();
+        if decimals > 0 {
+            text.push('.');
+            for i in 1..=places(decimals) {
+                text.push((b'0' + self.digit(self.exponent - i)) as char);
+            }
+        }
+        std::format!("{}e{}", text, self.exponent)
+    }
+}
+
+// 位数转换为指数的偏移，位数都经过 Digits::bounded 限制，不会超出 i32 的范围
+fn places(count: usize) -> i32 {
+    i32::try_from(count).unwrap_or(i32::MAX)
+}
+
+// 去掉小数部分末尾的 0，小数部分全为 0 时同时去掉小数点
+fn trim_zeros(text: &str) -> String {
+    match text.split_once('e') {
+        Some((mantissa, exponent)) => std::format!("{}e{}", trim_zeros(mantissa), exponent),
+        None if text.contains('.') => text.trim_end_matches('0').trim_end_matches('.').to_string(),
+        None => text.to_string(),
+    }
+}
+
+impl Digits {
+    // 解析位数设置：auto、fixed N、sig N、sci N，也可以只写 N 表示 N 位小数，位数超出范围时返回 None
+    pub fn parse(spec: &str) -> Option<Digits> {
+        let words: Vec<&str> = spec.split_whitespace().collect();
+        let count = |word: &str, range: std::ops::RangeInclusive<usize>| {
+            word.parse::<usize>().ok().filter(|n| range.contains(n))
+        };
+        match words.as_slice() {
+            ["auto"] => Some(Digits::Shortest),
+            [n] | ["fixed", n] => count(n, 0..=MAX_DECIMAL_PLACES).map(Digits::Decimals),
+            ["sig", n] => count(n, 1..=MAX_SIGNIFICANT_DIGITS).map(Digits::Significant),
+            ["sci", n] => count(n, 0..=MAX_SIGNIFICANT_DIGITS - 1).map(Digits::Scientific),
+            _ => None,
+        }
+    }
+
+    // 把位数限制在允许的范围内，超出的部分不会改变结果的精确程度
+    pub fn bounded(self) -> Digits {
+        match self {
+            Digits::Shortest => Digits::Shortest,
+            Digits::Decimals(n) => Digits::Decimals(n.min(MAX_DECIMAL_PLACES)),
+            Digits::Significant(n) => Digits::Significant(n.clamp(1, MAX_SIGNIFICANT_DIGITS)),
+            Digits::Scientific(n) => Digits::Scientific(n.min(MAX_SIGNIFICANT_DIGITS - 1)),
+        }
+    }
+}
+
+impl Rounding {
+    // 解析舍入方式：half-up 或 half-even
+    pub fn parse(name: &str) -> Option<Rounding> {
+        match name.trim() {
+            "half-up" => Some(Rounding::HalfUp),
+            "half-even" => Some(Rounding::HalfEven),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Digits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Digits::Shortest => write!(f, "auto"),
+            Digits::Decimals(n) => write!(f, "fixed {}", n),
+            Digits::Significant(n) => write!(f, "sig {}", n),
+            Digits::Scientific(n) => write!(f, "sci {}", n),
+        }
+    }
+}
+
+impl fmt::Display for Rounding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rounding::HalfUp => write!(f, "half-up"),
+            Rounding::HalfEven => write!(f, "half-even"),
+        }
+    }
+}
+
+impl DisplayFormat {
+    // 构造函数把位数限制在 Digits::bounded 的范围内
+    pub fn decimals(decimals: usize) -> Self {
+        DisplayFormat {
+            digits: Digits::Decimals(decimals).bounded(),
+            ..Self::default()
+        }
+    }
+
+    pub fn significant(digits: usize) -> Self {
+        DisplayFormat {
+            digits: Digits::Significant(digits).bounded(),
+            ..Self::default()
+        }
+    }
+
+    pub fn scientific(decimals: usize) -> Self {
+        DisplayFormat {
+            digits: Digits::Scientific(decimals).bounded(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    pub fn with_grouping(mut self, group: bool) -> Self {
+        self.group = group;
+        self
+    }
+
+    // 按格式输出一个数，inf 和 NaN 与 f64 的 Display 相同
+    pub fn format(&self, value: f64) -> String {
+        if !value.is_finite() || self.digits == Digits::Shortest {
+            let text = value.to_string();
+            return if self.group && value.is_finite() {
+                group_thousands(&text)
+            } else {
+                text
+            };
+        }
+        // 0 没有有效数字，按 0 的各种写法输出
+        let Some(exact) = Decimal::exact(value) else {
+            return self.finish(
+                false,
+                Decimal {
+                    digits: Vec::new(),
+                    exponent: 0,
+                },
+            );
+        };
+        let rounded = match self.digits.bounded() {
+            Digits::Decimals(decimals) => {
+                exact.round(exact.exponent + 1 + places(decimals), self.rounding)
+            }
+            Digits::Significant(digits) => exact.round(places(digits), self.rounding),
+            Digits::Scientific(decimals) => exact.round(places(decimals) + 1, self.rounding),
+            Digits::Shortest => unreachable!(),
+        };
+        self.finish(value < 0.0, rounded)
+    }
+
+    fn finish(&self, negative: bool, rounded: Decimal) -> String {
+        let zero = rounded.digits.iter().all(|&d| d == 0);
+        let exponent = if zero { 0 } else { rounded.exponent };
+        let rounded = Decimal {
+            exponent,
+            ..rounded
+        };
+        let (text, positional) = match self.digits.bounded() {
+            Digits::Decimals(decimals) => (rounded.positional(decimals), true),
+            Digits::Scientific(decimals) => (rounded.scientific(decimals), false),
+            // 与 C 的 %g 一样，指数小于 -4 或不小于有效数字的位数时用科学计数法
+            Digits::Significant(digits) => {
+                if exponent < -4 || exponent >= places(digits) {
+                    (trim_zeros(&rounded.scientific(digits - 1)), false)
+                } else {
+                    let decimals = usize::try_from(places(digits) - 1 - exponent).unwrap_or(0);
+                    (trim_zeros(&rounded.positional(decimals)), true)
+                }
+            }
+            Digits::Shortest => unreachable!(),
+        };
+        let text = if self.group && positional {
+            group_thousands(&text)
+        } else {
+            text
+        };
+        // 舍入为 0 的负数不带负号
+        if negative && !zero {
+            std::format!("-{}", text)
+        } else {
+            text
+        }
+    }
+
+    // 按格式输出一个值，向量和矩阵的每个元素分别格式化，日期和时长不受影响
+    pub fn format_value(&self, value: &Value) -> String {
+        tensor::render(value, &|n| self.format(n))
+    }
+}
+
+impl Expr<'_> {
+    // 设置 eval_formatted 输出结果的格式
+    pub fn with_display_format(mut self, format: DisplayFormat) -> Self {
+        self.display = format;
+        self
+    }
+
+    // 计算表达式的值并按输出格式转换为字符串，结果可以是向量、矩阵、日期或时长
+    pub fn eval_formatted(&mut self) -> Result<String> {
+        let value = self.eval_value()?;
+        Ok(self.display.format_value(&value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_formats() {
+        let third = 1.0 / 3.0;
+        assert_eq!(DisplayFormat::default().format(third), third.to_string());
+        assert_eq!(DisplayFormat::significant(6).format(third), "0.333333");
+        assert_eq!(DisplayFormat::decimals(2).format(third), "0.33");
+        assert_eq!(DisplayFormat::scientific(4).format(third), "3.3333e-1");
+        assert_eq!(DisplayFormat::decimals(3).format(2.0), "2.000");
+        assert_eq!(DisplayFormat::decimals(0).format(99.5), "100");
+        assert_eq!(DisplayFormat::decimals(2).format(-0.001), "0.00");
+        assert_eq!(DisplayFormat::decimals(2).format(0.0), "0.00");
+        assert_eq!(DisplayFormat::significant(3).format(0.0), "0");
+        assert_eq!(DisplayFormat::scientific(2).format(0.0), "0.00e0");
+        assert_eq!(DisplayFormat::significant(3).format(0.25), "0.25");
+        assert_eq!(DisplayFormat::significant(3).format(123456.0), "1.23e5");
+        assert_eq!(DisplayFormat::significant(3).format(0.00001234), "1.23e-5");
+        assert_eq!(DisplayFormat::significant(2).format(9.96), "10");
+        assert_eq!(DisplayFormat::scientific(1).format(-9.96), "-1.0e1");
+        assert_eq!(DisplayFormat::scientific(2).format(6.02e23), "6.02e23");
+        assert_eq!(
+            DisplayFormat::decimals(1)
+                .with_grouping(true)
+                .format(1234567.25),
+            "1,234,567.2"
+        );
+        assert_eq!(
+            DisplayFormat::default().with_grouping(true).format(-1e6),
+            "-1,000,000"
+        );
+        assert_eq!(DisplayFormat::decimals(2).format(f64::INFINITY), "inf");
+        assert_eq!(DisplayFormat::significant(3).format(f64::NAN), "NaN");
+        // 与 Rust 格式化的定点结果一致
+        for x in [0.1, 2.5, 1e-7, 12345.678, 1e21, -3.75, 0.045] {
+            for decimals in 0..8 {
+                assert_eq!(
+                    DisplayFormat::decimals(decimals).format(x),
+                    std::format!("{:.*}", decimals, x),
+                    "{} {}",
+                    x,
+                    decimals
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_digit_limits() {
+        // 超出范围的位数被限制，不会因为转换为 i32 溢出
+        assert_eq!(
+            DisplayFormat::significant(usize::MAX),
+            DisplayFormat::significant(MAX_SIGNIFICANT_DIGITS)
+        );
+        assert_eq!(
+            DisplayFormat::significant(usize::MAX).format(0.1),
+            "0.10000000000000001"
+        );
+        assert_eq!(
+            DisplayFormat::scientific(usize::MAX).format(0.1),
+            "1.0000000000000001e-1"
+        );
+        let tiny = DisplayFormat::decimals(usize::MAX).format(5e-324);
+        assert_eq!(tiny.len(), 2 + MAX_DECIMAL_PLACES);
+        assert!(tiny.ends_with("49406564584124654"));
+        // 直接构造的格式同样受限制
+        let format = DisplayFormat {
+            digits: Digits::Decimals(1 << 40),
+            ..DisplayFormat::default()
+        };
+        assert_eq!(format.format(0.5).len(), 2 + MAX_DECIMAL_PLACES);
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let half_up = |format: DisplayFormat| format.with_rounding(Rounding::HalfUp);
+        // 0.125、2.5 是精确的二进制小数，正好在中间
+        assert_eq!(DisplayFormat::decimals(2).format(0.125), "0.12");
+        assert_eq!(half_up(DisplayFormat::decimals(2)).format(0.125), "0.13");
+        assert_eq!(DisplayFormat::decimals(0).format(2.5), "2");
+        assert_eq!(half_up(DisplayFormat::decimals(0)).format(2.5), "3");
+        assert_eq!(half_up(DisplayFormat::decimals(0)).format(-2.5), "-3");
+        assert_eq!(DisplayFormat::decimals(0).format(3.5), "4");
+        assert_eq!(half_up(DisplayFormat::significant(1)).format(0.25), "0.3");
+        assert_eq!(DisplayFormat::significant(1).format(0.25), "0.2");
+        // 2.675 的 f64 值略小于 2.675，两种方式都舍去
+        assert_eq!(half_up(DisplayFormat::decimals(2)).format(2.675), "2.67");
+        assert_eq!(DisplayFormat::decimals(2).format(2.675), "2.67");
+        // 舍去的部分中有非 0 数字时总是进位
+        assert_eq!(DisplayFormat::decimals(0).format(2.5000001), "3");
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        assert_eq!(Digits::parse("auto"), Some(Digits::Shortest));
+        assert_eq!(Digits::parse("3"), Some(Digits::Decimals(3)));
+        assert_eq!(Digits::parse("fixed 2"), Some(Digits::Decimals(2)));
+        assert_eq!(Digits::parse(" sig  6 "), Some(Digits::Significant(6)));
+        assert_eq!(Digits::parse("sci 4"), Some(Digits::Scientific(4)));
+        assert_eq!(Digits::parse("fixed 340"), Some(Digits::Decimals(340)));
+        assert_eq!(Digits::parse("sig 17"), Some(Digits::Significant(17)));
+        assert_eq!(Digits::parse("sci 16"), Some(Digits::Scientific(16)));
+        for spec in [
+            "",
+            "sig 0",
+            "fixed",
+            "sci -1",
+            "exact 3",
+            "341",
+            "sig 18",
+            "sci 17",
+            "fixed 4294967296",
+            "sig 18446744073709551615",
+        ] {
+            assert_eq!(Digits::parse(spec), None, "{}", spec);
+        }
+        for digits in [
+            Digits::Shortest,
+            Digits::Decimals(2),
+            Digits::Significant(3),
+            Digits::Scientific(1),
+        ] {
+            assert_eq!(Digits::parse(&digits.to_string()), Some(digits));
+        }
+        assert_eq!(Rounding::parse("half-up"), Some(Rounding::HalfUp));
+        assert_eq!(Rounding::parse("half-even"), Some(Rounding::HalfEven));
+        assert_eq!(Rounding::parse("down"), None);
+    }
+
+    #[test]
+    fn test_eval_formatted() {
+        let eval = |input: &str, format: DisplayFormat| {
+            Expr::new(input)
+                .with_display_format(format)
+                .eval_formatted()
+                .unwrap()
+        };
+        assert_eq!(eval("1/3", DisplayFormat::significant(6)), "0.333333");
+        assert_eq!(eval("1/3", DisplayFormat::decimals(2)), "0.33");
+        assert_eq!(eval("1/3", DisplayFormat::scientific(4)), "3.3333e-1");
+        assert_eq!(
+            eval(
+                "[1/3, 2/3] * 1000",
+                DisplayFormat::decimals(1).with_grouping(true)
+            ),
+            "[333.3, 666.7]"
+        );
+        assert_eq!(eval("90m", DisplayFormat::decimals(2)), "1h 30m");
+        assert!(Expr::new("1 +").eval_formatted().is_err());
+    }
+}
@@ -0,0 +1,141 @@
+// 表达式等价判断：先比较化简后的规范形式，不同时再对变量随机取值，比较两边的数值
+// 如 `(x+1)^2` 与 `x^2 + 2x + 1` 规范形式不同，但取值总是相同，判断为等价
+use super::*;
+
+// 数值比较时的取样次数和至少需要的有效样本数
+const SAMPLES: usize = 64;
+const MIN_VALID_SAMPLES: usize = 16;
+// 变量取值的范围 [-RANGE, RANGE]
+const RANGE: f64 = 10.0;
+// 相对误差容限
+const TOLERANCE: f64 = 1e-9;
+
+impl Ast {
+    // 判断两个表达式是否是同一个函数
+    // 数值比较时跳过任一边求值出错或不是有限数的样本（如 sqrt(x)^2 在 x < 0 时），
+    // 有效样本太少时判断为不等价；使用固定的随机种子，结果可以复现
+    pub fn equivalent(&self, other: &Ast) -> bool {
+        let (lhs, rhs) = (self.simplify(), other.simplify());
+        if format::format(&lhs) == format::format(&rhs) {
+            return true;
+        }
+        let mut variables = free_variables(&lhs);
+        for name in free_variables(&rhs) {
+            if !variables.contains(&name) {
+                variables.push(name);
+            }
+        }
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let mut valid = 0;
+        for _ in 0..SAMPLES {
+            let values: Vec<f64> = variables.iter().map(|_| rng.next_in_range(RANGE)).collect();
+            let (Some(a), Some(b)) = (
+                sample(&lhs, &variables, &values),
+                sample(&rhs, &variables, &values),
+            ) else {
+                continue;
+            };
+            if (a - b).abs() > TOLERANCE * a.abs().max(b.abs()).max(1.0) {
+                return false;
+            }
+            valid += 1;
+        }
+        valid >= MIN_VALID_SAMPLES
+    }
+}
+
+// 在给定的变量取值下求值，出错或结果不是有限数时返回 None
+fn sample(ast: &Ast, variables: &[String], values: &[f64]) -> Option<f64> {
+    // 赋值语句会修改上下文，每次求值使用新的上下文
+    let mut context = EvalContext::default();
+    for (name, value) in variables.iter().zip(values) {
+        context.set(name, *value);
+    }
+    let mut evaluator = Evaluator::new();
+    evaluator.context = Some(&mut context);
+    evaluator.eval(ast).ok().filter(|value| value.is_finite())
+}
+
+// 表达式中出现的变量名（不包括内置常量），按第一次出现的顺序
+fn free_variables(ast: &Ast) -> Vec<String> {
+    let mut variables = Vec::new();
+    // 用显式的栈遍历，避免很长的运算链导致递归过深
+    let mut pending = vec![ast];
+    while let Some(node) = pending.pop() {
+        match node {
+            Ast::Var(name) if builtin_constant(name).is_none() && !variables.contains(name) => {
+                variables.push(name.clone())
+            }
+            Ast::Num(_) | Ast::Var(_) | Ast::Str(_) | Ast::Define(..) => {}
+            Ast::BinOp(_, lhs, rhs) => pending.extend([rhs.as_ref(), lhs.as_ref()]),
+            Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) => pending.push(operand),
+            Ast::Call(_, args) | Ast::Seq(args) | Ast::List(args) => {
+                pending.extend(args.iter().rev())
+            }
+            Ast::Cond(cond, then, otherwise) => {
+                pending.extend([otherwise.as_ref(), then.as_ref(), cond.as_ref()])
+            }
+        }
+    }
+    variables
+}
+
+// 简单的 xorshift 伪随机数生成器，只用于取样，不需要密码学强度
+struct XorShift(u64);
+
+impl XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // [-range, range] 内均匀分布的随机数
+    fn next_in_range(&mut self, range: f64) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+        (unit * 2.0 - 1.0) * range
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn equivalent(a: &str, b: &str) -> bool {
+        let parse = |input| {
+            Expr::new(input)
+                .with_implicit_multiplication(true)
+                .parse()
+                .unwrap()
+        };
+        parse(a).equivalent(&parse(b))
+    }
+
+    #[test]
+    fn test_structurally_equivalent() {
+        assert!(equivalent("x + 0", "x"));
+        assert!(equivalent("2*3 + y", "6 + y"));
+        assert!(equivalent("--(a * 1)", "a"));
+    }
+
+    #[test]
+    fn test_numerically_equivalent() {
+        assert!(equivalent("(x+1)^2", "x^2 + 2x + 1"));
+        assert!(equivalent("x*(y+1)", "x*y + x"));
+        assert!(equivalent("sin(x)^2 + cos(x)^2", "1"));
+        assert!(equivalent("exp(a + b)", "exp(a) * exp(b)"));
+        // 只在 x >= 0 时有定义，比较两边都有定义的样本
+        assert!(equivalent("sqrt(x)^2", "x"));
+    }
+
+    #[test]
+    fn test_not_equivalent() {
+        assert!(!equivalent("x + 1", "x"));
+        assert!(!equivalent("x^2", "2x"));
+        assert!(!equivalent("x + y", "x + z"));
+        assert!(!equivalent("x", "x + 0.001"));
+        // 没有有效样本时不能判断为等价
+        assert!(!equivalent("ln(-1 - x^2)", "sqrt(-1 - x^2)"));
+    }
+}
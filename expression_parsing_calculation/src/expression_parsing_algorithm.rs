@@ -1,191 +1,311 @@
-#[derive(Debug, Clone)]
-enum Token {
-    Number(f64),
-    Operator(char),
-    LeftParen,
-    RightParen,
+// 调度场（shunting-yard）解析策略：用运算符栈把中缀的 Token 序列转换为语法树
+// 与递归下降的 Expr::parse 共用 Tokenizer、Token、运算符优先级表、Ast 和求值器，新增的运算符和函数只需要实现一次，
+// 通过 Expr::with_strategy(ParseStrategy::ShuntingYard) 选择
+// 支持数、变量、时长字面量、二元运算符、前缀的 + - ~、后缀阶乘和括号，函数调用、向量、赋值等语法只能用递归下降解析
+use super::*;
+
+// 浮点数相等比较的默认容差
+pub const DEFAULT_EPSILON: f64 = 1e-9;
+
+// 在容差范围内判断两个浮点数是否近似相等
+// 当两个数的绝对值都不超过1时按绝对误差比较，否则按相对误差比较，
+// 因此 `0.1 + 0.2 == 0.3` 成立，但这只是近似相等而不是严格相等
+pub fn approx_eq(a: f64, b: f64, epsilon: f64) -> bool {
+    (a - b).abs() <= epsilon * a.abs().max(b.abs()).max(1.0)
 }
 
-// 定义一个函数 `tokenize`，它接受一个字符串切片 `expr` 作为参数，并返回一个 `Token` 类型的向量
-fn tokenize(expr: &str) -> Vec<Token> {
-    // 初始化一个空的 `Token` 向量，用于存储解析后的标记
-    let mut tokens = Vec::new();
-    // 初始化一个空的字符串，用于暂存数字字符
-    let mut num_str = String::new();
-    
-    // 遍历表达式中的每一个字符
-    for c in expr.chars() {
-        // 使用 `match` 语句对字符进行模式匹配
-        match c {
-            // 如果字符是数字或小数点，则将其添加到 `num_str` 中
-            '0'..='9' | '.' => num_str.push(c),
-            // 如果字符是运算符（+、-、*、/、^），则处理当前暂存的数字
-            '+' | '-' | '*' | '/' | '^' => {
-                // 如果 `num_str` 不为空，则将其解析为数字并添加到 `tokens` 中
-                if !num_str.is_empty() {
-                    tokens.push(Token::Number(num_str.parse().unwrap()));
-                    // 清空 `num_str` 以便存储下一个数字
-                    num_str.clear();
-                }
-                // 将当前运算符作为 `Token::Operator` 添加到 `tokens` 中
-                tokens.push(Token::Operator(c));
-            }
-            // 如果字符是左括号，则将其作为 `Token::LeftParen` 添加到 `tokens` 中
-            '(' => tokens.push(Token::LeftParen),
-            // 如果字符是右括号，则处理当前暂存的数字，并将其作为 `Token::RightParen` 添加到 `tokens` 中
-            ')' => {
-                // 如果 `num_str` 不为空，则将其解析为数字并添加到 `tokens` 中
-                if !num_str.is_empty() {
-                    tokens.push(Token::Number(num_str.parse().unwrap()));
-                    // 清空 `num_str` 以便存储下一个数字
-                    num_str.clear();
-                }
-                // 将右括号作为 `Token::RightParen` 添加到 `tokens` 中
-                tokens.push(Token::RightParen);
-            }
-            ' ' => {
-                // 如果 `num_str` 不为空，则将其解析为数字并添加到 `tokens` 中
-                if !num_str.is_empty() {
-                    tokens.push(Token::Number(num_str.parse().unwrap()));
-                    num_str.clear();
-                }
-            }
-            // 如果遇到无效字符，则抛出异常
-            _ => panic!("Invalid character in expression"),
+// 调度场解析的错误，span 是出错的 Token 在输入中的位置
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShuntingYardError {
+    Empty,                                           // 输入中没有表达式
+    InvalidCharacter { span: Span, lexeme: String }, // 无法识别的字符，如 `3 # 4` 中的 `#`
+    UnexpectedToken { span: Span, lexeme: String },  // 这里不能出现该 Token，如 `3 + * 4` 中的 `*`
+    MissingOperand { span: Span },                   // 运算符后面缺少操作数，如 `1 +`
+    UnclosedParenthesis { span: Span },              // 左括号没有匹配的右括号，如 `(1+2`
+    UnmatchedParenthesis { span: Span },             // 右括号没有匹配的左括号，如 `1+2)`
+    Unsupported { span: Span, lexeme: String },      // 只有递归下降解析支持的语法，如函数调用、向量
+}
+
+impl ShuntingYardError {
+    // 出错的位置，输入为空时没有位置
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            ShuntingYardError::Empty => None,
+            ShuntingYardError::InvalidCharacter { span, .. }
+            | ShuntingYardError::UnexpectedToken { span, .. }
+            | ShuntingYardError::MissingOperand { span }
+            | ShuntingYardError::UnclosedParenthesis { span }
+            | ShuntingYardError::UnmatchedParenthesis { span }
+            | ShuntingYardError::Unsupported { span, .. } => Some(*span),
         }
     }
-    // 如果遍历结束后 `num_str` 不为空，则将其解析为数字并添加到 `tokens` 中
-    if !num_str.is_empty() {
-        tokens.push(Token::Number(num_str.parse().unwrap()));
-    }
-    // 返回解析后的 `Token` 向量
-    tokens
 }
 
-// 定义一个函数 `precedence`，它接受一个字符 `op` 作为参数，并返回一个无符号8位整数（u8）
-fn precedence(op: char) -> u8 {
-    // 使用 `match` 表达式来匹配输入的运算符 `op`
-    match op {
-        // 如果 `op` 是 '+' 或 '-'，则返回优先级 1
-        '+' | '-' => 1,
-        // 如果 `op` 是 '*' 或 '/'，则返回优先级 2
-        '*' | '/' => 2,
-        // 如果 `op` 是 '^'，则返回优先级 3
-        '^' => 3,
-        // 如果 `op` 不匹配上述任何一种情况，则返回优先级 0
-        _ => 0,
+impl Display for ShuntingYardError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ShuntingYardError::Empty => write!(f, "empty expression"),
+            ShuntingYardError::InvalidCharacter { span, lexeme } => {
+                write!(
+                    f,
+                    "invalid character '{}' at position {}",
+                    lexeme, span.offset
+                )
+            }
+            ShuntingYardError::UnexpectedToken { span, lexeme } => {
+                write!(
+                    f,
+                    "unexpected token '{}' at position {}",
+                    lexeme, span.offset
+                )
+            }
+            ShuntingYardError::MissingOperand { span } => {
+                write!(f, "missing operand at position {}", span.offset)
+            }
+            ShuntingYardError::UnclosedParenthesis { span } => {
+                write!(f, "unclosed parenthesis at position {}", span.offset)
+            }
+            ShuntingYardError::UnmatchedParenthesis { span } => {
+                write!(
+                    f,
+                    "unmatched closing parenthesis at position {}",
+                    span.offset
+                )
+            }
+            ShuntingYardError::Unsupported { span, lexeme } => write!(
+                f,
+                "'{}' at position {} is not supported by the shunting-yard parser",
+                lexeme, span.offset
+            ),
+        }
     }
 }
 
-// 定义一个函数，将中缀表达式转换为后缀表达式
-fn to_postfix(tokens: Vec<Token>) -> Vec<Token> {
-    // 初始化输出向量，用于存储转换后的后缀表达式
-    let mut output = Vec::new();
-    // 初始化操作符栈，用于存储操作符
-    let mut operator_stack = Vec::new();
+// 运算符栈中的元素
+enum Pending {
+    Binary(Token, i32, i32), // 二元运算符及其优先级、结合性
+    Prefix(Token),           // 前缀运算符
+    Paren(Span),             // 左括号的位置，用于报告缺少右括号
+}
 
-    // 遍历输入的中缀表达式中的每个标记
-    for token in tokens {
-        // 使用模式匹配处理不同类型的标记
-        match token {
-            // 如果是数字，直接添加到输出向量
-            Token::Number(_) => output.push(token),
-            // 如果是操作符，进行以下处理
-            Token::Operator(op) => {
-                // 当操作符栈不为空且栈顶操作符的优先级大于等于当前操作符时
-                while let Some(Token::Operator(top_op)) = operator_stack.last() {
-                    if precedence(*top_op) >= precedence(op) {
-                        // 将栈顶操作符弹出并添加到输出向量
-                        output.push(operator_stack.pop().unwrap());
-                    } else {
-                        // 否则跳出循环
-                        break;
-                    }
-                }
-                // 将当前操作符压入操作符栈
-                operator_stack.push(Token::Operator(op));
-            }
-            // 如果是左括号，直接压入操作符栈
-            Token::LeftParen => operator_stack.push(token),
-            // 如果是右括号，进行以下处理
-            Token::RightParen => {
-                // 当操作符栈不为空时
-                while let Some(top_token) = operator_stack.pop() {
-                    match top_token {
-                        // 如果遇到左括号，跳出循环
-                        Token::LeftParen => break,
-                        // 否则将栈顶操作符添加到输出向量
-                        _ => output.push(top_token),
-                    }
+// 调度场算法的解析状态：输出栈保存已经构造好的子树，运算符栈保存还没有应用的运算符
+struct ShuntingYard {
+    output: Vec<Ast>,
+    operators: Vec<Pending>,
+}
+
+impl ShuntingYard {
+    // 弹出输出栈顶的操作数，交替检查保证了操作数足够，这里只是防御性的检查
+    fn operand(&mut self, expr: &Expr) -> Result<Ast> {
+        self.output.pop().ok_or(ExpError::ShuntingYardError(
+            ShuntingYardError::MissingOperand {
+                span: expr.last_span,
+            },
+        ))
+    }
+
+    // 弹出一个运算符，用输出栈顶的操作数构造子树
+    fn reduce(&mut self, expr: &mut Expr) -> Result<()> {
+        match self.operators.pop() {
+            Some(Pending::Binary(op, _, assoc)) => {
+                let right = self.operand(expr)?;
+                let left = self.operand(expr)?;
+                self.output
+                    .push(Ast::BinOp(op, Box::new(left), Box::new(right)));
+                if assoc != ASSOC_LEFT {
+                    expr.depth -= 1;
                 }
             }
+            Some(Pending::Prefix(op)) => {
+                let operand = self.operand(expr)?;
+                self.output.push(Ast::UnaryOp(op, Box::new(operand)));
+                expr.depth -= 1;
+            }
+            Some(Pending::Paren(span)) => {
+                return Err(ExpError::ShuntingYardError(
+                    ShuntingYardError::UnclosedParenthesis { span },
+                ))
+            }
+            None => {}
         }
+        Ok(())
     }
 
-    // 将操作符栈中剩余的操作符依次弹出并添加到输出向量
-    while let Some(token) = operator_stack.pop() {
-        output.push(token);
+    // 栈顶的运算符是否应该在优先级为 prec、结合性为 assoc 的二元运算符之前应用
+    // 前缀运算符的操作数与递归下降解析一样：数学语义下包含 ^，Excel 语义下不包含
+    fn binds_tighter(&self, expr: &Expr, prec: i32, assoc: i32) -> bool {
+        let power_prec = expr
+            .precedence
+            .get(&Token::Power)
+            .map_or(1, |(prec, _)| prec);
+        match self.operators.last() {
+            Some(Pending::Binary(_, top, _)) => {
+                *top > prec || (*top == prec && assoc == ASSOC_LEFT)
+            }
+            Some(Pending::Prefix(_)) => match expr.power_semantics {
+                PowerSemantics::Math => prec < power_prec,
+                PowerSemantics::Excel => prec <= power_prec,
+            },
+            Some(Pending::Paren(_)) | None => false,
+        }
     }
-    // 返回转换后的后缀表达式
-    output
 }
 
-// 定义一个函数 evaluate_postfix，用于计算后缀表达式的值
-fn evaluate_postfix(tokens: Vec<Token>) -> f64 {
-    // 创建一个空的栈，用于存储操作数
-    let mut stack = Vec::new();
+// 最近一次取出的 Token 的位置和原文
+fn last_lexeme(expr: &Expr) -> (Span, String) {
+    let span = expr.last_span;
+    (
+        span,
+        expr.source
+            .chars()
+            .skip(span.offset)
+            .take(span.len)
+            .collect(),
+    )
+}
+
+// 最近一次取出的 Token 不能出现在这里：无法识别的字符、只有递归下降支持的语法或者位置不对的 Token
+fn unexpected(expr: &Expr, token: &Lexeme) -> ExpError {
+    let (span, lexeme) = last_lexeme(expr);
+    ExpError::ShuntingYardError(match token {
+        Lexeme::Symbol(Token::Unknown(_)) => ShuntingYardError::InvalidCharacter { span, lexeme },
+        Lexeme::Str(_)
+        | Lexeme::Symbol(
+            Token::LBracket
+            | Token::RBracket
+            | Token::Comma
+            | Token::Semicolon
+            | Token::Assign
+            | Token::Question
+            | Token::Colon,
+        ) => ShuntingYardError::Unsupported { span, lexeme },
+        _ => ShuntingYardError::UnexpectedToken { span, lexeme },
+    })
+}
 
-    // 遍历输入的后缀表达式中的每个标记
-    for token in tokens {
-        // 使用 match 语句对标记进行模式匹配
+// 用调度场算法解析 expr 剩余的 Token
+pub fn parse(expr: &mut Expr) -> Result<Ast> {
+    let mut state = ShuntingYard {
+        output: Vec::new(),
+        operators: Vec::new(),
+    };
+    // 下一个 Token 应该是操作数（数、变量、前缀运算符、左括号）还是运算符
+    let mut expect_operand = true;
+    // 当前操作数后面连续的阶乘个数
+    let mut postfix = 0;
+    while let Some(token) = expr.next_token() {
+        if expect_operand {
+            match token {
+                Lexeme::Number(n) => {
+                    let number = expr.parse_number(n);
+                    state.output.push(number);
+                    expect_operand = false;
+                }
+                // 函数调用只有递归下降解析支持
+                Lexeme::Ident(_) if expr.peek_token() == Some(&Lexeme::Symbol(Token::LParen)) => {
+                    let (span, lexeme) = last_lexeme(expr);
+                    return Err(ExpError::ShuntingYardError(
+                        ShuntingYardError::Unsupported { span, lexeme },
+                    ));
+                }
+                Lexeme::Ident(name) => {
+                    state.output.push(Ast::Var(name.to_string()));
+                    expect_operand = false;
+                }
+                Lexeme::Symbol(Token::LParen) => {
+                    expr.enter_nesting()?;
+                    state.operators.push(Pending::Paren(expr.last_span));
+                }
+                Lexeme::Symbol(Token::Minus | Token::Plus | Token::BitNot) => {
+                    expr.enter_nesting()?;
+                    state.operators.push(Pending::Prefix(token.into()));
+                }
+                Lexeme::Operator(symbol)
+                    if expr
+                        .evaluator
+                        .operators
+                        .is_some_and(|operators| operators.prefix.contains_key(symbol)) =>
+                {
+                    expr.enter_nesting()?;
+                    state.operators.push(Pending::Prefix(token.into()));
+                }
+                token => return Err(unexpected(expr, &token)),
+            }
+            continue;
+        }
         match token {
-            // 如果标记是一个数字，则将其压入栈中
-            Token::Number(num) => stack.push(num),
-            // 如果标记是一个运算符，则从栈中弹出两个操作数进行计算，并将结果压入栈中
-            Token::Operator(op) => {
-                // 从栈中弹出第二个操作数
-                let b = stack.pop().unwrap();
-                // 从栈中弹出第一个操作数
-                let a = stack.pop().unwrap();
-                // 根据运算符进行相应的计算
-                let result = match op {
-                    '+' => a + b, // 加法
-                    '-' => a - b, // 减法
-                    '*' => a * b, // 乘法
-                    '/' => a / b, // 除法
-                    '^' => a.powf(b), // 幂运算
-                    _ => panic!("Unknown operator"), // 未知运算符，抛出异常
+            // 阶乘只作用于紧挨着的操作数，即输出栈顶的子树
+            // 连续的阶乘计入嵌套深度，遇到下一个运算符或右括号时退出
+            Lexeme::Symbol(Token::Factorial) => {
+                expr.enter_nesting()?;
+                postfix += 1;
+                let operand = state.operand(expr)?;
+                state
+                    .output
+                    .push(Ast::UnaryOp(Token::Factorial, Box::new(operand)));
+            }
+            Lexeme::Symbol(Token::RParen) => {
+                expr.depth -= std::mem::take(&mut postfix);
+                while !matches!(state.operators.last(), Some(Pending::Paren(_)) | None) {
+                    state.reduce(expr)?;
+                }
+                if state.operators.pop().is_none() {
+                    return Err(ExpError::ShuntingYardError(
+                        ShuntingYardError::UnmatchedParenthesis {
+                            span: expr.last_span,
+                        },
+                    ));
+                }
+                expr.depth -= 1;
+            }
+            token => {
+                let Some((prec, assoc)) = expr.binary_precedence(&token) else {
+                    return Err(unexpected(expr, &token));
                 };
-                // 将计算结果压入栈中
-                stack.push(result);
+                expr.depth -= std::mem::take(&mut postfix);
+                while state.binds_tighter(expr, prec, assoc) {
+                    state.reduce(expr)?;
+                }
+                // 右结合的运算符要等到整条链结束才能应用，如 2^2^...^2，计入嵌套深度
+                if assoc != ASSOC_LEFT {
+                    expr.enter_nesting()?;
+                }
+                state
+                    .operators
+                    .push(Pending::Binary(token.into(), prec, assoc));
+                expect_operand = true;
             }
-            // 如果标记既不是数字也不是运算符，则抛出异常
-            _ => panic!("Invalid token in postfix expression"),
         }
     }
-    // 返回栈顶元素，即最终的计算结果
-    stack.pop().unwrap()
+    if expect_operand {
+        // 没有任何 Token，或者最后是运算符、左括号
+        return Err(ExpError::ShuntingYardError(
+            if state.output.is_empty() && state.operators.is_empty() {
+                ShuntingYardError::Empty
+            } else {
+                ShuntingYardError::MissingOperand {
+                    span: expr.last_span,
+                }
+            },
+        ));
+    }
+    while !state.operators.is_empty() {
+        state.reduce(expr)?;
+    }
+    state.operand(expr)
 }
 
-// 定义一个公共函数 expression_parsing_algorithm，用于解析表达式并计算其结果
-// 参数 expr 是一个字符串切片，表示要解析的表达式
-// 返回值是一个 f64 类型的浮点数，表示表达式的计算结果
-pub fn expression_parsing_algorithm(expr: &str) -> f64 {
-    // 调用 tokenize 函数，将表达式字符串分割成一个个的标记（token）
-    // 例如，将 "3 + 4 * 2" 分割成 ["3", "+", "4", "*", "2"]
-    let tokens = tokenize(expr);
-    // 调用 to_postfix 函数，将标记列表从中缀表达式转换为后缀表达式（逆波兰表示法）
-    // 例如，将 ["3", "+", "4", "*", "2"] 转换为 ["3", "4", "2", "*", "+"]
-    let postfix = to_postfix(tokens);
-    // 调用 evaluate_postfix 函数，计算后缀表达式的值
-    // 例如，计算 ["3", "4", "2", "*", "+"] 的结果为 11.0
-    evaluate_postfix(postfix)
+// 用调度场算法解析并计算表达式的值
+pub fn expression_parsing_algorithm(expr: &str) -> Result<f64> {
+    expression_parsing_algorithm_with_epsilon(expr, DEFAULT_EPSILON)
 }
 
-fn main() {
-    let expr = "92 + 5 + 5 * 27 - (92 - 12) / 4 + 26";
-    let result = expression_parsing_algorithm(expr);
-    println!("Result: {}", result);
+// 与 expression_parsing_algorithm 相同，但可以指定 `==`、`!=` 比较时使用的容差
+pub fn expression_parsing_algorithm_with_epsilon(expr: &str, epsilon: f64) -> Result<f64> {
+    Expr::new(expr)
+        .with_strategy(ParseStrategy::ShuntingYard)
+        .with_epsilon(epsilon)
+        .eval()
 }
 
 #[cfg(test)]
@@ -194,14 +314,317 @@ mod tests {
 
     #[test]
     fn test_basic_operations() {
-        assert_eq!(expression_parsing_algorithm("3+2"), 5.0);
-        assert_eq!(expression_parsing_algorithm("3*2"), 6.0);
-        assert_eq!(expression_parsing_algorithm("6/2"), 3.0);
-        assert_eq!(expression_parsing_algorithm("2^3"), 8.0);
+        assert_eq!(expression_parsing_algorithm("3+2").unwrap(), 5.0);
+        assert_eq!(expression_parsing_algorithm("3*2").unwrap(), 6.0);
+        assert_eq!(expression_parsing_algorithm("6/2").unwrap(), 3.0);
+        assert_eq!(expression_parsing_algorithm("2^3").unwrap(), 8.0);
+    }
+
+    // 在默认容差内比较两个浮点数，用于测试浮点运算结果
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            approx_eq(actual, expected, DEFAULT_EPSILON),
+            "expected {} to be close to {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(expression_parsing_algorithm("7 % 3").unwrap(), 1.0);
+        assert_eq!(expression_parsing_algorithm("5.5 % 2").unwrap(), 1.5);
+        // 与乘除同级，从左到右结合
+        assert_eq!(expression_parsing_algorithm("2 * 7 % 4").unwrap(), 2.0);
+        assert_eq!(expression_parsing_algorithm("1 + 7 % 4").unwrap(), 4.0);
+        // 负数操作数：余数的符号与被除数相同
+        assert_eq!(expression_parsing_algorithm("(0 - 7) % 3").unwrap(), -1.0);
+        assert_eq!(expression_parsing_algorithm("7 % (0 - 3)").unwrap(), 1.0);
     }
 
     #[test]
     fn test_complex_expression() {
-        assert_eq!(expression_parsing_algorithm("3 + 4 * 2 / ( 1 - 5 ) ^ 2"), 3.5);
+        assert_eq!(
+            expression_parsing_algorithm("3 + 4 * 2 / ( 1 - 5 ) ^ 2").unwrap(),
+            3.5
+        );
+    }
+
+    #[test]
+    fn test_float_equality_within_tolerance() {
+        assert_close(expression_parsing_algorithm("0.1 + 0.2").unwrap(), 0.3);
+        assert_eq!(
+            expression_parsing_algorithm("0.1 + 0.2 == 0.3").unwrap(),
+            1.0
+        );
+        assert_eq!(
+            expression_parsing_algorithm("0.1 + 0.2 != 0.3").unwrap(),
+            0.0
+        );
+        assert_eq!(expression_parsing_algorithm("2 * 3 == 6").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_float_inequality_beyond_tolerance() {
+        assert_eq!(expression_parsing_algorithm("1 == 1.001").unwrap(), 0.0);
+        assert_eq!(expression_parsing_algorithm("1 != 1.001").unwrap(), 1.0);
+        // 放宽容差后，同样的两个数被视为相等
+        assert_eq!(
+            expression_parsing_algorithm_with_epsilon("1 == 1.001", 0.01).unwrap(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_malformed_input_errors() {
+        let error = |input: &str| match expression_parsing_algorithm(input) {
+            Err(ExpError::ShuntingYardError(e)) => e,
+            other => panic!(
+                "expected a shunting-yard error for {:?}, got {:?}",
+                input, other
+            ),
+        };
+        let span = |offset, len| Span { offset, len };
+        let lexeme = |text: &str| text.to_string();
+        assert_eq!(error(""), ShuntingYardError::Empty);
+        assert_eq!(error("   "), ShuntingYardError::Empty);
+        assert_eq!(
+            error("3 + * 4"),
+            ShuntingYardError::UnexpectedToken {
+                span: span(4, 1),
+                lexeme: lexeme("*")
+            }
+        );
+        assert_eq!(
+            error("1 2"),
+            ShuntingYardError::UnexpectedToken {
+                span: span(2, 1),
+                lexeme: lexeme("2")
+            }
+        );
+        assert_eq!(
+            error("(1+2"),
+            ShuntingYardError::UnclosedParenthesis { span: span(0, 1) }
+        );
+        assert_eq!(
+            error("1+(2*(3)"),
+            ShuntingYardError::UnclosedParenthesis { span: span(2, 1) }
+        );
+        assert_eq!(
+            error("1+2)"),
+            ShuntingYardError::UnmatchedParenthesis { span: span(3, 1) }
+        );
+        assert_eq!(
+            error("1 +"),
+            ShuntingYardError::MissingOperand { span: span(3, 0) }
+        );
+        assert_eq!(
+            error("(1 + )"),
+            ShuntingYardError::UnexpectedToken {
+                span: span(5, 1),
+                lexeme: lexeme(")")
+            }
+        );
+        assert_eq!(
+            error("3 # 4"),
+            ShuntingYardError::InvalidCharacter {
+                span: span(2, 1),
+                lexeme: lexeme("#")
+            }
+        );
+        // 只有小数点、没有数字
+        assert_eq!(
+            error("."),
+            ShuntingYardError::InvalidCharacter {
+                span: span(0, 1),
+                lexeme: lexeme(".")
+            }
+        );
+        assert_eq!(
+            error("1 + ."),
+            ShuntingYardError::InvalidCharacter {
+                span: span(4, 1),
+                lexeme: lexeme(".")
+            }
+        );
+        assert_eq!(
+            error("max(1, 2)"),
+            ShuntingYardError::Unsupported {
+                span: span(0, 3),
+                lexeme: lexeme("max")
+            }
+        );
+        assert_eq!(
+            error("x = 1"),
+            ShuntingYardError::Unsupported {
+                span: span(2, 1),
+                lexeme: lexeme("=")
+            }
+        );
+        assert_eq!(
+            expression_parsing_algorithm("3 + * 4")
+                .unwrap_err()
+                .to_string(),
+            "ShuntingYardError: unexpected token '*' at position 4"
+        );
+        // 求值阶段的错误与递归下降解析相同
+        assert!(matches!(
+            expression_parsing_algorithm("y + 1"),
+            Err(ExpError::ParseError(_))
+        ));
+    }
+
+    // 由种子确定的伪随机数（xorshift），让模糊测试的输入可以复现
+    fn next_random(state: &mut u64) -> u64 {
+        *state ^= *state << 13;
+        *state ^= *state >> 7;
+        *state ^= *state << 17;
+        *state
+    }
+
+    #[test]
+    fn test_random_input_never_panics() {
+        const ALPHABET: &[u8] = b"0123456789.+-*/%^()!=<> x,#";
+        let mut state = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..20_000 {
+            let len = next_random(&mut state) % 16;
+            let input: String = (0..len)
+                .map(|_| {
+                    ALPHABET[(next_random(&mut state) % ALPHABET.len() as u64) as usize] as char
+                })
+                .collect();
+            // 不 panic 即可；两种解析方式都成功时结果必须一致
+            let shunting_yard = expression_parsing_algorithm(&input);
+            if let (Ok(a), Ok(b)) = (&shunting_yard, evaluate(&input)) {
+                assert!(
+                    a == &b || (a.is_nan() && b.is_nan()),
+                    "{:?}: {} != {}",
+                    input,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_strategies_build_the_same_tree() {
+        let parse_with = |input: &str, strategy: ParseStrategy, semantics: PowerSemantics| {
+            Expr::new(input)
+                .with_strategy(strategy)
+                .with_power_semantics(semantics)
+                .parse()
+        };
+        for input in [
+            "1 + 2 * 3 - 4",
+            "2^3^2",
+            "-2^2",
+            "2^-3^2",
+            "-(1 + 2) * 3",
+            "2 * -3 + 1",
+            "--x + ~4",
+            "(1 + 2)! ^ 2",
+            "-3!",
+            "1 < 2 && 3 >= 2 || 0",
+            "a % b * c / d",
+            "3d + 12h",
+            "((((1))))",
+        ] {
+            for semantics in [PowerSemantics::Math, PowerSemantics::Excel] {
+                assert_eq!(
+                    parse_with(input, ParseStrategy::ShuntingYard, semantics).unwrap(),
+                    parse_with(input, ParseStrategy::Pratt, semantics).unwrap(),
+                    "{}",
+                    input
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsupported_syntax() {
+        // 这些语法只能用递归下降解析，调度场解析报告第一个不支持的 Token
+        for (input, offset, lexeme) in [
+            ("max(1, 2)", 0, "max"),
+            ("x > 0 ? 1 : 2", 6, "?"),
+            ("x = 1", 2, "="),
+            ("1; 2", 1, ";"),
+            ("[1, 2]", 0, "["),
+            ("\"text\"", 0, "\"text\""),
+        ] {
+            assert!(Expr::new(input).parse().is_ok(), "{}", input);
+            let error = match Expr::new(input)
+                .with_strategy(ParseStrategy::ShuntingYard)
+                .parse()
+            {
+                Err(ExpError::ShuntingYardError(e)) => e,
+                other => panic!(
+                    "expected a shunting-yard error for {:?}, got {:?}",
+                    input, other
+                ),
+            };
+            assert_eq!(
+                error,
+                ShuntingYardError::Unsupported {
+                    span: Span {
+                        offset,
+                        len: lexeme.chars().count()
+                    },
+                    lexeme: lexeme.to_string()
+                },
+                "{}",
+                input
+            );
+        }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_shared_evaluator() {
+        let mut context = EvalContext::new();
+        context.set("x", 4.0);
+        let mut eval = |input: &str| {
+            Expr::new(input)
+                .with_strategy(ParseStrategy::ShuntingYard)
+                .with_context(&mut context)
+                .eval()
+        };
+        assert_eq!(eval("x^2 - pi * 0").unwrap(), 16.0);
+        assert_eq!(eval("5! / 4!").unwrap(), 5.0);
+        // 自定义运算符同样使用共享的运算符表
+        let operators = OperatorTable::new()
+            .binary("//", 2, ASSOC_LEFT, |a, b| Ok((a / b).floor()))
+            .unwrap();
+        assert_eq!(
+            Expr::new("7 // 2 + 1")
+                .with_operators(&operators)
+                .with_strategy(ParseStrategy::ShuntingYard)
+                .eval()
+                .unwrap(),
+            4.0
+        );
+        for input in [
+            "",
+            "1 +",
+            "(1 + 2",
+            "1 + 2)",
+            "* 3",
+            "max(1, 2)",
+            "1 2",
+            "[1, 2]",
+        ] {
+            assert!(
+                Expr::new(input)
+                    .with_strategy(ParseStrategy::ShuntingYard)
+                    .eval()
+                    .is_err(),
+                "{}",
+                input
+            );
+        }
+        let deep = format!("{}1{}", "(".repeat(1000), ")".repeat(1000));
+        assert!(Expr::new(&deep)
+            .with_strategy(ParseStrategy::ShuntingYard)
+            .eval()
+            .is_err());
+    }
+}
@@ -0,0 +1,158 @@
+// C 语言接口，需要开启 `ffi` feature，构建时由 build.rs 在 OUT_DIR 中生成头文件 calc.h，
+// 设置环境变量 CALC_HEADER_DIR 时同时写入该目录，如 `CALC_HEADER_DIR=include` 更新仓库中的 include/calc.h
+// C 中的用法（链接 libexpression_parsing_calculation）：
+//   double value;
+//   if (calc_eval("3 + 4 * 2", &value) == CALC_STATUS_OK) { ... }
+//   else { puts(calc_last_error()); }
+// Python ctypes 中的用法：
+//   lib = ctypes.CDLL("libexpression_parsing_calculation.so")
+//   out = ctypes.c_double()
+//   lib.calc_eval(b"3 + 4 * 2", ctypes.byref(out))
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{catch_unwind, UnwindSafe};
+
+use super::*;
+
+/// calc_eval 的返回值，CALC_STATUS_OK 以外的值表示出错，详细信息由 calc_last_error 获取
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalcStatus {
+    Ok = 0,
+    NullPointer = 1,        // 表达式或输出参数为空指针
+    InvalidUtf8 = 2,        // 表达式不是合法的 UTF-8
+    SyntaxError = 3,        // 语法错误，带有出错的位置
+    ParseError = 4,         // 其他解析或求值错误，如未定义的变量、参数个数不对
+    Overflow = 5,           // 计算结果不是有限数
+    MathError = 6,          // 整数运算除以0或溢出
+    NotInteger = 7,         // 整数模式下出现了无法用整数表示的值
+    DimensionError = 8,     // 带单位计算时量纲不一致
+    Mismatch = 9,           // 两个求值器的结果不一致，现在不会再产生，保留取值不变
+    ShapeError = 10,        // 向量、矩阵的形状不匹配，或者结果不是数
+    ShuntingYardError = 11, // 调度场解析的语法错误
+    Panic = 12,             // 求值时发生了 panic，panic 不会穿过 C 的调用栈
+}
+
+impl From<&ExpError> for CalcStatus {
+    fn from(e: &ExpError) -> Self {
+        match e {
+            ExpError::ParseError(_) => CalcStatus::ParseError,
+            ExpError::SyntaxError { .. } => CalcStatus::SyntaxError,
+            ExpError::Overflow => CalcStatus::Overflow,
+            ExpError::Mismatch(..) => CalcStatus::Mismatch,
+            ExpError::NotInteger(_) => CalcStatus::NotInteger,
+            ExpError::DimensionError(_) => CalcStatus::DimensionError,
+            ExpError::ShapeError(_) => CalcStatus::ShapeError,
+            ExpError::MathError(_) => CalcStatus::MathError,
+            ExpError::ShuntingYardError(_) => CalcStatus::ShuntingYardError,
+        }
+    }
+}
+
+thread_local! {
+    // 当前线程最近一次出错的信息，成功时为空字符串
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    // 错误信息中不会出现 NUL，万一出现时截断到 NUL 之前
+    let message = message.split('\0').next().unwrap_or_default();
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+// 执行 f，把 panic 转换为 CalcStatus::Panic；panic 展开到 extern "C" 函数之外是未定义行为
+fn guarded(f: impl FnOnce() -> CalcStatus + UnwindSafe) -> CalcStatus {
+    catch_unwind(f).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        set_last_error(&format!("panic: {}", message));
+        CalcStatus::Panic
+    })
+}
+
+/// 求值以 NUL 结尾的 UTF-8 表达式，成功时把结果写入 out
+///
+/// # Safety
+///
+/// expression 必须是空指针或指向以 NUL 结尾的字符串，out 必须是空指针或指向可写的 double
+#[no_mangle]
+pub unsafe extern "C" fn calc_eval(expression: *const c_char, out: *mut f64) -> CalcStatus {
+    if expression.is_null() || out.is_null() {
+        set_last_error("null pointer");
+        return CalcStatus::NullPointer;
+    }
+    let Ok(input) = CStr::from_ptr(expression).to_str() else {
+        set_last_error("expression is not valid UTF-8");
+        return CalcStatus::InvalidUtf8;
+    };
+    guarded(|| match evaluate(input) {
+        Ok(value) => {
+            *out = value;
+            set_last_error("");
+            CalcStatus::Ok
+        }
+        Err(e) => {
+            set_last_error(&e.to_string());
+            CalcStatus::from(&e)
+        }
+    })
+}
+
+/// 当前线程最近一次 calc_eval 的错误信息，成功时为空字符串
+/// 返回的指针在同一线程下一次调用 calc_eval 之前有效，调用方不需要释放
+#[no_mangle]
+pub extern "C" fn calc_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &[u8]) -> (CalcStatus, f64, String) {
+        let expression = CString::new(expression).unwrap();
+        let mut out = f64::NAN;
+        let status = unsafe { calc_eval(expression.as_ptr(), &mut out) };
+        let message = unsafe { CStr::from_ptr(calc_last_error()) };
+        (status, out, message.to_str().unwrap().to_string())
+    }
+
+    #[test]
+    fn test_calc_eval() {
+        assert_eq!(eval(b"3 + 4 * 2"), (CalcStatus::Ok, 11.0, String::new()));
+        let (status, out, message) = eval(b"1 + * 2");
+        assert_eq!(status, CalcStatus::SyntaxError);
+        assert!(out.is_nan());
+        assert!(message.starts_with("ParseError: "));
+        assert_eq!(eval(b"x + 1").0, CalcStatus::ParseError);
+        // 成功后清空上一次的错误信息
+        assert_eq!(eval(b"2").2, "");
+    }
+
+    #[test]
+    fn test_calc_eval_invalid_arguments() {
+        let mut out = 0.0;
+        assert_eq!(
+            unsafe { calc_eval(std::ptr::null(), &mut out) },
+            CalcStatus::NullPointer
+        );
+        let expression = CString::new("1").unwrap();
+        assert_eq!(
+            unsafe { calc_eval(expression.as_ptr(), std::ptr::null_mut()) },
+            CalcStatus::NullPointer
+        );
+        assert_eq!(eval(b"1 + \xff").0, CalcStatus::InvalidUtf8);
+    }
+
+    #[test]
+    fn test_panic_becomes_status() {
+        assert_eq!(guarded(|| panic!("boom")), CalcStatus::Panic);
+        let message = unsafe { CStr::from_ptr(calc_last_error()) };
+        assert_eq!(message.to_str().unwrap(), "panic: boom");
+        assert_eq!(guarded(|| CalcStatus::Ok), CalcStatus::Ok);
+    }
+}
@@ -0,0 +1,235 @@
+// 表达式格式化：把语法树重新输出为规范的文本
+// 运算符两边各留一个空格，只在优先级和结合性需要时加括号，如 `((2)+(3*4))` 输出为 `2 + 3 * 4`
+use super::*;
+
+// 条件表达式、赋值和多条语句的结合优先级，都低于最低的二元运算符
+pub const CONDITIONAL_LEVEL: i32 = LOWEST_PRECEDENCE - 1;
+pub const STATEMENT_LEVEL: i32 = LOWEST_PRECEDENCE - 2;
+// 一元正负号和负数字面量：可以作为 `*` 的左操作数、`^` 的右操作数，作为 `^` 的左操作数时需要括号
+pub const UNARY_LEVEL: i32 = 3;
+// 数字、变量、函数调用和阶乘不需要括号
+pub const ATOM_LEVEL: i32 = i32::MAX;
+
+// 节点的结合优先级，子节点低于父节点要求的优先级时需要加括号
+pub fn level(ast: &Ast) -> i32 {
+    match ast {
+        Ast::Num(n) if n.is_sign_negative() => UNARY_LEVEL,
+        Ast::UnaryOp(Token::Factorial, _) => ATOM_LEVEL,
+        Ast::UnaryOp(..) => UNARY_LEVEL,
+        // 自定义运算符的优先级保存在 OperatorTable 中，格式化时不知道，两边总是加括号
+        Ast::BinOp(Token::Operator(_), ..) => CONDITIONAL_LEVEL,
+        Ast::BinOp(op, ..) => op.precedence(),
+        Ast::Cond(..) => CONDITIONAL_LEVEL,
+        Ast::Assign(..) | Ast::Define(..) | Ast::Seq(_) => STATEMENT_LEVEL,
+        Ast::Num(_) | Ast::Var(_) | Ast::Str(_) | Ast::Call(..) | Ast::List(_) => ATOM_LEVEL,
+    }
+}
+
+// 二元运算的左操作数是否需要加括号，level_of 给出节点的结合优先级
+pub fn left_needs_parens(op: &Token, lhs: &Ast, level_of: fn(&Ast) -> i32) -> bool {
+    let prec = level_of(lhs);
+    if let Token::Operator(_) = op {
+        return prec < ATOM_LEVEL;
+    }
+    prec < op.precedence() || (prec == op.precedence() && op.assoc() == ASSOC_RIGHT)
+}
+
+// 二元运算的右操作数不加括号时需要的最低优先级
+// 左结合运算符的右操作数优先级相同时也要加括号，如 1 - (2 - 3)
+pub fn right_min_level(op: &Token) -> i32 {
+    if let Token::Operator(_) = op {
+        ATOM_LEVEL
+    } else if op.assoc() == ASSOC_LEFT {
+        op.precedence() + 1
+    } else {
+        op.precedence()
+    }
+}
+
+// 把语法树格式化为规范的表达式文本
+pub fn format(ast: &Ast) -> String {
+    let mut out = String::new();
+    write_ast(ast, &mut out);
+    out
+}
+
+// 解析输入后重新格式化，输入有语法错误时返回错误
+pub fn format_expression(input: &str) -> Result<String> {
+    Ok(format(&Expr::new(strip_formula_prefix(input)?).parse()?))
+}
+
+// 输出子节点，优先级低于 min_level 时加括号
+fn write_operand(ast: &Ast, min_level: i32, out: &mut String) {
+    if level(ast) < min_level {
+        out.push('(');
+        write_ast(ast, out);
+        out.push(')');
+    } else {
+        write_ast(ast, out);
+    }
+}
+
+fn write_ast(ast: &Ast, out: &mut String) {
+    match ast {
+        Ast::Num(n) => out.push_str(&n.to_string()),
+        Ast::Var(name) => out.push_str(name),
+        Ast::Str(text) => out.push_str(&std::format!("\"{}\"", text)),
+        Ast::BinOp(..) => {
+            // 需要括号的左操作数单独输出
+            let (node, spine) =
+                ast.left_spine_while(|_| true, |op, lhs| !left_needs_parens(op, lhs, level));
+            match spine.first() {
+                Some((op, _)) if left_needs_parens(op, node, level) => {
+                    out.push('(');
+                    write_ast(node, out);
+                    out.push(')');
+                }
+                _ => write_ast(node, out),
+            }
+            for (op, rhs) in spine {
+                out.push_str(&std::format!(" {} ", op));
+                write_operand(rhs, right_min_level(op), out);
+            }
+        }
+        Ast::UnaryOp(Token::Factorial, operand) => {
+            write_operand(operand, ATOM_LEVEL, out);
+            out.push('!');
+        }
+        Ast::UnaryOp(op, operand) => {
+            out.push_str(&op.to_string());
+            // 单词形式的自定义运算符与操作数之间需要空格，如 `neg x`
+            if let Token::Operator(symbol) = op {
+                if symbol.ends_with(|c: char| c.is_alphanumeric() || c == '_') {
+                    out.push(' ');
+                }
+            }
+            write_operand(operand, UNARY_LEVEL, out);
+        }
+        // 时长字面量写回 `3d`
+        Ast::Call(name, args) if datetime::duration_literal(name, args).is_some() => {
+            if let Some((number, unit)) = datetime::duration_literal(name, args) {
+                write_operand(number, ATOM_LEVEL, out);
+                out.push_str(unit);
+            }
+        }
+        Ast::Call(name, args) => {
+            out.push_str(name);
+            out.push('(');
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_operand(arg, CONDITIONAL_LEVEL, out);
+            }
+            out.push(')');
+        }
+        Ast::List(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(", ");
+                }
+                write_operand(item, CONDITIONAL_LEVEL, out);
+            }
+            out.push(']');
+        }
+        Ast::Assign(name, value) => {
+            out.push_str(name);
+            out.push_str(" = ");
+            write_operand(value, CONDITIONAL_LEVEL, out);
+        }
+        Ast::Define(name, params, body) => {
+            out.push_str(&std::format!("{}({}) = ", name, params.join(", ")));
+            write_operand(body, CONDITIONAL_LEVEL, out);
+        }
+        Ast::Seq(statements) => {
+            for (i, statement) in statements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("; ");
+                }
+                write_ast(statement, out);
+            }
+        }
+        Ast::Cond(cond, then, otherwise) => {
+            // 条件表达式右结合，条件部分本身是条件表达式时需要括号
+            write_operand(cond, LOWEST_PRECEDENCE, out);
+            out.push_str(" ? ");
+            write_operand(then, CONDITIONAL_LEVEL, out);
+            out.push_str(" : ");
+            write_operand(otherwise, CONDITIONAL_LEVEL, out);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 格式化后的文本再次解析，结果应该与原表达式相同
+    fn assert_round_trip(input: &str) {
+        let formatted = format_expression(input).unwrap();
+        assert_eq!(format_expression(&formatted).unwrap(), formatted);
+        assert_eq!(evaluate(&formatted).unwrap(), evaluate(input).unwrap());
+    }
+
+    #[test]
+    fn test_format_removes_redundant_parens() {
+        assert_eq!(format_expression("((2)+(3*4))").unwrap(), "2 + 3 * 4");
+        assert_eq!(format_expression("(2+3)*4").unwrap(), "(2 + 3) * 4");
+        assert_eq!(format_expression("1-(2-3)").unwrap(), "1 - (2 - 3)");
+        assert_eq!(format_expression("(1-2)-3").unwrap(), "1 - 2 - 3");
+        assert_eq!(format_expression("2^(3^2)").unwrap(), "2 ^ 3 ^ 2");
+        assert_eq!(format_expression("(2^3)^2").unwrap(), "(2 ^ 3) ^ 2");
+        assert_eq!(format_expression("(-2)^2").unwrap(), "(-2) ^ 2");
+        assert_eq!(format_expression("-(2^2)").unwrap(), "-2 ^ 2");
+        assert_eq!(format_expression("-(1+2)").unwrap(), "-(1 + 2)");
+        assert_eq!(format_expression("(-3)!").unwrap(), "(-3)!");
+        assert_eq!(format_expression("2 ^ -2").unwrap(), "2 ^ -2");
+    }
+
+    #[test]
+    fn test_format_statements_and_calls() {
+        assert_eq!(format_expression("max( 1,2 ,3)").unwrap(), "max(1, 2, 3)");
+        assert_eq!(format_expression("x=3+4;x*2").unwrap(), "x = 3 + 4; x * 2");
+        assert_eq!(format_expression("f(x,y)=x*y").unwrap(), "f(x, y) = x * y");
+        assert_eq!(
+            format_expression("[[1,2],[ 3,(4) ]]*[]").unwrap(),
+            "[[1, 2], [3, 4]] * []"
+        );
+        assert_eq!(format_expression("a?b:c?d:e").unwrap(), "a ? b : c ? d : e");
+        assert_eq!(
+            format_expression("(a?b:c)?d:e").unwrap(),
+            "(a ? b : c) ? d : e"
+        );
+        assert_eq!(
+            format_expression("(1<2)&&(3 xor 1)").unwrap(),
+            "1 < 2 && 3 xor 1"
+        );
+        assert!(matches!(
+            repl_line(":fmt ((2)+(3*4))", &mut ReplState::default()),
+            ReplOutput::Print(output) if output == "2 + 3 * 4"
+        ));
+    }
+
+    #[test]
+    fn test_format_round_trip() {
+        for input in [
+            "((2)+(3*4))",
+            "-2^2 + 5 % 3",
+            "2*(3+4)/(5-6)",
+            "1 < 2 + 3 || 4",
+            "3! ^ 2 - -1",
+            "(1 == 1) ? 2 : 3",
+            "-dot([1, 2 + 3], [(4), 5]) ^ 2",
+        ] {
+            assert_round_trip(input);
+        }
+    }
+
+    #[test]
+    fn test_format_long_chain() {
+        let src = std::format!("1{}", "+1".repeat(100_000));
+        let formatted = format_expression(&src).unwrap();
+        assert_eq!(formatted.len(), 1 + 4 * 100_000);
+    }
+}
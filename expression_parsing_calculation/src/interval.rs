@@ -0,0 +1,331 @@
+// 区间求值：每个数带有不确定度，运算按区间算术传播误差，例如 `(1.0±0.1) * 3` 的结果是 [2.7, 3.3]
+// `a ± r`（也可以写作 `a +/- r`）表示 [a - r, a + r]，优先级与 `+`、`-` 相同，通常需要加括号
+// 区间算术不区分同一个量的多次出现，所以 x*x 的区间可能比 x^2 宽；端点不做向外舍入
+use std::fmt;
+
+use super::*;
+
+// 闭区间 [lo, hi]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f64,
+    pub hi: f64,
+}
+
+impl Interval {
+    // 只包含一个数的区间
+    pub fn point(value: f64) -> Self {
+        Interval {
+            lo: value,
+            hi: value,
+        }
+    }
+
+    // 中点
+    pub fn mid(&self) -> f64 {
+        (self.lo + self.hi) / 2.0
+    }
+
+    // 半径，即不确定度
+    pub fn radius(&self) -> f64 {
+        (self.hi - self.lo) / 2.0
+    }
+
+    // 由若干个端点组成的最小区间
+    fn hull(values: &[f64]) -> Self {
+        Interval {
+            lo: values.iter().copied().fold(f64::INFINITY, f64::min),
+            hi: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+
+    fn contains(&self, value: f64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)
+    }
+}
+
+// 表示不确定度的运算符，数值求值时取中心值
+const PLUS_MINUS: [&str; 2] = ["±", "+/-"];
+
+fn plus_minus_operators() -> OperatorTable {
+    PLUS_MINUS
+        .into_iter()
+        .try_fold(OperatorTable::new(), |table, symbol| {
+            table.binary(symbol, Token::Plus.precedence(), ASSOC_LEFT, |center, _| {
+                Ok(center)
+            })
+        })
+        .expect("valid operator symbols")
+}
+
+fn unsupported(what: &str) -> ExpError {
+    ExpError::ParseError(format!("{} is not supported in interval mode", what))
+}
+
+// 区间求值器，赋值的变量保存在 variables 中
+struct IntervalEvaluator {
+    functions: HashMap<&'static str, BuiltinFn>,
+    variables: HashMap<String, Interval>,
+}
+
+impl IntervalEvaluator {
+    fn eval(&mut self, ast: &Ast) -> Result<Interval> {
+        match ast {
+            Ast::Num(n) => Ok(Interval::point(*n)),
+            Ast::Var(name) => self
+                .variables
+                .get(name)
+                .copied()
+                .or_else(|| builtin_constant(name).map(Interval::point))
+                .ok_or_else(|| ExpError::ParseError(format!("Unknown variable: {}", name))),
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                let mut value = self.eval(node)?;
+                for (op, rhs) in spine {
+                    let rhs = self.eval(rhs)?;
+                    value = binary(op, value, rhs)?;
+                }
+                Ok(value)
+            }
+            Ast::UnaryOp(Token::Minus, operand) => {
+                let value = self.eval(operand)?;
+                Ok(Interval {
+                    lo: -value.hi,
+                    hi: -value.lo,
+                })
+            }
+            Ast::UnaryOp(Token::Plus, operand) => self.eval(operand),
+            Ast::UnaryOp(op, _) => Err(unsupported(&format!("operator {}", op))),
+            Ast::Call(name, args) if series_notation(name, args).is_some() => {
+                Err(unsupported(&format!("{}() notation", name)))
+            }
+            Ast::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<Interval>>>()?;
+                self.call(name, &args)
+            }
+            // 条件不确定时结果包含两个分支
+            Ast::Cond(cond, then, otherwise) => {
+                let cond = self.eval(cond)?;
+                if cond == Interval::point(0.0) {
+                    self.eval(otherwise)
+                } else if !cond.contains(0.0) {
+                    self.eval(then)
+                } else {
+                    let (then, otherwise) = (self.eval(then)?, self.eval(otherwise)?);
+                    Ok(Interval::hull(&[
+                        then.lo,
+                        then.hi,
+                        otherwise.lo,
+                        otherwise.hi,
+                    ]))
+                }
+            }
+            Ast::Assign(name, value) => {
+                let value = self.eval(value)?;
+                self.variables.insert(name.clone(), value);
+                Ok(value)
+            }
+            Ast::Seq(statements) => {
+                let mut value = Err(ExpError::ParseError("Empty expression".to_string()));
+                for statement in statements {
+                    value = Ok(self.eval(statement)?);
+                }
+                value
+            }
+            Ast::Define(..) => Err(unsupported("function definition")),
+            Ast::Str(_) => Err(unsupported("string")),
+            Ast::List(_) => Err(unsupported("vector")),
+        }
+    }
+
+    // 单调函数对两个端点求值，其他函数不支持
+    fn call(&self, name: &str, args: &[Interval]) -> Result<Interval> {
+        let function = *self
+            .functions
+            .get(name)
+            .ok_or_else(|| ExpError::ParseError(format!("Unknown function: {}", name)))?;
+        let result = match (name, args) {
+            // 单调不减的单参数函数
+            ("sqrt" | "exp" | "ln" | "log" | "asin" | "atan" | "floor" | "ceil" | "round", [x]) => {
+                Interval {
+                    lo: function(&[x.lo])?,
+                    hi: function(&[x.hi])?,
+                }
+            }
+            // 单调递减
+            ("acos", [x]) => Interval {
+                lo: function(&[x.hi])?,
+                hi: function(&[x.lo])?,
+            },
+            ("abs", [x]) if x.contains(0.0) => Interval {
+                lo: 0.0,
+                hi: x.lo.abs().max(x.hi.abs()),
+            },
+            ("abs", [x]) => Interval::hull(&[x.lo.abs(), x.hi.abs()]),
+            // 对每个参数都单调不减的多参数函数
+            ("min" | "max" | "sum" | "avg" | "mean" | "median", _) => {
+                let lo: Vec<f64> = args.iter().map(|x| x.lo).collect();
+                let hi: Vec<f64> = args.iter().map(|x| x.hi).collect();
+                Interval {
+                    lo: function(&lo)?,
+                    hi: function(&hi)?,
+                }
+            }
+            _ => return Err(unsupported(&format!("{}() with these arguments", name))),
+        };
+        if result.lo.is_nan() || result.hi.is_nan() {
+            return Err(ExpError::ParseError(format!(
+                "{}() is undefined on part of the interval",
+                name
+            )));
+        }
+        Ok(result)
+    }
+}
+
+// 区间的二元运算
+fn binary(op: &Token, a: Interval, b: Interval) -> Result<Interval> {
+    let result = match op {
+        Token::Plus => Interval {
+            lo: a.lo + b.lo,
+            hi: a.hi + b.hi,
+        },
+        Token::Minus => Interval {
+            lo: a.lo - b.hi,
+            hi: a.hi - b.lo,
+        },
+        Token::Multiply => Interval::hull(&[a.lo * b.lo, a.lo * b.hi, a.hi * b.lo, a.hi * b.hi]),
+        Token::Divide if b.contains(0.0) => {
+            return Err(ExpError::MathError(MathError::DivisionByZero))
+        }
+        Token::Divide => Interval::hull(&[a.lo / b.lo, a.lo / b.hi, a.hi / b.lo, a.hi / b.hi]),
+        Token::Power => power(a, b)?,
+        // 不确定度取绝对值最大的一端
+        Token::Operator(symbol) if PLUS_MINUS.contains(&symbol.as_str()) => {
+            let r = b.lo.abs().max(b.hi.abs());
+            Interval {
+                lo: a.lo - r,
+                hi: a.hi + r,
+            }
+        }
+        op => return Err(unsupported(&format!("operator {}", op))),
+    };
+    Ok(result)
+}
+
+// 乘方：指数是整数时允许负的底数，否则底数必须为正
+fn power(base: Interval, exponent: Interval) -> Result<Interval> {
+    if exponent.lo == exponent.hi && exponent.lo.fract() == 0.0 {
+        let n = exponent.lo;
+        let ends = [base.lo.powf(n), base.hi.powf(n)];
+        return Ok(if n > 0.0 && n % 2.0 == 0.0 && base.contains(0.0) {
+            // 偶数次幂在 0 处取最小值
+            Interval {
+                lo: 0.0,
+                hi: ends[0].max(ends[1]),
+            }
+        } else if n < 0.0 && base.contains(0.0) {
+            return Err(ExpError::MathError(MathError::DivisionByZero));
+        } else {
+            Interval::hull(&ends)
+        });
+    }
+    if base.lo <= 0.0 {
+        return Err(ExpError::ParseError(
+            "non-integer power of an interval containing non-positive numbers".to_string(),
+        ));
+    }
+    Ok(Interval::hull(&[
+        base.lo.powf(exponent.lo),
+        base.lo.powf(exponent.hi),
+        base.hi.powf(exponent.lo),
+        base.hi.powf(exponent.hi),
+    ]))
+}
+
+// 按区间算术求值，例如 eval_interval("(1.0±0.1) * 3") 返回 [2.7, 3.3]
+pub fn eval_interval(input: &str) -> Result<Interval> {
+    let operators = plus_minus_operators();
+    let ast = Expr::new(strip_formula_prefix(input)?)
+        .with_operators(&operators)
+        .parse()?;
+    IntervalEvaluator {
+        functions: builtin_functions(),
+        variables: HashMap::new(),
+    }
+    .eval(&ast)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_interval(input: &str, lo: f64, hi: f64) {
+        let result = eval_interval(input).unwrap();
+        assert!(
+            (result.lo - lo).abs() < 1e-12 && (result.hi - hi).abs() < 1e-12,
+            "{} = {}, expected [{}, {}]",
+            input,
+            result,
+            lo,
+            hi
+        );
+    }
+
+    #[test]
+    fn test_interval_arithmetic() {
+        assert_interval("(1.0±0.1) * 3", 2.7, 3.3);
+        assert_interval("(1 +/- 0.1) * 3", 2.7, 3.3);
+        assert_interval("(2±1) - (2±1)", -2.0, 2.0);
+        assert_interval("(2±1) * (-1±2)", -9.0, 3.0);
+        assert_interval("1 / (4±2)", 1.0 / 6.0, 0.5);
+        assert_interval("-(1±0.5)", -1.5, -0.5);
+        assert_interval("10 ± 1 + 1", 10.0, 12.0);
+        assert_eq!(eval_interval("2 + 3").unwrap(), Interval::point(5.0));
+        let result = eval_interval("(10±0.5) / 2").unwrap();
+        assert_eq!((result.mid(), result.radius()), (5.0, 0.25));
+        assert_eq!(result.to_string(), "[4.75, 5.25]");
+    }
+
+    #[test]
+    fn test_interval_powers_and_functions() {
+        assert_interval("(0±2)^2", 0.0, 4.0);
+        assert_interval("(-3±1)^3", -64.0, -8.0);
+        assert_interval("(4±0)^0.5", 2.0, 2.0);
+        assert_interval("sqrt(10±6)", 2.0, 4.0);
+        assert_interval("abs(-1±2)", 0.0, 3.0);
+        assert_interval("max(1±1, 1.5)", 1.5, 2.0);
+        assert_interval("median(1±1, 2±1, 9)", 1.0, 3.0);
+        assert_interval("acos(0±1)", 0.0, std::f64::consts::PI);
+        // 变量保存区间
+        assert_interval("x = 2±0.5; x * x", 2.25, 6.25);
+        // 条件不确定时取两个分支的并
+        assert_interval("(0±1) ? 5 : 7", 5.0, 7.0);
+    }
+
+    #[test]
+    fn test_interval_errors() {
+        assert!(matches!(
+            eval_interval("1 / (0±1)"),
+            Err(ExpError::MathError(MathError::DivisionByZero))
+        ));
+        assert!(eval_interval("sqrt(0±1)").is_err());
+        assert!(eval_interval("(-1±0.5)^0.5").is_err());
+        assert!(eval_interval("sin(1±0.1)").is_err());
+        assert!(eval_interval("(1±0.1) < 2").is_err());
+        assert!(eval_interval("f(x) = x").is_err());
+        assert!(eval_interval("y + 1").is_err());
+        assert!(eval_interval("sum(i, 1, 3, i ± 1)").is_err());
+        // 不启用区间求值时 ± 不是运算符
+        assert!(evaluate("1±0.1").is_err());
+    }
+}
@@ -0,0 +1,4808 @@
+// 表达式解析和求值库，命令行程序（main.rs）和其他项目都通过这里的公开接口使用求值器
+// 公开的接口：parse、eval、evaluate、evaluate_with_context、eval_script、Tokenizer、Token、不分配内存的 Lexeme、Ast、
+// 可以注册自定义运算符（OperatorTable）和函数来源（FunctionProvider）的解析器 Expr、EvalContext、记忆化求值的 Memoized 和 eval_memoized、
+// 编译为字节码的 compile 和批量求值的 eval_batch、区间求值的 eval_interval、结果可以是向量、矩阵、日期和时长的 evaluate_value、
+// evaluate_rpn、group_thousands、逐行处理交互输入的 repl_line 及其补全 repl_completions 和提示 repl_hint
+// 统计函数 mean、median、stdev、percentile 以及 min、max 可以直接作用于向量
+// 日期函数 date()、today()、days_between() 和时长字面量 3d、12h
+// 内置的 rand()、randint()、normal() 使用 EvalContext 中可设置种子的随机数生成器
+// 求和与求积记号 sum(i, 1, n, 通项)、prod(k, 1, n, 通项) 中的下标变量只在通项内有效
+// 语法树的格式化 format、渲染为 LaTeX 和 MathML 的 to_latex、to_mathml、符号求导 derivative、遍历语法树的 Visitor 和 Fold，
+// JSON 序列化 to_json、from_json（`serde` feature）和十进制精确求值 evaluate_decimal（`arbitrary-precision` feature）
+// Expr 的各项设置（优先级表、整数模式、溢出检查、比较容差、隐式乘法、数值后端等）以及 evaluate_integer、evaluate_checked、eval_batch_indexed
+// 以及错误类型 ExpError、MathError、Span、Diagnostic，错误信息可以按 Locale 翻译为中文，Expr::check 只校验语法、不求值
+use std::{collections::HashMap, fmt::Display, iter::Peekable};
+
+// 调度场算法的解析策略，与递归下降共用 Token、Ast 和求值器
+mod expression_parsing_algorithm;
+
+use expression_parsing_algorithm::approx_eq;
+pub use expression_parsing_algorithm::{
+    expression_parsing_algorithm, expression_parsing_algorithm_with_epsilon, ShuntingYardError,
+    DEFAULT_EPSILON,
+};
+
+// 任意精度十进制求值后端
+#[cfg(feature = "arbitrary-precision")]
+mod decimal;
+
+#[cfg(feature = "arbitrary-precision")]
+pub use decimal::evaluate_decimal;
+
+// 带单位的计算和单位换算
+mod units;
+
+// 把语法树格式化为规范的表达式文本
+mod format;
+
+pub use format::{format, format_expression};
+
+// 把语法树渲染为 LaTeX 和 MathML
+mod typeset;
+
+pub use typeset::{latex_expression, mathml_expression, to_latex, to_mathml};
+
+// 后缀（逆波兰）和前缀表示法的输入
+mod notation;
+
+pub use notation::{evaluate_prefix, evaluate_rpn};
+
+// 语法树的常量折叠和化简
+mod simplify;
+
+// 符号求导
+mod derivative;
+
+pub use derivative::{derivative, derivative_expression};
+
+// 比较两个表达式是否等价
+mod equivalence;
+
+// 字节码编译器和虚拟机，用于同一表达式的反复求值
+mod bytecode;
+
+pub use bytecode::{compile, eval_batch, Program, VarSet};
+
+// 语法树的遍历：Visitor 和 Fold trait
+mod visit;
+
+pub use visit::{Fold, Visitor};
+
+// 重复子表达式的记忆化求值
+mod memo;
+
+pub use memo::{eval_memoized, Memoized};
+
+// 种子可设置的随机数函数
+mod random;
+
+// 带不确定度的区间求值
+mod interval;
+
+pub use interval::{eval_interval, Interval};
+
+// 向量和矩阵的值与运算
+mod tensor;
+
+pub use tensor::{evaluate_value, evaluate_value_with_context};
+
+// 日期和时长
+mod datetime;
+
+// 错误信息的本地化
+mod locale;
+
+pub use locale::Locale;
+
+// 均值、中位数、标准差和百分位数等统计函数
+mod stats;
+
+pub use stats::{mean, median, percentile, stdev};
+
+// 结果的输出格式：有效数字、小数位数、舍入方式和千位分隔符
+mod display;
+
+pub use display::{Digits, DisplayFormat, Rounding, MAX_DECIMAL_PLACES, MAX_SIGNIFICANT_DIGITS};
+
+// REPL 的补全和行内提示
+mod completion;
+
+// REPL 会话的保存、加载和历史记录文件
+mod session;
+
+pub use completion::{repl_completions, repl_hint};
+
+// 语法树的 JSON 序列化
+#[cfg(feature = "serde")]
+mod serialize;
+
+#[cfg(feature = "serde")]
+pub use serialize::{from_json, to_json, SCHEMA_VERSION};
+
+// WebAssembly 绑定
+#[cfg(feature = "wasm")]
+mod wasm;
+
+// C 语言接口
+#[cfg(feature = "ffi")]
+mod ffi;
+
+pub type Result<T> = std::result::Result<T, ExpError>;
+
+// 输入中的一段位置，以字符为单位
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub offset: usize, // 起始字符的偏移量
+    pub len: usize,    // 字符数，输入结束处为 0
+}
+
+// 一条语法错误的诊断信息，恢复模式下一次解析可以收集多条
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+    pub lexeme: String, // 出错的片段，输入结束处为空
+}
+
+impl Diagnostic {
+    // 渲染为错误信息，下面两行是完整的输入和标出出错位置的 `^`
+    pub fn render(&self, source: &str) -> String {
+        self.render_in(source, Locale::EnUs)
+    }
+
+    // 按指定的语言渲染错误信息
+    pub fn render_in(&self, source: &str, locale: Locale) -> String {
+        let mut text = locale::translate(&self.message, locale);
+        if !self.lexeme.is_empty() {
+            text.push_str(&format!(" '{}'", self.lexeme));
+        }
+        format!(
+            "{}\n{}\n{}{}",
+            locale::at_position(&text, self.span.offset, locale),
+            source,
+            " ".repeat(self.span.offset),
+            "^".repeat(self.span.len.max(1))
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ExpError {
+    ParseError(String),
+    // 带位置信息的解析错误：出错的片段和完整的输入，用于在 Display 中标出出错位置
+    SyntaxError {
+        message: String,
+        span: Span,
+        lexeme: String,
+        source: String,
+        locale: Locale, // 渲染错误信息使用的语言，message 始终是英文
+    },
+    Overflow,                             // 有限的输入计算出了 inf 或 NaN
+    Mismatch(f64, f64),                   // 已不再产生：(递归下降, 调度场) 的结果不一致
+    NotInteger(String),                   // 整数模式下出现了无法用整数表示的值
+    DimensionError(String),               // 带单位计算时量纲不一致，如长度加时间
+    ShapeError(String), // 向量、矩阵的形状不匹配，日期、时长不支持该运算，或者在需要数的地方出现了向量
+    MathError(MathError), // 整数模式下的除以0或溢出
+    ShuntingYardError(ShuntingYardError), // 调度场解析的语法错误
+}
+
+// 整数运算的错误
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MathError {
+    DivisionByZero,
+    Overflow,         // 结果超出 i64 的范围，或者无法用 f64 精确表示，如 2^53 + 1
+    NegativeExponent, // 整数的负数次幂，如 2^-1
+}
+
+impl Display for MathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MathError::DivisionByZero => write!(f, "division by zero"),
+            MathError::Overflow => write!(f, "integer overflow"),
+            MathError::NegativeExponent => write!(f, "negative exponent"),
+        }
+    }
+}
+
+impl Display for ExpError {
+    // 定义一个名为fmt的方法，该方法接收一个可变引用的self和一个可变引用的Formatter作为参数，返回一个fmt::Result
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // 使用match表达式匹配self，根据self的值进行不同的处理
+        match self {
+            // 如果self是ExpError::ParseError，则将错误信息写入Formatter
+            ExpError::ParseError(s) => write!(f, "ParseError: {}", s),
+            // 如果self是ExpError::SyntaxError，在输入下面用 `^` 标出出错的位置
+            ExpError::SyntaxError {
+                message,
+                span,
+                lexeme,
+                source,
+                locale,
+            } => {
+                let diagnostic = Diagnostic {
+                    message: message.clone(),
+                    span: *span,
+                    lexeme: lexeme.clone(),
+                };
+                write!(f, "ParseError: {}", diagnostic.render_in(source, *locale))
+            }
+            // 如果self是ExpError::Overflow，说明计算结果超出了浮点数能表示的范围
+            ExpError::Overflow => write!(f, "Overflow: result is not finite"),
+            // 如果self是ExpError::Mismatch，说明两个求值器对同一表达式给出了不同的结果
+            ExpError::Mismatch(recursive, shunting_yard) => write!(
+                f,
+                "Mismatch: recursive descent = {}, shunting-yard = {}",
+                recursive, shunting_yard
+            ),
+            // 如果self是ExpError::NotInteger，说明表达式需要浮点数才能计算
+            ExpError::NotInteger(s) => write!(f, "NotInteger: {}", s),
+            // 如果self是ExpError::DimensionError，说明参与运算的单位量纲不一致
+            ExpError::DimensionError(s) => write!(f, "DimensionError: {}", s),
+            // 如果self是ExpError::ShapeError，说明向量或矩阵的形状不符合运算的要求
+            ExpError::ShapeError(s) => write!(f, "ShapeError: {}", s),
+            // 如果self是ExpError::MathError，说明整数运算除以0或溢出
+            ExpError::MathError(e) => write!(f, "MathError: {}", e),
+            // 如果self是ExpError::ShuntingYardError，说明调度场解析无法处理输入
+            ExpError::ShuntingYardError(e) => write!(f, "ShuntingYardError: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Token {
+    Number(f64),
+    Ident(String), // 标识符，目前用于函数名
+    Str(String),   // 双引号括起的字符串，只能作为函数参数，如 price("MSFT")
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+    Modulo,       // 取余
+    Power,        // 指数
+    Less,         // `<`
+    LessEqual,    // `<=`
+    Greater,      // `>`
+    GreaterEqual, // `>=`
+    Equal,        // `==`
+    NotEqual,     // `!=`
+    And,          // `&&`
+    Or,           // `||`
+    BitAnd,       // 按位与 `&`，仅整数模式
+    BitOr,        // 按位或 `|`，仅整数模式
+    Xor,          // 按位异或 `xor`，仅整数模式
+    ShiftLeft,    // 左移 `<<`，仅整数模式
+    ShiftRight,   // 算术右移 `>>`，仅整数模式
+    BitNot,       // 一元按位取反 `~`，仅整数模式
+    LParen,
+    RParen,
+    LBracket,         // 向量、矩阵字面量的 `[`
+    RBracket,         // `]`
+    Comma,            // 函数参数分隔符
+    Factorial,        // 后缀阶乘
+    Assign,           // 赋值 `=`
+    Semicolon,        // 语句分隔符 `;`
+    Question,         // 条件表达式 `cond ? a : b` 中的 `?`
+    Colon,            // 条件表达式中的 `:`
+    Unknown(char),    // 无法识别的字符，由解析器报告错误
+    Operator(String), // OperatorTable 中注册的自定义运算符
+}
+
+pub const ASSOC_LEFT: i32 = 0; // 左结合
+
+pub const ASSOC_RIGHT: i32 = 1; // 右结合
+
+// 最低的运算符优先级（`||`），完整的表达式从这一级开始解析
+pub const LOWEST_PRECEDENCE: i32 = -6;
+
+// 为 Token 实现标准库中的 Display trait，以便可以将其格式化为字符串
+impl Display for Token {
+    // 实现 fmt 方法，该方法接受一个可变的 Formatter 引用，并返回一个 fmt::Result
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        // 使用 write! 宏将格式化后的字符串写入 Formatter
+        write!(
+            f,
+            "{}",
+            // 使用 match 语句根据 Token 的不同变体返回相应的字符串表示
+            match self {
+                // 如果 Token 是 Number 变体，则将其值转换为字符串
+                Token::Number(n) => n.to_string(),
+                // 如果 Token 是 Ident 变体，则返回标识符本身
+                Token::Ident(name) => name.clone(),
+                // 字符串带上两边的引号
+                Token::Str(text) => format!("\"{}\"", text),
+                // 如果 Token 是 Plus 变体，则返回 "+" 字符串
+                Token::Plus => "+".to_string(),
+                // 如果 Token 是 Minus 变体，则返回 "-" 字符串
+                Token::Minus => "-".to_string(),
+                // 如果 Token 是 Multiply 变体，则返回 "*" 字符串
+                Token::Multiply => "*".to_string(),
+                // 如果 Token 是 Divide 变体，则返回 "/" 字符串
+                Token::Divide => "/".to_string(),
+                // 如果 Token 是 Modulo 变体，则返回 "%" 字符串
+                Token::Modulo => "%".to_string(),
+                // 如果 Token 是 Power 变体，则返回 "^" 字符串
+                Token::Power => "^".to_string(),
+                // 比较和逻辑运算符返回对应的符号
+                Token::Less => "<".to_string(),
+                Token::LessEqual => "<=".to_string(),
+                Token::Greater => ">".to_string(),
+                Token::GreaterEqual => ">=".to_string(),
+                Token::Equal => "==".to_string(),
+                Token::NotEqual => "!=".to_string(),
+                Token::And => "&&".to_string(),
+                Token::Or => "||".to_string(),
+                // 位运算符返回对应的符号
+                Token::BitAnd => "&".to_string(),
+                Token::BitOr => "|".to_string(),
+                Token::Xor => "xor".to_string(),
+                Token::ShiftLeft => "<<".to_string(),
+                Token::ShiftRight => ">>".to_string(),
+                Token::BitNot => "~".to_string(),
+                // 如果 Token 是 LParen 变体，则返回 "(" 字符串
+                Token::LParen => "(".to_string(),
+                // 如果 Token 是 RParen 变体，则返回 ")" 字符串
+                Token::RParen => ")".to_string(),
+                // 方括号用于向量和矩阵
+                Token::LBracket => "[".to_string(),
+                Token::RBracket => "]".to_string(),
+                // 如果 Token 是 Comma 变体，则返回 "," 字符串
+                Token::Comma => ",".to_string(),
+                // 如果 Token 是 Factorial 变体，则返回 "!" 字符串
+                Token::Factorial => "!".to_string(),
+                // 如果 Token 是 Assign 变体，则返回 "=" 字符串
+                Token::Assign => "=".to_string(),
+                // 如果 Token 是 Semicolon 变体，则返回 ";" 字符串
+                Token::Semicolon => ";".to_string(),
+                // 如果 Token 是 Question 变体，则返回 "?" 字符串
+                Token::Question => "?".to_string(),
+                // 如果 Token 是 Colon 变体，则返回 ":" 字符串
+                Token::Colon => ":".to_string(),
+                // 如果 Token 是 Unknown 变体，则返回该字符本身
+                Token::Unknown(c) => c.to_string(),
+                // 自定义运算符返回注册时的符号
+                Token::Operator(symbol) => symbol.clone(),
+            }
+        )
+    }
+}
+
+impl Token {
+    // 判断是不是运算符号
+    fn is_operator(&self) -> bool {
+        self.operator_symbol().is_some()
+    }
+
+    // 二元运算符的符号，用于在优先级表中查找而不需要分配字符串；其他 Token 返回 None
+    fn operator_symbol(&self) -> Option<&'static str> {
+        Some(match self {
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Modulo => "%",
+            Token::Power => "^",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::BitAnd => "&",
+            Token::BitOr => "|",
+            Token::Xor => "xor",
+            Token::ShiftLeft => "<<",
+            Token::ShiftRight => ">>",
+            _ => return None,
+        })
+    }
+
+    // 获取运算符的优先级
+    // 定义一个方法 `precedence`，它接收一个 `self` 引用，返回一个 `i32` 类型的值
+    fn precedence(&self) -> i32 {
+        // 使用 `match` 表达式来匹配 `self` 的不同值
+        match self {
+            // 逻辑、比较和位运算符的优先级低于算术运算符：|| 最低，其次是 &&，然后是比较，
+            // 再往上依次是 |、xor、& 和移位
+            Token::Or => -6,
+            Token::And => -5,
+            Token::Less
+            | Token::LessEqual
+            | Token::Greater
+            | Token::GreaterEqual
+            | Token::Equal
+            | Token::NotEqual => -4,
+            Token::BitOr => -3,
+            Token::Xor => -2,
+            Token::BitAnd => -1,
+            Token::ShiftLeft | Token::ShiftRight => 0,
+            // 如果 `self` 是 `Token::Plus` 或 `Token::Minus`，则返回 1
+            Token::Plus | Token::Minus => 1,
+            // 如果 `self` 是 `Token::Multiply`、`Token::Divide` 或 `Token::Modulo`，则返回 2
+            Token::Multiply | Token::Divide | Token::Modulo => 2,
+            // 如果 `self` 是 `Token::Power`，则返回 3
+            Token::Power => 3,
+            // 如果 `self` 是其他任何值，则返回 0
+            _ => 0,
+        }
+    }
+
+    // 获取运算符的结合性
+    // 定义一个名为assoc的方法，它返回一个i32类型的结果
+    fn assoc(&self) -> i32 {
+        // 使用match语句来匹配self的值，根据不同的Token枚举值返回不同的结果
+        match self {
+            // 如果self是Token::Power，则返回ASSOC_RIGHT
+            Token::Power => ASSOC_RIGHT,
+            // 如果self不是Token::Power，则返回ASSOC_LEFT
+            _ => ASSOC_LEFT,
+        }
+    }
+    // 判断是不是只能在整数模式下使用的位运算符
+    fn is_bitwise(&self) -> bool {
+        matches!(
+            self,
+            Token::BitAnd
+                | Token::BitOr
+                | Token::Xor
+                | Token::ShiftLeft
+                | Token::ShiftRight
+                | Token::BitNot
+        )
+    }
+
+    // 根据当前运算符进行计算
+    // 定义一个名为compute的方法，它接收两个f64类型的参数left和right，并返回一个f64类型的结果
+    fn compute(&self, left: f64, right: f64) -> Option<f64> {
+        self.compute_within(left, right, DEFAULT_EPSILON)
+    }
+
+    // 与 compute 相同，`==`、`!=` 使用指定的容差
+    fn compute_within(&self, left: f64, right: f64, epsilon: f64) -> Option<f64> {
+        // 使用match语句来匹配self的值，根据不同的Token枚举值执行不同的操作
+        match self {
+            // 如果self是Token::Plus，则返回left和right的和
+            Token::Plus => Some(left + right),
+            // 如果self是Token::Minus，则返回left和right的差
+            Token::Minus => Some(left - right),
+            // 如果self是Token::Multiply，则返回left和right的乘积
+            Token::Multiply => Some(left * right),
+            // 如果self是Token::Divide，则返回left除以right的结果
+            Token::Divide => Some(left / right),
+            // 如果self是Token::Modulo，则返回浮点数余数，符号与被除数相同（如 -7 % 3 = -1）
+            Token::Modulo => Some(left % right),
+            // 如果self是Token::Power，则返回left的right次幂
+            Token::Power => Some(left.powf(right)),
+            // 比较运算成立为1，否则为0；相等比较与调度场求值器一样使用容差
+            Token::Less => Some(bool_value(left < right)),
+            Token::LessEqual => Some(bool_value(left <= right)),
+            Token::Greater => Some(bool_value(left > right)),
+            Token::GreaterEqual => Some(bool_value(left >= right)),
+            Token::Equal => Some(bool_value(approx_eq(left, right, epsilon))),
+            Token::NotEqual => Some(bool_value(!approx_eq(left, right, epsilon))),
+            // 逻辑运算：非0为真
+            Token::And => Some(bool_value(left != 0.0 && right != 0.0)),
+            Token::Or => Some(bool_value(left != 0.0 || right != 0.0)),
+            // 如果self不是上述任何一种Token，则返回None
+            _ => None,
+        }
+    }
+}
+
+// 比较和逻辑运算的结果：真为1，假为0
+fn bool_value(b: bool) -> f64 {
+    if b {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// 数字和参数的书写格式，默认 `.` 为小数点、`,` 分隔函数参数
+// 部分地区习惯用 `,` 作小数点（如 3,14），此时参数需要改用 `;` 分隔
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumberFormat {
+    decimal_separator: char, // 小数点
+    arg_separator: char,     // 函数参数分隔符
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        NumberFormat {
+            decimal_separator: '.',
+            arg_separator: ',',
+        }
+    }
+}
+
+impl NumberFormat {
+    // 以 `,` 作小数点、`;` 分隔参数的格式
+    pub fn decimal_comma() -> Self {
+        NumberFormat {
+            decimal_separator: ',',
+            arg_separator: ';',
+        }
+    }
+
+    // 小数点和参数分隔符不能相同，否则 `f(3,14)` 无法区分
+    fn validate(&self) -> Result<()> {
+        if self.decimal_separator == self.arg_separator {
+            Err(ExpError::ParseError(format!(
+                "'{}' cannot be both the decimal separator and the argument separator",
+                self.decimal_separator
+            )))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// 运算符优先级表，键为运算符符号，值为 (优先级, 结合性)
+// 默认值与 Token::precedence/assoc 相同，可以覆盖以实验不同的优先级规则
+// 优先级必须大于等于 LOWEST_PRECEDENCE，因为表达式从该优先级开始解析
+// 隐式乘法使用单独的键 IMPLICIT_MULTIPLICATION，默认与 `*` 相同
+#[derive(Debug, Clone)]
+pub struct PrecedenceTable {
+    entries: HashMap<String, (i32, i32)>,
+}
+
+impl Default for PrecedenceTable {
+    fn default() -> Self {
+        // 键为运算符的符号，如 `+`、`<=`
+        let entries = [
+            Token::Plus,
+            Token::Minus,
+            Token::Multiply,
+            Token::Divide,
+            Token::Modulo,
+            Token::Power,
+            Token::Less,
+            Token::LessEqual,
+            Token::Greater,
+            Token::GreaterEqual,
+            Token::Equal,
+            Token::NotEqual,
+            Token::And,
+            Token::Or,
+            Token::BitAnd,
+            Token::BitOr,
+            Token::Xor,
+            Token::ShiftLeft,
+            Token::ShiftRight,
+        ]
+        .into_iter()
+        .map(|token| (token.to_string(), (token.precedence(), token.assoc())))
+        .chain([(
+            IMPLICIT_MULTIPLICATION.to_string(),
+            (Token::Multiply.precedence(), Token::Multiply.assoc()),
+        )])
+        .collect();
+        PrecedenceTable { entries }
+    }
+}
+
+impl PrecedenceTable {
+    // 覆盖一个运算符的优先级和结合性
+    pub fn set(mut self, symbol: impl Into<String>, precedence: i32, assoc: i32) -> Self {
+        self.entries.insert(symbol.into(), (precedence, assoc));
+        self
+    }
+
+    // 查找运算符的 (优先级, 结合性)，不是运算符时返回 None
+    fn get(&self, token: &Token) -> Option<(i32, i32)> {
+        self.entries.get(token.operator_symbol()?).copied()
+    }
+
+    // 隐式乘法的 (优先级, 结合性)
+    fn implicit_multiplication(&self) -> Option<(i32, i32)> {
+        self.entries.get(IMPLICIT_MULTIPLICATION).copied()
+    }
+}
+
+// 优先级表中隐式乘法的键
+const IMPLICIT_MULTIPLICATION: &str = "implicit *";
+
+// 把 Token 序列解析为语法树的方式，两种方式生成相同的 Ast，共用同一个求值器
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseStrategy {
+    #[default]
+    Pratt, // 递归下降（Pratt）解析，支持全部语法
+    // 调度场算法，不递归，只支持数、变量、字面量、运算符和括号，对这些输入生成与 Pratt 相同的 Ast；
+    // 函数调用、条件表达式 `?:`、赋值、`;` 分隔的多条语句、`[..]` 向量和字符串返回 ShuntingYardError::Unsupported
+    ShuntingYard,
+}
+
+// 一元负号与 ^ 的结合方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PowerSemantics {
+    #[default]
+    Math, // 数学习惯：负号的优先级低于 ^，-2^2 = -(2^2) = -4，^ 右结合，2^3^2 = 2^9
+    Excel, // 电子表格习惯：负号先作用于操作数，-2^2 = (-2)^2 = 4，^ 左结合，2^3^2 = 8^2
+}
+
+// 不分配内存的 Token：标识符、字符串和自定义运算符借用输入中的原文，数字直接从原文解析，
+// 需要保存到语法树中时再转换为 Token
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lexeme<'a> {
+    Number(f64),
+    Ident(&'a str),    // 标识符
+    Str(&'a str),      // 双引号括起的字符串，不含两边的引号
+    Operator(&'a str), // OperatorTable 中注册的自定义运算符
+    Symbol(Token),     // 其余的 Token：内置运算符、括号、分隔符和无法识别的字符，都不需要分配内存
+}
+
+impl From<Lexeme<'_>> for Token {
+    fn from(lexeme: Lexeme<'_>) -> Token {
+        match lexeme {
+            Lexeme::Number(n) => Token::Number(n),
+            Lexeme::Ident(name) => Token::Ident(name.to_string()),
+            Lexeme::Str(text) => Token::Str(text.to_string()),
+            Lexeme::Operator(symbol) => Token::Operator(symbol.to_string()),
+            Lexeme::Symbol(token) => token,
+        }
+    }
+}
+
+pub struct Tokenizer<'a> {
+    source: &'a str,                      // 输入字符串，Lexeme 中的原文都是它的切片
+    pos: usize,                           // 下一个字符的字节位置
+    format: NumberFormat,                 // 小数点和参数分隔符
+    offset: usize,                        // 已经读取的字符数，即下一个字符的位置
+    operators: Option<&'a OperatorTable>, // 需要识别的自定义运算符
+}
+
+impl<'a> Tokenizer<'a> {
+    // 创建一个新的 Tokenizer 实例
+    // 参数 expression 是一个字符串切片，表示要解析的表达式
+    pub fn new(expression: &'a str) -> Self {
+        Self::with_format(expression, NumberFormat::default())
+    }
+
+    // 使用指定的数字格式创建 Tokenizer
+    fn with_format(expression: &'a str, format: NumberFormat) -> Self {
+        Self {
+            source: expression,
+            pos: 0,
+            format,
+            offset: 0,
+            operators: None,
+        }
+    }
+
+    // 识别 operators 中注册的自定义运算符
+    fn with_operators(mut self, operators: &'a OperatorTable) -> Self {
+        self.operators = Some(operators);
+        self
+    }
+
+    // 逐个产生不分配内存的 Lexeme 及其位置，解析器和只需要原文和位置的场合（如校验、批量处理）使用
+    pub fn lexemes(self) -> Lexemes<'a> {
+        Lexemes { tokenizer: self }
+    }
+
+    // 尚未读取的输入
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    // 预览下一个字符
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    // 读取一个字符，同时更新位置
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        self.offset += 1;
+        Some(c)
+    }
+
+    // 下一个字符是 expected 时读取它并返回 true，用于识别 `<=`、`==` 这样的双字符运算符
+    fn bump_if(&mut self, expected: char) -> bool {
+        if self.peek() == Some(expected) {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    // 读取满足 accept 的连续字符
+    fn bump_while(&mut self, accept: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&accept) {
+            self.bump();
+        }
+    }
+
+    // 清楚空白字符
+    fn clear_whitespace(&mut self) {
+        self.bump_while(char::is_whitespace);
+    }
+
+    // 扫描带前缀的整数字面量：0x 十六进制、0b 二进制、0o 八进制，前缀大小写均可
+    // 前缀后面没有合法数字时返回 None，不消耗任何字符，按普通数字扫描（`0x` 会在解析时报错）
+    fn scan_radix_literal(&mut self) -> Option<Lexeme<'a>> {
+        let mut ahead = self.rest().chars();
+        if ahead.next() != Some('0') {
+            return None;
+        }
+        let radix = match ahead.next()? {
+            'x' | 'X' => 16,
+            'b' | 'B' => 2,
+            'o' | 'O' => 8,
+            _ => return None,
+        };
+        ahead.next()?.to_digit(radix)?;
+
+        // 跳过前缀，用 i64 逐位累加，遇到不属于该进制的字符时停止（如 0b102 中的 2 会作为下一个 Token）
+        self.bump();
+        self.bump();
+        let mut value = Some(0i64);
+        while let Some(digit) = self.peek().and_then(|c| c.to_digit(radix)) {
+            value = value
+                .and_then(|value| value.checked_mul(i64::from(radix)))
+                .and_then(|value| value.checked_add(i64::from(digit)));
+            self.bump();
+        }
+        // 超出 i64 或者无法用 f64 精确表示的字面量（如 0xFFFFFFFFFFFFFFFF）作为无法识别的 Token，由解析器报告
+        Some(match value {
+            Some(value) if value as f64 as i128 == i128::from(value) => {
+                Lexeme::Number(value as f64)
+            }
+            _ => Lexeme::Symbol(Token::Unknown('0')),
+        })
+    }
+
+    // 扫描数字，直接解析输入中的原文，不复制到新的字符串
+    fn scan_number(&mut self) -> Option<Lexeme<'a>> {
+        // 先尝试 0x、0b、0o 开头的整数字面量
+        if let Some(lexeme) = self.scan_radix_literal() {
+            return Some(lexeme);
+        }
+        let start = self.pos;
+        // 是否已经遇到过小数点，一个数字最多只有一个小数点
+        let mut seen_decimal = false;
+        // 只接受 ASCII 数字，其他数字字符 parse 无法解析
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() {
+                self.bump();
+            } else if c == self.format.decimal_separator && !seen_decimal {
+                seen_decimal = true;
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        // 没有扫描到数字
+        if self.pos == start {
+            return None;
+        }
+        // 科学计数法的指数部分
+        self.scan_exponent();
+        let text = &self.source[start..self.pos];
+        // 小数点不是 `.` 时才需要转换后再解析
+        let value = if self.format.decimal_separator == '.' {
+            text.parse()
+        } else {
+            text.replacen(self.format.decimal_separator, ".", 1).parse()
+        };
+        // 只有一个小数点、没有数字时无法解析，作为无法识别的字符由解析器报告出错位置
+        Some(match value {
+            Ok(value) => Lexeme::Number(value),
+            Err(_) => Lexeme::Symbol(Token::Unknown(self.format.decimal_separator)),
+        })
+    }
+
+    // 扫描科学计数法的指数部分，如 1.5e3、2E-4、1e+2
+    // e 后面（可选的正负号之后）必须紧跟数字，否则 e 不属于这个数字，如 `1e` 中的 e 会作为标识符
+    fn scan_exponent(&mut self) {
+        let mut ahead = self.rest().chars();
+        if !matches!(ahead.next(), Some('e' | 'E')) {
+            return;
+        }
+        let mut next = ahead.next();
+        let signed = matches!(next, Some('+' | '-'));
+        if signed {
+            next = ahead.next();
+        }
+        if !next.is_some_and(|c| c.is_ascii_digit()) {
+            return;
+        }
+
+        self.bump();
+        if signed {
+            self.bump();
+        }
+        self.bump_while(|c| c.is_ascii_digit());
+    }
+
+    // 扫描标识符：以字母或下划线开头，后面跟字母、数字或下划线
+    fn scan_identifier(&mut self) -> Option<Lexeme<'a>> {
+        let start = self.pos;
+        self.bump_while(|c| c.is_alphanumeric() || c == '_');
+        match &self.source[start..self.pos] {
+            "" => None,
+            // xor 是运算符，不能用作变量名或函数名
+            "xor" => Some(Lexeme::Symbol(Token::Xor)),
+            name => Some(Lexeme::Ident(name)),
+        }
+    }
+
+    // 扫描双引号括起的字符串，字符串中不能包含引号
+    // 缺少结尾的引号时整个剩余的输入作为一个无法识别的 Token，由解析器报告错误
+    fn scan_string(&mut self) -> Option<Lexeme<'a>> {
+        self.bump();
+        let start = self.pos;
+        while let Some(c) = self.bump() {
+            if c == '"' {
+                return Some(Lexeme::Str(&self.source[start..self.pos - 1]));
+            }
+        }
+        Some(Lexeme::Symbol(Token::Unknown('"')))
+    }
+
+    // 扫描运算符
+    // 定义一个名为 scan_operator 的方法，该方法接收一个可变引用的 self 参数，并返回一个 Option<Token> 类型的值
+    fn scan_operator(&mut self) -> Option<Token> {
+        // 使用 match 语句匹配 self.tokens 的下一个元素
+        match self.bump() {
+            // 如果下一个元素是 '+'，则返回 Some(Token::Plus)
+            Some('+') => Some(Token::Plus),
+            // 如果下一个元素是 '-'，则返回 Some(Token::Minus)
+            Some('-') => Some(Token::Minus),
+            // 如果下一个元素是 '*'，则返回 Some(Token::Multiply)
+            Some('*') => Some(Token::Multiply),
+            // 如果下一个元素是 '/'，则返回 Some(Token::Divide)
+            Some('/') => Some(Token::Divide),
+            // 如果下一个元素是 '%'，则返回 Some(Token::Modulo)
+            Some('%') => Some(Token::Modulo),
+            // 如果下一个元素是 '^'，则返回 Some(Token::Power)
+            Some('^') => Some(Token::Power),
+            // 如果下一个元素是 '('，则返回 Some(Token::LParen)
+            Some('(') => Some(Token::LParen),
+            // 如果下一个元素是 ')'，则返回 Some(Token::RParen)
+            Some(')') => Some(Token::RParen),
+            // 向量和矩阵字面量的方括号
+            Some('[') => Some(Token::LBracket),
+            Some(']') => Some(Token::RBracket),
+            // 如果下一个元素是 '!='，则返回 Some(Token::NotEqual)，单独的 '!' 返回 Some(Token::Factorial)
+            Some('!') if self.bump_if('=') => Some(Token::NotEqual),
+            Some('!') => Some(Token::Factorial),
+            // 移位运算符 '<<'、'>>' 和比较运算符 '<'、'<='、'>'、'>='
+            Some('<') if self.bump_if('<') => Some(Token::ShiftLeft),
+            Some('<') if self.bump_if('=') => Some(Token::LessEqual),
+            Some('<') => Some(Token::Less),
+            Some('>') if self.bump_if('>') => Some(Token::ShiftRight),
+            Some('>') if self.bump_if('=') => Some(Token::GreaterEqual),
+            Some('>') => Some(Token::Greater),
+            // 逻辑运算符 '&&'、'||' 和位运算符 '&'、'|'、'~'
+            Some('&') if self.bump_if('&') => Some(Token::And),
+            Some('&') => Some(Token::BitAnd),
+            Some('|') if self.bump_if('|') => Some(Token::Or),
+            Some('|') => Some(Token::BitOr),
+            Some('~') => Some(Token::BitNot),
+            // 如果下一个元素是参数分隔符（默认为 ','），则返回 Some(Token::Comma)
+            Some(c) if c == self.format.arg_separator => Some(Token::Comma),
+            // 如果下一个元素是 '=='，则返回 Some(Token::Equal)，单独的 '=' 返回 Some(Token::Assign)
+            Some('=') if self.bump_if('=') => Some(Token::Equal),
+            Some('=') => Some(Token::Assign),
+            // 如果下一个元素是 ';'（且没有被用作参数分隔符），则返回 Some(Token::Semicolon)
+            Some(';') => Some(Token::Semicolon),
+            // 如果下一个元素是 '?' 或 ':'，则返回条件表达式的 Token
+            Some('?') => Some(Token::Question),
+            Some(':') => Some(Token::Colon),
+            // 如果下一个元素不是上述任何一个，则返回 Some(Token::Unknown)，由解析器报告出错位置
+            Some(c) => Some(Token::Unknown(c)),
+            // 输入已经结束
+            None => None,
+        }
+    }
+
+    // 读取下一个 Lexeme 及其位置，输入结束时返回 None
+    fn next_lexeme(&mut self) -> Option<(Lexeme<'a>, Span)> {
+        // 调用 clear_whitespace 方法，清除当前标记中的空白字符
+        self.clear_whitespace();
+        let start = (self.pos, self.offset);
+        // 自定义运算符优先于内置的符号，所以注册了 `//` 时不会被拆成两个 `/`
+        let custom = self
+            .operators
+            .and_then(|operators| operators.longest_match(self.rest()));
+        // 使用 peek 方法查看当前标记的第一个字符
+        let lexeme = if let Some(symbol) = custom {
+            symbol.chars().for_each(|_| {
+                self.bump();
+            });
+            Some(Lexeme::Operator(&self.source[start.0..self.pos]))
+        } else if let Some(c) = self.peek() {
+            // 如果字符是数字，则调用 scan_number 方法进行数字解析
+            if c.is_ascii_digit() || c == self.format.decimal_separator {
+                // 以小数点开头的数字（如 .5）同样按数字解析
+                self.scan_number()
+            } else if c.is_alphabetic() || c == '_' {
+                // 如果字符是字母或下划线，则调用 scan_identifier 方法解析标识符
+                self.scan_identifier()
+            } else if c == '"' {
+                self.scan_string()
+            } else {
+                // 如果字符不是数字，则调用 scan_operator 方法进行操作符解析
+                self.scan_operator().map(Lexeme::Symbol)
+            }
+        } else {
+            // 如果没有更多的标记，则返回 None，表示解析结束
+            None
+        }?;
+        let span = Span {
+            offset: start.1,
+            len: self.offset - start.1,
+        };
+        Some((lexeme, span))
+    }
+}
+
+// Tokenizer::lexemes 返回的迭代器，每个 Lexeme 附带它在输入中的位置
+pub struct Lexemes<'a> {
+    tokenizer: Tokenizer<'a>,
+}
+
+impl<'a> Iterator for Lexemes<'a> {
+    type Item = (Lexeme<'a>, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.tokenizer.next_lexeme()
+    }
+}
+
+// 实现Iterator trait
+// 每个解析项附带它在输入中的位置，标识符、字符串和自定义运算符转换为拥有所有权的 Token
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = (Token, Span);
+
+    // 定义一个方法 next，用于获取下一个解析项
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_lexeme()
+            .map(|(lexeme, span)| (lexeme.into(), span))
+    }
+}
+
+// 内置函数的类型：接收全部参数，返回计算结果
+type BuiltinFn = fn(&[f64]) -> Result<f64>;
+
+// 宿主程序注册的自定义函数，调用时优先于内置函数
+pub type UserFn = Box<dyn Fn(&[f64]) -> Result<f64>>;
+pub type UserFunctions = HashMap<String, UserFn>;
+
+// 求值结果和函数参数的值，字符串参数只能传给 FunctionProvider 提供的函数
+// 向量、矩阵、日期和时长由 evaluate_value 求值，矩阵按行保存，每行的长度相同
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<'a> {
+    Number(f64),
+    Text(&'a str),
+    Vector(Vec<f64>),
+    Matrix(Vec<Vec<f64>>),
+    Date(chrono::NaiveDateTime), // 日期，没有时区
+    Duration(chrono::TimeDelta), // 时长
+}
+
+// 宿主程序提供的函数，在会话中定义的函数、注册的自定义函数和内置函数都找不到时查询，
+// 例如由行情数据提供 price("MSFT")，不需要修改解析器
+pub trait FunctionProvider {
+    // 不认识 name 时返回 None，求值器报告未知函数；参数不对时返回 Some(Err(..))
+    fn call(&self, name: &str, args: &[Value]) -> Option<Result<f64>>;
+}
+
+// 宿主程序注册的自定义二元运算符
+struct BinaryOperator {
+    precedence: i32,
+    assoc: i32,
+    eval: Box<dyn Fn(f64, f64) -> Result<f64>>,
+}
+
+// 自定义运算符表，通过 Expr::with_operators 使用，例如把 `//` 注册为向下取整的除法、`√` 注册为前缀的平方根
+// 符号由标点组成（如 `//`、`±`）或者是一个单词（如 `div`），解析时优先匹配最长的符号
+// 优先级与内置运算符比较：`+`、`-` 为 1，`*`、`/` 为 2，`^` 为 3，不能低于 LOWEST_PRECEDENCE；
+// 前缀运算符与一元负号的优先级相同
+#[derive(Default)]
+pub struct OperatorTable {
+    binary: HashMap<String, BinaryOperator>,
+    prefix: HashMap<String, Box<dyn Fn(f64) -> Result<f64>>>,
+}
+
+impl OperatorTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 注册二元运算符，assoc 为 ASSOC_LEFT 或 ASSOC_RIGHT
+    pub fn binary(
+        mut self,
+        symbol: &str,
+        precedence: i32,
+        assoc: i32,
+        eval: impl Fn(f64, f64) -> Result<f64> + 'static,
+    ) -> Result<Self> {
+        Self::validate_symbol(symbol)?;
+        // 左结合运算符的右边从优先级加 1 开始解析，i32::MAX 会溢出
+        if !(LOWEST_PRECEDENCE..i32::MAX).contains(&precedence) {
+            return Err(ExpError::ParseError(format!(
+                "Precedence of operator '{}' must be between {} and {}",
+                symbol,
+                LOWEST_PRECEDENCE,
+                i32::MAX - 1
+            )));
+        }
+        if assoc != ASSOC_LEFT && assoc != ASSOC_RIGHT {
+            return Err(ExpError::ParseError(format!(
+                "Associativity of operator '{}' must be ASSOC_LEFT or ASSOC_RIGHT",
+                symbol
+            )));
+        }
+        let operator = BinaryOperator {
+            precedence,
+            assoc,
+            eval: Box::new(eval),
+        };
+        self.binary.insert(symbol.to_string(), operator);
+        Ok(self)
+    }
+
+    // 注册前缀一元运算符
+    pub fn prefix(
+        mut self,
+        symbol: &str,
+        eval: impl Fn(f64) -> Result<f64> + 'static,
+    ) -> Result<Self> {
+        Self::validate_symbol(symbol)?;
+        self.prefix.insert(symbol.to_string(), Box::new(eval));
+        Ok(self)
+    }
+
+    // 符号不能为空、不能包含空白、不能以数字或小数点开头，也不能与内置的运算符、函数和常量重名
+    fn validate_symbol(symbol: &str) -> Result<()> {
+        let invalid = |reason: &str| {
+            Err(ExpError::ParseError(format!(
+                "Invalid operator symbol '{}': {}",
+                symbol, reason
+            )))
+        };
+        let Some(first) = symbol.chars().next() else {
+            return invalid("empty symbol");
+        };
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        if symbol.chars().any(char::is_whitespace) {
+            return invalid("contains whitespace");
+        }
+        if first.is_numeric() || first == '.' {
+            return invalid("starts with a digit");
+        }
+        if is_word_char(first) != symbol.chars().all(is_word_char) {
+            return invalid("mixes letters and punctuation");
+        }
+        let tokens: Vec<Token> = Tokenizer::new(symbol).map(|(token, _)| token).collect();
+        let builtin = match tokens.as_slice() {
+            [Token::Ident(name)] => {
+                builtin_functions().contains_key(name.as_str())
+                    || random::is_random(name)
+                    || builtin_constant(name).is_some()
+            }
+            [Token::Unknown(_)] => false,
+            [_] => true,
+            _ => false,
+        };
+        if builtin {
+            return invalid("conflicts with a built-in operator, function or constant");
+        }
+        Ok(())
+    }
+
+    // 输入开头匹配的最长符号，单词符号必须匹配完整的单词（`divide` 不匹配 `div`）
+    fn longest_match(&self, input: &str) -> Option<&str> {
+        let is_word_char = |c: char| c.is_alphanumeric() || c == '_';
+        self.binary
+            .keys()
+            .chain(self.prefix.keys())
+            .filter(|symbol| {
+                let word = symbol.ends_with(is_word_char);
+                input
+                    .strip_prefix(symbol.as_str())
+                    .is_some_and(|rest| !(word && rest.starts_with(is_word_char)))
+            })
+            .max_by_key(|symbol| symbol.chars().count())
+            .map(String::as_str)
+    }
+
+    // 二元运算符的 (优先级, 结合性)
+    fn binary_entry(&self, symbol: &str) -> Option<(i32, i32)> {
+        self.binary
+            .get(symbol)
+            .map(|operator| (operator.precedence, operator.assoc))
+    }
+}
+
+// 求值上下文，保存赋值语句定义的变量，可在多次求值之间复用
+// 变量按作用域嵌套保存：没有局部作用域时读写全局变量，push_scope 之后的赋值只写入最内层的作用域，
+// pop_scope 时一起丢弃，不会泄漏到全局变量中
+#[derive(Debug, Default, Clone)]
+pub struct EvalContext {
+    variables: HashMap<String, f64>,             // 全局变量
+    tensors: HashMap<String, Value<'static>>,    // 值为向量、矩阵、日期或时长的全局变量
+    scopes: Vec<Scope>,                          // 局部作用域，最后一个是最内层
+    functions: HashMap<String, DefinedFunction>, // `f(x) = ...` 定义的函数
+    rng: random::Rng,                            // rand() 等随机数函数使用的生成器
+}
+
+// 一层局部作用域
+#[derive(Debug, Default, Clone)]
+struct Scope {
+    variables: HashMap<String, f64>,
+    tensors: HashMap<String, Value<'static>>,
+    function: bool, // 定义函数的调用：函数体只能看到自己的参数和全局变量，看不到调用者的局部变量
+}
+
+// 用户在会话中定义的函数：参数名和函数体
+#[derive(Debug, Clone)]
+struct DefinedFunction {
+    params: Vec<String>,
+    body: Ast,
+}
+
+impl EvalContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 使用固定的随机种子，随机数函数的结果可以复现
+    pub fn with_seed(seed: u64) -> Self {
+        let mut context = Self::default();
+        context.seed(seed);
+        context
+    }
+
+    // 重新设置随机种子
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = random::Rng::seeded(seed);
+    }
+
+    // 数值变量的值，变量不存在或者是向量、矩阵时返回 None
+    pub fn get(&self, name: &str) -> Option<f64> {
+        match self.get_value(name)? {
+            Value::Number(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    // 从最内层的作用域向外查找，遇到函数调用的作用域后直接查找全局变量
+    pub fn get_value(&self, name: &str) -> Option<Value<'static>> {
+        let lookup = |variables: &HashMap<String, f64>,
+                      tensors: &HashMap<String, Value<'static>>| {
+            variables
+                .get(name)
+                .map(|value| Value::Number(*value))
+                .or_else(|| tensors.get(name).cloned())
+        };
+        for scope in self.scopes.iter().rev() {
+            if let Some(value) = lookup(&scope.variables, &scope.tensors) {
+                return Some(value);
+            }
+            if scope.function {
+                break;
+            }
+        }
+        lookup(&self.variables, &self.tensors)
+    }
+
+    // 写入最内层的作用域，没有局部作用域时写入全局变量
+    pub fn set(&mut self, name: &str, value: f64) {
+        self.set_value(name, Value::Number(value));
+    }
+
+    // 与 set 相同，值可以是向量或矩阵；字符串不能保存为变量，会被忽略
+    pub fn set_value(&mut self, name: &str, value: Value) {
+        let (variables, tensors) = match self.scopes.last_mut() {
+            Some(scope) => (&mut scope.variables, &mut scope.tensors),
+            None => (&mut self.variables, &mut self.tensors),
+        };
+        // 同一个作用域中一个名字只对应一个值
+        variables.remove(name);
+        tensors.remove(name);
+        match value {
+            Value::Number(value) => {
+                variables.insert(name.to_string(), value);
+            }
+            Value::Vector(items) => {
+                tensors.insert(name.to_string(), Value::Vector(items));
+            }
+            Value::Matrix(rows) => {
+                tensors.insert(name.to_string(), Value::Matrix(rows));
+            }
+            Value::Date(date) => {
+                tensors.insert(name.to_string(), Value::Date(date));
+            }
+            Value::Duration(duration) => {
+                tensors.insert(name.to_string(), Value::Duration(duration));
+            }
+            Value::Text(_) => {}
+        }
+    }
+
+    // 进入一层局部作用域，可以读取外层作用域的变量
+    pub fn push_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    // 离开最内层的作用域，丢弃其中的变量；没有局部作用域时返回 false
+    pub fn pop_scope(&mut self) -> bool {
+        self.scopes.pop().is_some()
+    }
+
+    // 当前局部作用域的层数，0 表示在全局作用域
+    pub fn scope_depth(&self) -> usize {
+        self.scopes.len()
+    }
+
+    // 进入定义函数调用的作用域，由 pop_scope 离开
+    fn push_function_scope(&mut self) {
+        self.scopes.push(Scope {
+            function: true,
+            ..Scope::default()
+        });
+    }
+
+    fn define(&mut self, name: &str, params: Vec<String>, body: Ast) {
+        self.functions
+            .insert(name.to_string(), DefinedFunction { params, body });
+    }
+
+    fn function(&self, name: &str) -> Option<&DefinedFunction> {
+        self.functions.get(name)
+    }
+
+    // 清空所有变量、作用域和定义的函数
+    fn clear(&mut self) {
+        self.variables.clear();
+        self.tensors.clear();
+        self.scopes.clear();
+        self.functions.clear();
+    }
+
+    // 按变量名排序的全部全局变量，包括值为向量和矩阵的变量
+    fn sorted_variables(&self) -> Vec<(&str, Value<'static>)> {
+        let mut variables: Vec<(&str, Value<'static>)> = self
+            .variables
+            .iter()
+            .map(|(name, value)| (name.as_str(), Value::Number(*value)))
+            .chain(
+                self.tensors
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.clone())),
+            )
+            .collect();
+        variables.sort_by(|a, b| a.0.cmp(b.0));
+        variables
+    }
+}
+
+// 计算阶乘，只接受非负整数，0! = 1
+fn factorial(n: f64) -> Result<f64> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(ExpError::ParseError(format!(
+            "factorial requires a non-negative integer, got {}",
+            n
+        )));
+    }
+    // 超过 170! 的结果为 inf，由溢出检查决定是否报错，也避免对极大的 n 逐项相乘
+    if n > 170.0 {
+        return Ok(f64::INFINITY);
+    }
+    Ok((1..=n as u64).map(|i| i as f64).product())
+}
+
+// 可变参数函数至少需要一个参数
+fn require_args<'a>(name: &str, args: &'a [f64]) -> Result<&'a [f64]> {
+    if args.is_empty() {
+        Err(ExpError::ParseError(format!(
+            "{}() requires at least one argument",
+            name
+        )))
+    } else {
+        Ok(args)
+    }
+}
+
+// 不求值地检查内置函数和向量函数的参数个数，返回求值时会得到的错误信息
+// 用个数相同的 0 调用内置函数表中的函数，这些函数只在参数个数不对时出错；
+// 汇总函数的参数可以是向量，只检查是否有参数
+fn builtin_arity_error(name: &str, args: &[Ast]) -> Option<String> {
+    if series_notation(name, args).is_some() {
+        return None;
+    }
+    let result = if tensor::TENSOR_FUNCTIONS.contains(&name) {
+        tensor::check_arity(name, args.len()).map(|_| 0.0)
+    } else if stats::AGGREGATE_FUNCTIONS.contains(&name) && !args.is_empty() {
+        return None;
+    } else {
+        builtin_functions().get(name)?(&vec![0.0; args.len()])
+    };
+    match result {
+        Err(ExpError::ParseError(message)) => Some(message),
+        _ => None,
+    }
+}
+
+// 内置常量的名字和值
+const BUILTIN_CONSTANTS: [(&str, f64); 4] = [
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("tau", std::f64::consts::TAU),
+    ("inf", f64::INFINITY),
+];
+
+// 内置常量，上下文中的同名变量优先
+fn builtin_constant(name: &str) -> Option<f64> {
+    BUILTIN_CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| *value)
+}
+
+// 检查只接受一个参数的函数，返回该参数
+fn single_arg(name: &str, args: &[f64]) -> Result<f64> {
+    match args {
+        [x] => Ok(*x),
+        _ => Err(ExpError::ParseError(format!(
+            "{}() takes exactly one argument, got {}",
+            name,
+            args.len()
+        ))),
+    }
+}
+
+// 内置函数表，以函数名为键，新增函数时只需要在这里注册
+fn builtin_functions() -> HashMap<&'static str, BuiltinFn> {
+    let mut functions: HashMap<&'static str, BuiltinFn> = HashMap::new();
+    functions.insert("sum", |args| Ok(require_args("sum", args)?.iter().sum()));
+    functions.insert("avg", |args| {
+        let args = require_args("avg", args)?;
+        Ok(args.iter().sum::<f64>() / args.len() as f64)
+    });
+    functions.insert("min", |args| {
+        Ok(require_args("min", args)?
+            .iter()
+            .cloned()
+            .fold(f64::INFINITY, f64::min))
+    });
+    functions.insert("max", |args| {
+        Ok(require_args("max", args)?
+            .iter()
+            .cloned()
+            .fold(f64::NEG_INFINITY, f64::max))
+    });
+    stats::register(&mut functions);
+
+    // 单参数数学函数，三角函数使用弧度
+    functions.insert("sqrt", |args| Ok(single_arg("sqrt", args)?.sqrt()));
+    functions.insert("sin", |args| Ok(single_arg("sin", args)?.sin()));
+    functions.insert("cos", |args| Ok(single_arg("cos", args)?.cos()));
+    functions.insert("tan", |args| Ok(single_arg("tan", args)?.tan()));
+    functions.insert("asin", |args| Ok(single_arg("asin", args)?.asin()));
+    functions.insert("acos", |args| Ok(single_arg("acos", args)?.acos()));
+    functions.insert("atan", |args| Ok(single_arg("atan", args)?.atan()));
+    functions.insert("exp", |args| Ok(single_arg("exp", args)?.exp()));
+    functions.insert("ln", |args| Ok(single_arg("ln", args)?.ln()));
+    functions.insert("abs", |args| Ok(single_arg("abs", args)?.abs()));
+    functions.insert("floor", |args| Ok(single_arg("floor", args)?.floor()));
+    functions.insert("ceil", |args| Ok(single_arg("ceil", args)?.ceil()));
+    functions.insert("round", |args| Ok(single_arg("round", args)?.round()));
+    // log(x) 为常用对数，log(x, base) 为以 base 为底的对数
+    functions.insert("log", |args| match args {
+        [x] => Ok(x.log10()),
+        [x, base] => Ok(x.log(*base)),
+        _ => Err(ExpError::ParseError(format!(
+            "log() takes one or two arguments, got {}",
+            args.len()
+        ))),
+    });
+    functions
+}
+
+// 表达式语法树，由 Expr::parse 生成，调用方可以在求值前检查或变换
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Ast {
+    Num(f64),
+    Var(String),                           // 变量或内置常量
+    Str(String),                           // 字符串，只能作为函数参数
+    BinOp(Token, Box<Ast>, Box<Ast>),      // 二元运算：运算符、左操作数、右操作数
+    UnaryOp(Token, Box<Ast>),              // 一元正负号（Plus/Minus）和后缀阶乘（Factorial）
+    Call(String, Vec<Ast>),                // 函数调用
+    Assign(String, Box<Ast>),              // 赋值语句
+    Seq(Vec<Ast>),                         // 用 `;` 分隔的多条语句，值为最后一条语句的值
+    Cond(Box<Ast>, Box<Ast>, Box<Ast>),    // 条件表达式：条件、条件非0时的值、条件为0时的值
+    Define(String, Vec<String>, Box<Ast>), // 函数定义：函数名、参数名、函数体
+    List(Vec<Ast>),                        // 方括号中的向量，元素都是向量时为矩阵的各行
+}
+
+// 语法树可能是很长的运算链（如 1+1+...+1），默认的逐层递归析构会导致栈溢出，这里改为迭代析构
+impl Drop for Ast {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        take_children(self, &mut pending);
+        while let Some(mut node) = pending.pop() {
+            take_children(&mut node, &mut pending);
+        }
+    }
+}
+
+// 取出节点的所有子节点，留下不含子节点的占位节点
+fn take_children(ast: &mut Ast, pending: &mut Vec<Ast>) {
+    match ast {
+        Ast::BinOp(_, lhs, rhs) => {
+            pending.push(std::mem::replace(&mut **lhs, Ast::Num(0.0)));
+            pending.push(std::mem::replace(&mut **rhs, Ast::Num(0.0)));
+        }
+        Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) | Ast::Define(_, _, operand) => {
+            pending.push(std::mem::replace(&mut **operand, Ast::Num(0.0)));
+        }
+        Ast::Call(_, children) | Ast::Seq(children) | Ast::List(children) => {
+            pending.append(children)
+        }
+        Ast::Cond(cond, then, otherwise) => {
+            pending.push(std::mem::replace(&mut **cond, Ast::Num(0.0)));
+            pending.push(std::mem::replace(&mut **then, Ast::Num(0.0)));
+            pending.push(std::mem::replace(&mut **otherwise, Ast::Num(0.0)));
+        }
+        Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => {}
+    }
+}
+
+// 语法树求值器，保存求值时用到的函数表、变量上下文和选项
+struct Evaluator<'a> {
+    functions: HashMap<&'static str, BuiltinFn>, // 可调用的函数表
+    user_functions: Option<&'a UserFunctions>,   // 宿主程序注册的自定义函数
+    provider: Option<&'a dyn FunctionProvider>,  // 其他函数都找不到时查询的函数来源
+    operators: Option<&'a OperatorTable>,        // 宿主程序注册的自定义运算符
+    context: Option<&'a mut EvalContext>,        // 变量上下文，没有时不支持赋值
+    check_overflow: bool,                        // 是否把有限输入得到的 inf/NaN 当作错误
+    integer_mode: bool,                          // 整数模式：只接受整数，除法向零取整
+    call_depth: usize,                           // 当前定义函数的调用深度
+    precision: Precision,                        // 数值后端
+    memo: Option<memo::Memo>,                    // 记忆化求值时已经计算过的子表达式
+    epsilon: f64,                                // `==`、`!=` 比较时使用的容差
+    series_terms: f64,                           // 本次求值中求和、求积记号已经计算的项数
+}
+
+// 求值使用的数值后端
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Precision {
+    #[default]
+    Float, // f64 浮点数，0.1 + 0.2 不等于 0.3
+    // 任意精度十进制（需要 `arbitrary-precision` feature），加减乘除和整数次幂精确计算
+    #[cfg(feature = "arbitrary-precision")]
+    Arbitrary,
+}
+
+// 定义函数的最大递归调用深度
+const MAX_CALL_DEPTH: usize = 200;
+
+// 一次求值中求和与求积记号最多计算的项数，嵌套的记号共用这一额度，
+// 防止 sum(i, 1, 1e12, i) 或 sum(i, 1, 1e6, sum(j, 1, 1e6, i + j)) 这样的输入长时间运行
+const MAX_SERIES_TERMS: f64 = 1_000_000.0;
+
+// 识别求和与求积记号 sum(i, a, b, body)、prod(i, a, b, body)，返回下标变量、上下界和通项
+// 只按语法判断：四个参数且第一个参数是变量名，如 sum(i, 1, 10, 2) 等于 20；
+// 需要对以变量开头的四个数求和时写成 sum(+x, 1, 2, 3)
+fn series_notation<'a>(
+    name: &str,
+    args: &'a [Ast],
+) -> Option<(&'a str, &'a Ast, &'a Ast, &'a Ast)> {
+    match (name, args) {
+        ("sum" | "prod", [Ast::Var(index), from, to, body]) => Some((index, from, to, body)),
+        _ => None,
+    }
+}
+
+impl<'a> Evaluator<'a> {
+    fn new() -> Self {
+        Evaluator {
+            functions: builtin_functions(),
+            user_functions: None,
+            provider: None,
+            operators: None,
+            context: None,
+            check_overflow: false,
+            integer_mode: false,
+            call_depth: 0,
+            precision: Precision::default(),
+            memo: None,
+            epsilon: DEFAULT_EPSILON,
+            series_terms: 0.0,
+        }
+    }
+
+    // 计算语法树的值
+    // 计算语法树的值，开启记忆化时重复的子表达式直接使用之前的结果
+    fn eval(&mut self, ast: &Ast) -> Result<f64> {
+        let id = match self.memoized(ast) {
+            Some((_, Some(value))) => return Ok(value),
+            Some((id, None)) => Some(id),
+            None => None,
+        };
+        let value = self.eval_node(ast)?;
+        self.remember(ast, id, value);
+        Ok(value)
+    }
+
+    fn eval_node(&mut self, ast: &Ast) -> Result<f64> {
+        match ast {
+            Ast::Num(n) if self.integer_mode && n.fract() != 0.0 => Err(ExpError::NotInteger(
+                format!("decimal literal {} in integer mode", n),
+            )),
+            Ast::Num(n) => Ok(*n),
+            Ast::Var(name) => self.variable(name),
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                let mut value = self.eval(node)?;
+                for (op, rhs) in spine {
+                    let rhs = self.eval(rhs)?;
+                    value = self.compute_binary(op, value, rhs)?;
+                }
+                Ok(value)
+            }
+            Ast::UnaryOp(op, operand) => {
+                let value = self.eval(operand)?;
+                self.compute_unary(op, value)
+            }
+            // 会话中定义的或宿主注册的同名函数优先于求和、求积记号
+            Ast::Call(name, args)
+                if series_notation(name, args).is_some() && !self.overridden(name) =>
+            {
+                let (index, from, to, body) = series_notation(name, args).unwrap();
+                self.eval_series(name, index, from, to, body)
+            }
+            // 用到向量或矩阵的函数调用和语句序列，如 dot(u, v)、`v = [1, 2]; dot(v, v)`，结果必须是数
+            Ast::List(_) => self.eval_number(ast),
+            Ast::Call(..) | Ast::Seq(_) if self.involves_tensor(ast) => self.eval_number(ast),
+            // 带字符串参数的调用只能交给 FunctionProvider，其他函数只接受数字
+            Ast::Call(name, args) if args.iter().any(|arg| matches!(arg, Ast::Str(_))) => {
+                let known = self.functions.contains_key(name.as_str())
+                    || random::is_random(name)
+                    || self.user_functions.is_some_and(|f| f.contains_key(name))
+                    || self
+                        .context
+                        .as_deref()
+                        .is_some_and(|context| context.function(name).is_some());
+                if known {
+                    return Err(ExpError::ParseError(format!(
+                        "{}() does not accept string arguments",
+                        name
+                    )));
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Ast::Str(text) => Ok(Value::Text(text)),
+                        arg => self.eval(arg).map(Value::Number),
+                    })
+                    .collect::<Result<Vec<Value>>>()?;
+                self.call_provider(name, &args)
+            }
+            Ast::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<Vec<f64>>>()?;
+                // 会话中定义的函数优先于宿主注册的函数和内置函数
+                let defined = self
+                    .context
+                    .as_deref()
+                    .and_then(|context| context.function(name))
+                    .cloned();
+                let overridden = self.user_functions.is_some_and(|f| f.contains_key(name));
+                match defined {
+                    Some(function) => self.call_defined(name, &function, &args),
+                    None if random::is_random(name) && !overridden => self.call_random(name, &args),
+                    None => self.call(name, &args),
+                }
+            }
+            Ast::Str(text) => Err(ExpError::ParseError(format!(
+                "String \"{}\" can only be used as a function argument",
+                text
+            ))),
+            // 函数定义保存到上下文中，值为 0
+            Ast::Define(name, params, body) => match self.context.as_deref_mut() {
+                Some(context) => {
+                    context.define(name, params.clone(), (**body).clone());
+                    Ok(0.0)
+                }
+                None => Err(ExpError::ParseError(
+                    "Function definition requires an EvalContext".to_string(),
+                )),
+            },
+            Ast::Assign(name, value) => {
+                let value = self.eval(value)?;
+                match self.context.as_deref_mut() {
+                    Some(context) => context.set(name, value),
+                    None => {
+                        return Err(ExpError::ParseError(
+                            "Assignment requires an EvalContext".to_string(),
+                        ))
+                    }
+                }
+                Ok(value)
+            }
+            // 只计算被选中的分支，另一个分支中的错误（如整数模式下除以0）不会影响结果
+            Ast::Cond(cond, then, otherwise) => {
+                if self.eval(cond)? != 0.0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Ast::Seq(statements) => {
+                let mut value = Err(ExpError::ParseError("Empty expression".to_string()));
+                for statement in statements {
+                    value = Ok(self.eval(statement)?);
+                }
+                value
+            }
+        }
+    }
+
+    // 计算一次一元运算
+    // 单独放在这里而不是 eval_node 中，避免增大递归求值时每一层的栈帧
+    #[inline(never)]
+    fn compute_unary(&self, op: &Token, value: f64) -> Result<f64> {
+        match op {
+            // -(-2^63) 超出 i64 的范围
+            Token::Minus if self.integer_mode => check_integer_range(-value),
+            Token::Minus => Ok(-value),
+            Token::BitNot if self.integer_mode => integer_result(Some(!bitwise_operand(value)?)),
+            Token::BitNot => Err(bitwise_requires_integer_mode(op)),
+            Token::Factorial if self.integer_mode => check_integer_range(factorial(value)?),
+            Token::Factorial => self.check_finite(&[value], factorial(value)?),
+            Token::Operator(symbol) => self.compute_custom(symbol, &[value]),
+            _ => Ok(value),
+        }
+    }
+
+    // 计算一次二元运算
+    fn compute_binary(&self, token: &Token, left: f64, right: f64) -> Result<f64> {
+        if let Token::Operator(symbol) = token {
+            return self.compute_custom(symbol, &[left, right]);
+        }
+        let result = if self.integer_mode {
+            // 整数模式下按整数规则计算
+            self.compute_integer(token, left, right)?
+        } else if token.is_bitwise() {
+            return Err(bitwise_requires_integer_mode(token));
+        } else {
+            token
+                .compute_within(left, right, self.epsilon)
+                .ok_or_else(|| ExpError::ParseError("Unexpected expr".into()))?
+        };
+        self.check_finite(&[left, right], result)
+    }
+
+    // 整数模式下的二元运算，操作数转换为 i64 后用 checked_* 计算
+    // 除以0、负数次幂和超出 i64 范围的结果返回 ExpError::MathError，而不是得到 inf 或不精确的值
+    fn compute_integer(&self, token: &Token, left: f64, right: f64) -> Result<f64> {
+        let (a, b) = if token.is_bitwise() {
+            (bitwise_operand(left)?, bitwise_operand(right)?)
+        } else {
+            (to_integer(left)?, to_integer(right)?)
+        };
+        let result = match token {
+            Token::Divide | Token::Modulo if b == 0 => {
+                return Err(ExpError::MathError(MathError::DivisionByZero))
+            }
+            Token::Plus => a.checked_add(b),
+            Token::Minus => a.checked_sub(b),
+            Token::Multiply => a.checked_mul(b),
+            // 向零取整
+            Token::Divide => a.checked_div(b),
+            Token::Modulo => a.checked_rem(b),
+            Token::Power if b < 0 => return Err(ExpError::MathError(MathError::NegativeExponent)),
+            Token::Power => match u32::try_from(b) {
+                Ok(b) => a.checked_pow(b),
+                // 指数超出 u32 时只有 0、1、-1 的幂不溢出，结果只取决于指数的奇偶
+                Err(_) if a.abs() <= 1 => a.checked_pow(2 + (b % 2) as u32),
+                Err(_) => None,
+            },
+            Token::BitAnd => Some(a & b),
+            Token::BitOr => Some(a | b),
+            Token::Xor => Some(a ^ b),
+            Token::ShiftLeft | Token::ShiftRight if !(0..64).contains(&b) => {
+                return Err(ExpError::ParseError(format!(
+                    "Invalid shift amount: {}",
+                    right
+                )))
+            }
+            // 移出了有效的位（包括符号位）时溢出
+            Token::ShiftLeft => Some(a << b).filter(|result| result >> b == a),
+            Token::ShiftRight => Some(a >> b),
+            // 比较和逻辑运算的结果是 0 或 1
+            _ => {
+                return token
+                    .compute(left, right)
+                    .ok_or_else(|| ExpError::ParseError("Unexpected expr".into()))
+            }
+        };
+        integer_result(result)
+    }
+
+    // 计算自定义运算符，args 有两个值时为二元运算，一个值时为前缀运算
+    fn compute_custom(&self, symbol: &str, args: &[f64]) -> Result<f64> {
+        let operators = self.operators;
+        let result = match args {
+            [left, right] => operators
+                .and_then(|operators| operators.binary.get(symbol))
+                .map(|operator| (operator.eval)(*left, *right)),
+            [operand] => operators
+                .and_then(|operators| operators.prefix.get(symbol))
+                .map(|eval| eval(*operand)),
+            _ => None,
+        }
+        .ok_or_else(|| ExpError::ParseError(format!("Unknown operator: {}", symbol)))??;
+        if self.integer_mode {
+            check_integer_range(result)
+        } else {
+            self.check_finite(args, result)
+        }
+    }
+
+    // 调用函数：先查找自定义函数，再使用内置函数，都找不到时交给 FunctionProvider
+    fn call(&self, name: &str, args: &[f64]) -> Result<f64> {
+        let result = if let Some(function) = self.user_functions.and_then(|f| f.get(name)) {
+            function(args)?
+        } else if let Some(function) = self.functions.get(name) {
+            function(args)?
+        } else {
+            let args: Vec<Value> = args.iter().copied().map(Value::Number).collect();
+            return self.call_provider(name, &args);
+        };
+        if self.integer_mode {
+            return check_integer_function(name, result);
+        }
+        self.check_finite(args, result)
+    }
+
+    // 调用随机数函数，需要上下文中的生成器
+    fn call_random(&mut self, name: &str, args: &[f64]) -> Result<f64> {
+        let result = match self.context.as_deref_mut() {
+            Some(context) => context.rng.call(name, args)?,
+            None => {
+                return Err(ExpError::ParseError(format!(
+                    "{}() requires an EvalContext",
+                    name
+                )))
+            }
+        };
+        if self.integer_mode {
+            return check_integer_function(name, result);
+        }
+        Ok(result)
+    }
+
+    // 调用 FunctionProvider 提供的函数，带字符串参数的调用只会到这里
+    fn call_provider(&self, name: &str, args: &[Value]) -> Result<f64> {
+        let result = self
+            .provider
+            .and_then(|provider| provider.call(name, args))
+            .ok_or_else(|| ExpError::ParseError(format!("Unknown function: {}", name)))??;
+        if self.integer_mode {
+            return check_integer_function(name, result);
+        }
+        let numbers: Vec<f64> = args
+            .iter()
+            .filter_map(|arg| match arg {
+                Value::Number(n) => Some(*n),
+                _ => None,
+            })
+            .collect();
+        self.check_finite(&numbers, result)
+    }
+
+    // 是否有会话中定义的或宿主注册的同名函数
+    fn overridden(&self, name: &str) -> bool {
+        self.user_functions.is_some_and(|f| f.contains_key(name))
+            || self
+                .context
+                .as_deref()
+                .is_some_and(|context| context.function(name).is_some())
+    }
+
+    // 计算求和或求积：下标变量从 from 到 to 逐个绑定在局部作用域中，上界小于下界时求和为 0、求积为 1
+    fn eval_series(
+        &mut self,
+        name: &str,
+        index: &str,
+        from: &Ast,
+        to: &Ast,
+        body: &Ast,
+    ) -> Result<f64> {
+        let (from, to) = (self.eval(from)?, self.eval(to)?);
+        if from.fract() != 0.0 || to.fract() != 0.0 {
+            return Err(ExpError::ParseError(format!(
+                "{}() bounds must be integers, got {} and {}",
+                name, from, to
+            )));
+        }
+        // 在计算之前扣除全部项数，嵌套的记号从剩余的额度中扣除
+        let count = (to - from + 1.0).max(0.0);
+        self.series_terms += count;
+        if self.series_terms > MAX_SERIES_TERMS {
+            return Err(ExpError::ParseError(format!(
+                "{}() exceeds the maximum of {} terms",
+                name, MAX_SERIES_TERMS
+            )));
+        }
+        let Some(context) = self.context.as_deref_mut() else {
+            return Err(ExpError::ParseError(format!(
+                "{}() notation requires an EvalContext",
+                name
+            )));
+        };
+        context.push_scope();
+        let empty = if name == "sum" { 0.0 } else { 1.0 };
+        let result = (0..count as u64).try_fold(empty, |acc, k| {
+            self.context
+                .as_deref_mut()
+                .unwrap()
+                .set(index, from + k as f64);
+            let term = self.eval(body)?;
+            Ok(if name == "sum" {
+                acc + term
+            } else {
+                acc * term
+            })
+        });
+        // 出错时同样离开局部作用域
+        self.context.as_deref_mut().unwrap().pop_scope();
+        self.check_finite(&[from, to], result?)
+    }
+
+    // 调用会话中定义的函数：参数绑定在新的函数作用域中，计算完函数体后离开该作用域
+    // 递归调用超过 MAX_CALL_DEPTH 层时返回错误，防止无限递归导致栈溢出
+    fn call_defined(
+        &mut self,
+        name: &str,
+        function: &DefinedFunction,
+        args: &[f64],
+    ) -> Result<f64> {
+        if args.len() != function.params.len() {
+            return Err(ExpError::ParseError(format!(
+                "{}() takes {} argument(s), got {}",
+                name,
+                function.params.len(),
+                args.len()
+            )));
+        }
+        if self.call_depth >= MAX_CALL_DEPTH {
+            return Err(ExpError::ParseError(format!(
+                "Maximum recursion depth exceeded in {}()",
+                name
+            )));
+        }
+        // 调用者已经确认存在上下文
+        let context = self.context.as_deref_mut().unwrap();
+        context.push_function_scope();
+        for (param, value) in function.params.iter().zip(args) {
+            context.set(param, *value);
+        }
+
+        self.call_depth += 1;
+        let result = self.eval(&function.body);
+        self.call_depth -= 1;
+
+        self.context.as_deref_mut().unwrap().pop_scope();
+        result
+    }
+
+    // 检查一次计算的结果，只有输入全部有限而结果不是有限值时才算溢出
+    fn check_finite(&self, inputs: &[f64], result: f64) -> Result<f64> {
+        if self.check_overflow && !result.is_finite() && inputs.iter().all(|v| v.is_finite()) {
+            Err(ExpError::Overflow)
+        } else {
+            Ok(result)
+        }
+    }
+}
+
+// i64 能表示的范围 [-2^63, 2^63)，两端都可以用 f64 精确表示
+const I64_RANGE: std::ops::Range<f64> = -9_223_372_036_854_775_808.0..9_223_372_036_854_775_808.0;
+
+// 整数模式下的结果必须在 i64 的范围内，-2^63 本身是合法的
+fn check_integer_range(result: f64) -> Result<f64> {
+    if I64_RANGE.contains(&result) {
+        Ok(result)
+    } else {
+        Err(ExpError::MathError(MathError::Overflow))
+    }
+}
+
+// 整数模式下函数的结果与运算符的结果一样必须是 i64 范围内的整数，如 sqrt(2)、avg(1, 2) 都是错误
+fn check_integer_function(name: &str, result: f64) -> Result<f64> {
+    if result.fract() != 0.0 && result.is_finite() {
+        return Err(ExpError::NotInteger(format!(
+            "{}() returned {}, which is not an integer",
+            name, result
+        )));
+    }
+    check_integer_range(result)
+}
+
+// 整数模式下的操作数转换为 i64
+fn to_integer(value: f64) -> Result<i64> {
+    if value.fract() != 0.0 && value.is_finite() {
+        return Err(ExpError::NotInteger(format!("{} is not an integer", value)));
+    }
+    check_integer_range(value)?;
+    Ok(value as i64)
+}
+
+// f64 能精确表示所有绝对值不超过它的整数，更大的数可能已经被舍入，如 9007199254740993 读入后是 2^53
+const MAX_EXACT_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+// 位运算的操作数按位计算，必须是能精确表示的整数
+fn bitwise_operand(value: f64) -> Result<i64> {
+    let value = to_integer(value)?;
+    if value.unsigned_abs() as f64 > MAX_EXACT_INTEGER {
+        return Err(ExpError::NotInteger(format!(
+            "bitwise operand {} is not exactly representable",
+            value
+        )));
+    }
+    Ok(value)
+}
+
+// i64 的计算结果转换回 f64，溢出或者转换后不相等（超过 2^53 的奇数等）时返回 MathError
+fn integer_result(result: Option<i64>) -> Result<f64> {
+    match result {
+        Some(value) if value as f64 as i128 == value as i128 => Ok(value as f64),
+        _ => Err(ExpError::MathError(MathError::Overflow)),
+    }
+}
+
+// 浮点数模式下使用位运算符时的错误
+fn bitwise_requires_integer_mode(token: &Token) -> ExpError {
+    ExpError::ParseError(format!("Operator {} requires integer mode", token))
+}
+
+// 使用默认的内置函数计算语法树的值，没有变量上下文
+pub fn eval(ast: &Ast) -> Result<f64> {
+    Evaluator::new().eval(ast)
+}
+
+// 默认允许的最大括号嵌套深度，防止恶意输入导致栈溢出
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+pub struct Expr<'a> {
+    iter: Peekable<Lexemes<'a>>,
+    format: NumberFormat,        // 小数点和参数分隔符，重新创建 Tokenizer 时使用
+    source: &'a str,             // 原始输入，用于错误信息
+    last_span: Span,             // 最近一次取出的 Token 的位置
+    depth: usize,                // 当前括号嵌套深度
+    max_depth: usize,            // 允许的最大嵌套深度
+    consumed: usize,             // 已经消耗的 Token 数量
+    precedence: PrecedenceTable, // 运算符优先级表
+    power_semantics: PowerSemantics, // 一元负号与 ^ 的结合方式
+    strategy: ParseStrategy,     // parse 使用的解析方式
+    statement_start: usize,      // 当前语句第一个 Token 的位置，赋值只能出现在语句开头
+    implicit_multiplication: bool, // 是否允许省略乘号，如 2(3+4)、3x
+    after_operand: bool,         // 最近一次取出的 Token 是数字或 `)`
+    recovering: bool,            // 恢复模式：遇到语法错误时记录下来并继续解析
+    memoize: bool,               // 求值时是否记忆化重复的子表达式
+    check_arity: bool,           // 解析时检查内置函数的参数个数，只在 Expr::check 中使用
+    locale: Locale,              // eval 返回的错误信息使用的语言
+    display: DisplayFormat,      // eval_formatted 输出结果的格式
+    diagnostics: Vec<Diagnostic>, // 恢复模式下收集到的语法错误
+    evaluator: Evaluator<'a>,    // eval 时使用的求值器
+}
+
+impl<'a> Expr<'a> {
+    // 创建一个新的表达式实例
+    pub fn new(input: &'a str) -> Self {
+        Self::build(input, NumberFormat::default())
+    }
+
+    // 使用指定的数字格式创建表达式，小数点和参数分隔符冲突时返回错误
+    pub fn with_format(input: &'a str, format: NumberFormat) -> Result<Self> {
+        format.validate()?;
+        Ok(Self::build(input, format))
+    }
+
+    fn build(input: &'a str, format: NumberFormat) -> Self {
+        Expr {
+            // 使用Tokenizer将输入字符串转换为Token迭代器，并使用peekable以便可以预览下一个Token
+            iter: Tokenizer::with_format(input, format).lexemes().peekable(),
+            format,
+            source: input,
+            last_span: Span { offset: 0, len: 0 },
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            consumed: 0,
+            precedence: PrecedenceTable::default(),
+            power_semantics: PowerSemantics::default(),
+            strategy: ParseStrategy::default(),
+            statement_start: 0,
+            implicit_multiplication: false,
+            after_operand: false,
+            recovering: false,
+            memoize: false,
+            check_arity: false,
+            locale: Locale::default(),
+            display: DisplayFormat::default(),
+            diagnostics: Vec::new(),
+            evaluator: Evaluator::new(),
+        }
+    }
+
+    // 使用变量上下文，表达式中的变量从中读取，赋值语句写入其中
+    pub fn with_context(mut self, context: &'a mut EvalContext) -> Self {
+        self.evaluator.context = Some(context);
+        self
+    }
+
+    // 使用自定义的运算符优先级表
+    pub fn with_precedence(mut self, precedence: PrecedenceTable) -> Self {
+        self.precedence = precedence;
+        self
+    }
+
+    // 选择解析方式，默认为递归下降
+    pub fn with_strategy(mut self, strategy: ParseStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // 设置 `==`、`!=` 比较时使用的容差，默认为 DEFAULT_EPSILON
+    pub fn with_epsilon(mut self, epsilon: f64) -> Self {
+        self.evaluator.epsilon = epsilon;
+        self
+    }
+
+    // 选择一元负号与 ^ 的结合方式，PowerSemantics::Excel 同时把 ^ 改为左结合
+    pub fn with_power_semantics(mut self, semantics: PowerSemantics) -> Self {
+        let power = Token::Power.to_string();
+        let prec = self
+            .precedence
+            .get(&Token::Power)
+            .map_or(Token::Power.precedence(), |(prec, _)| prec);
+        let assoc = match semantics {
+            PowerSemantics::Math => ASSOC_RIGHT,
+            PowerSemantics::Excel => ASSOC_LEFT,
+        };
+        self.precedence = std::mem::take(&mut self.precedence).set(power, prec, assoc);
+        self.power_semantics = semantics;
+        self
+    }
+
+    // 使用自定义运算符表，解析时识别其中的符号，求值时调用注册的函数
+    pub fn with_operators(mut self, operators: &'a OperatorTable) -> Self {
+        self.iter = Tokenizer::with_format(self.source, self.format)
+            .with_operators(operators)
+            .lexemes()
+            .peekable();
+        self.evaluator.operators = Some(operators);
+        self
+    }
+
+    // 使用宿主程序提供的函数，只在其他函数都找不到时查询
+    pub fn with_function_provider(mut self, provider: &'a dyn FunctionProvider) -> Self {
+        self.evaluator.provider = Some(provider);
+        self
+    }
+
+    // 注册自定义函数表，同名时覆盖内置函数
+    pub fn with_user_functions(mut self, functions: &'a UserFunctions) -> Self {
+        self.evaluator.user_functions = Some(functions);
+        self
+    }
+
+    // 设置最大嵌套深度，超过该深度时返回错误而不是继续递归
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    // 开启溢出检查：有限的输入计算出 inf 或 NaN 时返回 ExpError::Overflow，
+    // 避免 inf 在后续计算中继续传播
+    pub fn with_overflow_check(mut self, check_overflow: bool) -> Self {
+        self.evaluator.check_overflow = check_overflow;
+        self
+    }
+
+    // 开启隐式乘法：数字或 `)` 后面紧跟 `(` 或标识符时按 `*` 处理，
+    // 例如 2(3+4)、(1+2)(3+4)、3x；默认关闭，此时这些输入是语法错误
+    pub fn with_implicit_multiplication(mut self, implicit_multiplication: bool) -> Self {
+        self.implicit_multiplication = implicit_multiplication;
+        self
+    }
+
+    // 选择数值后端，Precision::Arbitrary 按十进制精确计算后再转换为 f64
+    pub fn with_precision(mut self, precision: Precision) -> Self {
+        self.evaluator.precision = precision;
+        self
+    }
+
+    // 开启整数模式：字面量必须是整数，`/` 为向零取整的整数除法，`^` 的指数必须是非负整数，
+    // 否则返回 ExpError::NotInteger
+    pub fn with_integer_mode(mut self, integer_mode: bool) -> Self {
+        self.evaluator.integer_mode = integer_mode;
+        self
+    }
+
+    // 开启记忆化：结构相同的子表达式只计算一次，适合有大量重复项的长表达式
+    // 只用于 f64 后端，函数需要是确定的
+    pub fn with_memoization(mut self, memoize: bool) -> Self {
+        self.memoize = memoize;
+        self
+    }
+
+    // 设置错误信息的语言，默认为英文
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    // 解析并计算表达式的值
+    pub fn eval(&mut self) -> Result<f64> {
+        let ast = self.parse().map_err(|e| e.localized(self.locale))?;
+        if self.memoize {
+            self.evaluator.memo = Some(memo::Memo::new(&ast));
+        }
+        let result = match self.evaluator.precision {
+            // 中间结果可能是向量、日期等其他值，如 3d / 12h
+            Precision::Float if self.evaluator.involves_tensor(&ast) => {
+                self.evaluator.eval_number(&ast)
+            }
+            Precision::Float => self.evaluator.eval(&ast),
+            #[cfg(feature = "arbitrary-precision")]
+            Precision::Arbitrary => self
+                .evaluator
+                .eval_decimal(&ast)
+                .and_then(|value| decimal::to_f64(&value)),
+        };
+        result.map_err(|e| e.localized(self.locale))
+    }
+
+    // 将输入解析为语法树
+    // 多条语句用 `;` 分隔（如 `x = 3 + 4; x * 2`），解析为 Ast::Seq，允许末尾多一个 `;`
+    pub fn parse(&mut self) -> Result<Ast> {
+        if self.strategy == ParseStrategy::ShuntingYard {
+            return expression_parsing_algorithm::parse(self);
+        }
+        let mut statements = Vec::new();
+        loop {
+            self.statement_start = self.consumed;
+            // 从最低优先级开始解析表达式
+            let mut statement = self.parse_conditional()?;
+            // 检查是否还有剩余的 Token，返回是否已经到达输入末尾
+            let end = loop {
+                match self.next_token() {
+                    None => break true,
+                    Some(Lexeme::Symbol(Token::Semicolon)) => break self.peek_token().is_none(),
+                    // 如果还有其他剩余的 Token，说明表达式有误
+                    Some(_) => {
+                        self.report("Unexpected token")?;
+                        // 恢复模式：跳过这个 Token，后面是运算符时接着解析当前语句，
+                        // 否则把后面的内容当作新的表达式
+                        statement = match self.peek_token().cloned() {
+                            None | Some(Lexeme::Symbol(Token::Semicolon)) => statement,
+                            Some(lexeme) if self.binary_precedence(&lexeme).is_some() => {
+                                self.parse_binary(statement, LOWEST_PRECEDENCE)?
+                            }
+                            Some(_) => self.parse_conditional()?,
+                        };
+                    }
+                }
+            };
+            statements.push(statement);
+            if end {
+                break;
+            }
+        }
+        if statements.len() == 1 {
+            Ok(statements.pop().unwrap())
+        } else {
+            Ok(Ast::Seq(statements))
+        }
+    }
+
+    // 以恢复模式解析，返回输入中所有的语法错误，没有错误时为空
+    // 嵌套过深等无法继续解析的错误会作为最后一条诊断信息
+    fn parse_recovering(&mut self) -> Vec<Diagnostic> {
+        self.recovering = true;
+        let result = self.parse();
+        let mut diagnostics = std::mem::take(&mut self.diagnostics);
+        let diagnostic = match result {
+            Ok(_) => return diagnostics,
+            Err(ExpError::SyntaxError {
+                message,
+                span,
+                lexeme,
+                ..
+            }) => Diagnostic {
+                message,
+                span,
+                lexeme,
+            },
+            Err(ExpError::ParseError(message)) => Diagnostic {
+                message,
+                span: self.last_span,
+                lexeme: String::new(),
+            },
+            Err(err) => Diagnostic {
+                message: err.to_string(),
+                span: self.last_span,
+                lexeme: String::new(),
+            },
+        };
+        push_diagnostic(&mut diagnostics, diagnostic);
+        diagnostics
+    }
+
+    // 只检查语法而不求值：Token 能否识别、括号是否匹配、运算符和函数调用是否都有操作数，
+    // 适合在用户输入的过程中实时校验；返回输入中所有的语法错误，位置相对于原始输入
+    pub fn check(input: &str) -> std::result::Result<(), Vec<Diagnostic>> {
+        let trimmed = input.trim_start();
+        // 被去掉的前导空白和 `=` 的字符数，诊断信息的位置需要加上它
+        let skipped = |rest: &str| input[..input.len() - rest.len()].chars().count();
+        let diagnostics = match strip_formula_prefix(input) {
+            Ok(rest) => {
+                let mut expr = Expr::new(rest);
+                expr.check_arity = true;
+                let mut diagnostics = expr.parse_recovering();
+                let skipped = skipped(rest);
+                for diagnostic in &mut diagnostics {
+                    diagnostic.span.offset += skipped;
+                }
+                diagnostics
+            }
+            // 只有一个 `=`
+            Err(err) => vec![Diagnostic {
+                message: match err {
+                    ExpError::ParseError(message) => message,
+                    err => err.to_string(),
+                },
+                span: Span {
+                    offset: skipped(trimmed),
+                    len: 1,
+                },
+                lexeme: "=".to_string(),
+            }],
+        };
+        if diagnostics.is_empty() {
+            Ok(())
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    // 计算输入开头尽可能长的一段表达式，遇到无法继续组成表达式的 Token 时停止
+    // 返回计算结果和已消耗的 Token 数量，剩余的输入不会被当作错误
+    pub fn eval_prefix(&mut self) -> Result<(f64, usize)> {
+        let result = self.compute_expr(LOWEST_PRECEDENCE)?;
+        Ok((result, self.consumed))
+    }
+
+    // 解析并计算一个表达式，参数min_prec表示当前处理的运算符的最小优先级
+    fn compute_expr(&mut self, min_prec: i32) -> Result<f64> {
+        let ast = self.parse_expr(min_prec)?;
+        self.evaluator.eval(&ast)
+    }
+
+    // 解析并计算一个原子表达式，只在测试中使用
+    #[cfg(test)]
+    fn compute_atom(&mut self) -> Result<f64> {
+        let ast = self.parse_atom()?;
+        self.evaluator.eval(&ast)
+    }
+
+    // 取出下一个 Token，并记录已消耗的数量和它的位置
+    // 输入结束时位置记为输入末尾；Token 以借用原文的 Lexeme 形式返回，只在构造语法树时才复制标识符
+    fn next_token(&mut self) -> Option<Lexeme<'a>> {
+        match self.iter.next() {
+            Some((lexeme, span)) => {
+                self.consumed += 1;
+                self.last_span = span;
+                self.after_operand =
+                    matches!(lexeme, Lexeme::Number(_) | Lexeme::Symbol(Token::RParen));
+                Some(lexeme)
+            }
+            None => {
+                self.last_span = Span {
+                    offset: self.source.chars().count(),
+                    len: 0,
+                };
+                None
+            }
+        }
+    }
+
+    // 预览下一个 Token
+    fn peek_token(&mut self) -> Option<&Lexeme<'a>> {
+        self.iter.peek().map(|(lexeme, _)| lexeme)
+    }
+
+    // 下一个 Token 的位置，输入结束时为输入末尾
+    fn peek_span(&mut self) -> Span {
+        match self.iter.peek() {
+            Some((_, span)) => *span,
+            None => Span {
+                offset: self.source.chars().count(),
+                len: 0,
+            },
+        }
+    }
+
+    // 在最近一次取出的 Token 处报告解析错误
+    fn syntax_error(&self, message: &str) -> ExpError {
+        self.syntax_error_at(self.last_span, message)
+    }
+
+    // 在指定位置报告解析错误
+    fn syntax_error_at(&self, span: Span, message: &str) -> ExpError {
+        ExpError::SyntaxError {
+            message: message.to_string(),
+            span,
+            lexeme: self
+                .source
+                .chars()
+                .skip(span.offset)
+                .take(span.len)
+                .collect(),
+            source: self.source.to_string(),
+            locale: Locale::default(),
+        }
+    }
+
+    // 在最近一次取出的 Token 处报告语法错误
+    fn report(&mut self, message: &str) -> Result<()> {
+        self.report_at(self.last_span, message)
+    }
+
+    // 报告语法错误：恢复模式下记录诊断信息并返回 Ok 继续解析，否则直接返回错误
+    fn report_at(&mut self, span: Span, message: &str) -> Result<()> {
+        let err = self.syntax_error_at(span, message);
+        match err {
+            ExpError::SyntaxError {
+                message,
+                span,
+                lexeme,
+                ..
+            } if self.recovering => {
+                push_diagnostic(
+                    &mut self.diagnostics,
+                    Diagnostic {
+                        message,
+                        span,
+                        lexeme,
+                    },
+                );
+                Ok(())
+            }
+            err => Err(err),
+        }
+    }
+
+    // 二元运算符的 (优先级, 结合性)，自定义运算符在运算符表中查找，不是二元运算符时返回 None
+    fn binary_precedence(&self, lexeme: &Lexeme) -> Option<(i32, i32)> {
+        match lexeme {
+            Lexeme::Operator(symbol) => self.evaluator.operators?.binary_entry(symbol),
+            Lexeme::Symbol(token) => self.precedence.get(token),
+            _ => None,
+        }
+    }
+
+    // 解析表达式，参数min_prec表示当前处理的运算符的最小优先级
+    fn parse_expr(&mut self, min_prec: i32) -> Result<Ast> {
+        // 解析第一个原子表达式
+        let lhs = self.parse_atom()?;
+        self.parse_binary(lhs, min_prec)
+    }
+
+    // 以已经解析好的 lhs 为左操作数，继续解析后面优先级不低于 min_prec 的二元运算
+    fn parse_binary(&mut self, mut lhs: Ast, min_prec: i32) -> Result<Ast> {
+        // 预览下一个 Token，没有时退出循环
+        while let Some(next) = self.peek_token().cloned() {
+            // 隐式乘法：在数字或 `)` 与紧跟的 `(` 或标识符之间补一个 `*`，不消耗 Token
+            let implicit = self.implicit_multiplication
+                && self.after_operand
+                && matches!(next, Lexeme::Symbol(Token::LParen) | Lexeme::Ident(_));
+
+            // 1. Token 一定是运算符（在优先级表中）
+            // 2. Token 的优先级必须大于等于 min_prec
+            let entry = if implicit {
+                self.precedence.implicit_multiplication()
+            } else {
+                self.binary_precedence(&next)
+            };
+            let (prec, assoc) = match entry {
+                Some((prec, assoc)) if prec >= min_prec => (prec, assoc),
+                // 如果当前 Token 不是运算符或优先级不够，退出循环
+                _ => break,
+            };
+
+            let mut next_prec = prec;
+            if assoc == ASSOC_LEFT {
+                // 如果是左结合运算符，下一级优先级加1
+                next_prec += 1;
+            }
+
+            // 移动到下一个 Token
+            let token = if implicit {
+                Token::Multiply
+            } else {
+                self.next_token();
+                next.into()
+            };
+
+            // 递归解析右边的表达式
+            // 右结合时右边会递归解析后面的整条链，如 2^2^...^2，计入嵌套深度防止栈溢出
+            let right = assoc != ASSOC_LEFT;
+            if right {
+                self.enter_nesting()?;
+            }
+            let rhs = self.parse_expr(next_prec)?;
+            if right {
+                self.depth -= 1;
+            }
+            lhs = Ast::BinOp(token, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    // 解析完整的表达式，包括优先级最低的条件表达式 `cond ? a : b`
+    // 条件表达式右结合：`a ? b : c ? d : e` 等价于 `a ? b : (c ? d : e)`
+    fn parse_conditional(&mut self) -> Result<Ast> {
+        let cond = self.parse_expr(LOWEST_PRECEDENCE)?;
+        if !matches!(self.peek_token(), Some(Lexeme::Symbol(Token::Question))) {
+            return Ok(cond);
+        }
+        self.parse_branches(cond)
+    }
+
+    // 解析条件表达式 `?` 后面的两个分支
+    // 每层括号都会经过 parse_conditional，分支的解析单独放在这里，避免增大递归解析时每一层的栈帧
+    #[inline(never)]
+    fn parse_branches(&mut self, cond: Ast) -> Result<Ast> {
+        self.next_token();
+        // 分支同样计入嵌套深度，防止很长的条件链导致栈溢出
+        self.enter_nesting()?;
+        let then = self.parse_conditional()?;
+        let otherwise = if let Some(Lexeme::Symbol(Token::Colon)) = self.peek_token() {
+            self.next_token();
+            self.parse_conditional()?
+        } else {
+            // 恢复模式下缺少的分支按 0 处理
+            let span = self.peek_span();
+            self.report_at(span, "Expected ':' in conditional expression")?;
+            Ast::Num(0.0)
+        };
+        self.depth -= 1;
+        Ok(Ast::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    // 进入一层括号，嵌套深度加1，超过限制直接报错
+    fn enter_nesting(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > self.max_depth {
+            return Err(ExpError::ParseError(
+                "expression too deeply nested".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // 标识符后面是 `(` 时为函数调用，是 `=` 时为赋值，否则为变量或内置常量
+    // 语句开头的 `f(x, y) = ...` 为函数定义
+    fn parse_ident(&mut self, name: &str) -> Result<Ast> {
+        // 赋值和函数定义只能作为一条语句的开头，`2 * x = 3`、`(x = 3)` 都是错误的
+        let at_statement_start = self.consumed == self.statement_start + 1;
+        match self.peek_token() {
+            Some(Lexeme::Symbol(Token::LParen)) => {
+                let call = self.parse_call(name)?;
+                if at_statement_start
+                    && matches!(self.peek_token(), Some(Lexeme::Symbol(Token::Assign)))
+                {
+                    self.next_token();
+                    self.parse_definition(call)
+                } else {
+                    Ok(call)
+                }
+            }
+            Some(Lexeme::Symbol(Token::Assign)) => {
+                self.next_token();
+                if !at_statement_start {
+                    self.report(&format!("Invalid assignment to {}", name))?;
+                }
+                let value = self.parse_conditional()?;
+                Ok(Ast::Assign(name.to_string(), Box::new(value)))
+            }
+            _ => Ok(Ast::Var(name.to_string())),
+        }
+    }
+
+    // 解析函数定义的函数体，call 是 `=` 左边已经解析的 `f(x, y)`，参数必须都是变量名
+    fn parse_definition(&mut self, call: Ast) -> Result<Ast> {
+        let Ast::Call(name, args) = &call else {
+            return Err(self.syntax_error("Invalid function definition"));
+        };
+        let mut params = Vec::new();
+        for arg in args {
+            match arg {
+                Ast::Var(param) if !params.contains(param) => params.push(param.clone()),
+                _ => {
+                    return Err(ExpError::ParseError(format!(
+                        "Invalid parameter in definition of {}()",
+                        name
+                    )))
+                }
+            }
+        }
+        let body = self.parse_conditional()?;
+        Ok(Ast::Define(name.clone(), params, Box::new(body)))
+    }
+
+    // 解析函数调用，函数名已经被消耗，接下来应该是 `(参数, 参数, ...)`
+    fn parse_call(&mut self, name: &str) -> Result<Ast> {
+        let name_span = self.last_span;
+        if !matches!(self.next_token(), Some(Lexeme::Symbol(Token::LParen))) {
+            return Err(self.syntax_error(&format!("Expected '(' after function name {}", name)));
+        }
+        let args = self.parse_separated(Token::RParen, "Expected ',' or ')' in function call")?;
+        if self.check_arity {
+            if let Some(message) = builtin_arity_error(name, &args) {
+                self.report_at(name_span, &message)?;
+            }
+        }
+        Ok(Ast::Call(name.to_string(), args))
+    }
+
+    // 解析用逗号分隔、以 close 结束的表达式列表，开头的括号已经被消耗，用于函数参数和向量的元素
+    fn parse_separated(&mut self, close: Token, message: &str) -> Result<Vec<Ast>> {
+        self.enter_nesting()?;
+        let mut items = Vec::new();
+        if matches!(self.peek_token(), Some(Lexeme::Symbol(token)) if *token == close) {
+            self.next_token();
+        } else {
+            loop {
+                items.push(self.parse_conditional()?);
+                match self.peek_token() {
+                    Some(Lexeme::Symbol(Token::Comma)) => {
+                        self.next_token();
+                    }
+                    Some(Lexeme::Symbol(token)) if *token == close => {
+                        self.next_token();
+                        break;
+                    }
+                    next => {
+                        // 恢复模式下不消耗这个 Token：后面是新的操作数时当作漏写了分隔符继续解析，
+                        // 否则把列表当作在此结束
+                        let missing_separator = matches!(
+                            next,
+                            Some(
+                                Lexeme::Number(_)
+                                    | Lexeme::Ident(_)
+                                    | Lexeme::Str(_)
+                                    | Lexeme::Symbol(Token::LParen | Token::LBracket)
+                            )
+                        );
+                        let span = self.peek_span();
+                        self.report_at(span, message)?;
+                        if !missing_separator {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        self.depth -= 1;
+        Ok(items)
+    }
+
+    // 解析原子表达式，并处理紧跟其后的后缀阶乘
+    // 阶乘比 ^ 结合得更紧，所以 2^3! = 2^6，而 -3! = -(3!)
+    // 每个阶乘都会增加一层语法树，同样计入嵌套深度，防止 3!!!...! 求值时栈溢出
+    fn parse_atom(&mut self) -> Result<Ast> {
+        let mut ast = self.parse_primary()?;
+        let mut postfix = 0;
+        while let Some(Lexeme::Symbol(Token::Factorial)) = self.peek_token() {
+            self.next_token();
+            self.enter_nesting()?;
+            postfix += 1;
+            ast = Ast::UnaryOp(Token::Factorial, Box::new(ast));
+        }
+        self.depth -= postfix;
+        Ok(ast)
+    }
+
+    // 消耗右括号，没有时报告错误
+    fn expect_closing_paren(&mut self) -> Result<()> {
+        if let Some(Lexeme::Symbol(Token::RParen)) = self.peek_token() {
+            self.next_token();
+            Ok(())
+        } else {
+            let span = self.peek_span();
+            self.report_at(span, "Expected closing parenthesis")
+        }
+    }
+
+    // 检查下一个 Token 能否作为基本表达式的开头，不能时报告错误
+    // 恢复模式下跳过无法识别的字符，其他 Token 不消耗，交给外层处理
+    fn expect_operand(&mut self) -> Result<bool> {
+        while let Some(Lexeme::Symbol(Token::Unknown(c))) = self.peek_token() {
+            // 以数字开头的无法识别的 Token 是超出范围的整数字面量
+            let message = if c.is_ascii_digit() {
+                "Integer literal out of range"
+            } else {
+                "Unexpected token"
+            };
+            self.next_token();
+            self.report(message)?;
+        }
+        let operators = self.evaluator.operators;
+        match self.peek_token() {
+            // 注册过的前缀运算符
+            Some(Lexeme::Operator(symbol))
+                if operators.is_some_and(|operators| operators.prefix.contains_key(*symbol)) =>
+            {
+                return Ok(true)
+            }
+            Some(
+                Lexeme::Number(_)
+                | Lexeme::Ident(_)
+                | Lexeme::Str(_)
+                | Lexeme::Symbol(
+                    Token::Minus | Token::Plus | Token::BitNot | Token::LParen | Token::LBracket,
+                ),
+            ) => return Ok(true),
+            // 其他 Token 返回错误
+            Some(_) => {
+                let span = self.peek_span();
+                self.report_at(span, "Unexpected token")?;
+            }
+            // 如果没有 Token，返回错误
+            None => {
+                self.next_token();
+                self.report("Unexpected end of input")?;
+            }
+        }
+        Ok(false)
+    }
+
+    // 解析基本表达式（数字、标识符、一元运算符或括号内的表达式）
+    // 恢复模式下缺少操作数时用 0 代替并继续解析
+    fn parse_primary(&mut self) -> Result<Ast> {
+        if !self.expect_operand()? {
+            return Ok(Ast::Num(0.0));
+        }
+        match self.next_token().unwrap() {
+            Lexeme::Number(n) => Ok(self.parse_number(n)), // 数字，后面紧跟时长单位时是时长字面量
+            Lexeme::Str(text) => Ok(Ast::Str(text.to_string())),
+            Lexeme::Ident(name) => self.parse_ident(name), // 如果是标识符，按函数调用、赋值或变量处理
+            Lexeme::Symbol(Token::LParen) => {
+                self.enter_nesting()?;
+                // 如果是左括号，解析括号内的表达式
+                let result = self.parse_conditional()?;
+                self.depth -= 1;
+                // 检查是否有匹配的右括号，恢复模式下缺少时不消耗 Token
+                self.expect_closing_paren()?;
+                Ok(result)
+            }
+            // 向量 `[1, 2, 3]`，矩阵按行书写为 `[[1, 2], [3, 4]]`
+            Lexeme::Symbol(Token::LBracket) => Ok(Ast::List(
+                self.parse_separated(Token::RBracket, "Expected ',' or ']' in vector")?,
+            )),
+            lexeme => {
+                let token = Token::from(lexeme);
+                // 一元负号/正号/按位取反：优先级低于 ^、高于乘除，所以 -2^2 = -4，而 5*-3、5--3、2^-2、+5 都合法
+                // Excel 语义下操作数不包含 ^，所以 -2^2 = (-2)^2 = 4
+                // 连续的符号同样计入嵌套深度，防止 "----...1" 这类输入导致栈溢出
+                self.enter_nesting()?;
+                let power_prec = self
+                    .precedence
+                    .get(&Token::Power)
+                    .map_or(1, |(prec, _)| prec);
+                let operand = match self.power_semantics {
+                    PowerSemantics::Math => self.parse_expr(power_prec)?,
+                    PowerSemantics::Excel => self.parse_expr(power_prec + 1)?,
+                };
+                self.depth -= 1;
+                Ok(Ast::UnaryOp(token, Box::new(operand)))
+            }
+        }
+    }
+}
+
+// 把表达式解析为语法树，可以保存下来用 eval 反复求值
+// 与 evaluate 一样会先去掉可选的前导 `=`
+pub fn parse(input: &str) -> Result<Ast> {
+    with_formula_prefix(input, |src| Expr::new(src).parse())
+}
+
+// 表达式求值入口
+// 兼容电子表格风格的输入（如 `=1+2*3`），会先去掉可选的前导 `=` 再解析
+pub fn evaluate(input: &str) -> Result<f64> {
+    evaluate_with_format(input, NumberFormat::default())
+}
+
+// 按指定的数字格式求值，例如 NumberFormat::decimal_comma() 下 `3,14 + 1` 等于 4.14
+// 变量只在本次求值内有效
+pub fn evaluate_with_format(input: &str, format: NumberFormat) -> Result<f64> {
+    let mut context = EvalContext::default();
+    with_formula_prefix(input, |src| {
+        Expr::with_format(src, format)?
+            .with_context(&mut context)
+            .eval()
+    })
+}
+
+// 记录一条诊断信息；恢复解析时同一个位置可能被多次报告，只保留第一条
+fn push_diagnostic(diagnostics: &mut Vec<Diagnostic>, diagnostic: Diagnostic) {
+    if !diagnostics.iter().any(|d| d.span == diagnostic.span) {
+        diagnostics.push(diagnostic);
+    }
+}
+
+// 检查输入中的所有语法错误，而不是在第一个错误处停止
+pub fn diagnose(input: &str) -> Vec<Diagnostic> {
+    match strip_formula_prefix(input) {
+        Ok(input) => Expr::new(input).parse_recovering(),
+        Err(_) => Vec::new(),
+    }
+}
+
+// 使用给定的上下文求值，赋值的变量在之后的求值中仍然可用
+pub fn evaluate_with_context(input: &str, context: &mut EvalContext) -> Result<f64> {
+    with_formula_prefix(input, |src| Expr::new(src).with_context(context).eval())
+}
+
+// 整数模式下使用给定的上下文求值，支持位运算符
+fn evaluate_integer_with_context(input: &str, context: &mut EvalContext) -> Result<f64> {
+    with_formula_prefix(input, |src| {
+        Expr::new(src)
+            .with_context(context)
+            .with_integer_mode(true)
+            .eval()
+    })
+}
+
+// 执行一段脚本，返回最后一条语句的值，变量和函数在语句之间共享
+// 语句之间用 `;` 或换行分隔，空行和 `#` 之后的注释会被忽略，例如：
+//   r = 2          # 半径
+//   area(r) = pi * r^2
+//   area(r)
+pub fn eval_script(script: &str) -> Result<f64> {
+    let mut context = EvalContext::new();
+    eval_script_with_context(script, &mut context)
+}
+
+// 使用给定的上下文执行脚本，脚本中定义的变量和函数在执行后仍然可用
+pub fn eval_script_with_context(script: &str, context: &mut EvalContext) -> Result<f64> {
+    let statements: Vec<&str> = script
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .map(|line| line.strip_suffix(';').unwrap_or(line))
+        .filter(|line| !line.is_empty())
+        .collect();
+    let source = statements.join("; ");
+    Expr::new(&source).with_context(context).eval()
+}
+
+// 使用宿主程序注册的自定义函数求值，例如注册 double 后 `double(21)` 等于 42
+pub fn evaluate_with_functions(input: &str, functions: &UserFunctions) -> Result<f64> {
+    with_formula_prefix(input, |src| {
+        Expr::new(src).with_user_functions(functions).eval()
+    })
+}
+
+// 求值，并检查调度场解析也能解析输入，调度场解析不支持函数调用等语法，无法解析时返回 ParseError
+// 两种解析方式生成相同的 Ast、共用同一个求值器，结果总是一致，因此不会返回 ExpError::Mismatch
+#[deprecated(
+    note = "both parse strategies share one evaluator, so the results always agree; use `evaluate`"
+)]
+pub fn evaluate_checked(input: &str) -> Result<f64> {
+    let value = evaluate(input)?;
+    let src = strip_formula_prefix(input)?;
+    Expr::new(src)
+        .with_strategy(ParseStrategy::ShuntingYard)
+        .parse()
+        .map_err(|_| {
+            ExpError::ParseError(format!("shunting-yard evaluator cannot parse: {}", src))
+        })?;
+    Ok(value)
+}
+
+// 在整数模式下求值，返回结果以及是否提升为了浮点数
+// promote 为 true 时，遇到 `2^-1`、`2^0.5` 这类整数无法表示的情况，整个表达式改为按浮点数重新求值；
+// 为 false 时直接返回 ExpError::NotInteger
+pub fn evaluate_integer(input: &str, promote: bool) -> Result<(f64, bool)> {
+    let src = strip_formula_prefix(input)?;
+    match Expr::new(src).with_integer_mode(true).eval() {
+        Ok(value) => Ok((value, false)),
+        Err(ExpError::NotInteger(_) | ExpError::MathError(MathError::NegativeExponent))
+            if promote =>
+        {
+            Ok((evaluate(src)?, true))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// 去掉电子表格风格的前导 `=`，只有一个 `=` 时返回错误
+fn strip_formula_prefix(input: &str) -> Result<&str> {
+    let src = input.trim_start();
+    match src.strip_prefix('=') {
+        // 只有一个 `=`，没有任何表达式
+        Some(rest) if rest.trim().is_empty() => Err(ExpError::ParseError(
+            "Empty expression after '='".to_string(),
+        )),
+        Some(rest) => Ok(rest),
+        None => Ok(src),
+    }
+}
+
+// 去掉可选的前导 `=` 之后再解析或求值，报告的语法错误仍然相对于原始输入，与 Expr::check 一致
+fn with_formula_prefix<T>(input: &str, f: impl FnOnce(&str) -> Result<T>) -> Result<T> {
+    let src = strip_formula_prefix(input)?;
+    f(src).map_err(|err| in_original_input(err, input, src))
+}
+
+// 把去掉前导 `=` 之后报告的语法错误换算回原始输入：位置加上被去掉的字符数，回显完整的输入
+fn in_original_input(err: ExpError, input: &str, rest: &str) -> ExpError {
+    match err {
+        ExpError::SyntaxError {
+            message,
+            span,
+            lexeme,
+            locale,
+            ..
+        } => ExpError::SyntaxError {
+            message,
+            span: Span {
+                offset: span.offset + input[..input.len() - rest.len()].chars().count(),
+                ..span
+            },
+            lexeme,
+            source: input.to_string(),
+            locale,
+        },
+        err => err,
+    }
+}
+
+// 批量求值时遇到错误的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BatchMode {
+    CollectAll, // 对所有表达式求值，收集全部结果
+    FailFast,   // 遇到第一个错误立即停止
+}
+
+// 批量求值，每个结果都带上输入中的原始下标，并保证按输入顺序返回
+// FailFast 模式下结果只包含到第一个出错的表达式为止
+pub fn eval_batch_indexed(inputs: &[&str], mode: BatchMode) -> Vec<(usize, Result<f64>)> {
+    let mut results = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.iter().enumerate() {
+        let result = evaluate(input);
+        let failed = result.is_err();
+        results.push((index, result));
+        if failed && mode == BatchMode::FailFast {
+            break;
+        }
+    }
+    results
+}
+
+// 为数字字符串的整数部分插入千位分隔符，小数部分保持不变，例如 1234567.5 -> 1,234,567.5
+pub fn group_thousands(number: &str) -> String {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (int_part, frac_part) = match digits.find('.') {
+        Some(pos) => digits.split_at(pos),
+        None => (digits, ""),
+    };
+
+    let mut grouped = String::new();
+    for (i, c) in int_part.chars().enumerate() {
+        // 从右往左每三位插入一个逗号
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    format!("{}{}{}", sign, grouped, frac_part)
+}
+
+// REPL 中保存上一次结果的变量名
+const ANSWER_VARIABLE: &str = "ans";
+
+// REPL 处理一行输入的结果
+#[derive(Debug, PartialEq)]
+pub enum ReplOutput {
+    Quit,          // 退出 REPL
+    Print(String), // 打印一行或多行输出
+    Nothing,       // 空行，不输出
+}
+
+// 输入是一条函数定义时返回函数签名，如 `f(x)`
+fn definition_signature(line: &str) -> Option<String> {
+    match &Expr::new(strip_formula_prefix(line).ok()?).parse().ok()? {
+        Ast::Define(name, params, _) => Some(format!("{}({})", name, params.join(", "))),
+        _ => None,
+    }
+}
+
+// REPL 的状态：变量上下文和显示、求值选项
+#[derive(Debug, Default)]
+pub struct ReplState {
+    context: EvalContext,
+    format: DisplayFormat, // 结果的输出格式，由 :digits、:round 命令设置
+    integer_mode: bool,    // 按整数模式求值，由 :int 命令切换
+    units: bool,           // 按带单位的表达式求值，由 :units 命令切换
+    locale: Locale,        // 错误信息的语言，由 :lang 命令切换
+}
+
+// 处理 REPL 的一行输入：以 `:` 开头的是命令（:quit、:vars、:clear、:int、:units、:lang、:digits、:round、:fmt、:simplify、:d/dx、:latex、:mathml、:rpn、:prefix），其余按表达式求值
+// 求值成功时结果保存到 ans 变量中，下一行可以继续使用
+pub fn repl_line(line: &str, state: &mut ReplState) -> ReplOutput {
+    let locale = state.locale;
+    let context = &mut state.context;
+    let line = line.trim();
+    match line {
+        "" => ReplOutput::Nothing,
+        ":quit" | ":q" => ReplOutput::Quit,
+        ":vars" => {
+            let variables = context.sorted_variables();
+            if variables.is_empty() {
+                return ReplOutput::Print("(no variables)".to_string());
+            }
+            let lines: Vec<String> = variables
+                .iter()
+                .map(|(name, value)| format!("{} = {}", name, value))
+                .collect();
+            ReplOutput::Print(lines.join("\n"))
+        }
+        ":clear" => {
+            context.clear();
+            ReplOutput::Print("cleared".to_string())
+        }
+        ":int" => {
+            state.integer_mode = !state.integer_mode;
+            let status = if state.integer_mode { "on" } else { "off" };
+            ReplOutput::Print(format!("integer mode {}", status))
+        }
+        ":units" => {
+            state.units = !state.units;
+            let status = if state.units { "on" } else { "off" };
+            ReplOutput::Print(format!("unit mode {}", status))
+        }
+        // :lang <语言>：切换错误信息的语言，如 :lang zh-CN
+        _ if line.starts_with(":lang ") => match Locale::parse(&line[":lang ".len()..]) {
+            Some(locale) => {
+                state.locale = locale;
+                ReplOutput::Print(format!("language {}", locale))
+            }
+            None => {
+                ReplOutput::Print("Error: unknown language, expected zh-CN or en-US".to_string())
+            }
+        },
+        // :digits <位数>：设置结果的位数，如 :digits sig 6、:digits fixed 2、:digits sci 4、:digits auto
+        _ if line.starts_with(":digits ") => match Digits::parse(&line[":digits ".len()..]) {
+            Some(digits) => {
+                state.format.digits = digits;
+                ReplOutput::Print(format!("digits {}", digits))
+            }
+            None => ReplOutput::Print(
+                "Error: unknown digits, expected auto, fixed N, sig N or sci N".to_string(),
+            ),
+        },
+        // :round <方式>：设置舍入方式，half-up 或 half-even
+        _ if line.starts_with(":round ") => match Rounding::parse(&line[":round ".len()..]) {
+            Some(rounding) => {
+                state.format.rounding = rounding;
+                ReplOutput::Print(format!("rounding {}", rounding))
+            }
+            None => ReplOutput::Print(
+                "Error: unknown rounding, expected half-up or half-even".to_string(),
+            ),
+        },
+        // :fmt <表达式>：输出格式化后的表达式，不求值
+        _ if line.starts_with(":fmt ") => match format::format_expression(&line[":fmt ".len()..]) {
+            Ok(output) => ReplOutput::Print(output),
+            Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+        },
+        // :simplify <表达式>：输出化简后的表达式，不求值
+        _ if line.starts_with(":simplify ") => {
+            match Expr::new(&line[":simplify ".len()..]).parse() {
+                Ok(ast) => ReplOutput::Print(format::format(&ast.simplify())),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        // :d/dx <表达式>：对 x 求导（也可以是 :d/dt 等其他变量），输出化简后的导数
+        _ if line.starts_with(":d/d") => {
+            let (var, input) = line[":d/d".len()..]
+                .split_once(' ')
+                .unwrap_or((&line[":d/d".len()..], ""));
+            match derivative::derivative_expression(input, var) {
+                Ok(ast) => ReplOutput::Print(format::format(&ast)),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        // :latex <表达式>、:mathml <表达式>：输出排版用的 LaTeX 或 MathML，不求值
+        _ if line.starts_with(":latex ") => {
+            match typeset::latex_expression(&line[":latex ".len()..]) {
+                Ok(output) => ReplOutput::Print(output),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        _ if line.starts_with(":mathml ") => {
+            match typeset::mathml_expression(&line[":mathml ".len()..]) {
+                Ok(output) => ReplOutput::Print(output),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        // :rpn <表达式>、:prefix <表达式>：按后缀或前缀表示法求值
+        _ if line.starts_with(":rpn ") => match notation::evaluate_rpn(&line[":rpn ".len()..]) {
+            Ok(value) => ReplOutput::Print(value.to_string()),
+            Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+        },
+        _ if line.starts_with(":prefix ") => {
+            match notation::evaluate_prefix(&line[":prefix ".len()..]) {
+                Ok(value) => ReplOutput::Print(value.to_string()),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        // :save <文件>、:load <文件>：把变量和定义的函数保存到会话文件，或者用会话文件替换当前的变量和函数
+        _ if line.starts_with(":save ") => {
+            let path = line[":save ".len()..].trim();
+            match session::save_session(context, std::path::Path::new(path)) {
+                Ok(()) => ReplOutput::Print(format!("saved {}", path)),
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        _ if line.starts_with(":load ") => {
+            let path = line[":load ".len()..].trim();
+            match session::load_session(std::path::Path::new(path)) {
+                Ok(mut loaded) => {
+                    // 随机数生成器继续使用当前的状态
+                    loaded.rng = std::mem::take(&mut context.rng);
+                    *context = loaded;
+                    ReplOutput::Print(format!("loaded {}", path))
+                }
+                Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+            }
+        }
+        _ if line.starts_with(':') => ReplOutput::Print(format!("Unknown command: {}", line)),
+        // 单位模式下不使用变量，直接输出带单位的结果
+        _ if state.units => match units::evaluate_with_units(line) {
+            Ok(output) => ReplOutput::Print(output),
+            Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+        },
+        _ => match if state.integer_mode {
+            evaluate_integer_with_context(line, context).map(Value::Number)
+        } else {
+            evaluate_value_with_context(line, context)
+        } {
+            // 函数定义不产生结果，输出函数签名
+            Ok(_) if definition_signature(line).is_some() => {
+                ReplOutput::Print(format!("defined {}", definition_signature(line).unwrap()))
+            }
+            Ok(Value::Number(value)) => {
+                context.set(ANSWER_VARIABLE, value);
+                ReplOutput::Print(state.format.format(value))
+            }
+            // 向量和矩阵同样保存到 ans 中
+            Ok(value) => {
+                let output = state.format.format_value(&value);
+                context.set_value(ANSWER_VARIABLE, value);
+                ReplOutput::Print(output)
+            }
+            // 语法错误时一次列出输入中所有的错误
+            Err(ExpError::SyntaxError { source, .. }) => {
+                let lines: Vec<String> = diagnose(line)
+                    .iter()
+                    .map(|diagnostic| {
+                        format!(
+                            "Error: ParseError: {}",
+                            diagnostic.render_in(&source, locale)
+                        )
+                    })
+                    .collect();
+                ReplOutput::Print(lines.join("\n"))
+            }
+            Err(e) => ReplOutput::Print(format!("Error: {}", e.localized(locale))),
+        },
+    }
+}
+
+// 交互式求值：逐行读取输入并输出结果，直到 :quit、Ctrl-C 或 Ctrl-D
+// 输入历史保存在 ~/.calc_history 中，下次启动时可以继续用上下键翻阅
+// format 是结果初始的输出格式，可以在交互中用 :digits、:round 命令修改
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_repl(format: DisplayFormat) -> rustyline::Result<()> {
+    let mut editor = rustyline::Editor::new()?;
+    // Tab 补全函数名、常量和变量，行尾显示当前输入的计算结果
+    editor.set_helper(Some(completion::ReplHelper {
+        state: ReplState {
+            format,
+            locale: Locale::from_env(),
+            ..ReplState::default()
+        },
+    }));
+    // 第一次运行时历史记录文件还不存在，读取失败不影响使用
+    let history = session::history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+    loop {
+        match editor.readline("> ") {
+            Ok(line) => {
+                if !line.trim().is_empty() {
+                    editor.add_history_entry(line.as_str())?;
+                }
+                let helper = editor.helper_mut().expect("REPL helper is set");
+                match repl_line(&line, &mut helper.state) {
+                    ReplOutput::Quit => break,
+                    ReplOutput::Print(output) => println!("{}", output),
+                    ReplOutput::Nothing => {}
+                }
+            }
+            // Ctrl-C 或 Ctrl-D 退出
+            Err(rustyline::error::ReadlineError::Interrupted)
+            | Err(rustyline::error::ReadlineError::Eof) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if let Some(path) = &history {
+        editor.save_history(path)?;
+    }
+    Ok(())
+}
+
+// 编写测试用例
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 统计当前线程的堆分配次数，用来检查解析过程中没有多余的分配
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(|count| count.get());
+        let result = f();
+        (result, ALLOCATIONS.with(|count| count.get()) - before)
+    }
+
+    #[test]
+    fn test_parse_allocations() {
+        // 每个变量一个 String，每个二元运算两个 Box，查优先级和读取 Token 不分配
+        let n = 200;
+        let input = (0..n)
+            .map(|i| format!("a{}", i))
+            .collect::<Vec<_>>()
+            .join(" + ");
+        for strategy in [ParseStrategy::Pratt, ParseStrategy::ShuntingYard] {
+            let expr = Expr::new(&input).with_strategy(strategy);
+            let (ast, count) = allocations(move || {
+                let mut expr = expr;
+                expr.parse()
+            });
+            assert!(ast.is_ok());
+            assert!(count <= 3 * n + 16, "{:?}: {} allocations", strategy, count);
+        }
+    }
+
+    #[test]
+    fn test_compute_atom() {
+        let mut expr = Expr::new("5");
+        let result = expr.compute_atom().unwrap();
+        assert_eq!(result, 5.0);
+    }
+
+    #[test]
+    fn test_compute_expr() {
+        let mut expr = Expr::new("5 + 5");
+        let result = expr.compute_expr(0).unwrap();
+        assert_eq!(result, 10.0);
+    }
+
+    #[test]
+    fn test_parse_to_ast() {
+        let ast = Expr::new("-2 * sqrt(x) + 3!").parse().unwrap();
+        let expected = Ast::BinOp(
+            Token::Plus,
+            Box::new(Ast::BinOp(
+                Token::Multiply,
+                Box::new(Ast::UnaryOp(Token::Minus, Box::new(Ast::Num(2.0)))),
+                Box::new(Ast::Call(
+                    "sqrt".to_string(),
+                    vec![Ast::Var("x".to_string())],
+                )),
+            )),
+            Box::new(Ast::UnaryOp(Token::Factorial, Box::new(Ast::Num(3.0)))),
+        );
+        assert_eq!(ast, expected);
+
+        let ast = Expr::new("y = 1; y").parse().unwrap();
+        assert!(matches!(&ast, Ast::Seq(statements) if statements.len() == 2));
+        assert!(Expr::new("1 +").parse().is_err());
+    }
+
+    #[test]
+    fn test_eval_transformed_ast() {
+        let ast = Expr::new("(1 + 2) * 4").parse().unwrap();
+        assert_eq!(eval(&ast).unwrap(), 12.0);
+
+        // 求值前把所有加法替换为减法
+        fn plus_to_minus(ast: &Ast) -> Ast {
+            match ast {
+                Ast::BinOp(op, lhs, rhs) => {
+                    let op = if *op == Token::Plus {
+                        Token::Minus
+                    } else {
+                        op.clone()
+                    };
+                    Ast::BinOp(
+                        op,
+                        Box::new(plus_to_minus(lhs)),
+                        Box::new(plus_to_minus(rhs)),
+                    )
+                }
+                other => other.clone(),
+            }
+        }
+        assert_eq!(eval(&plus_to_minus(&ast)).unwrap(), -4.0);
+
+        // 很长的左结合链不会导致求值时栈溢出
+        let src = format!("1{}", "+1".repeat(100_000));
+        assert_eq!(evaluate(&src).unwrap(), 100_001.0);
+        // 没有上下文时变量无法赋值
+        assert!(eval(&Expr::new("z = 1").parse().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_deeply_nested_expression() {
+        let src = format!("{}1{}", "(".repeat(100_000), ")".repeat(100_000));
+        match Expr::new(&src).eval() {
+            Err(ExpError::ParseError(msg)) => assert_eq!(msg, "expression too deeply nested"),
+            other => panic!("expected nesting error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_long_postfix_and_power_chains() {
+        // 很长的阶乘链和右结合的乘方链在两种解析策略下都报告嵌套过深，而不是栈溢出
+        let factorials = format!("3{}", "!".repeat(100_000));
+        let powers = format!("2{}", "^1".repeat(100_000));
+        for strategy in [ParseStrategy::Pratt, ParseStrategy::ShuntingYard] {
+            for src in [&factorials, &powers] {
+                match Expr::new(src).with_strategy(strategy).eval() {
+                    Err(ExpError::ParseError(msg)) => {
+                        assert_eq!(msg, "expression too deeply nested")
+                    }
+                    other => panic!("expected nesting error, got {:?}", other),
+                }
+            }
+            // 不超过限制的链仍然正常计算
+            let src = format!("2{}", "^1".repeat(200));
+            assert_eq!(Expr::new(&src).with_strategy(strategy).eval().unwrap(), 2.0);
+            assert_eq!(
+                Expr::new("3!!").with_strategy(strategy).eval().unwrap(),
+                720.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_custom_max_depth() {
+        assert!(Expr::new("((1))").with_max_depth(1).eval().is_err());
+        assert_eq!(Expr::new("((1))").with_max_depth(2).eval().unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_eval_prefix_stops_at_extra_input() {
+        assert_eq!(Expr::new("1+2 extra").eval_prefix().unwrap(), (3.0, 3));
+        assert_eq!(Expr::new("2 * (3 + 4) 5").eval_prefix().unwrap(), (14.0, 7));
+        assert!(Expr::new("2 * (3 + 4) 5").eval().is_err());
+        assert!(Expr::new("+").eval_prefix().is_err());
+    }
+
+    #[test]
+    fn test_variadic_functions() {
+        assert_eq!(evaluate("sum(1, 2, 3)").unwrap(), 6.0);
+        assert_eq!(evaluate("avg(4,8)").unwrap(), 6.0);
+        assert_eq!(evaluate("min(5, 2, 9)").unwrap(), 2.0);
+        assert_eq!(evaluate("max(5, 2, 9) * 2").unwrap(), 18.0);
+        assert_eq!(evaluate("sum(1 + 1, max(2, 3))").unwrap(), 5.0);
+        assert_eq!(evaluate("sum(7)").unwrap(), 7.0);
+    }
+
+    #[test]
+    fn test_math_functions() {
+        assert_eq!(evaluate("sqrt(16)").unwrap(), 4.0);
+        assert!((evaluate("sqrt(2)").unwrap() - 2f64.sqrt()).abs() < 1e-12);
+        assert_eq!(evaluate("sin(0) + cos(0)").unwrap(), 1.0);
+        assert_eq!(evaluate("log(100, 10)").unwrap(), 2.0);
+        assert_eq!(evaluate("log(1000)").unwrap(), 3.0);
+        assert_eq!(evaluate("ln(exp(2))").unwrap(), 2.0);
+        assert_eq!(
+            evaluate("abs(-3) + floor(2.7) + ceil(2.1) + round(2.5)").unwrap(),
+            11.0
+        );
+        assert_eq!(evaluate("x = 9; sqrt(x) * 2").unwrap(), 6.0);
+
+        match evaluate("sqrt(1, 2)") {
+            Err(ExpError::ParseError(msg)) => {
+                assert_eq!(msg, "sqrt() takes exactly one argument, got 2")
+            }
+            other => panic!("expected argument error, got {:?}", other),
+        }
+        assert!(evaluate("log()").is_err());
+    }
+
+    #[test]
+    fn test_variadic_functions_errors() {
+        match evaluate("sum()") {
+            Err(ExpError::ParseError(msg)) => {
+                assert_eq!(msg, "sum() requires at least one argument")
+            }
+            other => panic!("expected argument error, got {:?}", other),
+        }
+        assert!(evaluate("avg()").is_err());
+        assert!(evaluate("sum(1, 2").is_err());
+        assert!(evaluate("nope(1)").is_err());
+        assert!(evaluate("sum 1").is_err());
+    }
+
+    #[test]
+    fn test_unary_minus_after_operator() {
+        assert_eq!(evaluate("-3").unwrap(), -3.0);
+        assert_eq!(evaluate("5 - -3").unwrap(), 8.0);
+        assert_eq!(evaluate("5--3").unwrap(), 8.0);
+        assert_eq!(evaluate("5*-3").unwrap(), -15.0);
+        assert_eq!(evaluate("2^-2").unwrap(), 0.25);
+        assert_eq!(evaluate("-2^2").unwrap(), -4.0);
+        assert_eq!(evaluate("-(2 + 3) * 2").unwrap(), -10.0);
+        assert_eq!(evaluate("max(-1, -5)").unwrap(), -1.0);
+        assert_eq!(evaluate("-5 + 3").unwrap(), -2.0);
+        assert_eq!(evaluate("2 * -(4 + 1)").unwrap(), -10.0);
+    }
+
+    #[test]
+    fn test_variables_and_assignment() {
+        assert_eq!(evaluate("x = 3 + 4; x * 2").unwrap(), 14.0);
+        assert_eq!(evaluate("a = 2; b = a ^ 3; b - a;").unwrap(), 6.0);
+        // 变量名可以与函数名相同，按后面是否跟 `(` 区分
+        assert_eq!(evaluate("max = 5; max(max, 3)").unwrap(), 5.0);
+
+        // 上下文中的变量在多次求值之间保留
+        let mut context = EvalContext::new();
+        assert_eq!(
+            evaluate_with_context("rate = 0.5", &mut context).unwrap(),
+            0.5
+        );
+        assert_eq!(
+            evaluate_with_context("rate * 10", &mut context).unwrap(),
+            5.0
+        );
+        assert_eq!(context.get("rate"), Some(0.5));
+    }
+
+    #[test]
+    fn test_builtin_constants() {
+        use std::f64::consts::{E, PI, TAU};
+        assert_eq!(evaluate("pi").unwrap(), PI);
+        assert_eq!(evaluate("r = 2; 2 * pi * r").unwrap(), 2.0 * PI * 2.0);
+        assert_eq!(evaluate("ln(e)").unwrap(), E.ln());
+        assert_eq!(evaluate("tau / 2").unwrap(), TAU / 2.0);
+        assert_eq!(evaluate("-inf").unwrap(), f64::NEG_INFINITY);
+        // 没有上下文时同样可以使用常量
+        assert_eq!(Expr::new("tau").eval().unwrap(), TAU);
+
+        // 用户变量可以覆盖常量
+        assert_eq!(evaluate("pi = 3; pi * 2").unwrap(), 6.0);
+        let mut context = EvalContext::new();
+        evaluate_with_context("e = 1", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("e + 1", &mut context).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn test_repl_keeps_state_between_lines() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+
+        assert_eq!(repl_line("x = 3 + 4", &mut state), print("7"));
+        assert_eq!(repl_line("x * 2", &mut state), print("14"));
+        // 上一次的结果保存在 ans 中
+        assert_eq!(repl_line("ans + 1", &mut state), print("15"));
+        assert_eq!(repl_line(":vars", &mut state), print("ans = 15\nx = 7"));
+        state.format.group = true;
+        assert_eq!(repl_line("1000 * 1000", &mut state), print("1,000,000"));
+        state.format.group = false;
+
+        assert_eq!(repl_line(":clear", &mut state), print("cleared"));
+        assert_eq!(repl_line(":vars", &mut state), print("(no variables)"));
+        assert_eq!(
+            repl_line("x", &mut state),
+            print("Error: ParseError: Unknown variable: x")
+        );
+        assert_eq!(repl_line("   ", &mut state), ReplOutput::Nothing);
+        assert_eq!(
+            repl_line(":help", &mut state),
+            print("Unknown command: :help")
+        );
+        assert_eq!(repl_line(":quit", &mut state), ReplOutput::Quit);
+    }
+
+    #[test]
+    fn test_repl_vector_values() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+        assert_eq!(repl_line("v = [1, 2]", &mut state), print("[1, 2]"));
+        assert_eq!(repl_line("ans * 2", &mut state), print("[2, 4]"));
+        assert_eq!(repl_line("dot(ans, v)", &mut state), print("10"));
+        assert_eq!(
+            repl_line("[[1, 0], [0, 1]]", &mut state),
+            print("[[1, 0], [0, 1]]")
+        );
+        assert_eq!(
+            repl_line(":vars", &mut state),
+            print("ans = [[1, 0], [0, 1]]\nv = [1, 2]")
+        );
+        assert_eq!(
+            repl_line("v + [1]", &mut state),
+            print("Error: ShapeError: cannot apply + to a vector of length 2 and a vector of length 1")
+        );
+        // 日期和时长同样可以保存在 ans 中
+        assert_eq!(
+            repl_line("date(\"2024-01-01\") + 1w", &mut state),
+            print("2024-01-08")
+        );
+        assert_eq!(
+            repl_line("ans - date(\"2023-12-31\")", &mut state),
+            print("8d")
+        );
+    }
+
+    #[test]
+    fn test_repl_integer_mode_command() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+
+        assert_eq!(
+            repl_line("6 & 3", &mut state),
+            print("Error: ParseError: Operator & requires integer mode")
+        );
+        assert_eq!(repl_line(":int", &mut state), print("integer mode on"));
+        assert_eq!(repl_line("6 & 3", &mut state), print("2"));
+        assert_eq!(repl_line("7 / 2", &mut state), print("3"));
+        // 函数的结果同样必须是整数
+        assert_eq!(repl_line("sqrt(16) + max(1, 2)", &mut state), print("6"));
+        assert_eq!(
+            repl_line("sqrt(2)", &mut state),
+            print("Error: NotInteger: sqrt() returned 1.4142135623730951, which is not an integer")
+        );
+        assert!(
+            matches!(repl_line("avg(1, 2)", &mut state), ReplOutput::Print(s) if s.starts_with("Error: NotInteger"))
+        );
+        assert!(
+            matches!(repl_line("rand()", &mut state), ReplOutput::Print(s) if s.starts_with("Error: NotInteger"))
+        );
+        assert_eq!(repl_line(":int", &mut state), print("integer mode off"));
+        assert_eq!(repl_line("7 / 2", &mut state), print("3.5"));
+    }
+
+    #[test]
+    fn test_repl_display_commands() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+
+        assert_eq!(
+            repl_line(":digits sig 6", &mut state),
+            print("digits sig 6")
+        );
+        assert_eq!(repl_line("1/3", &mut state), print("0.333333"));
+        assert_eq!(
+            repl_line(":digits fixed 2", &mut state),
+            print("digits fixed 2")
+        );
+        assert_eq!(repl_line("[1/3, 0.125]", &mut state), print("[0.33, 0.12]"));
+        assert_eq!(
+            repl_line(":round half-up", &mut state),
+            print("rounding half-up")
+        );
+        assert_eq!(repl_line("0.125", &mut state), print("0.13"));
+        assert_eq!(
+            repl_line(":digits sci 4", &mut state),
+            print("digits sci 4")
+        );
+        assert_eq!(repl_line("1/3", &mut state), print("3.3333e-1"));
+        // 输出格式不影响保存到 ans 中的值
+        assert_eq!(repl_line(":digits auto", &mut state), print("digits auto"));
+        assert_eq!(repl_line("ans * 3", &mut state), print("1"));
+        assert_eq!(
+            repl_line(":digits many", &mut state),
+            print("Error: unknown digits, expected auto, fixed N, sig N or sci N")
+        );
+        assert_eq!(
+            repl_line(":round down", &mut state),
+            print("Error: unknown rounding, expected half-up or half-even")
+        );
+    }
+
+    #[test]
+    fn test_repl_unit_mode_command() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+
+        assert_eq!(repl_line(":units", &mut state), print("unit mode on"));
+        assert_eq!(
+            repl_line("90 mph to km/h", &mut state),
+            print("144.84096 km/h")
+        );
+        assert_eq!(
+            repl_line("1 m + 1 s", &mut state),
+            print("Error: DimensionError: cannot add length and time")
+        );
+        assert_eq!(repl_line(":units", &mut state), print("unit mode off"));
+        assert_eq!(repl_line("1 + 1", &mut state), print("2"));
+    }
+
+    #[test]
+    fn test_repl_function_definition() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+
+        assert_eq!(
+            repl_line("f(x, y) = x * y", &mut state),
+            print("defined f(x, y)")
+        );
+        assert_eq!(repl_line("f(3, 4)", &mut state), print("12"));
+        assert_eq!(repl_line(":clear", &mut state), print("cleared"));
+        assert_eq!(
+            repl_line("f(3, 4)", &mut state),
+            print("Error: ParseError: Unknown function: f")
+        );
+    }
+
+    #[test]
+    fn test_repl_save_and_load_session() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+        let path = std::env::temp_dir().join(format!("calc_{}.session", std::process::id()));
+        let path = path.display().to_string();
+
+        repl_line("r = 2", &mut state);
+        repl_line("area(r) = pi * r^2", &mut state);
+        assert_eq!(
+            repl_line(&format!(":save {}", path), &mut state),
+            print(&format!("saved {}", path))
+        );
+        // 加载时替换当前的变量和函数
+        repl_line(":clear", &mut state);
+        repl_line("other = 1", &mut state);
+        assert_eq!(
+            repl_line(&format!(":load {}", path), &mut state),
+            print(&format!("loaded {}", path))
+        );
+        assert_eq!(
+            repl_line("area(r) / r", &mut state),
+            repl_line("2 * pi", &mut state)
+        );
+        assert!(
+            matches!(repl_line("other", &mut state), ReplOutput::Print(s) if s.starts_with("Error: "))
+        );
+        std::fs::remove_file(&path).unwrap();
+        // 文件不存在时保留当前会话
+        assert!(matches!(
+            repl_line(&format!(":load {}", path), &mut state),
+            ReplOutput::Print(s) if s.starts_with("Error: ParseError: cannot read ")
+        ));
+        assert_eq!(state.context.get("r"), Some(2.0));
+    }
+
+    #[test]
+    fn test_bitwise_operators() {
+        let int = |src: &str| evaluate_integer(src, false).unwrap().0;
+        assert_eq!(int("12 & 10"), 8.0);
+        assert_eq!(int("12 | 3"), 15.0);
+        assert_eq!(int("12 xor 10"), 6.0);
+        assert_eq!(int("1 << 10"), 1024.0);
+        assert_eq!(int("-16 >> 2"), -4.0);
+        assert_eq!(int("~0"), -1.0);
+        assert_eq!(int("~5 & 255"), 250.0);
+        // 优先级：移位高于 &，& 高于 xor，xor 高于 |，| 高于比较
+        assert_eq!(int("1 + 1 << 2"), 8.0);
+        assert_eq!(int("1 | 2 xor 3 & 6"), 1.0);
+        assert_eq!(int("(6 & 3) == 2"), 1.0);
+        assert_eq!(int("1 << 53"), 2f64.powi(53));
+        assert_eq!(int("-1 << 63"), i64::MIN as f64);
+        assert_eq!(int("~(-9007199254740991)"), 9007199254740990.0);
+        assert!(evaluate_integer("1 << 64", false).is_err());
+        // 移位丢失了有效位，或者结果无法精确表示
+        for src in ["1 << 63", "3 << 62", "(1 << 53) + 1 xor 0"] {
+            assert!(
+                matches!(
+                    evaluate_integer(src, false),
+                    Err(ExpError::MathError(MathError::Overflow))
+                ),
+                "{}",
+                src
+            );
+        }
+        // 超过 2^53 的操作数可能已经被舍入
+        for src in ["9007199254740993 xor 0", "(1 << 60) | 1", "~(1 << 60)"] {
+            match evaluate_integer(src, false) {
+                Err(ExpError::NotInteger(msg)) => {
+                    assert!(msg.ends_with("is not exactly representable"), "{}", msg)
+                }
+                other => panic!("expected inexact operand for {}, got {:?}", src, other),
+            }
+        }
+        assert!(evaluate_integer("1 << -1", false).is_err());
+        // 浮点数模式下不支持位运算
+        assert!(evaluate("6 & 3").is_err());
+        assert!(evaluate("~1").is_err());
+    }
+
+    #[test]
+    fn test_user_defined_functions() {
+        let mut context = EvalContext::new();
+        evaluate_with_context("f(x) = x^2 + 1", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("f(3)", &mut context).unwrap(), 10.0);
+        evaluate_with_context("hyp(a, b) = sqrt(a^2 + b^2)", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("hyp(3, 4) + f(0)", &mut context).unwrap(),
+            6.0
+        );
+
+        // 参数不会覆盖同名的全局变量
+        evaluate_with_context("x = 100", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("f(2) + x", &mut context).unwrap(),
+            105.0
+        );
+        // 函数体可以使用全局变量，在调用时读取
+        evaluate_with_context("g(t) = t + x", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("g(1)", &mut context).unwrap(), 101.0);
+
+        // 递归，条件表达式只计算被选中的分支
+        evaluate_with_context("fact(n) = n <= 1 ? 1 : n * fact(n - 1)", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("fact(10)", &mut context).unwrap(),
+            3628800.0
+        );
+        // 可以重新定义，也可以覆盖内置函数
+        evaluate_with_context("f(x) = 2 * x", &mut context).unwrap();
+        assert_eq!(evaluate_with_context("f(3)", &mut context).unwrap(), 6.0);
+    }
+
+    #[test]
+    fn test_user_defined_function_errors() {
+        let mut context = EvalContext::new();
+        evaluate_with_context("f(x) = x + 1", &mut context).unwrap();
+        match evaluate_with_context("f(1, 2)", &mut context) {
+            Err(ExpError::ParseError(msg)) => assert_eq!(msg, "f() takes 1 argument(s), got 2"),
+            other => panic!("expected arity error, got {:?}", other),
+        }
+        evaluate_with_context("loop(x) = loop(x)", &mut context).unwrap();
+        match evaluate_with_context("loop(1)", &mut context) {
+            Err(ExpError::ParseError(msg)) => {
+                assert_eq!(msg, "Maximum recursion depth exceeded in loop()")
+            }
+            other => panic!("expected recursion error, got {:?}", other),
+        }
+        // 参数必须是不重复的变量名，定义只能出现在语句开头
+        assert!(evaluate_with_context("h(1) = 2", &mut context).is_err());
+        assert!(evaluate_with_context("h(x, x) = x", &mut context).is_err());
+        assert!(evaluate_with_context("1 + h(x) = x", &mut context).is_err());
+        assert!(Expr::new("h(x) = x").eval().is_err());
+    }
+
+    #[test]
+    fn test_scoped_context() {
+        let mut context = EvalContext::new();
+        context.set("x", 1.0);
+        context.push_scope();
+        // 局部作用域可以读取外层的变量，赋值只写入局部作用域
+        assert_eq!(
+            evaluate_with_context("y = x + 1", &mut context).unwrap(),
+            2.0
+        );
+        assert_eq!(
+            evaluate_with_context("x = 10; x + y", &mut context).unwrap(),
+            12.0
+        );
+        context.push_scope();
+        assert_eq!(context.get("y"), Some(2.0));
+        assert_eq!(context.scope_depth(), 2);
+        assert!(context.pop_scope());
+        assert!(context.pop_scope());
+        assert!(!context.pop_scope());
+        // 离开作用域后局部变量被丢弃，全局变量不受影响
+        assert_eq!(context.get("x"), Some(1.0));
+        assert_eq!(context.get("y"), None);
+        assert_eq!(context.scope_depth(), 0);
+    }
+
+    #[test]
+    fn test_function_lexical_scope() {
+        let mut context = EvalContext::new();
+        evaluate_with_context("y = 10", &mut context).unwrap();
+        evaluate_with_context("f(x) = x + y", &mut context).unwrap();
+        evaluate_with_context("g(y) = f(1) * y", &mut context).unwrap();
+        // f 中的 y 是全局变量，不是调用者 g 的参数
+        assert_eq!(evaluate_with_context("g(2)", &mut context).unwrap(), 22.0);
+        // 参数只在函数内可见
+        assert!(evaluate_with_context("f(1) + x", &mut context).is_err());
+        // 调用出错时同样离开函数作用域
+        evaluate_with_context("h(t) = t / 0 + missing", &mut context).unwrap();
+        assert!(evaluate_with_context("h(1)", &mut context).is_err());
+        assert_eq!(context.scope_depth(), 0);
+        assert_eq!(context.get("t"), None);
+    }
+
+    #[test]
+    fn test_series_notation() {
+        assert_eq!(evaluate("sum(i, 1, 100, i^2)").unwrap(), 338350.0);
+        assert_eq!(evaluate("prod(k, 1, 5, k)").unwrap(), 120.0);
+        assert_eq!(evaluate("n = 4; prod(k, 1, n, k)").unwrap(), 24.0);
+        assert_eq!(evaluate("sum(i, 1, 3, sum(j, 1, i, i * j))").unwrap(), 25.0);
+        assert_eq!(evaluate("f(n) = sum(i, 1, n, i); f(4)").unwrap(), 10.0);
+        // 上界小于下界时为空
+        assert_eq!(evaluate("sum(i, 5, 1, i) + prod(i, 5, 1, i)").unwrap(), 1.0);
+        // 按语法识别记号，通项不含下标时每一项都相同
+        assert_eq!(evaluate("sum(i, 1, 10, 2)").unwrap(), 20.0);
+        assert_eq!(evaluate("x = 4; sum(x, 1, 2, 3)").unwrap(), 6.0);
+        // 第一个参数不是单独的变量名，或者参数个数不是四个时，是对参数求和的内置函数
+        assert_eq!(evaluate("x = 4; sum(+x, 1, 2, 3)").unwrap(), 10.0);
+        assert_eq!(evaluate("x = 4; sum(x, 1, 2)").unwrap(), 7.0);
+        // 下标变量只在通项内可见，不影响同名的外部变量
+        let mut context = EvalContext::new();
+        assert_eq!(
+            evaluate_with_context("i = 10; sum(i, 1, 3, i) + i", &mut context).unwrap(),
+            16.0
+        );
+        assert!(evaluate_with_context("sum(j, 1, 3, j + missing)", &mut context).is_err());
+        assert_eq!(context.scope_depth(), 0);
+        assert_eq!(context.get("j"), None);
+        // 会话中定义的同名函数优先
+        evaluate_with_context("prod(a, b, c, d) = a + b + c + d", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("prod(i, 1, 2, 3)", &mut context).unwrap(),
+            16.0
+        );
+    }
+
+    #[test]
+    fn test_series_notation_errors() {
+        assert!(matches!(
+            evaluate("sum(i, 1, 1e12, i)"),
+            Err(ExpError::ParseError(msg)) if msg.contains("maximum")
+        ));
+        // 嵌套的记号共用项数额度
+        assert!(matches!(
+            evaluate("sum(i, 1, 1000000, sum(j, 1, 1000000, j + i))"),
+            Err(ExpError::ParseError(msg)) if msg.contains("maximum")
+        ));
+        assert!(matches!(
+            evaluate("sum(i, 1, 1000, sum(j, 1, 1000, j))"),
+            Err(ExpError::ParseError(msg)) if msg.contains("maximum")
+        ));
+        assert_eq!(
+            evaluate("sum(i, 1, 100, sum(j, 1, 100, 1))").unwrap(),
+            10000.0
+        );
+        assert!(evaluate("sum(i, 1, 2.5, i)").is_err());
+        assert!(evaluate("prod(k, 1, 3)").is_err());
+        assert!(Expr::new("sum(i, 1, 3, i)").eval().is_err());
+        assert!(compile("sum(i, 1, 3, i)").is_err());
+    }
+
+    #[test]
+    fn test_eval_script() {
+        assert_eq!(eval_script("x = 3 + 4; y = x * 2; y - 1").unwrap(), 13.0);
+        // 换行同样分隔语句，支持注释和行尾的 `;`
+        let script = "
+            # 圆的面积
+            r = 2;       # 半径
+            area(r) = r^2 * 3
+
+            area(r) + r
+        ";
+        assert_eq!(eval_script(script).unwrap(), 14.0);
+        assert!(eval_script("x = 1\n2 *").is_err());
+        assert!(eval_script("   # only a comment").is_err());
+
+        // 脚本执行后，上下文中保留定义的变量和函数
+        let mut context = EvalContext::new();
+        eval_script_with_context("a = 2\ndouble(v) = 2 * v", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("double(a)", &mut context).unwrap(),
+            4.0
+        );
+    }
+
+    #[test]
+    fn test_variable_errors() {
+        match evaluate("y + 1") {
+            Err(ExpError::ParseError(msg)) => assert_eq!(msg, "Unknown variable: y"),
+            other => panic!("expected unknown variable, got {:?}", other),
+        }
+        assert!(evaluate("2 * x = 3").is_err());
+        assert!(evaluate("(x = 3)").is_err());
+        assert!(evaluate("x = ").is_err());
+        assert!(evaluate("x = 1;; x").is_err());
+        // 没有上下文时不支持赋值
+        assert!(Expr::new("x = 1").eval().is_err());
+    }
+
+    #[test]
+    fn test_syntax_error_span() {
+        match evaluate("1 + )") {
+            Err(ExpError::SyntaxError { span, lexeme, .. }) => {
+                assert_eq!(span, Span { offset: 4, len: 1 });
+                assert_eq!(lexeme, ")");
+            }
+            other => panic!("expected syntax error, got {:?}", other),
+        }
+        match evaluate("max(1 2.5)") {
+            Err(ExpError::SyntaxError { span, lexeme, .. }) => {
+                assert_eq!(span, Span { offset: 6, len: 3 });
+                assert_eq!(lexeme, "2.5");
+            }
+            other => panic!("expected syntax error, got {:?}", other),
+        }
+        // 无法识别的字符不再被静默忽略
+        assert!(matches!(
+            evaluate("1 @ 2"),
+            Err(ExpError::SyntaxError {
+                span: Span { offset: 2, len: 1 },
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_syntax_error_display() {
+        let err = evaluate("2 * (3 + 4").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ParseError: Expected closing parenthesis at position 10\n2 * (3 + 4\n          ^"
+        );
+        let err = evaluate("max(1 2.5)").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "ParseError: Expected ',' or ')' in function call '2.5' at position 6\nmax(1 2.5)\n      ^^^"
+        );
+    }
+
+    #[test]
+    fn test_diagnose_reports_all_errors() {
+        let messages = |input: &str| -> Vec<(String, usize)> {
+            diagnose(input)
+                .into_iter()
+                .map(|d| (d.message, d.span.offset))
+                .collect()
+        };
+        assert!(diagnose("1 + 2 * (3 - 4)").is_empty());
+        // 缺少操作数、多余的右括号和无法识别的字符都会被报告
+        assert_eq!(
+            messages("1 + * 2 ) @ 3"),
+            vec![
+                ("Unexpected token".to_string(), 4),
+                ("Unexpected token".to_string(), 8),
+                ("Unexpected token".to_string(), 10),
+            ]
+        );
+        assert_eq!(
+            messages("(1 + @ 2"),
+            vec![
+                ("Unexpected token".to_string(), 5),
+                ("Expected closing parenthesis".to_string(), 8),
+            ]
+        );
+        assert_eq!(
+            messages("max(1 2; 3 +"),
+            vec![
+                ("Expected ',' or ')' in function call".to_string(), 6),
+                ("Expected ',' or ')' in function call".to_string(), 7),
+                ("Unexpected end of input".to_string(), 12),
+            ]
+        );
+        // 同一个位置只报告一次，漏写的分隔符之后继续解析参数
+        assert_eq!(
+            messages("f(1 2)"),
+            vec![("Expected ',' or ')' in function call".to_string(), 4)]
+        );
+        assert_eq!(
+            messages("[1 2, 3 4]"),
+            vec![
+                ("Expected ',' or ']' in vector".to_string(), 3),
+                ("Expected ',' or ']' in vector".to_string(), 8),
+            ]
+        );
+        // 第一条诊断信息和普通解析返回的错误一致
+        let first = diagnose("2 * (3 + 4").remove(0);
+        assert_eq!(
+            format!("ParseError: {}", first.render("2 * (3 + 4")),
+            evaluate("2 * (3 + 4").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_check_without_evaluating() {
+        let errors = |input: &str| -> Vec<(String, usize)> {
+            Expr::check(input)
+                .unwrap_err()
+                .into_iter()
+                .map(|d| (d.message, d.span.offset))
+                .collect()
+        };
+        assert_eq!(Expr::check("1 + 2 * (3 - 4)"), Ok(()));
+        // 未定义的变量、除以0和未知函数只在求值时出错，语法上是正确的
+        assert_eq!(Expr::check("y / 0 + foo(1, 2)"), Ok(()));
+        assert_eq!(Expr::check("x = 3; x * 2"), Ok(()));
+        // 内置函数的参数个数和求值时使用同一张函数表检查，位置是函数名
+        assert_eq!(
+            errors("sqrt() + sqrt(1, 2)"),
+            vec![
+                ("sqrt() takes exactly one argument, got 0".to_string(), 0),
+                ("sqrt() takes exactly one argument, got 2".to_string(), 9),
+            ]
+        );
+        assert_eq!(
+            errors("1 + dot()"),
+            vec![("dot() takes exactly 2 arguments, got 0".to_string(), 4)]
+        );
+        assert_eq!(
+            errors("log(1, 2, 3) * max()"),
+            vec![
+                ("log() takes one or two arguments, got 3".to_string(), 0),
+                ("max() requires at least one argument".to_string(), 15),
+            ]
+        );
+        assert_eq!(
+            Expr::check("log(8, 2) + sum(i, 1, 3, i) + mean([1, 2]) + transpose([1])"),
+            Ok(())
+        );
+        assert_eq!(
+            errors("(1 + 2"),
+            vec![("Expected closing parenthesis".to_string(), 6)]
+        );
+        assert_eq!(
+            errors("1 + * 2 ) @ 3"),
+            vec![
+                ("Unexpected token".to_string(), 4),
+                ("Unexpected token".to_string(), 8),
+                ("Unexpected token".to_string(), 10),
+            ]
+        );
+        assert_eq!(
+            errors("2 *"),
+            vec![("Unexpected end of input".to_string(), 3)]
+        );
+        // 位置相对于原始输入，包括前导空白和电子表格风格的 `=`
+        assert_eq!(
+            errors("  =1 +"),
+            vec![("Unexpected end of input".to_string(), 6)]
+        );
+        assert_eq!(
+            errors(" ="),
+            vec![("Empty expression after '='".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_repl_lists_all_syntax_errors() {
+        let mut state = ReplState::default();
+        match repl_line("1 + * 2 )", &mut state) {
+            ReplOutput::Print(output) => {
+                assert_eq!(
+                    output
+                        .matches("Error: ParseError: Unexpected token")
+                        .count(),
+                    2
+                )
+            }
+            _ => panic!("expected output"),
+        }
+    }
+
+    #[test]
+    fn test_power_semantics() {
+        let eval = |input: &str, semantics: PowerSemantics| {
+            Expr::new(input)
+                .with_power_semantics(semantics)
+                .eval()
+                .unwrap()
+        };
+        for (input, math, excel) in [
+            ("-2^2", -4.0, 4.0),
+            ("2^-3", 0.125, 0.125),
+            ("-2^-2", -0.25, 0.25),
+            ("2^-1^2", 0.5, 0.25),
+            ("2^3^2", 512.0, 64.0),
+            ("-2^3^2", -512.0, 64.0),
+            ("--2^2", 4.0, 4.0),
+            ("3 - -2^2", 7.0, -1.0),
+            ("-(2)^2", -4.0, 4.0),
+            ("-3!^2", -36.0, 36.0),
+            ("2 * -3^2", -18.0, 18.0),
+        ] {
+            assert_eq!(eval(input, PowerSemantics::Math), math, "{}", input);
+            assert_eq!(eval(input, PowerSemantics::Excel), excel, "{}", input);
+        }
+        // 默认是数学语义
+        assert_eq!(Expr::new("-2^2").eval().unwrap(), -4.0);
+        assert!(Expr::new("2^-")
+            .with_power_semantics(PowerSemantics::Excel)
+            .eval()
+            .is_err());
+    }
+
+    #[test]
+    fn test_unary_plus() {
+        assert_eq!(evaluate("+5").unwrap(), 5.0);
+        assert_eq!(evaluate("3 * +2").unwrap(), 6.0);
+        assert_eq!(evaluate("+-2").unwrap(), -2.0);
+        assert_eq!(evaluate("-+(1 + 1)").unwrap(), -2.0);
+        assert!(evaluate("+").is_err());
+        assert!(evaluate("5*+").is_err());
+    }
+
+    #[test]
+    fn test_invalid_operator_adjacency() {
+        assert!(evaluate("5*/3").is_err());
+        assert!(evaluate("5+*3").is_err());
+        assert!(evaluate("5^/2").is_err());
+        assert!(evaluate("5-").is_err());
+        assert!(evaluate("-").is_err());
+    }
+
+    #[test]
+    fn test_deeply_nested_unary_minus() {
+        let src = format!("{}1", "-".repeat(100_000));
+        assert!(Expr::new(&src).eval().is_err());
+    }
+
+    #[test]
+    fn test_custom_precedence_table() {
+        assert_eq!(Expr::new("2 + 3 * 4").eval().unwrap(), 14.0);
+
+        // + 和 * 优先级相同且左结合时，从左到右计算
+        let flat = PrecedenceTable::default().set('+', 2, ASSOC_LEFT);
+        assert_eq!(
+            Expr::new("2 + 3 * 4").with_precedence(flat).eval().unwrap(),
+            20.0
+        );
+
+        // ^ 改为左结合
+        let left_power = PrecedenceTable::default().set('^', 3, ASSOC_LEFT);
+        assert_eq!(Expr::new("2 ^ 3 ^ 2").eval().unwrap(), 512.0);
+        assert_eq!(
+            Expr::new("2 ^ 3 ^ 2")
+                .with_precedence(left_power)
+                .eval()
+                .unwrap(),
+            64.0
+        );
+    }
+
+    // 测试用的自定义运算符：`//` 为向下取整的除法，`div` 为向零取整的除法，`√` 为前缀平方根，`^^` 为右结合的幂
+    fn custom_operators() -> OperatorTable {
+        OperatorTable::new()
+            .binary("//", 2, ASSOC_LEFT, |a, b| Ok((a / b).floor()))
+            .unwrap()
+            .binary("div", 2, ASSOC_LEFT, |a, b| match b {
+                0.0 => Err(ExpError::MathError(MathError::DivisionByZero)),
+                _ => Ok((a / b).trunc()),
+            })
+            .unwrap()
+            .binary("^^", 3, ASSOC_RIGHT, |a, b| Ok(a.powf(b)))
+            .unwrap()
+            .prefix("√", |x| Ok(x.sqrt()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_custom_operators() {
+        let operators = custom_operators();
+        let eval = |input: &str| Expr::new(input).with_operators(&operators).eval();
+        assert_eq!(eval("7 // 2 + 1").unwrap(), 4.0);
+        assert_eq!(eval("1 + -7 // 2").unwrap(), -3.0);
+        assert_eq!(eval("2 * 7 // 4").unwrap(), 3.0);
+        assert_eq!(eval("-7 div 2").unwrap(), -3.0);
+        assert_eq!(eval("2 ^^ 3 ^^ 2").unwrap(), 512.0);
+        assert_eq!(eval("√16 + 1").unwrap(), 5.0);
+        assert_eq!(eval("2 * √(3 + 6)").unwrap(), 6.0);
+        // 单词运算符只匹配完整的单词，`/` 仍然是普通的除法
+        let mut context = EvalContext::new();
+        context.set("divide", 10.0);
+        let result = Expr::new("divide div 4 + 1 / 2")
+            .with_operators(&operators)
+            .with_context(&mut context)
+            .eval();
+        assert_eq!(result.unwrap(), 2.5);
+        assert!(matches!(
+            eval("1 div 0"),
+            Err(ExpError::MathError(MathError::DivisionByZero))
+        ));
+        // 二元运算符不能作为前缀，没有注册运算符表时 `//` 是语法错误
+        assert!(matches!(eval("// 2"), Err(ExpError::SyntaxError { .. })));
+        assert!(matches!(
+            Expr::new("7 // 2").eval(),
+            Err(ExpError::SyntaxError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_custom_operator_formatting() {
+        let operators = custom_operators();
+        for (input, expected) in [
+            ("7 // 2 + 1", "(7 // 2) + 1"),
+            ("(1 + 2) // 3", "(1 + 2) // 3"),
+            ("x div y div z", "(x div y) div z"),
+            ("√x * 2", "√x * 2"),
+        ] {
+            let ast = Expr::new(input).with_operators(&operators).parse().unwrap();
+            let formatted = format::format(&ast);
+            assert_eq!(formatted, expected);
+            let reparsed = Expr::new(&formatted)
+                .with_operators(&operators)
+                .parse()
+                .unwrap();
+            assert_eq!(reparsed, ast);
+        }
+    }
+
+    #[test]
+    fn test_invalid_custom_operators() {
+        let register =
+            |symbol: &str| OperatorTable::new().binary(symbol, 2, ASSOC_LEFT, |a, _| Ok(a));
+        for symbol in ["", "+", "<=", "xor", "sin", "pi", "1x", "a+", "a b"] {
+            assert!(register(symbol).is_err(), "{:?} should be rejected", symbol);
+        }
+        for symbol in ["//", "±", "div", "+-", "<=>"] {
+            assert!(register(symbol).is_ok(), "{:?} should be accepted", symbol);
+        }
+        let register = |precedence: i32, assoc: i32| {
+            OperatorTable::new().binary("??", precedence, assoc, |a, _| Ok(a))
+        };
+        assert!(register(LOWEST_PRECEDENCE - 1, ASSOC_LEFT).is_err());
+        assert!(register(i32::MAX, ASSOC_LEFT).is_err());
+        assert!(register(2, 2).is_err());
+        assert!(register(2, -1).is_err());
+        // 最高允许的优先级仍然可以解析
+        let operators = register(i32::MAX - 1, ASSOC_LEFT).unwrap();
+        assert_eq!(
+            Expr::new("1 ?? 2 ?? 3")
+                .with_operators(&operators)
+                .eval()
+                .unwrap(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn test_comparison_and_boolean_operators() {
+        assert_eq!(evaluate("3 < 4").unwrap(), 1.0);
+        assert_eq!(evaluate("3 >= 4").unwrap(), 0.0);
+        assert_eq!(evaluate("4 <= 4").unwrap(), 1.0);
+        assert_eq!(evaluate("2 + 2 == 4").unwrap(), 1.0);
+        assert_eq!(evaluate("0.1 + 0.2 == 0.3").unwrap(), 1.0);
+        assert_eq!(evaluate("1 != 1").unwrap(), 0.0);
+        // 比较低于算术运算，&& 高于 ||
+        assert_eq!(evaluate("1 + 1 > 1 * 1").unwrap(), 1.0);
+        assert_eq!(evaluate("1 || 0 && 0").unwrap(), 1.0);
+        assert_eq!(evaluate("(1 || 0) && 0").unwrap(), 0.0);
+        assert_eq!(evaluate("a = 5; b = 7; (a > 3) && (b < 10)").unwrap(), 1.0);
+        assert_eq!(evaluate("a = 2; b = 7; a > 3 || b > 10").unwrap(), 0.0);
+        // 按位与只能在整数模式下使用，`3! = 6` 需要空格才能与 `!=` 区分
+        assert!(evaluate("1 & 1").is_err());
+        assert_eq!(evaluate("3! == 6").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_expr_epsilon() {
+        assert_eq!(Expr::new("0.1 + 0.2 == 0.3").eval().unwrap(), 1.0);
+        let eval = |src: &str, epsilon: f64| Expr::new(src).with_epsilon(epsilon).eval().unwrap();
+        assert_eq!(eval("1 == 1.001", DEFAULT_EPSILON), 0.0);
+        assert_eq!(eval("1 == 1.001", 0.01), 1.0);
+        assert_eq!(eval("1 != 1.001", 0.01), 0.0);
+        // 容差为 0 时按精确比较
+        assert_eq!(eval("0.1 + 0.2 == 0.3", 0.0), 0.0);
+    }
+
+    #[test]
+    fn test_conditional_expression() {
+        assert_eq!(evaluate("1 ? 2 : 3").unwrap(), 2.0);
+        assert_eq!(evaluate("0 ? 2 : 3").unwrap(), 3.0);
+        assert_eq!(evaluate("2 > 1 ? 10 + 1 : 20").unwrap(), 11.0);
+        // 右结合
+        assert_eq!(evaluate("x = 5; x < 0 ? -1 : x == 0 ? 0 : 1").unwrap(), 1.0);
+        assert_eq!(evaluate("max(1 ? 4 : 5, 2)").unwrap(), 4.0);
+        assert!(evaluate("1 ? 2").is_err());
+        assert!(evaluate("1 ? 2 : ").is_err());
+    }
+
+    #[test]
+    fn test_conditional_skips_untaken_branch() {
+        // 未选中分支中的错误不会被计算
+        assert_eq!(evaluate("0 ? y : 3").unwrap(), 3.0);
+        assert_eq!(
+            evaluate_integer("1 ? 4 : 1 / 0", false).unwrap(),
+            (4.0, false)
+        );
+        assert!(evaluate_integer("0 ? 4 : 1 / 0", false).is_err());
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let implicit = |src: &str| {
+            let mut context = EvalContext::new();
+            context.set("x", 5.0);
+            Expr::new(src)
+                .with_context(&mut context)
+                .with_implicit_multiplication(true)
+                .eval()
+        };
+        assert_eq!(implicit("2(3+4)").unwrap(), 14.0);
+        assert_eq!(implicit("(1+2)(3+4)").unwrap(), 21.0);
+        assert_eq!(implicit("3x").unwrap(), 15.0);
+        assert_eq!(implicit("2x + 1").unwrap(), 11.0);
+        assert_eq!(implicit("2sqrt(16)").unwrap(), 8.0);
+        // 与 `*` 的优先级相同
+        assert_eq!(implicit("1 + 2(3)").unwrap(), 7.0);
+        assert_eq!(implicit("2^2(3)").unwrap(), 12.0);
+        // 只在数字或 `)` 之后补乘号
+        assert!(implicit("x(2)").is_err());
+        assert!(implicit("2 3").is_err());
+
+        // 默认严格模式下仍然是语法错误
+        assert!(evaluate("2(3+4)").is_err());
+        assert!(evaluate("x = 5; 3x").is_err());
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(evaluate("5!").unwrap(), 120.0);
+        assert_eq!(evaluate("0!").unwrap(), 1.0);
+        assert_eq!(evaluate("(2+1)!").unwrap(), 6.0);
+        assert_eq!(evaluate("3!!").unwrap(), 720.0);
+        assert_eq!(evaluate("2^3!").unwrap(), 64.0);
+        assert_eq!(evaluate("-3!").unwrap(), -6.0);
+        assert_eq!(evaluate("1 + 3! * 2").unwrap(), 13.0);
+    }
+
+    #[test]
+    fn test_factorial_errors() {
+        assert!(evaluate("(-1)!").is_err());
+        assert!(evaluate("2.5!").is_err());
+        assert!(evaluate("!3").is_err());
+        assert!(evaluate("1000000000000000000!").unwrap().is_infinite());
+        match Expr::new("171!").with_overflow_check(true).eval() {
+            Err(ExpError::Overflow) => {}
+            other => panic!("expected overflow error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_user_functions() {
+        let mut functions: UserFunctions = HashMap::new();
+        functions.insert(
+            "double".to_string(),
+            Box::new(|args| match args {
+                [x] => Ok(2.0 * x),
+                _ => Err(ExpError::ParseError(
+                    "double() takes one argument".to_string(),
+                )),
+            }),
+        );
+        // 同名的自定义函数覆盖内置函数
+        functions.insert("max".to_string(), Box::new(|_| Ok(-1.0)));
+
+        assert_eq!(
+            evaluate_with_functions("double(21)", &functions).unwrap(),
+            42.0
+        );
+        assert_eq!(
+            evaluate_with_functions("=double(sum(1, 2)) + 1", &functions).unwrap(),
+            7.0
+        );
+        assert_eq!(
+            evaluate_with_functions("max(1, 2)", &functions).unwrap(),
+            -1.0
+        );
+        assert!(evaluate_with_functions("double(1, 2)", &functions).is_err());
+        assert!(evaluate("double(21)").is_err());
+    }
+
+    // 按股票代码查询价格，ma(n) 返回最近 n 天的均价
+    struct Prices(HashMap<&'static str, f64>);
+
+    impl FunctionProvider for Prices {
+        fn call(&self, name: &str, args: &[Value]) -> Option<Result<f64>> {
+            let result = match (name, args) {
+                ("price", [Value::Text(symbol)]) => self
+                    .0
+                    .get(symbol)
+                    .copied()
+                    .ok_or_else(|| ExpError::ParseError(format!("Unknown symbol: {}", symbol))),
+                ("price", _) => Err(ExpError::ParseError("price() takes a symbol".to_string())),
+                ("ma", [Value::Number(days)]) => Ok(100.0 + days),
+                _ => return None,
+            };
+            Some(result)
+        }
+    }
+
+    #[test]
+    fn test_function_provider() {
+        let prices = Prices(HashMap::from([("MSFT", 420.5), ("AAPL", 230.0)]));
+        let eval = |input: &str| {
+            let mut context = EvalContext::new();
+            Expr::new(input)
+                .with_context(&mut context)
+                .with_function_provider(&prices)
+                .eval()
+        };
+        assert_eq!(eval("price(\"MSFT\") * 2").unwrap(), 841.0);
+        assert_eq!(eval("price(\"AAPL\") - ma(30)").unwrap(), 100.0);
+        assert_eq!(eval("x = 10; max(ma(x), 1)").unwrap(), 110.0);
+        // 会话中定义的函数和内置函数优先于 FunctionProvider
+        assert_eq!(eval("ma(n) = n; ma(5)").unwrap(), 5.0);
+        assert!(
+            matches!(eval("price(\"IBM\")"), Err(ExpError::ParseError(e)) if e == "Unknown symbol: IBM")
+        );
+        assert!(
+            matches!(eval("price(1)"), Err(ExpError::ParseError(e)) if e == "price() takes a symbol")
+        );
+        assert!(
+            matches!(eval("volume(\"MSFT\")"), Err(ExpError::ParseError(e)) if e == "Unknown function: volume")
+        );
+        assert!(
+            matches!(eval("max(\"MSFT\")"), Err(ExpError::ParseError(e)) if e == "max() does not accept string arguments")
+        );
+        // 字符串只能作为函数参数
+        assert!(eval("\"MSFT\" + 1").is_err());
+        assert!(eval("price(\"MSFT)").is_err());
+        assert!(evaluate("price(\"MSFT\")").is_err());
+        assert_eq!(
+            format::format(&parse("price(\"MSFT\")*2").unwrap()),
+            "price(\"MSFT\") * 2"
+        );
+    }
+
+    #[test]
+    fn test_radix_literals() {
+        assert_eq!(evaluate("0xFF").unwrap(), 255.0);
+        assert_eq!(evaluate("0Xff + 1").unwrap(), 256.0);
+        assert_eq!(evaluate("0b1010").unwrap(), 10.0);
+        assert_eq!(evaluate("0o755").unwrap(), 493.0);
+        assert_eq!(evaluate("0 + 0x10").unwrap(), 16.0);
+        assert_eq!(
+            evaluate_integer("0xF0 | 0b1111", false).unwrap(),
+            (255.0, false)
+        );
+        // 前缀后面缺少数字或出现不属于该进制的数字
+        assert!(evaluate("0x").is_err());
+        assert!(evaluate("0b102").is_err());
+        assert!(evaluate("0o8").is_err());
+        assert!(evaluate("0xFG").is_err());
+        // 超出范围或无法精确表示的字面量报错，而不是悄悄舍入
+        assert_eq!(
+            evaluate("0x7FFFFFFFFFFFFC00").unwrap(),
+            9223372036854774784.0
+        );
+        assert_eq!(evaluate("0x20000000000000").unwrap(), 2f64.powi(53));
+        for src in [
+            "0xFFFFFFFFFFFFFFFF",
+            "0x8000000000000000",
+            "0x20000000000001",
+        ] {
+            match evaluate(src) {
+                Err(ExpError::SyntaxError {
+                    message, lexeme, ..
+                }) => {
+                    assert_eq!(message, "Integer literal out of range");
+                    assert_eq!(lexeme, src);
+                }
+                other => panic!("expected out of range error for {}, got {:?}", src, other),
+            }
+        }
+        assert!(matches!(
+            expression_parsing_algorithm("0xFFFFFFFFFFFFFFFF"),
+            Err(ExpError::ShuntingYardError(
+                ShuntingYardError::InvalidCharacter { .. }
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_scientific_notation() {
+        assert_eq!(evaluate("1.5e3").unwrap(), 1500.0);
+        assert_eq!(evaluate("2E-4").unwrap(), 0.0002);
+        assert_eq!(evaluate("1e+2 + 1").unwrap(), 101.0);
+        assert_eq!(evaluate(".5e1").unwrap(), 5.0);
+        assert_eq!(evaluate("2e3 - 1e3").unwrap(), 1000.0);
+        // 指数部分不完整时 e 不属于数字，`1e` 是数字后跟常量 e，`e5` 是未知变量
+        assert!(evaluate("1e").is_err());
+        assert!(evaluate("1e+").is_err());
+        assert!(evaluate("e5").is_err());
+        assert!(evaluate("1e3e").is_err());
+        // e 单独出现时仍然是常量
+        assert_eq!(evaluate("2 * e").unwrap(), 2.0 * std::f64::consts::E);
+        assert_eq!(evaluate_integer("1e3", false).unwrap(), (1000.0, false));
+    }
+
+    #[test]
+    fn test_decimal_point() {
+        assert_eq!(evaluate("1.5 + 1").unwrap(), 2.5);
+        // 默认按浮点数计算，除法不会截断
+        assert_eq!(evaluate("7 / 2").unwrap(), 3.5);
+        assert_eq!(evaluate("-10 / 4").unwrap(), -2.5);
+        assert_eq!(evaluate(".5 * 4").unwrap(), 2.0);
+        assert_eq!(evaluate("sum(1.5, 2.5)").unwrap(), 4.0);
+        assert!(evaluate("1.2.3").is_err());
+        // 只有小数点、没有数字时报告语法错误，而不是 panic
+        for input in [".", "1+.", "1 + . * 2", ".e5"] {
+            assert!(
+                matches!(evaluate(input), Err(ExpError::SyntaxError { .. })),
+                "{:?} should be a syntax error",
+                input
+            );
+        }
+        assert!(matches!(
+            evaluate_with_format("1 + ,", NumberFormat::decimal_comma()),
+            Err(ExpError::SyntaxError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decimal_comma_format() {
+        let format = NumberFormat::decimal_comma();
+        let result = evaluate_with_format("3,14 + 1", format).unwrap();
+        assert!((result - 4.14).abs() < 1e-12);
+        assert_eq!(evaluate_with_format("sum(1,5; 2)", format).unwrap(), 3.5);
+        // 默认格式下 `,` 仍然是参数分隔符
+        assert!(evaluate("3,14 + 1").is_err());
+        assert_eq!(evaluate_with_format("2,5e2", format).unwrap(), 250.0);
+    }
+
+    #[test]
+    fn test_lexemes_borrow_input() {
+        let input = "max(x_1, 2.5e1) + price(\"MSFT\") xor 0b11";
+        let lexemes: Vec<(Lexeme, Span)> = Tokenizer::new(input).lexemes().collect();
+        assert_eq!(
+            lexemes
+                .iter()
+                .map(|(lexeme, _)| lexeme.clone())
+                .collect::<Vec<_>>(),
+            [
+                Lexeme::Ident("max"),
+                Lexeme::Symbol(Token::LParen),
+                Lexeme::Ident("x_1"),
+                Lexeme::Symbol(Token::Comma),
+                Lexeme::Number(25.0),
+                Lexeme::Symbol(Token::RParen),
+                Lexeme::Symbol(Token::Plus),
+                Lexeme::Ident("price"),
+                Lexeme::Symbol(Token::LParen),
+                Lexeme::Str("MSFT"),
+                Lexeme::Symbol(Token::RParen),
+                Lexeme::Symbol(Token::Xor),
+                Lexeme::Number(3.0),
+            ]
+        );
+        // 标识符和字符串是输入的切片，而不是复制出来的字符串
+        let Lexeme::Ident(name) = lexemes[2].0 else {
+            panic!("expected an identifier");
+        };
+        assert!(input.as_bytes().as_ptr_range().contains(&name.as_ptr()));
+        assert_eq!(lexemes[4].1, Span { offset: 9, len: 5 });
+        // 位置以字符为单位，多字节字符之后也正确
+        let spans: Vec<Span> = Tokenizer::new("π × 2")
+            .lexemes()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans[2], Span { offset: 4, len: 1 });
+        // 转换为 Token 后与直接迭代 Tokenizer 的结果相同
+        let tokens: Vec<Token> = Tokenizer::new(input).map(|(token, _)| token).collect();
+        let converted: Vec<Token> = lexemes
+            .into_iter()
+            .map(|(lexeme, _)| lexeme.into())
+            .collect();
+        assert_eq!(tokens, converted);
+    }
+
+    #[test]
+    fn test_conflicting_separators_rejected() {
+        let format = NumberFormat {
+            decimal_separator: ',',
+            arg_separator: ',',
+        };
+        assert!(evaluate_with_format("3,14", format).is_err());
+        assert!(Expr::with_format("1", format).is_err());
+    }
+
+    #[test]
+    fn test_overflow_check() {
+        // 默认不检查，inf 和 NaN 照常返回
+        assert!(Expr::new("2 ^ 1024").eval().unwrap().is_infinite());
+        assert!(Expr::new("0 / 0").eval().unwrap().is_nan());
+
+        match Expr::new("2 ^ 1024").with_overflow_check(true).eval() {
+            Err(ExpError::Overflow) => {}
+            other => panic!("expected overflow error, got {:?}", other),
+        }
+        match Expr::new("0 / 0").with_overflow_check(true).eval() {
+            Err(ExpError::Overflow) => {}
+            other => panic!("expected overflow error, got {:?}", other),
+        }
+        assert!(Expr::new("sum(2 ^ 1023, 2 ^ 1023)")
+            .with_overflow_check(true)
+            .eval()
+            .is_err());
+        assert_eq!(
+            Expr::new("2 ^ 10")
+                .with_overflow_check(true)
+                .eval()
+                .unwrap(),
+            1024.0
+        );
+    }
+
+    #[test]
+    fn test_evaluate_spreadsheet_prefix() {
+        assert_eq!(evaluate("=1+2").unwrap(), 3.0);
+        assert_eq!(evaluate("  =1+2*3").unwrap(), 7.0);
+        assert_eq!(evaluate("1+2").unwrap(), 3.0);
+    }
+
+    #[test]
+    fn test_eval_batch_indexed_preserves_order() {
+        let results = eval_batch_indexed(&["1+1", "2*", "3*3", "(4"], BatchMode::CollectAll);
+        let indices: Vec<usize> = results.iter().map(|(i, _)| *i).collect();
+        assert_eq!(indices, vec![0, 1, 2, 3]);
+        assert_eq!(*results[0].1.as_ref().unwrap(), 2.0);
+        assert!(results[1].1.is_err());
+        assert_eq!(*results[2].1.as_ref().unwrap(), 9.0);
+        assert!(results[3].1.is_err());
+    }
+
+    #[test]
+    fn test_eval_batch_indexed_fail_fast() {
+        let results = eval_batch_indexed(&["1+1", "2*", "3*3"], BatchMode::FailFast);
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].0, 1);
+        assert!(results[1].1.is_err());
+    }
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands("1000000"), "1,000,000");
+        assert_eq!(group_thousands("1234567.891"), "1,234,567.891");
+        assert_eq!(group_thousands("-1234"), "-1,234");
+        assert_eq!(group_thousands("999"), "999");
+        assert_eq!(group_thousands("0.5"), "0.5");
+    }
+
+    #[test]
+    fn test_evaluate_bare_equals() {
+        assert!(evaluate("=").is_err());
+        assert!(evaluate(" =  ").is_err());
+    }
+
+    #[test]
+    fn test_evaluate_prefix_error_positions() {
+        // 错误位置和回显的输入都相对于去掉 `=` 之前的原始输入
+        for (input, offset) in [("==1", 1), ("  =1+*2", 5)] {
+            match evaluate(input) {
+                Err(ExpError::SyntaxError { span, source, .. }) => {
+                    assert_eq!(span.offset, offset, "{}", input);
+                    assert_eq!(source, input);
+                }
+                other => panic!("expected syntax error for {}, got {:?}", input, other),
+            }
+        }
+        let message = evaluate("==1").unwrap_err().to_string();
+        assert!(message.ends_with("==1\n ^"), "{}", message);
+    }
+
+    #[test]
+    fn test_integer_mode_promotes_fractional_power() {
+        assert_eq!(evaluate_integer("2^-1", true).unwrap(), (0.5, true));
+        assert_eq!(
+            evaluate_integer("2 ^ 0.5", true).unwrap(),
+            (2f64.sqrt(), true)
+        );
+        // 提升后整个表达式按浮点数计算，7/2 不再取整
+        assert_eq!(evaluate_integer("7/2 + 2^-1", true).unwrap(), (4.0, true));
+        // 不允许提升时报错，负数次幂是 MathError
+        assert!(matches!(
+            evaluate_integer("2^-1", false),
+            Err(ExpError::MathError(MathError::NegativeExponent))
+        ));
+        assert!(matches!(
+            evaluate_integer("2 ^ 0.5", false),
+            Err(ExpError::NotInteger(_))
+        ));
+    }
+
+    #[test]
+    fn test_integer_mode_stays_integer() {
+        // 整数除法向零取整
+        assert_eq!(
+            evaluate_integer("7/2*2 + 2^3", true).unwrap(),
+            (14.0, false)
+        );
+        assert_eq!(evaluate_integer("-7/2", false).unwrap(), (-3.0, false));
+        assert!(evaluate_integer("1/0", true).is_err());
+        assert_eq!(evaluate_integer("-7 % 3", false).unwrap(), (-1.0, false));
+        assert!(evaluate_integer("7 % 0", false).is_err());
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(evaluate("7 % 3").unwrap(), 1.0);
+        assert_eq!(evaluate("5.5 % 2").unwrap(), 1.5);
+        // 与乘除同级，从左到右结合
+        assert_eq!(evaluate("2 * 7 % 4").unwrap(), 2.0);
+        assert_eq!(evaluate("1 + 7 % 4").unwrap(), 4.0);
+        // 负数操作数：余数的符号与被除数相同
+        assert_eq!(evaluate("-7 % 3").unwrap(), -1.0);
+        assert_eq!(evaluate("7 % -3").unwrap(), 1.0);
+        assert_eq!(evaluate("-7 % -3").unwrap(), -1.0);
+        assert!(evaluate("5 % 0").unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_integer_mode_checked_arithmetic() {
+        for src in ["1 / 0", "7 % 0", "1 / (2 - 2)"] {
+            match evaluate_integer(src, false) {
+                Err(ExpError::MathError(MathError::DivisionByZero)) => {}
+                other => panic!("expected division by zero for {}, got {:?}", src, other),
+            }
+        }
+        for src in [
+            "2 ^ 63",
+            "9223372036854775807 * 2",
+            "30!",
+            "2 ^ 62 * 4",
+            // 结果在 i64 范围内，但无法用 f64 精确表示
+            "2 ^ 53 + 1",
+            "2 ^ 62 + 1",
+            // i64::MIN 的相反数和 i64::MIN / -1
+            "-(-(2 ^ 62) * 2)",
+            "(-(2 ^ 62) * 2) / -1",
+            "2 ^ 10000000000",
+        ] {
+            match evaluate_integer(src, false) {
+                Err(ExpError::MathError(MathError::Overflow)) => {}
+                other => panic!("expected overflow for {}, got {:?}", src, other),
+            }
+        }
+        assert_eq!(
+            evaluate_integer("2 ^ 62", false).unwrap(),
+            (2f64.powi(62), false)
+        );
+        // i64::MIN 在范围内
+        for src in ["-(2 ^ 62) * 2", "(-2) ^ 63", "-(2 ^ 62) - 2 ^ 62"] {
+            assert_eq!(
+                evaluate_integer(src, false).unwrap(),
+                (i64::MIN as f64, false),
+                "{}",
+                src
+            );
+        }
+        assert_eq!(
+            evaluate_integer("2 ^ 53 + 2", false).unwrap(),
+            (9007199254740994.0, false)
+        );
+        assert_eq!(
+            evaluate_integer("1 ^ 10000000001 + (-1) ^ 10000000001", false).unwrap(),
+            (0.0, false)
+        );
+        assert_eq!(
+            evaluate_integer("2 ^ -1", false).unwrap_err().to_string(),
+            "MathError: negative exponent"
+        );
+        assert_eq!(
+            evaluate_integer("1 / 0", false).unwrap_err().to_string(),
+            "MathError: division by zero"
+        );
+        // 浮点数模式仍然按 IEEE 754 计算
+        assert!(evaluate("1 / 0").unwrap().is_infinite());
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_evaluate_checked_engines_agree() {
+        let cases = [
+            "1+2*3",
+            "(1+2)*3",
+            "10/4",
+            "7/2",
+            "2^10",
+            "0.1+0.2",
+            "3 + 4 * 2 / ( 1 - 5 ) ^ 2",
+            "92 + 5 + 5 * 27 - (92 - 12) / 4 + 26",
+            "=8-3-2",
+            "17 % 5 * 2",
+        ];
+        for case in cases {
+            assert!(
+                evaluate_checked(case).is_ok(),
+                "engines disagree on {}",
+                case
+            );
+        }
+        assert_eq!(evaluate_checked("7/2").unwrap(), 3.5);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_evaluate_checked_shares_precedence_table() {
+        // 两种解析方式共用优先级表，`^` 都是右结合（2^9=512）
+        assert_eq!(evaluate_checked("2^3^2").unwrap(), 512.0);
+        assert_eq!(evaluate_checked("-2^2").unwrap(), -4.0);
+        // 调度场解析不支持函数调用
+        assert!(matches!(
+            evaluate_checked("max(1,2)"),
+            Err(ExpError::ParseError(_))
+        ));
+    }
+
+    #[test]
+    fn test_public_api() {
+        // 解析一次，换不同的变量反复求值
+        let ast = parse("=x * 2 + 1").unwrap();
+        assert_eq!(ast, Expr::new("x * 2 + 1").parse().unwrap());
+        let mut context = EvalContext::new();
+        context.set("x", 3.0);
+        assert_eq!(
+            evaluate_with_context("x * 2 + 1", &mut context).unwrap(),
+            7.0
+        );
+        assert!(matches!(eval(&ast), Err(ExpError::ParseError(_))));
+        assert!(matches!(parse("1 +"), Err(ExpError::SyntaxError { .. })));
+        let tokens: Vec<Token> = Tokenizer::new("1 + x").map(|(token, _)| token).collect();
+        assert_eq!(
+            tokens,
+            [
+                Token::Number(1.0),
+                Token::Plus,
+                Token::Ident("x".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,408 @@
+// 错误信息的本地化：错误在内部用英文构造，返回给调用方之前按语言设置翻译
+// 翻译表以英文信息的格式为键，`{}` 匹配任意文本，中文格式中的 `{0}`、`{1}` 按顺序引用匹配到的文本；
+// 表中没有的信息保持英文。Expr::with_locale 设置求值器的语言，命令行程序和 REPL 由环境变量选择
+use super::*;
+
+// 错误信息的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Locale {
+    #[default]
+    EnUs, // en-US，与内部的信息相同
+    ZhCn, // zh-CN，简体中文
+}
+
+impl std::fmt::Display for Locale {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Locale::EnUs => write!(f, "en-US"),
+            Locale::ZhCn => write!(f, "zh-CN"),
+        }
+    }
+}
+
+// 选择语言的环境变量，按顺序查找第一个非空的；CALC_LANG 只影响本程序，其余是 POSIX 的语言设置
+const LOCALE_VARIABLES: [&str; 4] = ["CALC_LANG", "LC_ALL", "LC_MESSAGES", "LANG"];
+
+impl Locale {
+    // 解析语言标签，如 zh-CN、zh_CN.UTF-8、en、C，不认识时返回 None
+    pub fn parse(tag: &str) -> Option<Locale> {
+        let tag = tag.trim().to_ascii_lowercase();
+        if tag.starts_with("zh") {
+            Some(Locale::ZhCn)
+        } else if tag.starts_with("en") || tag == "c" || tag.starts_with("c.") || tag == "posix" {
+            Some(Locale::EnUs)
+        } else {
+            None
+        }
+    }
+
+    // 从环境变量读取语言设置，没有设置或不认识时为英文
+    pub fn from_env() -> Locale {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Locale {
+        LOCALE_VARIABLES
+            .iter()
+            .filter_map(|name| lookup(name))
+            .find(|value| !value.is_empty())
+            .and_then(|value| Locale::parse(&value))
+            .unwrap_or_default()
+    }
+}
+
+// 英文信息的格式和对应的中文格式
+const CATALOG: &[(&str, &str)] = &[
+    // 解析
+    ("Unexpected token", "意外的符号"),
+    ("Unexpected end of input", "输入意外结束"),
+    ("Expected closing parenthesis", "缺少右括号"),
+    (
+        "Expected ':' in conditional expression",
+        "条件表达式中缺少 ':'",
+    ),
+    (
+        "Expected ',' or ')' in function call",
+        "函数调用中缺少 ',' 或 ')'",
+    ),
+    ("Expected ',' or ']' in vector", "向量中缺少 ',' 或 ']'"),
+    (
+        "Expected '(' after function name {}",
+        "函数名 {0} 后面缺少 '('",
+    ),
+    ("Invalid function definition", "无效的函数定义"),
+    (
+        "Invalid parameter in definition of {}()",
+        "{0}() 的定义中有无效的参数",
+    ),
+    ("expression too deeply nested", "表达式嵌套过深"),
+    ("Integer literal out of range", "整数字面量超出范围"),
+    ("Empty expression", "空表达式"),
+    ("Empty expression after '='", "'=' 后面的表达式为空"),
+    ("Unexpected expr", "意外的表达式"),
+    (
+        "'{}' cannot be both the decimal separator and the argument separator",
+        "'{0}' 不能同时作为小数点和参数分隔符",
+    ),
+    (
+        "Precedence of operator '{}' must be at least {}",
+        "运算符 '{0}' 的优先级至少为 {1}",
+    ),
+    (
+        "Invalid operator symbol '{}': {}",
+        "无效的运算符符号 '{0}'：{1}",
+    ),
+    (
+        "Too many operands: {} values left on the stack",
+        "操作数过多：栈中剩余 {0} 个值",
+    ),
+    // 变量和函数
+    ("Unknown variable: {}", "未知变量：{0}"),
+    ("Unknown function: {}", "未知函数：{0}"),
+    ("Unknown operator: {}", "未知运算符：{0}"),
+    ("Assignment requires an EvalContext", "赋值需要 EvalContext"),
+    (
+        "Function definition requires an EvalContext",
+        "函数定义需要 EvalContext",
+    ),
+    ("{}() requires an EvalContext", "{0}() 需要 EvalContext"),
+    (
+        "{}() notation requires an EvalContext",
+        "{0}() 记号需要 EvalContext",
+    ),
+    (
+        "Maximum recursion depth exceeded in {}()",
+        "{0}() 超过了最大递归深度",
+    ),
+    (
+        "String \"{}\" can only be used as a function argument",
+        "字符串 \"{0}\" 只能作为函数参数",
+    ),
+    (
+        "{}() does not accept string arguments",
+        "{0}() 不接受字符串参数",
+    ),
+    // 参数
+    (
+        "{}() requires at least one argument",
+        "{0}() 至少需要一个参数",
+    ),
+    (
+        "{}() takes exactly one argument, got {}",
+        "{0}() 只接受一个参数，实际有 {1} 个",
+    ),
+    (
+        "{}() takes {} argument(s), got {}",
+        "{0}() 接受 {1} 个参数，实际有 {2} 个",
+    ),
+    (
+        "log() takes one or two arguments, got {}",
+        "log() 接受一个或两个参数，实际有 {0} 个",
+    ),
+    (
+        "factorial requires a non-negative integer, got {}",
+        "阶乘需要非负整数，实际为 {0}",
+    ),
+    (
+        "Operator {} requires integer mode",
+        "运算符 {0} 需要整数模式",
+    ),
+    ("Invalid shift amount: {}", "无效的移位位数：{0}"),
+    (
+        "{}() bounds must be integers, got {} and {}",
+        "{0}() 的上下限必须是整数，实际为 {1} 和 {2}",
+    ),
+    (
+        "{}() exceeds the maximum of {} terms",
+        "{0}() 超过了最多 {1} 项的限制",
+    ),
+    (
+        "randint() requires integers a <= b, got {} and {}",
+        "randint() 需要满足 a <= b 的整数，实际为 {0} 和 {1}",
+    ),
+    (
+        "normal() requires a non-negative sigma, got {}",
+        "normal() 需要非负的 sigma，实际为 {0}",
+    ),
+    ("{}() requires at least one value", "{0}() 至少需要一个数"),
+    (
+        "stdev() requires at least two values, got {}",
+        "stdev() 至少需要两个数，实际有 {0} 个",
+    ),
+    (
+        "percentile() requires a percentile between 0 and 100, got {}",
+        "percentile() 的百分位数必须在 0 到 100 之间，实际为 {0}",
+    ),
+    (
+        "percentile() requires data and a percentile between 0 and 100",
+        "percentile() 需要数据和 0 到 100 之间的百分位数",
+    ),
+    (
+        "Invalid date: \"{}\", expected YYYY-MM-DD or YYYY-MM-DD HH:MM[:SS]",
+        "无效的日期：\"{0}\"，应为 YYYY-MM-DD 或 YYYY-MM-DD HH:MM[:SS]",
+    ),
+    (
+        "Unknown duration unit: \"{}\", expected one of w, d, h, m, s",
+        "未知的时长单位：\"{0}\"，应为 w、d、h、m、s 之一",
+    ),
+    // 其他求值方式
+    ("Cannot differentiate {}", "无法求导：{0}"),
+    ("{} is not supported in interval mode", "区间模式不支持 {0}"),
+    (
+        "{}() is undefined on part of the interval",
+        "{0}() 在区间的一部分上没有定义",
+    ),
+    (
+        "non-integer power of an interval containing non-positive numbers",
+        "包含非正数的区间不能取非整数次幂",
+    ),
+    (
+        "{}() notation is not supported in compiled programs",
+        "编译的程序不支持 {0}() 记号",
+    ),
+    (
+        "Only single expressions can be compiled",
+        "只能编译单个表达式",
+    ),
+    ("String arguments cannot be compiled", "字符串参数不能编译"),
+    ("Vectors cannot be compiled", "向量不能编译"),
+    (
+        "Program takes {} variable(s), got {}",
+        "程序接受 {0} 个变量，实际有 {1} 个",
+    ),
+    ("Unknown unit: {}", "未知单位：{0}"),
+    (
+        "Unsupported expression in unit calculation",
+        "带单位的计算不支持该表达式",
+    ),
+    (
+        "Operator {} is not supported in unit calculation",
+        "带单位的计算不支持运算符 {0}",
+    ),
+    (
+        "shunting-yard evaluator cannot parse: {}",
+        "调度场求值器无法解析：{0}",
+    ),
+];
+
+// 按格式匹配信息，返回每个 `{}` 匹配到的文本，`{}` 尽量匹配短的文本
+fn match_template<'a>(template: &str, message: &'a str) -> Option<Vec<&'a str>> {
+    let mut parts = template.split("{}");
+    let mut rest = message.strip_prefix(parts.next()?)?;
+    let parts: Vec<&str> = parts.collect();
+    let mut args = Vec::new();
+    for (i, part) in parts.iter().enumerate() {
+        let end = if i + 1 == parts.len() {
+            rest.strip_suffix(part)?.len()
+        } else {
+            rest.find(part)?
+        };
+        args.push(&rest[..end]);
+        rest = &rest[end + part.len()..];
+    }
+    rest.is_empty().then_some(args)
+}
+
+// 把英文信息翻译为指定的语言
+pub fn translate(message: &str, locale: Locale) -> String {
+    if locale == Locale::EnUs {
+        return message.to_string();
+    }
+    CATALOG
+        .iter()
+        .find_map(|(english, chinese)| {
+            let args = match_template(english, message)?;
+            let mut text = chinese.to_string();
+            for (i, arg) in args.iter().enumerate() {
+                text = text.replace(&std::format!("{{{}}}", i), arg);
+            }
+            Some(text)
+        })
+        .unwrap_or_else(|| message.to_string())
+}
+
+// 出错位置的说明
+pub fn at_position(text: &str, offset: usize, locale: Locale) -> String {
+    match locale {
+        Locale::EnUs => std::format!("{} at position {}", text, offset),
+        Locale::ZhCn => std::format!("{}（位置 {}）", text, offset),
+    }
+}
+
+impl ExpError {
+    // 按指定的语言翻译错误信息，错误的种类不变；语法错误在显示时翻译
+    pub fn localized(self, locale: Locale) -> ExpError {
+        match self {
+            ExpError::ParseError(message) => ExpError::ParseError(translate(&message, locale)),
+            ExpError::SyntaxError {
+                message,
+                span,
+                lexeme,
+                source,
+                ..
+            } => ExpError::SyntaxError {
+                message,
+                span,
+                lexeme,
+                source,
+                locale,
+            },
+            error => error,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate() {
+        let zh = |message: &str| translate(message, Locale::ZhCn);
+        assert_eq!(zh("Unknown variable: x"), "未知变量：x");
+        assert_eq!(
+            zh("sqrt() takes exactly one argument, got 2"),
+            "sqrt() 只接受一个参数，实际有 2 个"
+        );
+        assert_eq!(
+            zh("rand() takes 0 argument(s), got 1"),
+            "rand() 接受 0 个参数，实际有 1 个"
+        );
+        assert_eq!(
+            zh("Maximum recursion depth exceeded in f()"),
+            "f() 超过了最大递归深度"
+        );
+        assert_eq!(zh("Empty expression"), "空表达式");
+        assert_eq!(zh("Empty expression after '='"), "'=' 后面的表达式为空");
+        // 表中没有的信息保持英文
+        assert_eq!(zh("something else"), "something else");
+        assert_eq!(
+            translate("Unknown variable: x", Locale::EnUs),
+            "Unknown variable: x"
+        );
+    }
+
+    #[test]
+    fn test_localized_errors() {
+        let eval = |input: &str, locale| {
+            let mut context = EvalContext::new();
+            Expr::new(input)
+                .with_context(&mut context)
+                .with_locale(locale)
+                .eval()
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(eval("y + 1", Locale::ZhCn), "ParseError: 未知变量：y");
+        assert_eq!(
+            eval("y + 1", Locale::EnUs),
+            "ParseError: Unknown variable: y"
+        );
+        assert_eq!(
+            eval("(1 + 2", Locale::ZhCn),
+            "ParseError: 缺少右括号（位置 6）\n(1 + 2\n      ^"
+        );
+        // 其他种类的错误不翻译
+        assert_eq!(
+            eval("[1, 2] + 1", Locale::ZhCn),
+            "ShapeError: expected a number, got a vector of length 2"
+        );
+    }
+
+    #[test]
+    fn test_locale_selection() {
+        assert_eq!(Locale::parse("zh-CN"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("zh_CN.UTF-8"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("en_US.UTF-8"), Some(Locale::EnUs));
+        assert_eq!(Locale::parse("C.UTF-8"), Some(Locale::EnUs));
+        assert_eq!(Locale::parse("fr_FR"), None);
+
+        let lookup = |pairs: &'static [(&'static str, &'static str)]| {
+            Locale::from_lookup(move |name| {
+                pairs
+                    .iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            })
+        };
+        assert_eq!(lookup(&[]), Locale::EnUs);
+        assert_eq!(lookup(&[("LANG", "zh_CN.UTF-8")]), Locale::ZhCn);
+        // CALC_LANG 优先，空值跳过
+        assert_eq!(
+            lookup(&[("CALC_LANG", "en"), ("LANG", "zh_CN.UTF-8")]),
+            Locale::EnUs
+        );
+        assert_eq!(
+            lookup(&[("LC_ALL", ""), ("LANG", "zh_CN.UTF-8")]),
+            Locale::ZhCn
+        );
+        assert_eq!(lookup(&[("LANG", "fr_FR")]), Locale::EnUs);
+    }
+
+    #[test]
+    fn test_repl_language_command() {
+        let mut state = ReplState::default();
+        let print = |s: &str| ReplOutput::Print(s.to_string());
+        assert_eq!(
+            repl_line(":lang zh-CN", &mut state),
+            print("language zh-CN")
+        );
+        assert_eq!(
+            repl_line("sqrt(1, 2)", &mut state),
+            print("Error: ParseError: sqrt() 只接受一个参数，实际有 2 个")
+        );
+        assert_eq!(
+            repl_line("1 +", &mut state),
+            print("Error: ParseError: 输入意外结束（位置 3）\n1 +\n   ^")
+        );
+        assert_eq!(
+            repl_line(":lang fr", &mut state),
+            print("Error: unknown language, expected zh-CN or en-US")
+        );
+        assert_eq!(repl_line(":lang en", &mut state), print("language en-US"));
+        assert_eq!(
+            repl_line("y", &mut state),
+            print("Error: ParseError: Unknown variable: y")
+        );
+    }
+}
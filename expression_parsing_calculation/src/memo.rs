@@ -0,0 +1,252 @@
+// 子表达式记忆化：先对语法树做哈希合并（hash-consing），结构相同的子树得到同一个编号，
+// 求值时每个编号只计算一次，例如 `sqrt(x^2+1) * sin(x) + sqrt(x^2+1)` 中的 sqrt(x^2+1) 只计算一次
+// 假设函数在一次求值内是确定的（相同参数得到相同结果），赋值和函数定义之后已缓存的值全部作废；
+// 包含 rand() 等随机数函数的子树以及求和、求积记号的通项每次都重新计算
+use std::rc::Rc;
+
+use super::*;
+
+// 求值使用的记忆表，节点按地址查找编号，只在语法树的生命周期内有效
+// 编号表在多次求值之间共享，计算结果每次求值重新开始
+#[derive(Clone)]
+pub struct Memo {
+    ids: Rc<HashMap<*const Ast, usize>>, // 需要缓存的节点对应的编号
+    values: HashMap<usize, f64>,         // 本次求值已经计算过的编号
+}
+
+// 哈希合并的键：节点本身的标签和子节点的编号，结构相同的子树键相同
+type NodeKey = (String, Vec<usize>);
+
+impl Memo {
+    // 自底向上为语法树的每个节点分配编号，用显式的栈遍历，避免很长的运算链导致递归过深
+    pub fn new(ast: &Ast) -> Self {
+        let mut interned: HashMap<NodeKey, usize> = HashMap::new();
+        let mut ids = HashMap::new();
+        // 待处理的节点、是否已经处理过子节点、是否在求和或求积的通项中（下标变量每次不同）
+        let mut pending = vec![(ast, false, false)];
+        // 已经分配好编号的子树及其中是否没有随机数函数，按从左到右的顺序
+        let mut done: Vec<(usize, bool)> = Vec::new();
+        while let Some((node, visited, in_series)) = pending.pop() {
+            let children = children(node);
+            if !visited {
+                let series =
+                    matches!(node, Ast::Call(name, args) if series_notation(name, args).is_some());
+                pending.push((node, true, in_series));
+                pending.extend(
+                    children
+                        .into_iter()
+                        .rev()
+                        .map(|child| (child, false, in_series || series)),
+                );
+                continue;
+            }
+            let parts = done.split_off(done.len() - children.len());
+            let deterministic = parts.iter().all(|(_, deterministic)| *deterministic)
+                && !matches!(node, Ast::Call(name, _) if random::is_random(name));
+            let key = (label(node), parts.into_iter().map(|(id, _)| id).collect());
+            let next = interned.len();
+            let id = *interned.entry(key).or_insert(next);
+            // 数字、变量直接求值更快，赋值、定义和语句序列有副作用，都不缓存
+            if deterministic
+                && !in_series
+                && matches!(
+                    node,
+                    Ast::BinOp(..) | Ast::UnaryOp(..) | Ast::Call(..) | Ast::Cond(..)
+                )
+            {
+                ids.insert(node as *const Ast, id);
+            }
+            done.push((id, deterministic));
+        }
+        Memo {
+            ids: Rc::new(ids),
+            values: HashMap::new(),
+        }
+    }
+
+    // 不同结构的子树个数
+    #[cfg(test)]
+    fn unique(&self) -> usize {
+        self.ids.values().collect::<std::collections::HashSet<_>>().len()
+    }
+
+    fn id(&self, ast: &Ast) -> Option<usize> {
+        self.ids.get(&(ast as *const Ast)).copied()
+    }
+}
+
+// 节点本身的标签，不包括子节点
+fn label(ast: &Ast) -> String {
+    match ast {
+        // 用二进制表示区分 0 和 -0
+        Ast::Num(n) => std::format!("n{}", n.to_bits()),
+        Ast::Var(name) => std::format!("v{}", name),
+        Ast::Str(text) => std::format!("s{}", text),
+        Ast::BinOp(op, ..) => std::format!("b{:?}", op),
+        Ast::UnaryOp(op, _) => std::format!("u{:?}", op),
+        Ast::Call(name, _) => std::format!("c{}", name),
+        Ast::Assign(name, _) => std::format!("a{}", name),
+        Ast::Seq(_) => ";".to_string(),
+        Ast::List(_) => "[".to_string(),
+        Ast::Cond(..) => "?".to_string(),
+        Ast::Define(name, params, _) => std::format!("d{}({})", name, params.join(",")),
+    }
+}
+
+// 节点的子节点，按从左到右的顺序
+fn children(ast: &Ast) -> Vec<&Ast> {
+    match ast {
+        Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => Vec::new(),
+        Ast::BinOp(_, lhs, rhs) => vec![lhs, rhs],
+        Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) | Ast::Define(_, _, operand) => {
+            vec![operand]
+        }
+        Ast::Call(_, args) | Ast::Seq(args) | Ast::List(args) => args.iter().collect(),
+        Ast::Cond(cond, then, otherwise) => vec![cond, then, otherwise],
+    }
+}
+
+impl Evaluator<'_> {
+    // 查找缓存的值，定义函数的函数体每次调用的参数不同，不使用缓存
+    pub fn memoized(&self, ast: &Ast) -> Option<(usize, Option<f64>)> {
+        let memo = self.memo.as_ref().filter(|_| self.call_depth == 0)?;
+        let id = memo.id(ast)?;
+        Some((id, memo.values.get(&id).copied()))
+    }
+
+    // 保存计算结果，赋值和函数定义会改变之后的结果，缓存全部作废
+    pub fn remember(&mut self, ast: &Ast, id: Option<usize>, value: f64) {
+        let Some(memo) = self.memo.as_mut() else {
+            return;
+        };
+        match (ast, id) {
+            (Ast::Assign(..) | Ast::Define(..), _) => memo.values.clear(),
+            (_, Some(id)) => {
+                memo.values.insert(id, value);
+            }
+            _ => {}
+        }
+    }
+}
+
+// 做好哈希合并的语法树，建立编号表需要遍历整棵树，反复求值时只做一次
+pub struct Memoized<'a> {
+    ast: &'a Ast,
+    memo: Memo,
+}
+
+impl<'a> Memoized<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Memoized {
+            ast,
+            memo: Memo::new(ast),
+        }
+    }
+
+    // 不使用变量求值，结果与 eval 相同
+    pub fn eval(&self) -> Result<f64> {
+        let mut evaluator = Evaluator::new();
+        evaluator.memo = Some(self.memo.clone());
+        evaluator.eval(self.ast)
+    }
+
+    // 使用给定的上下文求值，每次求值前缓存清空，所以两次求值之间可以改变变量的值
+    pub fn eval_with_context(&self, context: &mut EvalContext) -> Result<f64> {
+        let mut evaluator = Evaluator::new();
+        evaluator.context = Some(context);
+        evaluator.memo = Some(self.memo.clone());
+        evaluator.eval(self.ast)
+    }
+}
+
+// 使用记忆化计算语法树的值，结果与 eval 相同，重复的子表达式只计算一次
+pub fn eval_memoized(ast: &Ast) -> Result<f64> {
+    Memoized::new(ast).eval()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn parse(input: &str) -> Ast {
+        Expr::new(input).parse().unwrap()
+    }
+
+    #[test]
+    fn test_hash_consing() {
+        let memo = Memo::new(&parse("sqrt(2^2+1) * sin(2) + sqrt(2^2+1)"));
+        // 2^2、2^2+1、sqrt(..)、sin(2)、`*`、`+`
+        assert_eq!(memo.unique(), 6);
+        // 操作数或运算符不同的是不同的子树
+        assert_eq!(Memo::new(&parse("(0*1) + (-0*1) + (0/1)")).unique(), 6);
+    }
+
+    #[test]
+    fn test_memoized_evaluation() {
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let mut functions: UserFunctions = HashMap::new();
+        functions.insert(
+            "slow".to_string(),
+            Box::new(move |args| {
+                counter.set(counter.get() + 1);
+                Ok(args[0] * 2.0)
+            }),
+        );
+        let eval = |input: &str, memoize: bool| {
+            let mut context = EvalContext::new();
+            Expr::new(input)
+                .with_context(&mut context)
+                .with_user_functions(&functions)
+                .with_memoization(memoize)
+                .eval()
+        };
+
+        calls.set(0);
+        assert_eq!(eval("slow(1+2) * slow(1+2) - slow(1+2)", false).unwrap(), 30.0);
+        assert_eq!(calls.get(), 3);
+        calls.set(0);
+        assert_eq!(eval("slow(1+2) * slow(1+2) - slow(1+2)", true).unwrap(), 30.0);
+        assert_eq!(calls.get(), 1);
+
+        // 赋值之后变量的值变了，之前缓存的值不能再用
+        calls.set(0);
+        assert_eq!(eval("x = 1; a = slow(x); x = 2; a + slow(x)", true).unwrap(), 6.0);
+        assert_eq!(calls.get(), 2);
+        // 定义函数的参数每次调用不同
+        assert_eq!(eval("f(n) = n * n; f(2) + f(3) + f(2)", true).unwrap(), 17.0);
+        // 通项随下标变化
+        assert_eq!(
+            eval("sum(i, 1, 3, i^2) + sum(i, 1, 3, i^2)", true).unwrap(),
+            28.0
+        );
+        // 随机数每次重新生成
+        let mut context = EvalContext::with_seed(3);
+        let value = Expr::new("(rand() + 1) - (rand() + 1)")
+            .with_context(&mut context)
+            .with_memoization(true)
+            .eval()
+            .unwrap();
+        assert_ne!(value, 0.0);
+    }
+
+    #[test]
+    fn test_memoized_long_chain() {
+        let ast = parse(&std::format!("1{}", "+sqrt(4)".repeat(100_000)));
+        assert_eq!(eval_memoized(&ast).unwrap(), 200_001.0);
+        assert_eq!(Memo::new(&ast).unique(), 100_001);
+    }
+
+    #[test]
+    fn test_memoized_reuse() {
+        let ast = parse("(x^2 + 1) / (x^2 + 1) + x^2");
+        let memoized = Memoized::new(&ast);
+        let mut context = EvalContext::new();
+        for x in [1.0, 2.0, 3.0] {
+            context.set("x", x);
+            assert_eq!(memoized.eval_with_context(&mut context).unwrap(), 1.0 + x * x);
+        }
+        assert!(memoized.eval().is_err());
+    }
+}
@@ -0,0 +1,178 @@
+// 后缀（逆波兰）和前缀表示法的输入，与中缀表达式共用 Token 和语法树
+// 如 `3 4 2 * +` 和 `+ 3 * 4 2` 都等价于 `3 + 4 * 2`
+// 二元运算符总是取两个操作数，`!` 和内置函数名（如 sqrt、sin）取一个操作数，`neg` 表示取负
+use super::*;
+
+// 取负的关键字：`-` 总是二元减法，负数写作 `3 neg`（后缀）或 `neg 3`（前缀）
+const NEGATE: &str = "neg";
+
+// 用后缀表示法求值，如 `3 4 2 * +` 等于 11
+pub fn evaluate_rpn(input: &str) -> Result<f64> {
+    Evaluator::new().eval(&parse_rpn(input)?)
+}
+
+// 用前缀表示法求值，如 `+ 3 * 4 2` 等于 11
+pub fn evaluate_prefix(input: &str) -> Result<f64> {
+    Evaluator::new().eval(&parse_prefix(input)?)
+}
+
+// 把后缀表达式解析为语法树，边读取 Token 边组合，不需要先收集全部 Token
+pub fn parse_rpn(input: &str) -> Result<Ast> {
+    build(input, Tokenizer::new(input), false)
+}
+
+// 把前缀表达式解析为语法树：从右向左读取时就是操作数顺序相反的后缀表达式
+// 反向读取需要先收集，收集的是借用输入的 Lexeme，只有用到时才转换为 Token
+pub fn parse_prefix(input: &str) -> Result<Ast> {
+    let lexemes: Vec<(Lexeme, Span)> = Tokenizer::new(input).lexemes().collect();
+    let tokens = lexemes
+        .into_iter()
+        .rev()
+        .map(|(lexeme, span)| (Token::from(lexeme), span));
+    build(input, tokens, true)
+}
+
+// 一个操作数需要的参数个数，不能作为运算符时返回 None
+fn arity(token: &Token, functions: &HashMap<&'static str, BuiltinFn>) -> Option<usize> {
+    match token {
+        Token::Number(_) => Some(0),
+        Token::Ident(name) if name == NEGATE || functions.contains_key(name.as_str()) => Some(1),
+        Token::Ident(_) => Some(0),
+        Token::Factorial | Token::BitNot => Some(1),
+        token if token.is_operator() => Some(2),
+        _ => None,
+    }
+}
+
+// 用栈把 Token 序列组合成语法树，reversed 为 true 时弹出的两个操作数顺序互换
+// 栈中同时记录每棵子树的嵌套深度，超过 DEFAULT_MAX_DEPTH 时报错，防止求值时栈溢出
+fn build(
+    source: &str,
+    tokens: impl IntoIterator<Item = (Token, Span)>,
+    reversed: bool,
+) -> Result<Ast> {
+    let functions = builtin_functions();
+    let mut stack: Vec<(Ast, usize)> = Vec::new();
+    for (token, span) in tokens {
+        let error = |message: &str| ExpError::SyntaxError {
+            message: message.to_string(),
+            span,
+            lexeme: source.chars().skip(span.offset).take(span.len).collect(),
+            source: source.to_string(),
+            locale: Locale::default(),
+        };
+        let needed = arity(&token, &functions).ok_or_else(|| error("Unexpected token"))?;
+        if stack.len() < needed {
+            return Err(error("Not enough operands for"));
+        }
+        let (node, depth) = match needed {
+            0 => match token {
+                Token::Number(n) => (Ast::Num(n), 0),
+                Token::Ident(name) => (Ast::Var(name), 0),
+                _ => unreachable!(),
+            },
+            1 => {
+                let (operand, depth) = stack.pop().unwrap();
+                let node = match token {
+                    Token::Ident(name) if name == NEGATE => {
+                        Ast::UnaryOp(Token::Minus, Box::new(operand))
+                    }
+                    Token::Ident(name) => Ast::Call(name, vec![operand]),
+                    token => Ast::UnaryOp(token, Box::new(operand)),
+                };
+                (node, depth + 1)
+            }
+            _ => {
+                let mut rhs = stack.pop().unwrap();
+                let mut lhs = stack.pop().unwrap();
+                if reversed {
+                    std::mem::swap(&mut lhs, &mut rhs);
+                }
+                // 求值时沿左侧的运算链迭代，只有右操作数会增加递归深度
+                (
+                    Ast::BinOp(token, Box::new(lhs.0), Box::new(rhs.0)),
+                    lhs.1.max(rhs.1 + 1),
+                )
+            }
+        };
+        if depth > DEFAULT_MAX_DEPTH {
+            return Err(ExpError::ParseError(
+                "expression too deeply nested".to_string(),
+            ));
+        }
+        stack.push((node, depth));
+    }
+    match stack.len() {
+        0 => Err(ExpError::ParseError("Empty expression".to_string())),
+        1 => Ok(stack.pop().unwrap().0),
+        n => Err(ExpError::ParseError(format!(
+            "Too many operands: {} values left on the stack",
+            n
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate_rpn() {
+        assert_eq!(evaluate_rpn("3 4 2 * +").unwrap(), 11.0);
+        assert_eq!(evaluate_rpn("3 4 + 2 *").unwrap(), 14.0);
+        assert_eq!(evaluate_rpn("10 4 -").unwrap(), 6.0);
+        assert_eq!(evaluate_rpn("2 3 ^ 2 ^").unwrap(), 64.0);
+        assert_eq!(evaluate_rpn("3 ! 2 neg *").unwrap(), -12.0);
+        assert_eq!(evaluate_rpn("16 sqrt pi 0 * +").unwrap(), 4.0);
+        assert_eq!(evaluate_rpn("1 2 < 5 7 ==  ||").unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_evaluate_prefix() {
+        assert_eq!(evaluate_prefix("+ 3 * 4 2").unwrap(), 11.0);
+        assert_eq!(evaluate_prefix("* + 3 4 2").unwrap(), 14.0);
+        assert_eq!(evaluate_prefix("- 10 4").unwrap(), 6.0);
+        assert_eq!(evaluate_prefix("/ 1 neg 4").unwrap(), -0.25);
+        assert_eq!(evaluate_prefix("sqrt + 9 16").unwrap(), 5.0);
+    }
+
+    #[test]
+    fn test_notation_builds_same_ast() {
+        let infix = format::format_expression("3 + 4 * 2 - 1").unwrap();
+        assert_eq!(format::format(&parse_rpn("3 4 2 * + 1 -").unwrap()), infix);
+        assert_eq!(
+            format::format(&parse_prefix("- + 3 * 4 2 1").unwrap()),
+            infix
+        );
+    }
+
+    #[test]
+    fn test_notation_errors() {
+        match evaluate_rpn("3 +") {
+            Err(ExpError::SyntaxError {
+                message, lexeme, ..
+            }) => {
+                assert_eq!(message, "Not enough operands for");
+                assert_eq!(lexeme, "+");
+            }
+            other => panic!("expected syntax error, got {:?}", other),
+        }
+        assert!(matches!(
+            evaluate_rpn("3 ( 4 +"),
+            Err(ExpError::SyntaxError {
+                span: Span { offset: 2, len: 1 },
+                ..
+            })
+        ));
+        assert_eq!(
+            evaluate_prefix("1 2").unwrap_err().to_string(),
+            "ParseError: Too many operands: 2 values left on the stack"
+        );
+        assert!(evaluate_rpn("").is_err());
+        // 很深的右侧嵌套在求值前报错，而很长的左结合链不受限制
+        let deep = std::format!("{}{}", "1 ".repeat(100_000), "+ ".repeat(99_999));
+        assert!(evaluate_rpn(&deep).is_err());
+        let long = std::format!("1{}", " 1 +".repeat(100_000));
+        assert_eq!(evaluate_rpn(&long).unwrap(), 100_001.0);
+    }
+}
@@ -0,0 +1,199 @@
+// 随机数函数 rand()、randint(a, b)、normal(mu, sigma)
+// 随机数生成器保存在 EvalContext 中，用 EvalContext::with_seed 指定种子后结果可以复现，
+// 默认每个上下文使用不同的随机种子；没有上下文时调用这些函数会报错
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+use super::*;
+
+// 需要随机数生成器的函数，不在内置函数表中，因为内置函数不能访问上下文
+pub const RANDOM_FUNCTIONS: [&str; 3] = ["rand", "randint", "normal"];
+
+pub fn is_random(name: &str) -> bool {
+    RANDOM_FUNCTIONS.contains(&name)
+}
+
+// SplitMix64 伪随机数生成器，状态只有一个 u64，足够用于模拟，不能用于密码学
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Default for Rng {
+    // 使用标准库的随机哈希种子
+    fn default() -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        Rng::seeded(hasher.finish())
+    }
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // [0, 1) 上的均匀分布，使用高 53 位
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // [0, n) 上的均匀整数，拒绝采样避免取余带来的偏差
+    fn below(&mut self, n: u64) -> u64 {
+        let limit = u64::MAX - u64::MAX % n;
+        loop {
+            let value = self.next_u64();
+            if value < limit {
+                return value % n;
+            }
+        }
+    }
+
+    // 正态分布，Box-Muller 变换
+    fn normal(&mut self, mu: f64, sigma: f64) -> f64 {
+        // 1 - u 在 (0, 1] 上，ln 不会得到 -inf
+        let u = 1.0 - self.next_f64();
+        let v = self.next_f64();
+        mu + sigma * (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+    }
+
+    // 按函数名生成一个随机数
+    pub fn call(&mut self, name: &str, args: &[f64]) -> Result<f64> {
+        match (name, args) {
+            ("rand", []) => Ok(self.next_f64()),
+            ("randint", [a, b]) => {
+                if a.fract() != 0.0 || b.fract() != 0.0 || a > b {
+                    return Err(ExpError::ParseError(format!(
+                        "randint() requires integers a <= b, got {} and {}",
+                        a, b
+                    )));
+                }
+                // 非有限的和超出 i64 范围的端点转换时会饱和，结果不在 [a, b] 中
+                if !I64_RANGE.contains(a) || !I64_RANGE.contains(b) {
+                    return Err(ExpError::ParseError(format!(
+                        "randint() bounds must be within the i64 range, got {} and {}",
+                        a, b
+                    )));
+                }
+                // 端点都能表示为 i64 时差值不会溢出 u64
+                let (a, b) = (*a as i64, *b as i64);
+                let span = b.wrapping_sub(a) as u64;
+                let offset = match span.checked_add(1) {
+                    Some(n) => self.below(n),
+                    None => self.next_u64(),
+                };
+                Ok(a.wrapping_add(offset as i64) as f64)
+            }
+            ("normal", [mu, sigma]) if *sigma >= 0.0 => Ok(self.normal(*mu, *sigma)),
+            ("normal", [_, sigma]) => Err(ExpError::ParseError(format!(
+                "normal() requires a non-negative sigma, got {}",
+                sigma
+            ))),
+            _ => {
+                let expected = match name {
+                    "rand" => 0,
+                    _ => 2,
+                };
+                Err(ExpError::ParseError(format!(
+                    "{}() takes {} argument(s), got {}",
+                    name,
+                    expected,
+                    args.len()
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(input: &str, context: &mut EvalContext) -> f64 {
+        evaluate_with_context(input, context).unwrap()
+    }
+
+    #[test]
+    fn test_seeded_random_functions() {
+        let (mut a, mut b) = (EvalContext::with_seed(42), EvalContext::with_seed(42));
+        for input in ["rand()", "randint(1, 6)", "normal(0, 1)", "rand() + rand()"] {
+            assert_eq!(eval(input, &mut a), eval(input, &mut b));
+        }
+        // 重新设置种子后从头开始
+        a.seed(7);
+        let first = eval("rand()", &mut a);
+        a.seed(7);
+        assert_eq!(eval("rand()", &mut a), first);
+        assert_ne!(eval("rand()", &mut a), first);
+    }
+
+    #[test]
+    fn test_random_ranges() {
+        let mut context = EvalContext::with_seed(1);
+        let mut counts = [0; 6];
+        for _ in 0..6000 {
+            let x = eval("rand()", &mut context);
+            assert!((0.0..1.0).contains(&x));
+            let n = eval("randint(1, 6)", &mut context);
+            counts[n as usize - 1] += 1;
+        }
+        assert!(
+            counts.iter().all(|&c| (800..1200).contains(&c)),
+            "{:?}",
+            counts
+        );
+        assert_eq!(eval("randint(3, 3)", &mut context), 3.0);
+        assert_eq!(
+            eval("randint(-2^63, -2^63)", &mut context),
+            -9_223_372_036_854_775_808.0
+        );
+        assert_eq!(eval("normal(5, 0)", &mut context), 5.0);
+
+        let samples: Vec<f64> = (0..10_000)
+            .map(|_| eval("normal(10, 2)", &mut context))
+            .collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+        let var = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+        assert!((mean - 10.0).abs() < 0.1, "mean {}", mean);
+        assert!((var.sqrt() - 2.0).abs() < 0.1, "sigma {}", var.sqrt());
+    }
+
+    #[test]
+    fn test_random_errors() {
+        let mut context = EvalContext::with_seed(0);
+        for input in [
+            "rand(1)",
+            "randint(1)",
+            "randint(6, 1)",
+            "randint(1.5, 2)",
+            "randint(1e300, 1e300)",
+            "randint(-1e19, 0)",
+            "randint(0, 1/0)",
+            "randint(0/0, 1)",
+            "normal(0, -1)",
+            "rand(\"x\")",
+        ] {
+            assert!(
+                evaluate_with_context(input, &mut context).is_err(),
+                "{}",
+                input
+            );
+        }
+        // 没有上下文时没有随机数生成器
+        assert!(eval_ast("rand()").is_err());
+        // 会话中定义的同名函数优先
+        assert_eq!(eval("rand() = 4; rand()", &mut context), 4.0);
+    }
+
+    fn eval_ast(input: &str) -> Result<f64> {
+        super::eval(&parse(input)?)
+    }
+}
@@ -0,0 +1,121 @@
+// 语法树的 JSON 序列化，需要开启 `serde` feature
+// 输出带版本号的文档 `{"version":1,"ast":...}`，格式变化时增加版本号，读取时拒绝不认识的版本
+// 节点使用 serde 默认的外部标签表示，如 `2 + x` 为 {"BinOp":["Plus",{"Num":2.0},{"Var":"x"}]}
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+// 当前的文档格式版本
+pub const SCHEMA_VERSION: u32 = 1;
+
+// serde_json 默认最多允许 128 层嵌套，超过时无法读回，所以序列化时同样限制深度
+// 每个节点在 JSON 中占两层（外部标签的对象和它的值）
+const MAX_SERIALIZE_DEPTH: usize = 60;
+
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+    version: u32,
+    ast: &'a Ast,
+}
+
+#[derive(Deserialize)]
+struct Document {
+    version: u32,
+    ast: serde_json::Value,
+}
+
+// 把语法树序列化为 JSON 文档
+pub fn to_json(ast: &Ast) -> Result<String> {
+    if depth(ast) > MAX_SERIALIZE_DEPTH {
+        return Err(ExpError::ParseError(
+            "expression too deeply nested to serialize".to_string(),
+        ));
+    }
+    serde_json::to_string(&DocumentRef {
+        version: SCHEMA_VERSION,
+        ast,
+    })
+    .map_err(|e| ExpError::ParseError(format!("cannot serialize expression: {}", e)))
+}
+
+// 从 JSON 文档读回语法树，版本号不是 SCHEMA_VERSION 时返回错误
+pub fn from_json(json: &str) -> Result<Ast> {
+    let invalid =
+        |e: serde_json::Error| ExpError::ParseError(format!("invalid expression JSON: {}", e));
+    let document: Document = serde_json::from_str(json).map_err(invalid)?;
+    if document.version != SCHEMA_VERSION {
+        return Err(ExpError::ParseError(format!(
+            "unsupported expression schema version {}, expected {}",
+            document.version, SCHEMA_VERSION
+        )));
+    }
+    serde_json::from_value(document.ast).map_err(invalid)
+}
+
+// 语法树的深度
+fn depth(ast: &Ast) -> usize {
+    #[derive(Default)]
+    struct Depth {
+        current: usize,
+        max: usize,
+    }
+    impl visit::Visitor for Depth {
+        fn enter(&mut self, _ast: &Ast) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+        }
+        fn leave(&mut self, _ast: &Ast) {
+            self.current -= 1;
+        }
+    }
+    let mut depth = Depth::default();
+    ast.walk(&mut depth);
+    depth.max
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_round_trip() {
+        for input in [
+            "2 + x",
+            "-(3 ^ 2)! * max(1, y, 3)",
+            "x = 2; f(a, b) = a > b ? a : b; f(x, 1)",
+            "1 < 2 && 3 != 4",
+        ] {
+            let ast = Expr::new(input).parse().unwrap();
+            let json = to_json(&ast).unwrap();
+            assert_eq!(from_json(&json).unwrap(), ast, "round trip of {}", input);
+        }
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let ast = Expr::new("2 + x").parse().unwrap();
+        assert_eq!(
+            to_json(&ast).unwrap(),
+            r#"{"version":1,"ast":{"BinOp":["Plus",{"Num":2.0},{"Var":"x"}]}}"#
+        );
+        // 读回的语法树可以直接求值，不需要重新解析
+        let ast =
+            from_json(r#"{"version":1,"ast":{"BinOp":["Multiply",{"Num":6.0},{"Num":7.0}]}}"#)
+                .unwrap();
+        assert_eq!(eval(&ast).unwrap(), 42.0);
+    }
+
+    #[test]
+    fn test_json_errors() {
+        assert!(matches!(
+            from_json(r#"{"version":2,"ast":{"Num":1.0}}"#),
+            Err(ExpError::ParseError(msg)) if msg.contains("unsupported expression schema version 2")
+        ));
+        assert!(from_json(r#"{"ast":{"Num":1.0}}"#).is_err());
+        assert!(from_json(r#"{"version":1,"ast":{"Bogus":1.0}}"#).is_err());
+        let deep = Expr::new(&std::format!("1{}", "+1".repeat(100)))
+            .parse()
+            .unwrap();
+        assert!(to_json(&deep).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+// REPL 会话的保存和加载，以及交互模式的历史记录文件
+// 会话文件是一段可以重新执行的脚本，每行定义一个函数或者给一个变量赋值，如：
+//   area(r) = pi * r ^ 2
+//   rate = 0.05
+//   v = [1.0, 2.0]
+// 加载时在新的上下文中逐行执行，全部成功后才替换当前会话
+use std::path::{Path, PathBuf};
+
+use chrono::Timelike;
+
+use super::*;
+
+// 交互模式的历史记录文件名，保存在主目录下
+const HISTORY_FILE: &str = ".calc_history";
+
+// 会话文件的第一行
+const SESSION_HEADER: &str = "# calc session";
+
+// 历史记录文件的路径，找不到主目录时返回 None，此时不保存历史
+pub fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+// 可以精确还原的数字字面量，Debug 格式的 f64 在解析后得到相同的值，很大或很小的数使用科学计数法
+// NaN 和无穷写成除法，不依赖可能被用户重新赋值的 inf 变量
+fn number_literal(value: f64) -> String {
+    if value.is_nan() {
+        "0 / 0".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "1 / 0" } else { "-1 / 0" }.to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn vector_literal(items: &[f64]) -> String {
+    let items: Vec<String> = items.iter().map(|item| number_literal(*item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+// 时长写成秒数，精确到纳秒
+fn duration_literal(duration: &chrono::TimeDelta) -> String {
+    let seconds = duration.num_seconds() as f64 + duration.subsec_nanos() as f64 / 1e9;
+    format!("duration({}, \"s\")", number_literal(seconds))
+}
+
+// 求值后得到 value 的表达式
+fn value_literal(value: &Value) -> Option<String> {
+    match value {
+        Value::Number(n) => Some(number_literal(*n)),
+        Value::Vector(items) => Some(vector_literal(items)),
+        Value::Matrix(rows) => {
+            let rows: Vec<String> = rows.iter().map(|row| vector_literal(row)).collect();
+            Some(format!("[{}]", rows.join(", ")))
+        }
+        // date() 只接受到秒的时间，不足一秒的部分用时长补上
+        Value::Date(date) => {
+            let literal = format!("date(\"{}\")", date.format("%Y-%m-%d %H:%M:%S"));
+            match date.nanosecond() {
+                0 => Some(literal),
+                nanos => Some(format!(
+                    "{} + {}",
+                    literal,
+                    duration_literal(&chrono::TimeDelta::nanoseconds(nanos as i64))
+                )),
+            }
+        }
+        Value::Duration(duration) => Some(duration_literal(duration)),
+        Value::Text(_) => None,
+    }
+}
+
+// 把上下文中定义的函数和全局变量写成脚本，函数在前，都按名字排序
+pub fn session_script(context: &EvalContext) -> String {
+    let mut lines = vec![SESSION_HEADER.to_string()];
+    let mut functions: Vec<(&String, &DefinedFunction)> = context.functions.iter().collect();
+    functions.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, function) in functions {
+        lines.push(format!(
+            "{}({}) = {}",
+            name,
+            function.params.join(", "),
+            format::format(&function.body)
+        ));
+    }
+    for (name, value) in context.sorted_variables() {
+        if let Some(literal) = value_literal(&value) {
+            lines.push(format!("{} = {}", name, literal));
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+// 把会话保存到 path，已有的文件会被覆盖
+pub fn save_session(context: &EvalContext, path: &Path) -> Result<()> {
+    std::fs::write(path, session_script(context))
+        .map_err(|e| ExpError::ParseError(format!("cannot write {}: {}", path.display(), e)))
+}
+
+// 逐行执行会话脚本，返回得到的上下文；空行和 `#` 开头的注释行会被忽略
+pub fn load_script(script: &str) -> Result<EvalContext> {
+    let mut context = EvalContext::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        evaluate_value_with_context(line, &mut context)
+            .map_err(|e| ExpError::ParseError(format!("line {}: {}", number + 1, e)))?;
+    }
+    Ok(context)
+}
+
+// 从 path 加载会话
+pub fn load_session(path: &Path) -> Result<EvalContext> {
+    let script = std::fs::read_to_string(path)
+        .map_err(|e| ExpError::ParseError(format!("cannot read {}: {}", path.display(), e)))?;
+    load_script(&script).map_err(|e| match e {
+        ExpError::ParseError(message) => {
+            ExpError::ParseError(format!("{}: {}", path.display(), message))
+        }
+        e => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_round_trip() {
+        let mut state = ReplState::default();
+        for line in [
+            "rate = 0.25",
+            "big = 1e300 * 3",
+            "neg = -inf",
+            "inf = 1",
+            "x = 1/0",
+            "area(r) = pi * r^2",
+            "grow(x, n) = x * (1 + rate)^n",
+            "v = [1, 2.5]",
+            "m = [[1, 2], [3, 4]]",
+            "start = date(\"2024-03-01 12:30:00\")",
+            "wait = 3d + 12h",
+        ] {
+            repl_line(line, &mut state);
+        }
+        let script = session_script(&state.context);
+        assert_eq!(
+            script,
+            [
+                SESSION_HEADER,
+                "area(r) = pi * r ^ 2",
+                "grow(x, n) = x * (1 + rate) ^ n",
+                "ans = duration(302400.0, \"s\")",
+                "big = 3e300",
+                "inf = 1.0",
+                "m = [[1.0, 2.0], [3.0, 4.0]]",
+                "neg = -1 / 0",
+                "rate = 0.25",
+                "start = date(\"2024-03-01 12:30:00\")",
+                "v = [1.0, 2.5]",
+                "wait = duration(302400.0, \"s\")",
+                "x = 1 / 0",
+                "",
+            ]
+            .join("\n")
+        );
+        let loaded = load_script(&script).unwrap();
+        assert_eq!(session_script(&loaded), script);
+        let mut restored = ReplState {
+            context: loaded,
+            ..ReplState::default()
+        };
+        assert_eq!(
+            repl_line("grow(100, 2) - area(1) / pi", &mut restored),
+            ReplOutput::Print("155.25".to_string())
+        );
+    }
+
+    #[test]
+    fn test_load_reports_failing_line() {
+        let error = load_script("x = 1\n\n# comment\ny = x +").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("ParseError: line 4: ParseError: Unexpected end of input"));
+        assert!(load_script("x = 1\nNaN").is_err());
+        // NaN 写成 0 / 0，加载后仍然是 NaN
+        assert_eq!(
+            load_script("x = 0 / 0").unwrap().get("x").map(f64::is_nan),
+            Some(true)
+        );
+    }
+}
@@ -0,0 +1,177 @@
+// 语法树化简：折叠常量子表达式（`2*3+x` → `6 + x`），去掉恒等运算（`x*1`、`x+0`），
+// 消去双重取负（`--x` → `x`）
+// 按浮点数规则化简，结果不是有限数的运算（如 1/0）保持原样，留给求值时报告错误；
+// 函数调用和变量可能被会话中的定义覆盖，不会被折叠
+use super::*;
+
+impl Ast {
+    // 返回化简后的语法树
+    pub fn simplify(&self) -> Ast {
+        match self {
+            Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => self.clone(),
+            Ast::BinOp(..) => {
+                let (node, spine) = self.left_spine();
+                let mut value = node.simplify();
+                for (op, rhs) in spine {
+                    value = simplify_binary(op, value, rhs.simplify());
+                }
+                value
+            }
+            Ast::UnaryOp(op, operand) => simplify_unary(op, operand.simplify()),
+            Ast::Call(name, args) => {
+                let mut simplified: Vec<Ast> = args.iter().map(Ast::simplify).collect();
+                // 通项化简后不再含有下标时（如 0 * i）会变成普通的 sum，这时保留原来的通项
+                if series_notation(name, args).is_some()
+                    && series_notation(name, &simplified).is_none()
+                {
+                    simplified[3] = args[3].clone();
+                }
+                Ast::Call(name.clone(), simplified)
+            }
+            Ast::Assign(name, value) => Ast::Assign(name.clone(), Box::new(value.simplify())),
+            Ast::Seq(statements) => Ast::Seq(statements.iter().map(Ast::simplify).collect()),
+            Ast::List(items) => Ast::List(items.iter().map(Ast::simplify).collect()),
+            // 条件是常量时只保留被选中的分支
+            Ast::Cond(cond, then, otherwise) => match cond.simplify() {
+                Ast::Num(n) if n != 0.0 => then.simplify(),
+                Ast::Num(_) => otherwise.simplify(),
+                cond => Ast::Cond(
+                    Box::new(cond),
+                    Box::new(then.simplify()),
+                    Box::new(otherwise.simplify()),
+                ),
+            },
+            Ast::Define(name, params, body) => {
+                Ast::Define(name.clone(), params.clone(), Box::new(body.simplify()))
+            }
+        }
+    }
+}
+
+// 结果是有限数时返回折叠后的常量
+fn fold(result: Option<f64>) -> Option<Ast> {
+    result.filter(|n| n.is_finite()).map(Ast::Num)
+}
+
+// 化简一次二元运算，两个操作数都已经化简过
+fn simplify_binary(op: &Token, lhs: Ast, rhs: Ast) -> Ast {
+    // 位运算的结果取决于求值模式，不折叠
+    if let (Ast::Num(l), Ast::Num(r)) = (&lhs, &rhs) {
+        if !op.is_bitwise() {
+            if let Some(folded) = fold(op.compute(*l, *r)) {
+                return folded;
+            }
+        }
+    }
+    let is = |ast: &Ast, value: f64| matches!(ast, Ast::Num(n) if *n == value);
+    match op {
+        Token::Plus if is(&rhs, 0.0) => lhs,
+        Token::Plus if is(&lhs, 0.0) => rhs,
+        Token::Minus if is(&rhs, 0.0) => lhs,
+        Token::Minus if is(&lhs, 0.0) => simplify_unary(&Token::Minus, rhs),
+        Token::Multiply if is(&rhs, 1.0) => lhs,
+        Token::Multiply if is(&lhs, 1.0) => rhs,
+        Token::Divide if is(&rhs, 1.0) => lhs,
+        Token::Power if is(&rhs, 1.0) => lhs,
+        // 与 f64::powf 一致，任何数的 0 次幂都是 1
+        Token::Power if is(&rhs, 0.0) => Ast::Num(1.0),
+        _ => Ast::BinOp(op.clone(), Box::new(lhs), Box::new(rhs)),
+    }
+}
+
+// 化简一次一元运算，操作数已经化简过
+fn simplify_unary(op: &Token, operand: Ast) -> Ast {
+    match (op, &operand) {
+        (Token::Plus, _) => operand,
+        (Token::Minus, Ast::Num(n)) => Ast::Num(-n),
+        // 双重取负
+        (Token::Minus, Ast::UnaryOp(Token::Minus, inner)) => (**inner).clone(),
+        (Token::Factorial, Ast::Num(n)) => match fold(factorial(*n).ok()) {
+            Some(folded) => folded,
+            None => Ast::UnaryOp(op.clone(), Box::new(operand)),
+        },
+        _ => Ast::UnaryOp(op.clone(), Box::new(operand)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 化简后按规范格式输出
+    fn simplified(input: &str) -> String {
+        format::format(&Expr::new(input).parse().unwrap().simplify())
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(simplified("2*3+x"), "6 + x");
+        assert_eq!(simplified("x + 2*3"), "x + 6");
+        assert_eq!(simplified("(1+2)*(3+4)"), "21");
+        assert_eq!(simplified("2^10 - 3!"), "1018");
+        assert_eq!(simplified("sqrt(2*8)"), "sqrt(16)");
+        assert_eq!(simplified("-(2+3) * x"), "-5 * x");
+        assert_eq!(simplified("1 < 2 ? x : y"), "x");
+        assert_eq!(simplified("2 == 3 ? x : y + 0"), "y");
+        assert_eq!(simplified("y = 1 + 1; y * 1"), "y = 2; y");
+    }
+
+    #[test]
+    fn test_algebraic_identities() {
+        for (input, expected) in [
+            ("x * 1", "x"),
+            ("1 * x", "x"),
+            ("x + 0", "x"),
+            ("0 + x", "x"),
+            ("x - 0", "x"),
+            ("0 - x", "-x"),
+            ("x / 1", "x"),
+            ("x ^ 1", "x"),
+            ("x ^ 0", "1"),
+            ("--x", "x"),
+            ("-(-(x + y))", "x + y"),
+            ("+x", "x"),
+            ("(x + 0) * (1 * y)", "x * y"),
+            ("x * (3 - 2) + (5 - 5)", "x"),
+            ("0 - -x", "x"),
+        ] {
+            assert_eq!(simplified(input), expected, "simplifying {}", input);
+        }
+    }
+
+    #[test]
+    fn test_simplify_keeps_errors_and_meaning() {
+        // 结果不是有限数的运算和位运算不折叠
+        assert_eq!(simplified("1/0"), "1 / 0");
+        assert_eq!(simplified("171!"), "171!");
+        assert_eq!(simplified("6 & 3"), "6 & 3");
+        assert_eq!(simplified("x * 0"), "x * 0");
+        // 通项不含下标后就不再是求和记号
+        assert_eq!(simplified("sum(i, 1, 3, 0 * i)"), "sum(i, 1, 3, 0 * i)");
+        // 化简前后的值相同
+        for input in [
+            "x*1 + 2*3 - (0 - y)",
+            "--x ^ 2 / (4 - 3)",
+            "x > 0 ? x + 0 : 2 * 3",
+            "sum(i, 1, 3, i * 1 + x)",
+        ] {
+            for (x, y) in [(2.0, 3.0), (-1.5, 0.0)] {
+                let mut context = EvalContext::default();
+                context.set("x", x);
+                context.set("y", y);
+                let expected = evaluate_with_context(input, &mut context).unwrap();
+                let ast = Expr::new(input).parse().unwrap().simplify();
+                let actual = Expr::new(&format::format(&ast))
+                    .with_context(&mut context)
+                    .eval()
+                    .unwrap();
+                assert_eq!(actual, expected, "simplifying {}", input);
+            }
+        }
+        let src = std::format!("x{}", "+1".repeat(100_000));
+        assert_eq!(
+            simplified(&src),
+            "x + 1".to_string() + &" + 1".repeat(99_999)
+        );
+    }
+}
@@ -0,0 +1,144 @@
+// 统计函数 mean、median、stdev、percentile，和 min、max 一样接受任意多个数，
+// 参数是向量或矩阵时取出其中所有的元素，例如 mean([1, 2, 3]) 和 mean(1, 2, 3) 相同
+// 这里的函数也作为库的公开接口，供其他项目直接对 f64 切片计算
+use super::*;
+
+// 对所有元素做汇总的函数，向量和矩阵参数展开为元素列表，而不是按元素计算
+pub const AGGREGATE_FUNCTIONS: [&str; 8] = [
+    "sum",
+    "avg",
+    "min",
+    "max",
+    "mean",
+    "median",
+    "stdev",
+    "percentile",
+];
+
+// 注册到内置函数表
+pub fn register(functions: &mut HashMap<&'static str, BuiltinFn>) {
+    functions.insert("mean", |args| mean(require_args("mean", args)?));
+    functions.insert("median", |args| median(require_args("median", args)?));
+    functions.insert("stdev", stdev);
+    // 最后一个参数是百分位数，其余是数据
+    functions.insert("percentile", |args| match args {
+        [data @ .., p] if !data.is_empty() => percentile(data, *p),
+        _ => Err(ExpError::ParseError(
+            "percentile() requires data and a percentile between 0 and 100".to_string(),
+        )),
+    });
+}
+
+fn empty(name: &str) -> ExpError {
+    ExpError::ParseError(format!("{}() requires at least one value", name))
+}
+
+// 算术平均数
+pub fn mean(data: &[f64]) -> Result<f64> {
+    if data.is_empty() {
+        return Err(empty("mean"));
+    }
+    Ok(data.iter().sum::<f64>() / data.len() as f64)
+}
+
+// 中位数，个数为偶数时取中间两个数的平均
+pub fn median(data: &[f64]) -> Result<f64> {
+    if data.is_empty() {
+        return Err(empty("median"));
+    }
+    percentile(data, 50.0)
+}
+
+// 样本标准差（除以 n - 1），至少需要两个数
+pub fn stdev(data: &[f64]) -> Result<f64> {
+    if data.len() < 2 {
+        return Err(ExpError::ParseError(format!(
+            "stdev() requires at least two values, got {}",
+            data.len()
+        )));
+    }
+    let mean = mean(data)?;
+    let squares: f64 = data.iter().map(|x| (x - mean).powi(2)).sum();
+    Ok((squares / (data.len() - 1) as f64).sqrt())
+}
+
+// 第 p 百分位数（0 <= p <= 100），在排序后相邻的两个数之间线性插值
+pub fn percentile(data: &[f64], p: f64) -> Result<f64> {
+    if data.is_empty() {
+        return Err(empty("percentile"));
+    }
+    if !(0.0..=100.0).contains(&p) {
+        return Err(ExpError::ParseError(format!(
+            "percentile() requires a percentile between 0 and 100, got {}",
+            p
+        )));
+    }
+    if data.iter().any(|x| x.is_nan()) {
+        return Ok(f64::NAN);
+    }
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lower, upper) = (rank.floor() as usize, rank.ceil() as usize);
+    let weight = rank - lower as f64;
+    Ok(sorted[lower] + (sorted[upper] - sorted[lower]) * weight)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_statistics() {
+        let data = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(mean(&data).unwrap(), 5.0);
+        assert_eq!(median(&data).unwrap(), 4.5);
+        assert_eq!(median(&[3.0, 1.0, 2.0]).unwrap(), 2.0);
+        assert!((stdev(&data).unwrap() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+        assert_eq!(percentile(&data, 0.0).unwrap(), 2.0);
+        assert_eq!(percentile(&data, 100.0).unwrap(), 9.0);
+        assert_eq!(percentile(&[1.0, 2.0, 3.0, 4.0, 5.0], 90.0).unwrap(), 4.6);
+        assert!(percentile(&[1.0, f64::NAN], 50.0).unwrap().is_nan());
+    }
+
+    #[test]
+    fn test_statistics_functions() {
+        for (input, expected) in [
+            ("mean(1, 2, 3, 6)", 3.0),
+            ("mean([1, 2, 3, 6])", 3.0),
+            ("median([5, 1, 3])", 3.0),
+            ("median([[1, 2], [3, 4]])", 2.5),
+            ("stdev([1, 3])", std::f64::consts::SQRT_2),
+            ("percentile([1, 2, 3, 4, 5], 25)", 2.0),
+            ("percentile(10, 20, 30, 50)", 20.0),
+            // 向量和数可以混合，都作为数据
+            ("max([1, 7], 3)", 7.0),
+            ("min([[4, 2], [8, 6]])", 2.0),
+            ("sum([1, 2, 3])", 6.0),
+            ("avg([1, 2], [3, 4])", 2.5),
+        ] {
+            assert_eq!(evaluate(input).unwrap(), expected, "{}", input);
+        }
+        let mut context = EvalContext::new();
+        evaluate_value_with_context("prices = [10, 12, 11, 15]", &mut context).unwrap();
+        assert_eq!(
+            evaluate_with_context("mean(prices) + median(prices)", &mut context).unwrap(),
+            23.5
+        );
+    }
+
+    #[test]
+    fn test_statistics_errors() {
+        for input in [
+            "mean()",
+            "median()",
+            "stdev(1)",
+            "stdev([4])",
+            "percentile(50)",
+            "percentile([1, 2], 101)",
+            "percentile([1, 2], -1)",
+        ] {
+            assert!(evaluate(input).is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,591 @@
+// 向量和矩阵：`[1, 2, 3]` 是向量，`[[1, 2], [3, 4]]` 是按行书写的矩阵
+// 四则运算、乘方和比较按元素计算，数与向量、矩阵运算时作用到每个元素上，单参数的内置函数同样按元素计算；
+// dot(u, v) 是点积，matmul(a, b) 是矩阵乘法（a、b 之一可以是向量），transpose(a) 是转置
+// 结果可能是向量或矩阵时使用 evaluate_value 求值，evaluate 只接受结果是数的表达式
+use std::fmt;
+
+use super::*;
+
+// 参数是向量或矩阵的内置函数，会话中定义的和宿主注册的同名函数优先
+pub const TENSOR_FUNCTIONS: [&str; 3] = ["dot", "matmul", "transpose"];
+
+// 检查向量函数的参数个数，transpose 接受一个参数，dot 和 matmul 接受两个
+pub(crate) fn check_arity(name: &str, count: usize) -> Result<()> {
+    let arity = if name == "transpose" { 1 } else { 2 };
+    if count == arity {
+        return Ok(());
+    }
+    Err(ExpError::ParseError(format!(
+        "{}() takes exactly {} argument{}, got {}",
+        name,
+        arity,
+        if arity == 1 { "" } else { "s" },
+        count
+    )))
+}
+
+impl fmt::Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_items(f: &mut fmt::Formatter, items: &[f64]) -> fmt::Result {
+            write!(f, "[")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", item)?;
+            }
+            write!(f, "]")
+        }
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Text(text) => write!(f, "\"{}\"", text),
+            Value::Vector(items) => write_items(f, items),
+            Value::Matrix(rows) => {
+                write!(f, "[")?;
+                for (i, row) in rows.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write_items(f, row)?;
+                }
+                write!(f, "]")
+            }
+            Value::Date(date) => datetime::write_date(f, date),
+            Value::Duration(duration) => datetime::write_duration(f, duration),
+        }
+    }
+}
+
+// 用指定的方式格式化值中的数，向量和矩阵逐个元素格式化，其他值与 Display 相同
+pub fn render(value: &Value, number: &dyn Fn(f64) -> String) -> String {
+    let items = |items: &[f64]| {
+        let items: Vec<String> = items.iter().map(|&item| number(item)).collect();
+        std::format!("[{}]", items.join(", "))
+    };
+    match value {
+        Value::Number(n) => number(*n),
+        Value::Vector(values) => items(values),
+        Value::Matrix(rows) => {
+            let rows: Vec<String> = rows.iter().map(|row| items(row)).collect();
+            std::format!("[{}]", rows.join(", "))
+        }
+        _ => value.to_string(),
+    }
+}
+
+// 值的种类和形状，用于错误信息
+pub fn describe(value: &Value) -> String {
+    match value {
+        Value::Number(_) => "a number".to_string(),
+        Value::Text(_) => "a string".to_string(),
+        Value::Vector(items) => std::format!("a vector of length {}", items.len()),
+        Value::Matrix(rows) => std::format!(
+            "a {}x{} matrix",
+            rows.len(),
+            rows.first().map_or(0, Vec::len)
+        ),
+        Value::Date(_) => "a date".to_string(),
+        Value::Duration(_) => "a duration".to_string(),
+    }
+}
+
+fn shape_error(message: String) -> ExpError {
+    ExpError::ShapeError(message)
+}
+
+// 需要数的地方出现了向量或矩阵
+pub fn expected_number(value: &Value) -> ExpError {
+    shape_error(std::format!("expected a number, got {}", describe(value)))
+}
+
+// 对向量或矩阵的每个元素计算 f
+fn map(value: Value, mut f: impl FnMut(f64) -> Result<f64>) -> Result<Value<'static>> {
+    Ok(match value {
+        Value::Number(n) => Value::Number(f(n)?),
+        Value::Vector(items) => Value::Vector(items.into_iter().map(f).collect::<Result<_>>()?),
+        Value::Matrix(rows) => Value::Matrix(
+            rows.into_iter()
+                .map(|row| row.into_iter().map(&mut f).collect::<Result<_>>())
+                .collect::<Result<_>>()?,
+        ),
+        value => return Err(expected_number(&value)),
+    })
+}
+
+// 两个形状相同的向量或矩阵逐个元素计算 f
+// 数、向量或矩阵中的所有元素，矩阵按行展开
+fn elements(value: &Value) -> Result<Vec<f64>> {
+    match value {
+        Value::Number(n) => Ok(vec![*n]),
+        Value::Vector(items) => Ok(items.clone()),
+        Value::Matrix(rows) => Ok(rows.concat()),
+        _ => Err(shape_error(std::format!(
+            "expected numbers, got {}",
+            describe(value)
+        ))),
+    }
+}
+
+fn zip(a: &[f64], b: &[f64], f: &mut impl FnMut(f64, f64) -> Result<f64>) -> Result<Vec<f64>> {
+    a.iter().zip(b).map(|(x, y)| f(*x, *y)).collect()
+}
+
+// 矩阵的列数，矩阵至少有一行
+fn columns(rows: &[Vec<f64>]) -> usize {
+    rows[0].len()
+}
+
+fn transpose(rows: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    (0..columns(rows))
+        .map(|j| rows.iter().map(|row| row[j]).collect())
+        .collect()
+}
+
+fn dot(u: &[f64], v: &[f64]) -> f64 {
+    u.iter().zip(v).map(|(x, y)| x * y).sum()
+}
+
+// 矩阵乘法，向量在左边时是行向量，在右边时是列向量，结果仍然是向量
+fn matmul(a: &Value, b: &Value) -> Result<Value<'static>> {
+    let mismatch = || {
+        shape_error(std::format!(
+            "matmul() cannot multiply {} by {}",
+            describe(a),
+            describe(b)
+        ))
+    };
+    match (a, b) {
+        (Value::Matrix(a), Value::Matrix(b)) if columns(a) == b.len() => {
+            let b = transpose(b);
+            Ok(Value::Matrix(
+                a.iter()
+                    .map(|row| b.iter().map(|column| dot(row, column)).collect())
+                    .collect(),
+            ))
+        }
+        (Value::Matrix(a), Value::Vector(v)) if columns(a) == v.len() => {
+            Ok(Value::Vector(a.iter().map(|row| dot(row, v)).collect()))
+        }
+        (Value::Vector(v), Value::Matrix(b)) if v.len() == b.len() => Ok(Value::Vector(
+            transpose(b).iter().map(|column| dot(v, column)).collect(),
+        )),
+        _ => Err(mismatch()),
+    }
+}
+
+impl Evaluator<'_> {
+    // 表达式的值是否可能是向量或矩阵，或者用到了向量和矩阵，用显式的栈遍历
+    pub fn involves_tensor(&self, ast: &Ast) -> bool {
+        let mut pending = vec![ast];
+        while let Some(node) = pending.pop() {
+            match node {
+                Ast::List(_) => return true,
+                Ast::Var(name)
+                    if self
+                        .context
+                        .as_deref()
+                        .and_then(|context| context.get_value(name))
+                        .is_some_and(|value| !matches!(value, Value::Number(_))) =>
+                {
+                    return true
+                }
+                Ast::Call(name, _)
+                    if (TENSOR_FUNCTIONS.contains(&name.as_str())
+                        || datetime::is_date_function(name))
+                        && !self.overridden(name) =>
+                {
+                    return true
+                }
+                Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => {}
+                Ast::BinOp(_, lhs, rhs) => pending.extend([lhs.as_ref(), rhs.as_ref()]),
+                Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) => pending.push(operand),
+                Ast::Call(_, args) | Ast::Seq(args) => pending.extend(args.iter()),
+                Ast::Cond(cond, then, otherwise) => {
+                    pending.extend([cond.as_ref(), then.as_ref(), otherwise.as_ref()])
+                }
+                // 函数体在调用时求值
+                Ast::Define(..) => {}
+            }
+        }
+        false
+    }
+
+    // 变量的值必须是数，上下文中没有时查找内置常量
+    pub fn variable(&self, name: &str) -> Result<f64> {
+        match self
+            .context
+            .as_deref()
+            .and_then(|context| context.get_value(name))
+        {
+            Some(Value::Number(value)) => Ok(value),
+            Some(value) => Err(expected_number(&value)),
+            None => builtin_constant(name)
+                .ok_or_else(|| ExpError::ParseError(std::format!("Unknown variable: {}", name))),
+        }
+    }
+
+    // 计算用到了向量或矩阵的表达式，结果必须是数
+    // 不放在 eval_node 中，避免增大递归求值时每一层的栈帧
+    #[inline(never)]
+    pub fn eval_number(&mut self, ast: &Ast) -> Result<f64> {
+        match self.eval_value(ast)? {
+            Value::Number(value) => Ok(value),
+            value => Err(expected_number(&value)),
+        }
+    }
+
+    // 计算语法树的值，结果可以是数、向量或矩阵；不涉及向量和矩阵的部分按数计算
+    pub fn eval_value(&mut self, ast: &Ast) -> Result<Value<'static>> {
+        if !self.involves_tensor(ast) {
+            return self.eval(ast).map(Value::Number);
+        }
+        match ast {
+            Ast::List(items) => self.eval_list(items),
+            Ast::Var(name) => Ok(self
+                .context
+                .as_deref()
+                .and_then(|context| context.get_value(name))
+                .expect("tensor variable")),
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                let mut value = self.eval_value(node)?;
+                for (op, rhs) in spine {
+                    let rhs = self.eval_value(rhs)?;
+                    value = self.combine(op, value, rhs)?;
+                }
+                Ok(value)
+            }
+            // 一元运算按元素计算，与数的一元运算规则相同
+            Ast::UnaryOp(op, operand) => {
+                let value = self.eval_value(operand)?;
+                if let Some(result) = datetime::unary(op, &value) {
+                    return result;
+                }
+                map(value, |x| {
+                    self.eval_node(&Ast::UnaryOp(op.clone(), Box::new(Ast::Num(x))))
+                })
+            }
+            Ast::Call(name, args) if series_notation(name, args).is_some() => {
+                self.eval(ast).map(Value::Number)
+            }
+            Ast::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| match arg {
+                        Ast::Str(text) => Ok(Value::Text(text)),
+                        arg => self.eval_value(arg),
+                    })
+                    .collect::<Result<Vec<Value>>>()?;
+                self.call_value(name, &args)
+            }
+            Ast::Cond(cond, then, otherwise) => match self.eval_value(cond)? {
+                Value::Number(n) if n != 0.0 => self.eval_value(then),
+                Value::Number(_) => self.eval_value(otherwise),
+                value => Err(expected_number(&value)),
+            },
+            Ast::Assign(name, value) => {
+                let value = self.eval_value(value)?;
+                match self.context.as_deref_mut() {
+                    Some(context) => context.set_value(name, value.clone()),
+                    None => {
+                        return Err(ExpError::ParseError(
+                            "Assignment requires an EvalContext".to_string(),
+                        ))
+                    }
+                }
+                Ok(value)
+            }
+            Ast::Seq(statements) => {
+                let mut value = Err(ExpError::ParseError("Empty expression".to_string()));
+                for statement in statements {
+                    value = Ok(self.eval_value(statement)?);
+                }
+                value
+            }
+            _ => self.eval(ast).map(Value::Number),
+        }
+    }
+
+    // 元素都是数时为向量，都是长度相同的向量时为矩阵
+    fn eval_list(&mut self, items: &[Ast]) -> Result<Value<'static>> {
+        let items = items
+            .iter()
+            .map(|item| self.eval_value(item))
+            .collect::<Result<Vec<Value>>>()?;
+        if items.iter().all(|item| matches!(item, Value::Number(_))) {
+            return Ok(Value::Vector(
+                items
+                    .into_iter()
+                    .map(|item| match item {
+                        Value::Number(n) => n,
+                        _ => unreachable!(),
+                    })
+                    .collect(),
+            ));
+        }
+        let mut rows = Vec::new();
+        for item in items {
+            match item {
+                Value::Vector(row)
+                    if rows
+                        .first()
+                        .is_none_or(|first: &Vec<f64>| first.len() == row.len()) =>
+                {
+                    rows.push(row)
+                }
+                Value::Vector(row) => {
+                    return Err(shape_error(std::format!(
+                        "matrix rows must have the same length, got {} and {}",
+                        rows[0].len(),
+                        row.len()
+                    )))
+                }
+                item => {
+                    return Err(shape_error(std::format!(
+                        "matrix rows must be vectors, got {}",
+                        describe(&item)
+                    )))
+                }
+            }
+        }
+        Ok(Value::Matrix(rows))
+    }
+
+    // 按元素计算二元运算，数与向量、矩阵运算时作用到每个元素上
+    fn combine(&self, op: &Token, a: Value<'static>, b: Value<'static>) -> Result<Value<'static>> {
+        if let Some(result) = datetime::binary(op, &a, &b) {
+            return result;
+        }
+        let mut f = |x, y| self.compute_binary(op, x, y);
+        match (a, b) {
+            (Value::Number(x), b) => map(b, |y| f(x, y)),
+            (a, Value::Number(y)) => map(a, |x| f(x, y)),
+            (Value::Vector(u), Value::Vector(v)) if u.len() == v.len() => {
+                Ok(Value::Vector(zip(&u, &v, &mut f)?))
+            }
+            (Value::Matrix(a), Value::Matrix(b))
+                if a.len() == b.len() && columns(&a) == columns(&b) =>
+            {
+                Ok(Value::Matrix(
+                    a.iter()
+                        .zip(&b)
+                        .map(|(x, y)| zip(x, y, &mut f))
+                        .collect::<Result<_>>()?,
+                ))
+            }
+            (a, b) => Err(shape_error(std::format!(
+                "cannot apply {} to {} and {}",
+                op,
+                describe(&a),
+                describe(&b)
+            ))),
+        }
+    }
+
+    // 调用参数中有向量或矩阵的函数
+    fn call_value(&mut self, name: &str, args: &[Value]) -> Result<Value<'static>> {
+        if datetime::is_date_function(name) && !self.overridden(name) {
+            return datetime::call(name, args);
+        }
+        let tensors = args
+            .iter()
+            .any(|arg| !matches!(arg, Value::Number(_) | Value::Text(_)));
+        let builtin = !self.overridden(name);
+        // 向量函数的参数个数不对或者都是数时直接报错，按普通的函数调用计算会再回到这里
+        let tensor_function = builtin && TENSOR_FUNCTIONS.contains(&name);
+        if tensor_function {
+            check_arity(name, args.len())?;
+        }
+        // 参数都是数时按普通的函数调用计算
+        if !tensors && !tensor_function {
+            let args = args
+                .iter()
+                .map(|arg| match arg {
+                    Value::Number(n) => Ast::Num(*n),
+                    Value::Text(text) => Ast::Str(text.to_string()),
+                    _ => unreachable!(),
+                })
+                .collect();
+            return self
+                .eval_node(&Ast::Call(name.to_string(), args))
+                .map(Value::Number);
+        }
+        match (name, args) {
+            ("dot", [Value::Vector(u), Value::Vector(v)]) if builtin && u.len() == v.len() => {
+                let result = dot(u, v);
+                self.check_finite(&[], result).map(Value::Number)
+            }
+            ("matmul", [a, b]) if builtin => map(matmul(a, b)?, |x| self.check_finite(&[], x)),
+            ("transpose", [Value::Matrix(rows)]) if builtin => Ok(Value::Matrix(transpose(rows))),
+            // 汇总函数对所有元素计算，向量和矩阵展开后与其他参数合在一起
+            _ if builtin && stats::AGGREGATE_FUNCTIONS.contains(&name) => {
+                let values = args.iter().map(elements).collect::<Result<Vec<_>>>()?;
+                self.call(name, &values.concat()).map(Value::Number)
+            }
+            // 单参数的内置函数按元素计算
+            (_, [value]) if builtin && self.functions.contains_key(name) => {
+                map(value.clone(), |x| self.call(name, &[x]))
+            }
+            // 宿主程序的函数可以直接接收向量和矩阵
+            _ if builtin
+                && !self.functions.contains_key(name)
+                && !TENSOR_FUNCTIONS.contains(&name)
+                && !random::is_random(name) =>
+            {
+                self.call_provider(name, args).map(Value::Number)
+            }
+            _ => Err(shape_error(std::format!(
+                "{}() does not accept {}",
+                name,
+                args.iter()
+                    .map(describe)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ))),
+        }
+    }
+}
+
+impl Expr<'_> {
+    // 解析并计算表达式的值，结果可以是数、向量或矩阵
+    pub fn eval_value(&mut self) -> Result<Value<'static>> {
+        let ast = self.parse().map_err(|e| e.localized(self.locale))?;
+        self.evaluator
+            .eval_value(&ast)
+            .map_err(|e| e.localized(self.locale))
+    }
+}
+
+// 表达式求值，结果可以是向量或矩阵，例如 evaluate_value("[1, 2] * 3") 返回 [3, 6]
+pub fn evaluate_value(input: &str) -> Result<Value<'static>> {
+    let mut context = EvalContext::default();
+    evaluate_value_with_context(input, &mut context)
+}
+
+// 使用给定的上下文求值，值为向量或矩阵的变量同样保存在上下文中
+pub fn evaluate_value_with_context(
+    input: &str,
+    context: &mut EvalContext,
+) -> Result<Value<'static>> {
+    Expr::new(strip_formula_prefix(input)?)
+        .with_context(context)
+        .eval_value()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(input: &str) -> String {
+        evaluate_value(input).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_vector_arithmetic() {
+        assert_eq!(value("[1, 2, 3]"), "[1, 2, 3]");
+        assert_eq!(value("[1, 2, 3] + [10, 20, 30]"), "[11, 22, 33]");
+        assert_eq!(value("[1, 2] * 3 - 1"), "[2, 5]");
+        assert_eq!(value("2 ^ [1, 2, 3]"), "[2, 4, 8]");
+        assert_eq!(value("-[1, -2]"), "[-1, 2]");
+        assert_eq!(value("[1, 2, 3] > 1"), "[0, 1, 1]");
+        assert_eq!(value("sqrt([4, 9])"), "[2, 3]");
+        assert_eq!(value("[]"), "[]");
+        assert_eq!(value("[1 + 1, max(3, 4)]"), "[2, 4]");
+        // 结果是数时 evaluate 也可以使用
+        assert_eq!(evaluate("dot([1, 2, 3], [4, 5, 6])").unwrap(), 32.0);
+        assert_eq!(value("1 + 2"), "3");
+    }
+
+    #[test]
+    fn test_matrix_operations() {
+        assert_eq!(value("[[1, 2], [3, 4]]"), "[[1, 2], [3, 4]]");
+        assert_eq!(value("[[1, 2], [3, 4]] * 2"), "[[2, 4], [6, 8]]");
+        assert_eq!(
+            value("[[1, 2], [3, 4]] + [[10, 20], [30, 40]]"),
+            "[[11, 22], [33, 44]]"
+        );
+        assert_eq!(
+            value("matmul([[1, 2], [3, 4]], [[5, 6], [7, 8]])"),
+            "[[19, 22], [43, 50]]"
+        );
+        assert_eq!(
+            value("matmul([[1, 2, 3], [4, 5, 6]], [1, 0, -1])"),
+            "[-2, -2]"
+        );
+        assert_eq!(value("matmul([1, 1], [[1, 2], [3, 4]])"), "[4, 6]");
+        assert_eq!(
+            value("transpose([[1, 2, 3], [4, 5, 6]])"),
+            "[[1, 4], [2, 5], [3, 6]]"
+        );
+    }
+
+    #[test]
+    fn test_tensor_variables() {
+        let mut context = EvalContext::new();
+        let eval = |input: &str, context: &mut EvalContext| {
+            evaluate_value_with_context(input, context)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(eval("v = [3, 4]", &mut context), "[3, 4]");
+        assert_eq!(eval("sqrt(dot(v, v))", &mut context), "5");
+        assert_eq!(eval("w = v * 2; w - v", &mut context), "[3, 4]");
+        assert_eq!(context.get("v"), None);
+        assert_eq!(context.get_value("v"), Some(Value::Vector(vec![3.0, 4.0])));
+        // 赋值为数后不再是向量
+        assert_eq!(eval("v = 1; v + 1", &mut context), "2");
+        assert_eq!(context.get("v"), Some(1.0));
+        // 向量变量只在作用域内有效
+        context.push_scope();
+        context.set_value("m", Value::Matrix(vec![vec![1.0]]));
+        assert_eq!(eval("m * 5", &mut context), "[[5]]");
+        context.pop_scope();
+        assert!(evaluate_value_with_context("m", &mut context).is_err());
+    }
+
+    #[test]
+    fn test_shape_errors() {
+        for input in [
+            "[1, 2] + [1, 2, 3]",
+            "[[1, 2], [3]]",
+            "[[1, 2], 3]",
+            "[[[1]]]",
+            "dot([1, 2], [1])",
+            "matmul([[1, 2]], [[1, 2]])",
+            "matmul([1, 2], [1, 2])",
+            "[[1, 2]] + [1, 2]",
+            "[1, 2] ? 1 : 0",
+            "log([1, 2], 3)",
+            "rand([1])",
+            "dot(1, 2)",
+            "transpose(3)",
+        ] {
+            assert!(
+                matches!(evaluate_value(input), Err(ExpError::ShapeError(_))),
+                "{}",
+                input
+            );
+        }
+        // evaluate 的结果必须是数
+        assert_eq!(
+            evaluate("[1, 2] * 2").unwrap_err().to_string(),
+            "ShapeError: expected a number, got a vector of length 2"
+        );
+        assert!(evaluate("v = [1]; v + 1").is_err());
+        assert!(evaluate_value("[1, 2").is_err());
+        assert!(evaluate_value("[1 2]").is_err());
+        // 参数都是数或者个数不对时报错，而不是无限递归
+        assert_eq!(
+            evaluate("dot()").unwrap_err().to_string(),
+            "ParseError: dot() takes exactly 2 arguments, got 0"
+        );
+        assert_eq!(
+            evaluate("transpose()").unwrap_err().to_string(),
+            "ParseError: transpose() takes exactly 1 argument, got 0"
+        );
+        assert_eq!(
+            evaluate("dot(1, 2)").unwrap_err().to_string(),
+            "ShapeError: dot() does not accept a number, a number"
+        );
+    }
+}
@@ -0,0 +1,642 @@
+// 把语法树渲染为 LaTeX 和 MathML，便于在文档中排版公式
+// 除法输出为分式、乘方输出为上标，括号规则与 format 模块相同，分式本身不需要括号，作为乘方的底数时除外
+use super::format::{
+    left_needs_parens, level, right_min_level, ATOM_LEVEL, CONDITIONAL_LEVEL, UNARY_LEVEL,
+};
+use super::*;
+
+// 分式和上标自带边界，排版时当作原子处理
+fn typeset_level(ast: &Ast) -> i32 {
+    match ast {
+        Ast::BinOp(Token::Divide, ..) => ATOM_LEVEL,
+        _ => level(ast),
+    }
+}
+
+// 在一行内排版的二元运算符，除法和乘方单独处理
+fn is_inline(op: &Token) -> bool {
+    !matches!(op, Token::Divide | Token::Power)
+}
+
+// 乘方的底数是否需要括号；分式也要加括号，否则上标看起来只属于分母
+fn base_needs_parens(base: &Ast) -> bool {
+    matches!(base, Ast::BinOp(Token::Divide, ..)) || typeset_level(base) < ATOM_LEVEL
+}
+
+// 一行内排版的左侧运算链，遇到分式、上标或需要括号的左操作数时停止
+fn inline_spine(ast: &Ast) -> (&Ast, Vec<(&Token, &Ast)>) {
+    ast.left_spine_while(is_inline, |op, lhs| {
+        !left_needs_parens(op, lhs, typeset_level)
+    })
+}
+
+// 解析输入后渲染为 LaTeX，输入有语法错误时返回错误
+pub fn latex_expression(input: &str) -> Result<String> {
+    Ok(to_latex(&Expr::new(strip_formula_prefix(input)?).parse()?))
+}
+
+// 解析输入后渲染为 MathML，输入有语法错误时返回错误
+pub fn mathml_expression(input: &str) -> Result<String> {
+    Ok(to_mathml(&Expr::new(strip_formula_prefix(input)?).parse()?))
+}
+
+// 把语法树渲染为 LaTeX，如 `(1+x)/2^n` 输出为 `\frac{1 + x}{2^{n}}`
+pub fn to_latex(ast: &Ast) -> String {
+    let mut out = String::new();
+    write_latex(ast, &mut out);
+    out
+}
+
+// 把语法树渲染为 MathML，外层是 `<math>` 元素
+pub fn to_mathml(ast: &Ast) -> String {
+    let mut out = String::from("<math xmlns=\"http://www.w3.org/1998/Math/MathML\">");
+    write_mathml(ast, &mut out);
+    out.push_str("</math>");
+    out
+}
+
+// 二元运算符的 LaTeX 写法
+fn latex_operator(op: &Token) -> String {
+    let symbol = match op {
+        // 自定义运算符按原样输出为文本
+        Token::Operator(symbol) => return std::format!("\\mathbin{{\\text{{{}}}}}", symbol),
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Multiply => "\\cdot",
+        Token::Modulo => "\\bmod",
+        Token::Less => "<",
+        Token::LessEqual => "\\le",
+        Token::Greater => ">",
+        Token::GreaterEqual => "\\ge",
+        Token::Equal => "=",
+        Token::NotEqual => "\\ne",
+        Token::And => "\\land",
+        Token::Or => "\\lor",
+        Token::BitAnd => "\\mathbin{\\&}",
+        Token::BitOr => "\\mathbin{|}",
+        Token::Xor => "\\oplus",
+        Token::ShiftLeft => "\\ll",
+        Token::ShiftRight => "\\gg",
+        _ => "?",
+    };
+    symbol.to_string()
+}
+
+// 标识符的 LaTeX 写法：内置常量用对应的符号，多个字母的名字用正体
+fn latex_ident(name: &str) -> String {
+    match name {
+        "pi" => "\\pi".to_string(),
+        "tau" => "\\tau".to_string(),
+        "inf" => "\\infty".to_string(),
+        _ if name.chars().count() == 1 => name.to_string(),
+        _ => std::format!("\\mathrm{{{}}}", name.replace('_', "\\_")),
+    }
+}
+
+// 字符串在 \text{} 中的写法，LaTeX 的特殊字符需要转义
+fn latex_text(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\\' => "\\textbackslash{}".to_string(),
+            '{' | '}' | '_' | '&' | '%' | '$' | '#' => std::format!("\\{}", c),
+            '^' => "\\^{}".to_string(),
+            '~' => "\\~{}".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// 输出子节点，优先级低于 min_level 时加括号
+fn write_latex_operand(ast: &Ast, min_level: i32, out: &mut String) {
+    write_latex_grouped(ast, typeset_level(ast) < min_level, out);
+}
+
+fn write_latex_grouped(ast: &Ast, parens: bool, out: &mut String) {
+    if parens {
+        out.push_str("\\left(");
+        write_latex(ast, out);
+        out.push_str("\\right)");
+    } else {
+        write_latex(ast, out);
+    }
+}
+
+// 输出用逗号分隔的参数
+fn write_latex_args(args: &[Ast], out: &mut String) {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_latex_operand(arg, CONDITIONAL_LEVEL, out);
+    }
+}
+
+fn write_latex(ast: &Ast, out: &mut String) {
+    match ast {
+        Ast::Num(n) => out.push_str(&n.to_string()),
+        Ast::Var(name) => out.push_str(&latex_ident(name)),
+        Ast::Str(text) => out.push_str(&std::format!("\\text{{\"{}\"}}", latex_text(text))),
+        Ast::BinOp(Token::Divide, lhs, rhs) => {
+            out.push_str("\\frac{");
+            write_latex(lhs, out);
+            out.push_str("}{");
+            write_latex(rhs, out);
+            out.push('}');
+        }
+        Ast::BinOp(Token::Power, base, exponent) => {
+            write_latex_grouped(base, base_needs_parens(base), out);
+            out.push_str("^{");
+            write_latex(exponent, out);
+            out.push('}');
+        }
+        Ast::BinOp(..) => {
+            let (node, spine) = inline_spine(ast);
+            match spine.first() {
+                Some((op, _)) if left_needs_parens(op, node, typeset_level) => {
+                    write_latex_operand(node, ATOM_LEVEL, out)
+                }
+                _ => write_latex(node, out),
+            }
+            for (op, rhs) in spine {
+                out.push_str(&std::format!(" {} ", latex_operator(op)));
+                write_latex_operand(rhs, right_min_level(op), out);
+            }
+        }
+        Ast::UnaryOp(Token::Factorial, operand) => {
+            write_latex_operand(operand, ATOM_LEVEL, out);
+            out.push('!');
+        }
+        Ast::UnaryOp(op, operand) => {
+            match op {
+                Token::BitNot => out.push_str("\\sim "),
+                Token::Operator(symbol) => {
+                    out.push_str(&std::format!("\\mathop{{\\text{{{}}}}}", symbol))
+                }
+                _ => out.push_str(&op.to_string()),
+            }
+            write_latex_operand(operand, UNARY_LEVEL, out);
+        }
+        Ast::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("sqrt", [x]) => {
+                out.push_str("\\sqrt{");
+                write_latex(x, out);
+                out.push('}');
+            }
+            ("abs", [x]) => {
+                out.push_str("\\left|");
+                write_latex(x, out);
+                out.push_str("\\right|");
+            }
+            ("floor", [x]) => {
+                out.push_str("\\left\\lfloor ");
+                write_latex(x, out);
+                out.push_str("\\right\\rfloor");
+            }
+            ("ceil", [x]) => {
+                out.push_str("\\left\\lceil ");
+                write_latex(x, out);
+                out.push_str("\\right\\rceil");
+            }
+            // 求和与求积记号，通项是加减运算时需要括号
+            (_, [index, from, to, body]) if series_notation(name, args).is_some() => {
+                out.push_str(if name == "sum" { "\\sum_{" } else { "\\prod_{" });
+                write_latex(index, out);
+                out.push('=');
+                write_latex(from, out);
+                out.push_str("}^{");
+                write_latex(to, out);
+                out.push_str("} ");
+                write_latex_operand(body, Token::Multiply.precedence(), out);
+            }
+            ("log", [x, base]) => {
+                out.push_str("\\log_{");
+                write_latex(base, out);
+                out.push_str("}\\left(");
+                write_latex(x, out);
+                out.push_str("\\right)");
+            }
+            _ => {
+                // 常见函数有对应的 LaTeX 命令，其余用 \operatorname
+                match name.as_str() {
+                    "sin" | "cos" | "tan" | "exp" | "ln" | "log" | "min" | "max" => {
+                        out.push_str(&std::format!("\\{}", name))
+                    }
+                    "asin" | "acos" | "atan" => out.push_str(&std::format!("\\arc{}", &name[1..])),
+                    _ => out.push_str(&std::format!(
+                        "\\operatorname{{{}}}",
+                        name.replace('_', "\\_")
+                    )),
+                }
+                out.push_str("\\left(");
+                write_latex_args(args, out);
+                out.push_str("\\right)");
+            }
+        },
+        Ast::Assign(name, value) => {
+            out.push_str(&latex_ident(name));
+            out.push_str(" = ");
+            write_latex_operand(value, CONDITIONAL_LEVEL, out);
+        }
+        Ast::Define(name, params, body) => {
+            out.push_str(&latex_ident(name));
+            out.push_str("\\left(");
+            let params: Vec<String> = params.iter().map(|param| latex_ident(param)).collect();
+            out.push_str(&params.join(", "));
+            out.push_str("\\right) = ");
+            write_latex_operand(body, CONDITIONAL_LEVEL, out);
+        }
+        Ast::Seq(statements) => {
+            for (i, statement) in statements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(";\\quad ");
+                }
+                write_latex(statement, out);
+            }
+        }
+        Ast::Cond(cond, then, otherwise) => {
+            // 条件表达式输出为分段函数
+            out.push_str("\\begin{cases} ");
+            write_latex(then, out);
+            out.push_str(" & \\text{if } ");
+            write_latex(cond, out);
+            out.push_str(" \\\\ ");
+            write_latex(otherwise, out);
+            out.push_str(" & \\text{otherwise} \\end{cases}");
+        }
+        // 向量输出为一行的矩阵
+        Ast::List(items) => {
+            out.push_str("\\begin{bmatrix}");
+            for (i, row) in matrix_rows(items).into_iter().enumerate() {
+                if i > 0 {
+                    out.push_str(" \\\\");
+                }
+                for (j, item) in row.iter().enumerate() {
+                    out.push_str(if j > 0 { " & " } else { " " });
+                    write_latex(item, out);
+                }
+            }
+            out.push_str(" \\end{bmatrix}");
+        }
+    }
+}
+
+// 矩阵的各行：元素都是向量时是矩阵，否则是只有一行的向量
+fn matrix_rows(items: &[Ast]) -> Vec<&[Ast]> {
+    let rows: Option<Vec<&[Ast]>> = items
+        .iter()
+        .map(|item| match item {
+            Ast::List(row) => Some(row.as_slice()),
+            _ => None,
+        })
+        .collect();
+    match rows {
+        Some(rows) if !rows.is_empty() => rows,
+        _ => vec![items],
+    }
+}
+
+// 二元运算符的 MathML 写法，`<`、`>`、`&` 需要转义
+fn mathml_operator(op: &Token) -> String {
+    let symbol = match op {
+        Token::Operator(symbol) => return mathml_text(symbol),
+        Token::Plus => "+",
+        Token::Minus => "\u{2212}",
+        Token::Multiply => "\u{22c5}",
+        Token::Modulo => "mod",
+        Token::Less => "&lt;",
+        Token::LessEqual => "\u{2264}",
+        Token::Greater => "&gt;",
+        Token::GreaterEqual => "\u{2265}",
+        Token::Equal => "=",
+        Token::NotEqual => "\u{2260}",
+        Token::And => "\u{2227}",
+        Token::Or => "\u{2228}",
+        Token::BitAnd => "&amp;",
+        Token::BitOr => "|",
+        Token::Xor => "\u{2295}",
+        Token::ShiftLeft => "\u{226a}",
+        Token::ShiftRight => "\u{226b}",
+        _ => "?",
+    };
+    symbol.to_string()
+}
+
+// 标识符的 MathML 写法
+fn mathml_ident(name: &str) -> String {
+    match name {
+        "pi" => "<mi>\u{3c0}</mi>".to_string(),
+        "tau" => "<mi>\u{3c4}</mi>".to_string(),
+        "inf" => "<mi>\u{221e}</mi>".to_string(),
+        _ => std::format!("<mi>{}</mi>", name),
+    }
+}
+
+// 文本在 MathML 中的写法，`<`、`>`、`&` 需要转义
+fn mathml_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+// 输出子节点，优先级低于 min_level 时加括号
+fn write_mathml_operand(ast: &Ast, min_level: i32, out: &mut String) {
+    write_mathml_grouped(ast, typeset_level(ast) < min_level, out);
+}
+
+fn write_mathml_grouped(ast: &Ast, parens: bool, out: &mut String) {
+    if parens {
+        out.push_str("<mrow><mo>(</mo>");
+        write_mathml(ast, out);
+        out.push_str("<mo>)</mo></mrow>");
+    } else {
+        write_mathml(ast, out);
+    }
+}
+
+// 输出 `<mrow>` 包裹的子节点，用于分式、上标等只接受一个子元素的位置
+fn write_mathml_row(ast: &Ast, out: &mut String) {
+    out.push_str("<mrow>");
+    write_mathml(ast, out);
+    out.push_str("</mrow>");
+}
+
+fn write_mathml(ast: &Ast, out: &mut String) {
+    match ast {
+        Ast::Num(n) if n.is_sign_negative() => out.push_str(&std::format!(
+            "<mrow><mo>\u{2212}</mo><mn>{}</mn></mrow>",
+            -n
+        )),
+        Ast::Num(n) => out.push_str(&std::format!("<mn>{}</mn>", n)),
+        Ast::Var(name) => out.push_str(&mathml_ident(name)),
+        Ast::Str(text) => out.push_str(&std::format!("<ms>{}</ms>", mathml_text(text))),
+        Ast::BinOp(Token::Divide, lhs, rhs) => {
+            out.push_str("<mfrac>");
+            write_mathml_row(lhs, out);
+            write_mathml_row(rhs, out);
+            out.push_str("</mfrac>");
+        }
+        Ast::BinOp(Token::Power, base, exponent) => {
+            out.push_str("<msup><mrow>");
+            write_mathml_grouped(base, base_needs_parens(base), out);
+            out.push_str("</mrow>");
+            write_mathml_row(exponent, out);
+            out.push_str("</msup>");
+        }
+        Ast::BinOp(..) => {
+            let (node, spine) = inline_spine(ast);
+            out.push_str("<mrow>");
+            match spine.first() {
+                Some((op, _)) if left_needs_parens(op, node, typeset_level) => {
+                    write_mathml_operand(node, ATOM_LEVEL, out)
+                }
+                _ => write_mathml(node, out),
+            }
+            for (op, rhs) in spine {
+                out.push_str(&std::format!("<mo>{}</mo>", mathml_operator(op)));
+                write_mathml_operand(rhs, right_min_level(op), out);
+            }
+            out.push_str("</mrow>");
+        }
+        Ast::UnaryOp(Token::Factorial, operand) => {
+            out.push_str("<mrow>");
+            write_mathml_operand(operand, ATOM_LEVEL, out);
+            out.push_str("<mo>!</mo></mrow>");
+        }
+        Ast::UnaryOp(op, operand) => {
+            let symbol = match op {
+                Token::Minus => "\u{2212}".to_string(),
+                _ => op.to_string(),
+            };
+            out.push_str(&std::format!("<mrow><mo>{}</mo>", symbol));
+            write_mathml_operand(operand, UNARY_LEVEL, out);
+            out.push_str("</mrow>");
+        }
+        Ast::Call(name, args) => match (name.as_str(), args.as_slice()) {
+            ("sqrt", [x]) => {
+                out.push_str("<msqrt>");
+                write_mathml(x, out);
+                out.push_str("</msqrt>");
+            }
+            ("abs", [x]) => write_mathml_fenced("|", x, "|", out),
+            ("floor", [x]) => write_mathml_fenced("\u{230a}", x, "\u{230b}", out),
+            ("ceil", [x]) => write_mathml_fenced("\u{2308}", x, "\u{2309}", out),
+            (_, [index, from, to, body]) if series_notation(name, args).is_some() => {
+                // U+2211 和 U+220F 是求和与求积符号
+                let symbol = if name == "sum" {
+                    "\u{2211}"
+                } else {
+                    "\u{220f}"
+                };
+                out.push_str(&std::format!("<mrow><munderover><mo>{}</mo><mrow>", symbol));
+                write_mathml(index, out);
+                out.push_str("<mo>=</mo>");
+                write_mathml(from, out);
+                out.push_str("</mrow>");
+                write_mathml_row(to, out);
+                out.push_str("</munderover>");
+                write_mathml_operand(body, Token::Multiply.precedence(), out);
+                out.push_str("</mrow>");
+            }
+            _ => {
+                out.push_str("<mrow>");
+                if let ("log", [_, base]) = (name.as_str(), args.as_slice()) {
+                    out.push_str("<msub><mi>log</mi>");
+                    write_mathml_row(base, out);
+                    out.push_str("</msub>");
+                } else {
+                    out.push_str(&std::format!("<mi>{}</mi>", name));
+                }
+                // U+2061 是不可见的函数应用运算符
+                out.push_str("<mo>\u{2061}</mo><mrow><mo>(</mo>");
+                let args = match (name.as_str(), args.as_slice()) {
+                    ("log", [x, _]) => std::slice::from_ref(x),
+                    _ => args.as_slice(),
+                };
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        out.push_str("<mo>,</mo>");
+                    }
+                    write_mathml_operand(arg, CONDITIONAL_LEVEL, out);
+                }
+                out.push_str("<mo>)</mo></mrow></mrow>");
+            }
+        },
+        Ast::Assign(name, value) => {
+            out.push_str(&std::format!("<mrow>{}<mo>=</mo>", mathml_ident(name)));
+            write_mathml_operand(value, CONDITIONAL_LEVEL, out);
+            out.push_str("</mrow>");
+        }
+        Ast::Define(name, params, body) => {
+            out.push_str(&std::format!(
+                "<mrow><mi>{}</mi><mo>\u{2061}</mo><mrow><mo>(</mo>",
+                name
+            ));
+            let params: Vec<String> = params.iter().map(|param| mathml_ident(param)).collect();
+            out.push_str(&params.join("<mo>,</mo>"));
+            out.push_str("<mo>)</mo></mrow><mo>=</mo>");
+            write_mathml_operand(body, CONDITIONAL_LEVEL, out);
+            out.push_str("</mrow>");
+        }
+        Ast::Seq(statements) => {
+            out.push_str("<mrow>");
+            for (i, statement) in statements.iter().enumerate() {
+                if i > 0 {
+                    out.push_str("<mo separator=\"true\">;</mo>");
+                }
+                write_mathml(statement, out);
+            }
+            out.push_str("</mrow>");
+        }
+        Ast::Cond(cond, then, otherwise) => {
+            // 条件表达式输出为分段函数
+            out.push_str("<mrow><mo>{</mo><mtable><mtr><mtd>");
+            write_mathml(then, out);
+            out.push_str("</mtd><mtd><mtext>if\u{a0}</mtext>");
+            write_mathml(cond, out);
+            out.push_str("</mtd></mtr><mtr><mtd>");
+            write_mathml(otherwise, out);
+            out.push_str("</mtd><mtd><mtext>otherwise</mtext></mtd></mtr></mtable></mrow>");
+        }
+        Ast::List(items) => {
+            out.push_str("<mrow><mo>[</mo><mtable>");
+            for row in matrix_rows(items) {
+                out.push_str("<mtr>");
+                for item in row {
+                    out.push_str("<mtd>");
+                    write_mathml(item, out);
+                    out.push_str("</mtd>");
+                }
+                out.push_str("</mtr>");
+            }
+            out.push_str("</mtable><mo>]</mo></mrow>");
+        }
+    }
+}
+
+// 输出两侧带定界符的子节点，如绝对值
+fn write_mathml_fenced(open: &str, ast: &Ast, close: &str, out: &mut String) {
+    out.push_str(&std::format!("<mrow><mo>{}</mo>", open));
+    write_mathml(ast, out);
+    out.push_str(&std::format!("<mo>{}</mo></mrow>", close));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latex_fractions_and_powers() {
+        assert_eq!(
+            latex_expression("(1+x)/2^n").unwrap(),
+            "\\frac{1 + x}{2^{n}}"
+        );
+        assert_eq!(latex_expression("2*3/4").unwrap(), "\\frac{2 \\cdot 3}{4}");
+        assert_eq!(
+            latex_expression("3*(1/2)").unwrap(),
+            "3 \\cdot \\frac{1}{2}"
+        );
+        assert_eq!(
+            latex_expression("(-2)^(1+1)").unwrap(),
+            "\\left(-2\\right)^{1 + 1}"
+        );
+        assert_eq!(
+            latex_expression("(1/2)^2 + 2^(1/2)").unwrap(),
+            "\\left(\\frac{1}{2}\\right)^{2} + 2^{\\frac{1}{2}}"
+        );
+        assert_eq!(
+            latex_expression("(a*b)^2").unwrap(),
+            "\\left(a \\cdot b\\right)^{2}"
+        );
+        assert_eq!(
+            latex_expression("-(x - 1)").unwrap(),
+            "-\\left(x - 1\\right)"
+        );
+        assert_eq!(
+            latex_expression("x <= 2*pi").unwrap(),
+            "x \\le 2 \\cdot \\pi"
+        );
+    }
+
+    #[test]
+    fn test_latex_functions_and_statements() {
+        assert_eq!(
+            latex_expression("sqrt(x^2+1)").unwrap(),
+            "\\sqrt{x^{2} + 1}"
+        );
+        assert_eq!(latex_expression("abs(-3)").unwrap(), "\\left|-3\\right|");
+        assert_eq!(
+            latex_expression("sin(x)+atan(1)").unwrap(),
+            "\\sin\\left(x\\right) + \\arctan\\left(1\\right)"
+        );
+        assert_eq!(
+            latex_expression("log(8, 2)").unwrap(),
+            "\\log_{2}\\left(8\\right)"
+        );
+        assert_eq!(
+            latex_expression("rate_2 = 3").unwrap(),
+            "\\mathrm{rate\\_2} = 3"
+        );
+        assert_eq!(
+            latex_expression("x > 0 ? x : -x").unwrap(),
+            "\\begin{cases} x & \\text{if } x > 0 \\\\ -x & \\text{otherwise} \\end{cases}"
+        );
+        assert_eq!(
+            latex_expression("price(\"A&B_1\")").unwrap(),
+            "\\operatorname{price}\\left(\\text{\"A\\&B\\_1\"}\\right)"
+        );
+        assert_eq!(
+            latex_expression("sum(i, 1, n, i^2) + prod(k, 1, 5, k + 1)").unwrap(),
+            "\\sum_{i=1}^{n} i^{2} + \\prod_{k=1}^{5} \\left(k + 1\\right)"
+        );
+        assert_eq!(
+            latex_expression("[[1, 2], [3, x]] * [1, 2]").unwrap(),
+            "\\begin{bmatrix} 1 & 2 \\\\ 3 & x \\end{bmatrix} \\cdot \\begin{bmatrix} 1 & 2 \\end{bmatrix}"
+        );
+        // 四个参数、第一个是变量名时是求和记号，其他情况仍然是函数
+        assert_eq!(
+            latex_expression("sum(x, 1, 2, 3)").unwrap(),
+            "\\sum_{x=1}^{2} 3"
+        );
+        assert_eq!(
+            latex_expression("sum(x, 1, 2)").unwrap(),
+            "\\operatorname{sum}\\left(x, 1, 2\\right)"
+        );
+    }
+
+    #[test]
+    fn test_mathml_output() {
+        assert_eq!(
+            mathml_expression("(1+x)/2").unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mfrac><mrow><mrow><mn>1</mn><mo>+</mo><mi>x</mi></mrow></mrow><mrow><mn>2</mn></mrow></mfrac></math>"
+        );
+        assert_eq!(
+            mathml_expression("x^2 < 4").unwrap(),
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\"><mrow><msup><mrow><mi>x</mi></mrow><mrow><mn>2</mn></mrow></msup><mo>&lt;</mo><mn>4</mn></mrow></math>"
+        );
+        assert!(mathml_expression("(1/2)^2")
+            .unwrap()
+            .contains("<msup><mrow><mrow><mo>(</mo><mfrac>"));
+        assert!(mathml_expression("sqrt(2) * pi")
+            .unwrap()
+            .contains("<msqrt><mn>2</mn></msqrt><mo>\u{22c5}</mo><mi>\u{3c0}</mi>"));
+        assert!(mathml_expression("max(1, 2)")
+            .unwrap()
+            .contains("<mi>max</mi><mo>\u{2061}</mo><mrow><mo>(</mo><mn>1</mn><mo>,</mo><mn>2</mn><mo>)</mo></mrow>"));
+        assert!(mathml_expression("price(\"A&B\")")
+            .unwrap()
+            .contains("<ms>A&amp;B</ms>"));
+        assert!(mathml_expression("[1, 2]")
+            .unwrap()
+            .contains("<mrow><mo>[</mo><mtable><mtr><mtd><mn>1</mn></mtd><mtd><mn>2</mn></mtd></mtr></mtable><mo>]</mo></mrow>"));
+        assert!(mathml_expression("sum(i, 1, 3, i)")
+            .unwrap()
+            .contains("<munderover><mo>\u{2211}</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mrow><mn>3</mn></mrow></munderover><mi>i</mi>"));
+    }
+
+    #[test]
+    fn test_typeset_long_chain() {
+        let src = std::format!("1{}", "+1".repeat(100_000));
+        assert_eq!(latex_expression(&src).unwrap().len(), 1 + 4 * 100_000);
+        assert!(mathml_expression(&src).is_ok());
+    }
+}
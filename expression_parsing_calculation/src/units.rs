@@ -0,0 +1,450 @@
+// 带单位的计算：在表达式求值器之上增加一层量纲分析
+// 数字后面直接跟单位（隐式乘法），如 `3 km + 200 m`、`90 mph to km/h`、`100 degC to degF`
+// 省略的乘号优先级高于 `*`、`/`，低于 `^`，所以 `100 m / 10 s` 等于 10 m/s
+// 内部统一换算为国际单位制的基本单位（m、kg、s、K）计算，量纲不一致时返回 ExpError::DimensionError
+use super::*;
+
+// 量纲：长度、质量、时间、温度的指数，如速度为 [1, 0, -1, 0]
+type Dims = [i32; 4];
+
+const DIMENSIONLESS: Dims = [0, 0, 0, 0];
+const LENGTH: Dims = [1, 0, 0, 0];
+const MASS: Dims = [0, 1, 0, 0];
+const TIME: Dims = [0, 0, 1, 0];
+const TEMPERATURE: Dims = [0, 0, 0, 1];
+
+// 基本单位的符号，与 Dims 的顺序一致
+const BASE_UNITS: [&str; 4] = ["m", "kg", "s", "K"];
+
+// 一个单位：基本单位值 = (数值 + offset) * scale
+// 只有摄氏度、华氏度的 offset 不为 0，且只在 `to` 转换单个温度时使用，运算中按温差处理
+struct Unit {
+    scale: f64,
+    offset: f64,
+    dims: Dims,
+}
+
+// 查找单位
+fn lookup_unit(name: &str) -> Option<Unit> {
+    let (scale, dims) = match name {
+        // 长度
+        "m" => (1.0, LENGTH),
+        "km" => (1000.0, LENGTH),
+        "cm" => (0.01, LENGTH),
+        "mm" => (0.001, LENGTH),
+        "mi" => (1609.344, LENGTH),
+        "yd" => (0.9144, LENGTH),
+        "ft" => (0.3048, LENGTH),
+        "in" => (0.0254, LENGTH),
+        // 质量
+        "kg" => (1.0, MASS),
+        "g" => (0.001, MASS),
+        "mg" => (1e-6, MASS),
+        "t" => (1000.0, MASS),
+        "lb" => (0.45359237, MASS),
+        "oz" => (0.028349523125, MASS),
+        // 时间
+        "s" => (1.0, TIME),
+        "ms" => (0.001, TIME),
+        "min" => (60.0, TIME),
+        "h" => (3600.0, TIME),
+        "day" => (86400.0, TIME),
+        // 速度
+        "mph" => (1609.344 / 3600.0, [1, 0, -1, 0]),
+        // 温度
+        "K" => (1.0, TEMPERATURE),
+        "degC" => {
+            return Some(Unit {
+                scale: 1.0,
+                offset: 273.15,
+                dims: TEMPERATURE,
+            })
+        }
+        "degF" => {
+            return Some(Unit {
+                scale: 5.0 / 9.0,
+                offset: 459.67,
+                dims: TEMPERATURE,
+            })
+        }
+        _ => return None,
+    };
+    Some(Unit {
+        scale,
+        offset: 0.0,
+        dims,
+    })
+}
+
+// 带量纲的数值，value 为基本单位下的值
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    dims: Dims,
+}
+
+impl Quantity {
+    fn scalar(value: f64) -> Self {
+        Quantity {
+            value,
+            dims: DIMENSIONLESS,
+        }
+    }
+
+    // 基本单位表示的单位，如 `m/s`、`kg*m^2`，无量纲时为空字符串
+    pub fn unit(&self) -> String {
+        let part = |i: usize, power: i32| match power {
+            1 => BASE_UNITS[i].to_string(),
+            _ => format!("{}^{}", BASE_UNITS[i], power),
+        };
+        let numerator: Vec<String> = (0..4)
+            .filter(|&i| self.dims[i] > 0)
+            .map(|i| part(i, self.dims[i]))
+            .collect();
+        let denominator: Vec<String> = (0..4)
+            .filter(|&i| self.dims[i] < 0)
+            .map(|i| part(i, -self.dims[i]))
+            .collect();
+        match (numerator.is_empty(), denominator.is_empty()) {
+            (_, true) => numerator.join("*"),
+            (true, false) => format!("1/{}", denominator.join("/")),
+            (false, false) => format!("{}/{}", numerator.join("*"), denominator.join("/")),
+        }
+    }
+}
+
+// 量纲的名称，用于错误信息
+fn dimension_name(dims: Dims) -> String {
+    match dims {
+        DIMENSIONLESS => "dimensionless".to_string(),
+        LENGTH => "length".to_string(),
+        MASS => "mass".to_string(),
+        TIME => "time".to_string(),
+        TEMPERATURE => "temperature".to_string(),
+        _ => Quantity { value: 1.0, dims }.unit(),
+    }
+}
+
+// 带单位的表达式求值器，函数调用的参数必须是无量纲的
+struct UnitEvaluator {
+    scalar: Evaluator<'static>, // 计算函数调用
+}
+
+impl UnitEvaluator {
+    fn new() -> Self {
+        UnitEvaluator {
+            scalar: Evaluator::new(),
+        }
+    }
+
+    fn eval(&self, ast: &Ast) -> Result<Quantity> {
+        match ast {
+            Ast::Num(n) => Ok(Quantity::scalar(*n)),
+            Ast::Var(name) => {
+                if let Some(unit) = lookup_unit(name) {
+                    Ok(Quantity {
+                        value: unit.scale,
+                        dims: unit.dims,
+                    })
+                } else if let Some(value) = builtin_constant(name) {
+                    Ok(Quantity::scalar(value))
+                } else {
+                    Err(ExpError::ParseError(format!("Unknown unit: {}", name)))
+                }
+            }
+            Ast::BinOp(..) => {
+                let (node, spine) = ast.left_spine();
+                let mut value = self.eval(node)?;
+                for (op, rhs) in spine {
+                    let rhs = self.eval(rhs)?;
+                    value = self.compute_binary(op, value, rhs)?;
+                }
+                Ok(value)
+            }
+            Ast::UnaryOp(Token::Minus, operand) => {
+                let q = self.eval(operand)?;
+                Ok(Quantity {
+                    value: -q.value,
+                    ..q
+                })
+            }
+            Ast::UnaryOp(Token::Plus, operand) => self.eval(operand),
+            Ast::Call(name, args) => {
+                let args = args
+                    .iter()
+                    .map(|arg| {
+                        self.eval(arg)
+                            .and_then(|q| self.require_dimensionless(name, q))
+                    })
+                    .collect::<Result<Vec<f64>>>()?;
+                Ok(Quantity::scalar(self.scalar.call(name, &args)?))
+            }
+            _ => Err(ExpError::ParseError(
+                "Unsupported expression in unit calculation".to_string(),
+            )),
+        }
+    }
+
+    fn compute_binary(&self, op: &Token, left: Quantity, right: Quantity) -> Result<Quantity> {
+        let mismatch = |verb: &str| {
+            ExpError::DimensionError(format!(
+                "cannot {} {} and {}",
+                verb,
+                dimension_name(left.dims),
+                dimension_name(right.dims)
+            ))
+        };
+        // 量纲的指数溢出时无法表示，如 (1 m)^2000000000 * (1 m)^2000000000
+        let overflow = || {
+            ExpError::DimensionError(format!(
+                "dimension of {} and {} is out of range",
+                dimension_name(left.dims),
+                dimension_name(right.dims)
+            ))
+        };
+        let combine = |sign: i32| {
+            let mut dims = left.dims;
+            for (d, r) in dims.iter_mut().zip(right.dims) {
+                *d = r
+                    .checked_mul(sign)
+                    .and_then(|r| d.checked_add(r))
+                    .ok_or_else(overflow)?;
+            }
+            Ok(dims)
+        };
+        let (value, dims) = match op {
+            Token::Plus | Token::Minus | Token::Modulo if left.dims != right.dims => {
+                let verb = match op {
+                    Token::Plus => "add",
+                    Token::Minus => "subtract",
+                    _ => "take the remainder of",
+                };
+                return Err(mismatch(verb));
+            }
+            Token::Plus => (left.value + right.value, left.dims),
+            Token::Minus => (left.value - right.value, left.dims),
+            Token::Modulo => (left.value % right.value, left.dims),
+            Token::Multiply => (left.value * right.value, combine(1)?),
+            Token::Divide => (left.value / right.value, combine(-1)?),
+            Token::Power => {
+                // 指数必须是无量纲的整数，否则量纲无法表示
+                if right.dims != DIMENSIONLESS || right.value.fract() != 0.0 {
+                    return Err(mismatch("raise"));
+                }
+                // 超出 i32 范围的指数（包括 inf）无法作为量纲的指数
+                let power = i32::try_from(right.value as i64)
+                    .ok()
+                    .filter(|&power| f64::from(power) == right.value)
+                    .ok_or_else(|| {
+                        ExpError::DimensionError(format!(
+                            "exponent {} is out of range",
+                            right.value
+                        ))
+                    })?;
+                let mut dims = left.dims;
+                for d in dims.iter_mut() {
+                    *d = d.checked_mul(power).ok_or_else(overflow)?;
+                }
+                (left.value.powi(power), dims)
+            }
+            _ => {
+                return Err(ExpError::ParseError(format!(
+                    "Operator {} is not supported in unit calculation",
+                    op
+                )))
+            }
+        };
+        Ok(Quantity { value, dims })
+    }
+
+    fn require_dimensionless(&self, name: &str, q: Quantity) -> Result<f64> {
+        if q.dims == DIMENSIONLESS {
+            Ok(q.value)
+        } else {
+            Err(ExpError::DimensionError(format!(
+                "{}() expects a dimensionless argument, got {}",
+                name,
+                dimension_name(q.dims)
+            )))
+        }
+    }
+}
+
+// 解析带单位的表达式，数字和单位之间的乘号可以省略
+// 省略的乘号单独占一级优先级，位于 `*`、`/` 和 `^` 之间
+fn parse_units(input: &str) -> Result<Ast> {
+    let precedence = PrecedenceTable::default().set('^', 4, ASSOC_RIGHT).set(
+        IMPLICIT_MULTIPLICATION,
+        3,
+        ASSOC_LEFT,
+    );
+    Expr::new(input)
+        .with_precedence(precedence)
+        .with_implicit_multiplication(true)
+        .parse()
+}
+
+// 计算带单位的表达式，结果为基本单位下的值
+pub fn evaluate_quantity(input: &str) -> Result<Quantity> {
+    UnitEvaluator::new().eval(&parse_units(input)?)
+}
+
+// 单个温度值，如 `100 degC`、`-40 degF`，返回数值和单位
+fn temperature_literal(ast: &Ast) -> Option<(f64, Unit)> {
+    match ast {
+        Ast::BinOp(Token::Multiply, value, unit) => {
+            let value = match &**value {
+                Ast::Num(n) => *n,
+                Ast::UnaryOp(Token::Minus, n) => match &**n {
+                    Ast::Num(n) => -n,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            temperature_unit(unit).map(|unit| (value, unit))
+        }
+        _ => None,
+    }
+}
+
+fn temperature_unit(ast: &Ast) -> Option<Unit> {
+    match ast {
+        Ast::Var(name) => lookup_unit(name).filter(|unit| unit.dims == TEMPERATURE),
+        _ => None,
+    }
+}
+
+// 计算带单位的表达式并格式化结果
+// `expr to unit` 将结果转换为指定单位，如 `90 mph to km/h` 得到 `144.84096 km/h`；
+// 没有 `to` 时使用基本单位，如 `3 km + 200 m` 得到 `3200 m`
+pub fn evaluate_with_units(input: &str) -> Result<String> {
+    let Some((expr, target)) = input.split_once(" to ") else {
+        let q = evaluate_quantity(input)?;
+        return Ok(format_quantity(q.value, &q.unit()));
+    };
+    let (expr, target) = (expr.trim(), target.trim());
+    let source = parse_units(expr)?;
+    let target_ast = parse_units(target)?;
+
+    // 单个温度之间的转换需要考虑零点的偏移
+    if let (Some((value, from)), Some(to)) =
+        (temperature_literal(&source), temperature_unit(&target_ast))
+    {
+        let kelvin = (value + from.offset) * from.scale;
+        return Ok(format_quantity(kelvin / to.scale - to.offset, target));
+    }
+
+    let evaluator = UnitEvaluator::new();
+    let q = evaluator.eval(&source)?;
+    let unit = evaluator.eval(&target_ast)?;
+    if q.dims != unit.dims {
+        return Err(ExpError::DimensionError(format!(
+            "cannot convert {} to {}",
+            dimension_name(q.dims),
+            dimension_name(unit.dims)
+        )));
+    }
+    Ok(format_quantity(q.value / unit.value, target))
+}
+
+// 格式化数值和单位，数值最多保留 9 位小数，去掉末尾的 0
+fn format_quantity(value: f64, unit: &str) -> String {
+    let mut number = format!("{:.9}", value);
+    if number.contains('.') {
+        number = number
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string();
+    }
+    if number == "-0" {
+        number = "0".to_string();
+    }
+    if unit.is_empty() {
+        number
+    } else {
+        format!("{} {}", number, unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unit_arithmetic() {
+        assert_eq!(evaluate_with_units("3 km + 200 m").unwrap(), "3200 m");
+        assert_eq!(
+            evaluate_with_units("2 h + 30 min to min").unwrap(),
+            "150 min"
+        );
+        assert_eq!(evaluate_with_units("100 m / 10 s").unwrap(), "10 m/s");
+        assert_eq!(evaluate_with_units("2 m^2 * 3").unwrap(), "6 m^2");
+        assert_eq!(evaluate_with_units("(2 m)^2").unwrap(), "4 m^2");
+        assert_eq!(evaluate_with_units("3 kg * 2").unwrap(), "6 kg");
+        assert_eq!(evaluate_with_units("10 km / 2 km").unwrap(), "5");
+        assert_eq!(evaluate_quantity("1 lb").unwrap().value, 0.45359237);
+    }
+
+    #[test]
+    fn test_unit_conversion() {
+        assert_eq!(
+            evaluate_with_units("90 mph to km/h").unwrap(),
+            "144.84096 km/h"
+        );
+        assert_eq!(evaluate_with_units("1 mi to ft").unwrap(), "5280 ft");
+        assert_eq!(evaluate_with_units("16 oz to lb").unwrap(), "1 lb");
+        assert_eq!(evaluate_with_units("1 day to s").unwrap(), "86400 s");
+        // 温度转换考虑零点偏移
+        assert_eq!(evaluate_with_units("100 degC to degF").unwrap(), "212 degF");
+        assert_eq!(evaluate_with_units("-40 degF to degC").unwrap(), "-40 degC");
+        assert_eq!(evaluate_with_units("0 degC to K").unwrap(), "273.15 K");
+    }
+
+    #[test]
+    fn test_incompatible_dimensions() {
+        match evaluate_with_units("3 km + 2 s") {
+            Err(ExpError::DimensionError(msg)) => assert_eq!(msg, "cannot add length and time"),
+            other => panic!("expected dimension error, got {:?}", other),
+        }
+        match evaluate_with_units("5 kg to m") {
+            Err(ExpError::DimensionError(msg)) => assert_eq!(msg, "cannot convert mass to length"),
+            other => panic!("expected dimension error, got {:?}", other),
+        }
+        assert!(matches!(
+            evaluate_with_units("sqrt(4 m)"),
+            Err(ExpError::DimensionError(_))
+        ));
+        assert!(matches!(
+            evaluate_with_units("2 ^ (1 s)"),
+            Err(ExpError::DimensionError(_))
+        ));
+        assert!(evaluate_with_units("3 parsecs").is_err());
+    }
+
+    #[test]
+    fn test_exponent_out_of_range() {
+        let dimension_error = |input: &str| match evaluate_with_units(input) {
+            Err(ExpError::DimensionError(msg)) => msg,
+            other => panic!("expected dimension error for {}, got {:?}", input, other),
+        };
+        assert_eq!(
+            dimension_error("2 ^ 1e10"),
+            "exponent 10000000000 is out of range"
+        );
+        assert_eq!(
+            dimension_error("(1 m) ^ (-1e12)"),
+            "exponent -1000000000000 is out of range"
+        );
+        assert_eq!(
+            dimension_error("(1 m) ^ (1 / 0)"),
+            "cannot raise length and dimensionless"
+        );
+        // 量纲的指数相乘或相加溢出
+        assert!(dimension_error("(1 m) ^ 2000000000 ^ 2").contains("out of range"));
+        assert!(dimension_error("(1 m) ^ 2000000000 * (1 m) ^ 2000000000").contains("out of range"));
+        assert!(
+            dimension_error("(1 m) ^ 2000000000 / (1 m) ^ (-2000000000)").contains("out of range")
+        );
+        assert_eq!(evaluate_with_units("(2 m) ^ 2 / (1 m)").unwrap(), "4 m");
+    }
+}
@@ -0,0 +1,318 @@
+// 语法树的遍历：Visitor 只读访问每个节点，Fold 自底向上重建一棵新的语法树
+// 两者都用显式的栈遍历，很长的运算链（如 1+1+...+1）也不会导致递归过深
+use super::*;
+
+// 只读访问语法树，按先序、从左到右的顺序调用
+// 复合节点的 visit_* 返回 false 时跳过它的子节点，默认访问所有节点
+pub trait Visitor {
+    // 进入任意节点时调用，在对应的 visit_* 之前
+    fn enter(&mut self, _ast: &Ast) {}
+    // 离开节点时调用，此时它的子节点都已访问完
+    fn leave(&mut self, _ast: &Ast) {}
+
+    fn visit_number(&mut self, _n: f64) {}
+    fn visit_variable(&mut self, _name: &str) {}
+    fn visit_string(&mut self, _text: &str) {}
+    fn visit_binop(&mut self, _op: &Token, _lhs: &Ast, _rhs: &Ast) -> bool {
+        true
+    }
+    fn visit_unary(&mut self, _op: &Token, _operand: &Ast) -> bool {
+        true
+    }
+    fn visit_call(&mut self, _name: &str, _args: &[Ast]) -> bool {
+        true
+    }
+    fn visit_assign(&mut self, _name: &str, _value: &Ast) -> bool {
+        true
+    }
+    fn visit_seq(&mut self, _statements: &[Ast]) -> bool {
+        true
+    }
+    fn visit_cond(&mut self, _cond: &Ast, _then: &Ast, _otherwise: &Ast) -> bool {
+        true
+    }
+    fn visit_define(&mut self, _name: &str, _params: &[String], _body: &Ast) -> bool {
+        true
+    }
+    fn visit_list(&mut self, _items: &[Ast]) -> bool {
+        true
+    }
+}
+
+// 自底向上重建语法树：先变换子节点，再用变换后的子节点调用 fold_*
+// 默认实现原样重建节点，只需要覆盖关心的方法
+pub trait Fold {
+    fn fold_number(&mut self, n: f64) -> Ast {
+        Ast::Num(n)
+    }
+    fn fold_variable(&mut self, name: &str) -> Ast {
+        Ast::Var(name.to_string())
+    }
+    fn fold_string(&mut self, text: &str) -> Ast {
+        Ast::Str(text.to_string())
+    }
+    fn fold_binop(&mut self, op: &Token, lhs: Ast, rhs: Ast) -> Ast {
+        Ast::BinOp(op.clone(), Box::new(lhs), Box::new(rhs))
+    }
+    fn fold_unary(&mut self, op: &Token, operand: Ast) -> Ast {
+        Ast::UnaryOp(op.clone(), Box::new(operand))
+    }
+    fn fold_call(&mut self, name: &str, args: Vec<Ast>) -> Ast {
+        Ast::Call(name.to_string(), args)
+    }
+    fn fold_assign(&mut self, name: &str, value: Ast) -> Ast {
+        Ast::Assign(name.to_string(), Box::new(value))
+    }
+    fn fold_seq(&mut self, statements: Vec<Ast>) -> Ast {
+        Ast::Seq(statements)
+    }
+    fn fold_cond(&mut self, cond: Ast, then: Ast, otherwise: Ast) -> Ast {
+        Ast::Cond(Box::new(cond), Box::new(then), Box::new(otherwise))
+    }
+    fn fold_define(&mut self, name: &str, params: &[String], body: Ast) -> Ast {
+        Ast::Define(name.to_string(), params.to_vec(), Box::new(body))
+    }
+    fn fold_list(&mut self, items: Vec<Ast>) -> Ast {
+        Ast::List(items)
+    }
+}
+
+// 节点的子节点，按从左到右的顺序
+fn children(ast: &Ast) -> Vec<&Ast> {
+    match ast {
+        Ast::Num(_) | Ast::Var(_) | Ast::Str(_) => Vec::new(),
+        Ast::BinOp(_, lhs, rhs) => vec![lhs, rhs],
+        Ast::UnaryOp(_, operand) | Ast::Assign(_, operand) | Ast::Define(_, _, operand) => {
+            vec![operand]
+        }
+        Ast::Call(_, args) | Ast::Seq(args) | Ast::List(args) => args.iter().collect(),
+        Ast::Cond(cond, then, otherwise) => vec![cond, then, otherwise],
+    }
+}
+
+// 遍历栈中的任务：第一次遇到节点时访问它，子节点处理完后离开（或重建）它
+enum Step<'a> {
+    Enter(&'a Ast),
+    Leave(&'a Ast),
+}
+
+impl Ast {
+    // 用 visitor 访问整棵语法树
+    pub fn walk<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        let mut pending = vec![Step::Enter(self)];
+        while let Some(step) = pending.pop() {
+            let node = match step {
+                Step::Enter(node) => node,
+                Step::Leave(node) => {
+                    visitor.leave(node);
+                    continue;
+                }
+            };
+            visitor.enter(node);
+            let descend = match node {
+                Ast::Num(n) => {
+                    visitor.visit_number(*n);
+                    false
+                }
+                Ast::Var(name) => {
+                    visitor.visit_variable(name);
+                    false
+                }
+                Ast::Str(text) => {
+                    visitor.visit_string(text);
+                    false
+                }
+                Ast::BinOp(op, lhs, rhs) => visitor.visit_binop(op, lhs, rhs),
+                Ast::UnaryOp(op, operand) => visitor.visit_unary(op, operand),
+                Ast::Call(name, args) => visitor.visit_call(name, args),
+                Ast::Assign(name, value) => visitor.visit_assign(name, value),
+                Ast::Seq(statements) => visitor.visit_seq(statements),
+                Ast::Cond(cond, then, otherwise) => visitor.visit_cond(cond, then, otherwise),
+                Ast::Define(name, params, body) => visitor.visit_define(name, params, body),
+                Ast::List(items) => visitor.visit_list(items),
+            };
+            pending.push(Step::Leave(node));
+            if descend {
+                // 逆序入栈，出栈时从左到右
+                pending.extend(children(node).into_iter().rev().map(Step::Enter));
+            }
+        }
+    }
+
+    // 沿左侧的二元运算链向下，返回链最左端的节点和链上的 (运算符, 右操作数)，按从左到右的顺序
+    // 求值、编译、化简和格式化都用它迭代处理很长的左结合链（如 1+1+...+1），避免递归过深
+    pub(crate) fn left_spine(&self) -> (&Ast, Vec<(&Token, &Ast)>) {
+        self.left_spine_while(|_| true, |_, _| true)
+    }
+
+    // 只收集 take 接受的运算符；收集一个运算符后 descend 返回 false 时停止，它的左操作数作为链的最左端
+    pub(crate) fn left_spine_while(
+        &self,
+        take: impl Fn(&Token) -> bool,
+        descend: impl Fn(&Token, &Ast) -> bool,
+    ) -> (&Ast, Vec<(&Token, &Ast)>) {
+        let mut spine = Vec::new();
+        let mut node = self;
+        while let Ast::BinOp(op, lhs, rhs) = node {
+            if !take(op) {
+                break;
+            }
+            spine.push((op, rhs.as_ref()));
+            node = lhs;
+            if !descend(op, lhs) {
+                break;
+            }
+        }
+        spine.reverse();
+        (node, spine)
+    }
+
+    // 用 folder 重建整棵语法树
+    pub fn fold<F: Fold + ?Sized>(&self, folder: &mut F) -> Ast {
+        let mut pending = vec![Step::Enter(self)];
+        // 已经重建好的子树，按从左到右的顺序
+        let mut done: Vec<Ast> = Vec::new();
+        while let Some(step) = pending.pop() {
+            match step {
+                Step::Enter(node) => {
+                    pending.push(Step::Leave(node));
+                    pending.extend(children(node).into_iter().rev().map(Step::Enter));
+                }
+                Step::Leave(node) => {
+                    let count = children(node).len();
+                    let mut parts = done.split_off(done.len() - count).into_iter();
+                    let mut next = || parts.next().expect("child already folded");
+                    let rebuilt = match node {
+                        Ast::Num(n) => folder.fold_number(*n),
+                        Ast::Var(name) => folder.fold_variable(name),
+                        Ast::Str(text) => folder.fold_string(text),
+                        Ast::BinOp(op, ..) => {
+                            let lhs = next();
+                            folder.fold_binop(op, lhs, next())
+                        }
+                        Ast::UnaryOp(op, _) => folder.fold_unary(op, next()),
+                        Ast::Call(name, _) => {
+                            folder.fold_call(name, (0..count).map(|_| next()).collect())
+                        }
+                        Ast::Assign(name, _) => folder.fold_assign(name, next()),
+                        Ast::Seq(_) => folder.fold_seq((0..count).map(|_| next()).collect()),
+                        Ast::Cond(..) => {
+                            let (cond, then) = (next(), next());
+                            folder.fold_cond(cond, then, next())
+                        }
+                        Ast::Define(name, params, _) => folder.fold_define(name, params, next()),
+                        Ast::List(_) => folder.fold_list((0..count).map(|_| next()).collect()),
+                    };
+                    done.push(rebuilt);
+                }
+            }
+        }
+        done.pop().expect("root already folded")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 语法树的最大深度
+    #[derive(Default)]
+    struct Depth {
+        current: usize,
+        max: usize,
+    }
+
+    impl Visitor for Depth {
+        fn enter(&mut self, _ast: &Ast) {
+            self.current += 1;
+            self.max = self.max.max(self.current);
+        }
+        fn leave(&mut self, _ast: &Ast) {
+            self.current -= 1;
+        }
+    }
+
+    // 收集变量名和函数名，不进入函数定义
+    #[derive(Default)]
+    struct Names(Vec<String>);
+
+    impl Visitor for Names {
+        fn visit_variable(&mut self, name: &str) {
+            self.0.push(name.to_string());
+        }
+        fn visit_call(&mut self, name: &str, _args: &[Ast]) -> bool {
+            self.0.push(std::format!("{}()", name));
+            true
+        }
+        fn visit_define(&mut self, _name: &str, _params: &[String], _body: &Ast) -> bool {
+            false
+        }
+    }
+
+    // 把变量 x 替换为给定的表达式
+    struct Substitute(Ast);
+
+    impl Fold for Substitute {
+        fn fold_variable(&mut self, name: &str) -> Ast {
+            match name {
+                "x" => self.0.clone(),
+                _ => Ast::Var(name.to_string()),
+            }
+        }
+    }
+
+    fn parse(input: &str) -> Ast {
+        Expr::new(input).parse().unwrap()
+    }
+
+    #[test]
+    fn test_visitor() {
+        let mut depth = Depth::default();
+        parse("1 + 2 * (3 - x)").walk(&mut depth);
+        assert_eq!(depth.max, 4);
+        assert_eq!(depth.current, 0);
+
+        let mut names = Names::default();
+        parse("f(a) = a * b; y = max(x, 1 ? z : w); f(y)").walk(&mut names);
+        // 赋值的目标由 visit_assign 访问，不是 visit_variable
+        assert_eq!(
+            names.0,
+            ["max()", "x", "z", "w", "f()", "y"].map(String::from)
+        );
+    }
+
+    #[test]
+    fn test_fold() {
+        // 默认实现原样重建
+        let ast = parse("x = 1; g(a, b) = a < b ? -a : b!; g(x, 2) + pi");
+        assert_eq!(ast.fold(&mut Substitute(Ast::Var("x".to_string()))), ast);
+        let replaced = parse("x^2 + y * x").fold(&mut Substitute(parse("t + 1")));
+        assert_eq!(format::format(&replaced), "(t + 1) ^ 2 + y * (t + 1)");
+    }
+
+    #[test]
+    fn test_left_spine() {
+        let ast = parse("a - b * c + d");
+        let (node, spine) = ast.left_spine();
+        assert_eq!(node, &Ast::Var("a".to_string()));
+        let ops: Vec<String> = spine
+            .iter()
+            .map(|(op, rhs)| std::format!("{} {}", op, format::format(rhs)))
+            .collect();
+        assert_eq!(ops, ["- b * c", "+ d"]);
+        // 只收集加号时停在减法处
+        let (node, spine) = ast.left_spine_while(|op| *op == Token::Plus, |_, _| true);
+        assert_eq!(format::format(node), "a - b * c");
+        assert_eq!(spine.len(), 1);
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let ast = parse(&std::format!("x{}", "+1".repeat(100_000)));
+        let mut depth = Depth::default();
+        ast.walk(&mut depth);
+        assert_eq!(depth.max, 100_001);
+        let folded = ast.fold(&mut Substitute(Ast::Num(1.0)));
+        assert_eq!(eval(&folded).unwrap(), 100_001.0);
+    }
+}
@@ -0,0 +1,138 @@
+// WebAssembly 绑定，需要开启 `wasm` feature，用 wasm-pack 构建：
+//   wasm-pack build --target web -- --features wasm
+// JS 中的用法：
+//   import { evaluate, parse } from "./pkg/expression_parsing_calculation.js";
+//   evaluate("3 + 4 * 2");              // 11
+//   const expr = parse("x ^ 2 + 1");
+//   expr.evalWith(["x"], [3]);          // 10
+// 出错时抛出 CalcError 对象，带有 kind、message 以及语法错误的位置（以字符为单位）
+// JS 模块中不能导出名为 eval 的函数，所以求值函数在 JS 中叫 evaluate
+use wasm_bindgen::prelude::*;
+
+use super::*;
+
+// 抛给 JS 的错误对象
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalcError {
+    pub kind: String, // 错误类型，与 ExpError 的变体同名，如 "SyntaxError"、"Overflow"
+    pub message: String, // 完整的错误信息，语法错误带有标出位置的 `^`
+    pub offset: Option<usize>, // 语法错误的起始位置
+    pub length: Option<usize>, // 语法错误片段的长度
+}
+
+impl From<ExpError> for CalcError {
+    fn from(e: ExpError) -> Self {
+        let kind = match &e {
+            ExpError::ParseError(_) => "ParseError",
+            ExpError::SyntaxError { .. } => "SyntaxError",
+            ExpError::Overflow => "Overflow",
+            ExpError::Mismatch(..) => "Mismatch",
+            ExpError::NotInteger(_) => "NotInteger",
+            ExpError::DimensionError(_) => "DimensionError",
+            ExpError::ShapeError(_) => "ShapeError",
+            ExpError::MathError(_) => "MathError",
+            ExpError::ShuntingYardError(_) => "ShuntingYardError",
+        };
+        let span = match &e {
+            ExpError::SyntaxError { span, .. } => Some(*span),
+            ExpError::ShuntingYardError(e) => e.span(),
+            _ => None,
+        };
+        CalcError {
+            kind: kind.to_string(),
+            message: e.to_string(),
+            offset: span.map(|span| span.offset),
+            length: span.map(|span| span.len),
+        }
+    }
+}
+
+// 解析好的表达式，可以反复求值而不需要重新解析
+#[wasm_bindgen]
+pub struct Expression {
+    ast: Ast,
+}
+
+#[wasm_bindgen]
+impl Expression {
+    // 不使用变量求值
+    pub fn eval(&self) -> std::result::Result<f64, CalcError> {
+        Ok(super::eval(&self.ast)?)
+    }
+
+    // 给定变量的名字和值求值，两个数组按下标一一对应
+    #[wasm_bindgen(js_name = evalWith)]
+    pub fn eval_with(
+        &self,
+        names: Vec<String>,
+        values: Vec<f64>,
+    ) -> std::result::Result<f64, CalcError> {
+        if names.len() != values.len() {
+            return Err(ExpError::ParseError(format!(
+                "{} variable names but {} values",
+                names.len(),
+                values.len()
+            ))
+            .into());
+        }
+        let mut context = EvalContext::new();
+        for (name, value) in names.iter().zip(values) {
+            context.set(name, value);
+        }
+        let mut evaluator = Evaluator::new();
+        evaluator.context = Some(&mut context);
+        Ok(evaluator.eval(&self.ast)?)
+    }
+
+    // 规范格式的表达式文本
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_text(&self) -> String {
+        format::format(&self.ast)
+    }
+}
+
+// 解析表达式
+#[wasm_bindgen(js_name = parse)]
+pub fn parse_expression(input: &str) -> std::result::Result<Expression, CalcError> {
+    Ok(Expression { ast: parse(input)? })
+}
+
+// 解析并求值表达式
+#[wasm_bindgen(js_name = evaluate)]
+pub fn evaluate_expression(input: &str) -> std::result::Result<f64, CalcError> {
+    Ok(evaluate(input)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wasm_evaluate() {
+        assert_eq!(evaluate_expression("3 + 4 * 2"), Ok(11.0));
+        let error = evaluate_expression("1 + * 2").unwrap_err();
+        assert_eq!(error.kind, "SyntaxError");
+        assert_eq!((error.offset, error.length), (Some(4), Some(1)));
+        let error = evaluate_expression("max()").unwrap_err();
+        assert_eq!(error.kind, "ParseError");
+        assert_eq!(error.offset, None);
+    }
+
+    #[test]
+    fn test_wasm_expression() {
+        let expression = parse_expression("x ^ 2 + 1").unwrap();
+        assert_eq!(expression.to_text(), "x ^ 2 + 1");
+        assert_eq!(
+            expression.eval_with(vec!["x".to_string()], vec![3.0]),
+            Ok(10.0)
+        );
+        assert_eq!(
+            expression.eval_with(vec!["x".to_string()], vec![-1.0]),
+            Ok(2.0)
+        );
+        assert!(expression.eval().is_err());
+        assert!(expression.eval_with(vec!["x".to_string()], vec![]).is_err());
+        assert!(parse_expression("(1").is_err());
+    }
+}
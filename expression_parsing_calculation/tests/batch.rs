@@ -0,0 +1,14 @@
+// 批量求值是库的公开接口，库外的调用方只通过 crate 根路径使用它
+use expression_parsing_calculation::{eval_batch_indexed, BatchMode};
+
+#[test]
+fn test_batch_api_is_public() {
+    let results = eval_batch_indexed(&["1+1", "2*", "3*3"], BatchMode::CollectAll);
+    let indices: Vec<usize> = results.iter().map(|(index, _)| *index).collect();
+    assert_eq!(indices, [0, 1, 2]);
+    assert_eq!(results[2].1.as_ref().ok(), Some(&9.0));
+    assert_eq!(
+        eval_batch_indexed(&["1+1", "2*", "3*3"], BatchMode::FailFast).len(),
+        2
+    );
+}
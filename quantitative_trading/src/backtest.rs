@@ -0,0 +1,209 @@
+use crate::chart::signal_history;
+use crate::signal_aggregator::PriceData;
+use crate::TradeSignal;
+
+/// 一笔模拟交易（只做多，每次一个单位）
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub entry_index: usize, // 开仓K线下标，以该K线收盘价成交
+    pub exit_index: usize,  // 平仓K线下标，以该K线收盘价成交
+    pub entry_price: f64,
+    pub exit_price: f64,
+    pub pnl: f64, // 每单位的已实现盈亏
+    pub mae: f64, // 最大不利偏移：持仓期间最差的未实现盈亏（<= 0）
+    pub mfe: f64, // 最大有利偏移：持仓期间最好的未实现盈亏（>= 0）
+}
+
+impl Trade {
+    /// 这笔交易的收益率
+    pub fn return_pct(&self) -> f64 {
+        self.pnl / self.entry_price
+    }
+}
+
+/// 回测结果
+#[derive(Debug, Clone, Default)]
+pub struct BacktestResult {
+    pub trades: Vec<Trade>,
+}
+
+impl BacktestResult {
+    /// 每笔交易的收益率序列
+    pub fn returns(&self) -> Vec<f64> {
+        self.trades.iter().map(Trade::return_pct).collect()
+    }
+
+    /// 所有交易收益率复利后的总收益率
+    pub fn total_return(&self) -> f64 {
+        self.returns().iter().fold(1.0, |acc, r| acc * (1.0 + r)) - 1.0
+    }
+
+    /// 每笔交易收益率相对 target_return 的索提诺比率，见 sortino_ratio
+    pub fn sortino_ratio(&self, target_return: f64) -> Option<f64> {
+        sortino_ratio(&self.returns(), target_return)
+    }
+}
+
+/// 下行标准差：只统计低于目标收益率的部分，高于目标的收益按 0 计入
+/// sqrt(Σ min(0, r - target)² / N)，空序列返回 0
+pub fn downside_deviation(returns: &[f64], target_return: f64) -> f64 {
+    if returns.is_empty() {
+        return 0.0;
+    }
+    let sum_sq: f64 = returns
+        .iter()
+        .map(|r| (r - target_return).min(0.0).powi(2))
+        .sum();
+    (sum_sq / returns.len() as f64).sqrt()
+}
+
+/// 索提诺比率：(平均收益率 - 目标收益率) / 下行标准差
+/// 与夏普比率不同，上涨带来的波动不会被惩罚
+/// 没有任何收益低于目标（下行标准差为 0）时比率为无穷大，此时返回 None；空序列同样返回 None
+pub fn sortino_ratio(returns: &[f64], target_return: f64) -> Option<f64> {
+    let downside = downside_deviation(returns, target_return);
+    if returns.is_empty() || downside == 0.0 {
+        return None;
+    }
+    let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+    Some((mean - target_return) / downside)
+}
+
+/// 按给定的 (K线下标, 信号) 列表回测：空仓时遇到 Buy 开仓，持仓时遇到 Sell 平仓，
+/// 回测结束时仍持有的仓位按最后一根K线收盘价平仓
+pub fn run_backtest(price_data: &PriceData, signals: &[(usize, TradeSignal)]) -> BacktestResult {
+    let mut result = BacktestResult::default();
+    let last_index = match price_data.closes.len() {
+        0 => return result,
+        len => len - 1,
+    };
+
+    let mut entry: Option<usize> = None;
+    for (index, signal) in signals {
+        if *index > last_index {
+            break;
+        }
+        match (signal, entry) {
+            (TradeSignal::Buy, None) => entry = Some(*index),
+            (TradeSignal::Sell, Some(entry_index)) => {
+                result
+                    .trades
+                    .push(close_trade(price_data, entry_index, *index));
+                entry = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(entry_index) = entry {
+        result
+            .trades
+            .push(close_trade(price_data, entry_index, last_index));
+    }
+    result
+}
+
+/// 用策略逐根K线回放得到的信号进行回测
+pub fn backtest_strategy(price_data: &PriceData) -> BacktestResult {
+    run_backtest(price_data, &signal_history(price_data))
+}
+
+// 生成一笔交易，并遍历持仓期间的K线计算 MAE/MFE
+// 开仓K线以收盘价成交，所以只统计开仓之后到平仓（含）的K线
+fn close_trade(price_data: &PriceData, entry_index: usize, exit_index: usize) -> Trade {
+    let entry_price = price_data.closes[entry_index];
+    let exit_price = price_data.closes[exit_index];
+
+    let mut mae: f64 = 0.0;
+    let mut mfe: f64 = 0.0;
+    for i in entry_index + 1..=exit_index {
+        mae = mae.min(price_data.lows[i] - entry_price);
+        mfe = mfe.max(price_data.highs[i] - entry_price);
+    }
+
+    Trade {
+        entry_index,
+        exit_index,
+        entry_price,
+        exit_price,
+        pnl: exit_price - entry_price,
+        mae,
+        mfe,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64], highs: &[f64], lows: &[f64]) -> PriceData {
+        PriceData {
+            timestamps: Vec::new(),
+            prices: closes.to_vec(),
+            highs: highs.to_vec(),
+            lows: lows.to_vec(),
+            closes: closes.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_mae_mfe_for_known_dip_and_peak() {
+        // 100开仓，持仓期间最低跌到95，最高涨到112，最后106平仓
+        let data = bars(
+            &[100.0, 98.0, 96.0, 104.0, 110.0, 106.0, 107.0],
+            &[101.0, 99.0, 97.0, 105.0, 112.0, 107.0, 108.0],
+            &[99.0, 97.0, 95.0, 103.0, 108.0, 105.0, 106.0],
+        );
+        let result = run_backtest(&data, &[(0, TradeSignal::Buy), (5, TradeSignal::Sell)]);
+
+        assert_eq!(result.trades.len(), 1);
+        let trade = &result.trades[0];
+        assert_eq!((trade.entry_index, trade.exit_index), (0, 5));
+        assert_eq!(trade.pnl, 6.0);
+        assert_eq!(trade.mae, -5.0);
+        assert_eq!(trade.mfe, 12.0);
+        assert!((result.total_return() - 0.06).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sortino_ratio_known_value() {
+        let returns = [0.1, -0.05, 0.02, -0.03];
+        // 下行部分：(-0.05)² + (-0.03)² = 0.0034，除以4后开方
+        let downside = downside_deviation(&returns, 0.0);
+        assert!((downside - 0.00085f64.sqrt()).abs() < 1e-12);
+        // 平均收益 0.01
+        let sortino = sortino_ratio(&returns, 0.0).unwrap();
+        assert!((sortino - 0.01 / 0.00085f64.sqrt()).abs() < 1e-12);
+
+        // 目标收益率提高后，比率变为负数
+        assert!(sortino_ratio(&returns, 0.02).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn test_sortino_ratio_without_downside() {
+        assert_eq!(sortino_ratio(&[0.01, 0.02, 0.03], 0.0), None);
+        assert_eq!(sortino_ratio(&[], 0.0), None);
+        assert_eq!(downside_deviation(&[0.01, 0.02], 0.0), 0.0);
+
+        let data = bars(&[10.0, 11.0], &[10.0, 11.0], &[10.0, 11.0]);
+        let result = run_backtest(&data, &[(0, TradeSignal::Buy), (1, TradeSignal::Sell)]);
+        assert_eq!(result.sortino_ratio(0.0), None);
+        assert!(result.sortino_ratio(0.2).unwrap() < 0.0);
+    }
+
+    #[test]
+    fn test_open_position_closed_at_last_bar() {
+        let data = bars(&[10.0, 11.0, 12.0], &[10.5, 11.5, 12.5], &[9.5, 10.5, 11.5]);
+        // 重复的 Buy 和空仓时的 Sell 被忽略
+        let signals = [
+            (0, TradeSignal::Sell),
+            (0, TradeSignal::Buy),
+            (1, TradeSignal::Buy),
+        ];
+        let result = run_backtest(&data, &signals);
+        assert_eq!(result.trades.len(), 1);
+        assert_eq!(result.trades[0].exit_index, 2);
+        // 价格一直上涨，没有不利偏移
+        assert_eq!(result.trades[0].mae, 0.0);
+        assert_eq!(result.trades[0].mfe, 2.5);
+    }
+}
@@ -0,0 +1,90 @@
+use crate::signal_aggregator::{execute_trading_strategy, PriceData};
+use crate::TradeSignal;
+
+// 从低到高的八级方块字符
+const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// 将序列渲染为一行ASCII火花线，每个值对应一个方块字符
+/// 最小值对应最低的方块，最大值对应最高的方块，所有值相等时全部使用最低的方块
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    values
+        .iter()
+        .map(|v| {
+            if range <= 0.0 {
+                return BLOCKS[0];
+            }
+            let level = ((v - min) / range * (BLOCKS.len() - 1) as f64).round() as usize;
+            BLOCKS[level.min(BLOCKS.len() - 1)]
+        })
+        .collect()
+}
+
+/// 生成与火花线等长的信号标记行：买入处为 ▲，卖出处为 ▼，冲突处为 ◆，其余为空格
+pub fn signal_markers(len: usize, signals: &[(usize, TradeSignal)]) -> String {
+    let mut markers = vec![' '; len];
+    for (index, signal) in signals {
+        if let Some(marker) = markers.get_mut(*index) {
+            *marker = match signal {
+                TradeSignal::Buy => '▲',
+                TradeSignal::Sell => '▼',
+                TradeSignal::Conflict => '◆',
+                TradeSignal::Hold => ' ',
+            };
+        }
+    }
+    markers.into_iter().collect()
+}
+
+/// 逐根K线回放策略，返回每根K线上产生的非 Hold 信号及其下标
+pub fn signal_history(price_data: &PriceData) -> Vec<(usize, TradeSignal)> {
+    (1..=price_data.closes.len())
+        .filter_map(|len| {
+            let prefix = PriceData {
+                timestamps: price_data.timestamps.iter().take(len).copied().collect(),
+                prices: price_data.prices[..len].to_vec(),
+                highs: price_data.highs[..len].to_vec(),
+                lows: price_data.lows[..len].to_vec(),
+                closes: price_data.closes[..len].to_vec(),
+            };
+            match execute_trading_strategy(&prefix) {
+                TradeSignal::Hold => None,
+                signal => Some((len - 1, signal)),
+            }
+        })
+        .collect()
+}
+
+/// 打印收盘价火花线以及下方对齐的信号标记
+pub fn print_chart(price_data: &PriceData) {
+    let signals = signal_history(price_data);
+    println!("{}", sparkline(&price_data.closes));
+    println!("{}", signal_markers(price_data.closes.len(), &signals));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparkline_rising_series() {
+        let line = sparkline(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]);
+        assert_eq!(line, "▁▂▃▄▅▆▇█");
+
+        let levels: Vec<char> = sparkline(&[10.0, 11.0, 12.5, 20.0]).chars().collect();
+        assert!(levels.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(sparkline(&[3.0, 3.0]), "▁▁");
+        assert_eq!(sparkline(&[]), "");
+    }
+
+    #[test]
+    fn test_signal_markers() {
+        let markers = signal_markers(5, &[(1, TradeSignal::Buy), (3, TradeSignal::Sell)]);
+        assert_eq!(markers, " ▲ ▼ ");
+        // 越界的下标被忽略
+        assert_eq!(signal_markers(2, &[(5, TradeSignal::Buy)]), "  ");
+    }
+}
@@ -0,0 +1,173 @@
+use std::error::Error;
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+
+use crate::TradeSignalWithRisk;
+
+/// 产生非 Hold 信号时的通知钩子，例如执行命令、调用 webhook 或发送桌面通知
+pub trait SignalHook {
+    /// payload 为信号详情的 JSON
+    fn notify(&self, payload: &Value) -> Result<(), Box<dyn Error>>;
+}
+
+/// 通过 `sh -c` 执行一条命令：信号 JSON 作为第一个参数（$1）传入，同时写入环境变量 SIGNAL_JSON
+/// 命令在后台运行，不等待其结束，不会阻塞主流程
+pub struct CommandHook {
+    command: String,
+}
+
+impl CommandHook {
+    pub fn new(command: impl Into<String>) -> Self {
+        CommandHook {
+            command: command.into(),
+        }
+    }
+}
+
+impl SignalHook for CommandHook {
+    fn notify(&self, payload: &Value) -> Result<(), Box<dyn Error>> {
+        let payload = payload.to_string();
+        Command::new("sh")
+            .arg("-c")
+            .arg(&self.command)
+            .arg("sh") // $0
+            .arg(&payload)
+            .env("SIGNAL_JSON", &payload)
+            .stdin(Stdio::null())
+            .spawn()?;
+        Ok(())
+    }
+}
+
+/// 信号详情的 JSON，Hold 信号返回 None
+pub fn signal_payload(symbol: &str, signal: &TradeSignalWithRisk) -> Option<Value> {
+    let (side, entry_price, stop_loss, take_profit, quantity, generated_at) = match signal {
+        TradeSignalWithRisk::Buy {
+            entry_price,
+            stop_loss,
+            take_profit,
+            quantity,
+            generated_at,
+            ..
+        } => (
+            "BUY",
+            entry_price,
+            stop_loss,
+            take_profit,
+            quantity,
+            generated_at,
+        ),
+        TradeSignalWithRisk::Sell {
+            entry_price,
+            stop_loss,
+            take_profit,
+            quantity,
+            generated_at,
+            ..
+        } => (
+            "SELL",
+            entry_price,
+            stop_loss,
+            take_profit,
+            quantity,
+            generated_at,
+        ),
+        TradeSignalWithRisk::Hold => return None,
+    };
+    Some(json!({
+        "symbol": symbol,
+        "side": side,
+        "entry_price": entry_price,
+        "stop_loss": stop_loss,
+        "take_profit": take_profit,
+        "quantity": quantity,
+        "generated_at": generated_at.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }))
+}
+
+/// 信号不是 Hold 时调用钩子，返回钩子是否被调用；钩子执行失败只打印错误，不中断主流程
+pub fn notify_signal(hook: &dyn SignalHook, symbol: &str, signal: &TradeSignalWithRisk) -> bool {
+    let Some(payload) = signal_payload(symbol, signal) else {
+        return false;
+    };
+    if let Err(e) = hook.notify(&payload) {
+        eprintln!("Error running signal hook: {}", e);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use chrono::{Duration, NaiveDateTime};
+
+    use super::*;
+
+    // 记录每次调用收到的 payload
+    #[derive(Default)]
+    struct RecordingHook {
+        payloads: RefCell<Vec<Value>>,
+    }
+
+    impl SignalHook for RecordingHook {
+        fn notify(&self, payload: &Value) -> Result<(), Box<dyn Error>> {
+            self.payloads.borrow_mut().push(payload.clone());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_buy_invokes_hook_with_payload() {
+        let generated_at =
+            NaiveDateTime::parse_from_str("2024-01-02 10:05:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let buy = TradeSignalWithRisk::Buy {
+            entry_price: 101.5,
+            stop_loss: 99.5,
+            take_profit: 104.5,
+            quantity: 10.0,
+            generated_at,
+            valid_for: Duration::minutes(15),
+        };
+        let hook = RecordingHook::default();
+
+        assert!(notify_signal(&hook, "MSFT", &buy));
+        assert!(!notify_signal(&hook, "MSFT", &TradeSignalWithRisk::Hold));
+
+        let payloads = hook.payloads.borrow();
+        assert_eq!(payloads.len(), 1);
+        assert_eq!(
+            payloads[0],
+            json!({
+                "symbol": "MSFT",
+                "side": "BUY",
+                "entry_price": 101.5,
+                "stop_loss": 99.5,
+                "take_profit": 104.5,
+                "quantity": 10.0,
+                "generated_at": "2024-01-02T10:05:00",
+            })
+        );
+    }
+
+    #[test]
+    fn test_command_hook_receives_json_argument() {
+        let path = std::env::temp_dir().join(format!("signal_hook_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let hook = CommandHook::new(format!("printf '%s' \"$1\" > {}", path.display()));
+        hook.notify(&json!({"side": "BUY"})).unwrap();
+
+        // 命令在后台运行，等待其写完文件
+        let mut written = String::new();
+        for _ in 0..50 {
+            written = std::fs::read_to_string(&path).unwrap_or_default();
+            if !written.is_empty() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(written, r#"{"side":"BUY"}"#);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::VecDeque;
+use std::error::Error;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::signal_aggregator::{kdj_strength, macd_strength, PriceData, SignalAggregator};
+use crate::TradeSignal;
+
+// MACD 短期、长期EMA的窗口大小
+const MACD_SHORT_WINDOW: usize = 12;
+const MACD_LONG_WINDOW: usize = 26;
+// MACD 信号线（MACD线的EMA）的窗口大小
+const MACD_SIGNAL_WINDOW: usize = 9;
+// KDJ 计算RSV的周期
+const KDJ_PERIOD: usize = 9;
+
+/// 一根K线
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Bar {
+    pub timestamp: NaiveDateTime,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// 增量聚合器的全部内部状态，可以序列化保存，重启后恢复以避免重新预热
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AggregatorState {
+    window: usize,           // 保留的K线数量
+    bars: VecDeque<Bar>,     // 最近 window 根K线
+    short_ema: Option<f64>,  // 短期EMA的递归值
+    long_ema: Option<f64>,   // 长期EMA的递归值
+    signal_ema: Option<f64>, // MACD信号线的递归值
+    bars_seen: usize,        // 累计输入的K线数量，用于判断MACD是否预热完成
+    k: f64,                  // KDJ 的 K 值递归
+    d: f64,                  // KDJ 的 D 值递归
+}
+
+/// 逐根K线更新的信号聚合器：维护滚动窗口和EMA、KDJ的递归状态，
+/// 每来一根新K线只做一次更新，而不是对全部历史重新计算
+pub struct IncrementalAggregator {
+    aggregator: SignalAggregator,
+    state: AggregatorState,
+}
+
+impl IncrementalAggregator {
+    pub fn new(aggregator: SignalAggregator, window: usize) -> Self {
+        Self {
+            aggregator,
+            state: AggregatorState {
+                window,
+                bars: VecDeque::with_capacity(window),
+                short_ema: None,
+                long_ema: None,
+                signal_ema: None,
+                bars_seen: 0,
+                k: 50.0,
+                d: 50.0,
+            },
+        }
+    }
+
+    /// 输入一根新K线，更新递归状态并返回当前的综合信号
+    pub fn push_bar(&mut self, bar: Bar) -> TradeSignal {
+        let state = &mut self.state;
+        state.bars_seen += 1;
+        state.bars.push_back(bar);
+        if state.bars.len() > state.window {
+            state.bars.pop_front();
+        }
+
+        // EMA递归：EMA = (当前价格 - 上一个EMA) * 平滑因子 + 上一个EMA
+        state.short_ema = Some(next_ema(state.short_ema, bar.close, MACD_SHORT_WINDOW));
+        let long_ema = next_ema(state.long_ema, bar.close, MACD_LONG_WINDOW);
+        state.long_ema = Some(long_ema);
+        let macd_line = state.short_ema.unwrap_or(bar.close) - long_ema;
+        state.signal_ema = Some(next_ema(state.signal_ema, macd_line, MACD_SIGNAL_WINDOW));
+
+        // KDJ递归：K = 2/3 * 前K + 1/3 * RSV，D = 2/3 * 前D + 1/3 * K
+        if state.bars.len() >= KDJ_PERIOD {
+            let recent = state.bars.iter().skip(state.bars.len() - KDJ_PERIOD);
+            let (high, low) = recent.fold((f64::MIN, f64::MAX), |(h, l), b| {
+                (h.max(b.high), l.min(b.low))
+            });
+            let rsv = if high == low {
+                50.0
+            } else {
+                (bar.close - low) / (high - low) * 100.0
+            };
+            state.k = state.k * 2.0 / 3.0 + rsv / 3.0;
+            state.d = state.d * 2.0 / 3.0 + state.k / 3.0;
+        }
+
+        // RSI、布林带和均线交叉只看固定长度的回看窗口，MACD和KDJ使用递归状态，
+        // 预热完成前保留批量计算的结果（零强度，或 Renormalize 模式下不参与）
+        let mut signals = self.aggregator.generate_signals(&self.price_data());
+        if let Some(histogram) = self.macd_histogram() {
+            signals.insert("MACD".to_string(), macd_strength(histogram));
+        }
+        if self.state.bars.len() >= KDJ_PERIOD {
+            signals.insert("KDJ".to_string(), kdj_strength(self.kdj().2));
+        }
+        self.aggregator.generate_composite_signal(&signals)
+    }
+
+    /// 当前的MACD线（短期EMA减去长期EMA），还没有数据时返回 None
+    pub fn macd_line(&self) -> Option<f64> {
+        Some(self.state.short_ema? - self.state.long_ema?)
+    }
+
+    /// 当前的MACD直方图（MACD线减去信号线），不足长期EMA窗口的K线时返回 None
+    pub fn macd_histogram(&self) -> Option<f64> {
+        if self.state.bars_seen < MACD_LONG_WINDOW {
+            return None;
+        }
+        Some(self.macd_line()? - self.state.signal_ema?)
+    }
+
+    /// 当前递归得到的 (K, D, J)
+    pub fn kdj(&self) -> (f64, f64, f64) {
+        let (k, d) = (self.state.k, self.state.d);
+        (k, d, 3.0 * k - 2.0 * d)
+    }
+
+    pub fn state(&self) -> &AggregatorState {
+        &self.state
+    }
+
+    /// 将内部状态序列化为JSON字符串
+    pub fn save_state(&self) -> Result<String, Box<dyn Error>> {
+        Ok(serde_json::to_string(&self.state)?)
+    }
+
+    /// 从 save_state 得到的JSON字符串恢复内部状态，聚合器的权重配置保持不变
+    pub fn load_state(&mut self, saved: &str) -> Result<(), Box<dyn Error>> {
+        self.state = serde_json::from_str(saved)?;
+        Ok(())
+    }
+
+    // 将滚动窗口转换成 PriceData，供各个指标计算
+    fn price_data(&self) -> PriceData {
+        let bars = &self.state.bars;
+        PriceData {
+            timestamps: bars.iter().map(|b| b.timestamp).collect(),
+            prices: bars.iter().map(|b| b.close).collect(),
+            highs: bars.iter().map(|b| b.high).collect(),
+            lows: bars.iter().map(|b| b.low).collect(),
+            closes: bars.iter().map(|b| b.close).collect(),
+        }
+    }
+}
+
+// 计算下一步的EMA，第一根K线直接使用价格作为初始值
+fn next_ema(prev: Option<f64>, price: f64, window: usize) -> f64 {
+    let multiplier = 2.0 / (window as f64 + 1.0);
+    match prev {
+        Some(ema) => (price - ema) * multiplier + ema,
+        None => price,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    fn bars(count: usize) -> Vec<Bar> {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 2)
+            .unwrap()
+            .and_hms_opt(9, 30, 0)
+            .unwrap();
+        (0..count)
+            .map(|i| {
+                let close = 100.0 + (i as f64 * 0.7).sin() * 5.0 + i as f64 * 0.1;
+                Bar {
+                    timestamp: start + Duration::minutes(5 * i as i64),
+                    high: close + 0.8,
+                    low: close - 0.6,
+                    close,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_restore_state_matches_uninterrupted_run() {
+        let bars = bars(60);
+        let (history, last) = bars.split_at(59);
+
+        let mut uninterrupted = IncrementalAggregator::new(SignalAggregator::new(0.6), 40);
+        for bar in history {
+            uninterrupted.push_bar(*bar);
+        }
+        let saved = uninterrupted.save_state().unwrap();
+
+        // 模拟重启：新建聚合器并恢复状态
+        let mut restored = IncrementalAggregator::new(SignalAggregator::new(0.6), 40);
+        restored.load_state(&saved).unwrap();
+        assert_eq!(restored.state(), uninterrupted.state());
+
+        let expected = uninterrupted.push_bar(last[0]);
+        let actual = restored.push_bar(last[0]);
+        assert_eq!(actual, expected);
+        assert_eq!(restored.state(), uninterrupted.state());
+        assert_eq!(restored.macd_line(), uninterrupted.macd_line());
+        assert_eq!(restored.kdj(), uninterrupted.kdj());
+    }
+
+    #[test]
+    fn test_signal_follows_recursive_state() {
+        let bars = bars(60);
+        let (history, last) = bars.split_at(59);
+
+        let mut uninterrupted = IncrementalAggregator::new(SignalAggregator::new(0.6), 40);
+        for bar in history {
+            uninterrupted.push_bar(*bar);
+        }
+        let mut restored = IncrementalAggregator::new(SignalAggregator::new(0.6), 40);
+        restored
+            .load_state(&uninterrupted.save_state().unwrap())
+            .unwrap();
+        // 恢复的K、D值决定下一根K线的信号：J值远低于20时KDJ给出强烈的买入信号
+        restored.state.k = 0.0;
+        restored.state.d = 100.0;
+        let expected = uninterrupted.push_bar(last[0]);
+        assert_ne!(expected, TradeSignal::Buy);
+        assert_eq!(restored.push_bar(last[0]), TradeSignal::Buy);
+
+        // 窗口短于长期EMA时，MACD仍然由递归状态得到
+        let mut short_window = IncrementalAggregator::new(SignalAggregator::new(0.6), 10);
+        for bar in &bars[..MACD_LONG_WINDOW - 1] {
+            short_window.push_bar(*bar);
+        }
+        assert_eq!(short_window.macd_histogram(), None);
+        short_window.push_bar(bars[MACD_LONG_WINDOW]);
+        assert!(short_window.macd_histogram().is_some());
+    }
+
+    #[test]
+    fn test_load_state_rejects_invalid_input() {
+        let mut aggregator = IncrementalAggregator::new(SignalAggregator::new(0.6), 40);
+        assert!(aggregator.load_state("not json").is_err());
+    }
+}
@@ -70,9 +70,5 @@ fn should_place_order(ema_value: f64, rsi_value: f64) -> bool {
     let ema_threshold = 150.0; // 设定的EMA阈值
     let rsi_threshold = 70.0; // 设定的RSI阈值
 
-    if ema_value > ema_threshold && rsi_value > rsi_threshold {
-        true
-    } else {
-        false
-    }
+    ema_value > ema_threshold && rsi_value > rsi_threshold
 }
@@ -0,0 +1,279 @@
+use chrono::NaiveDateTime;
+
+use crate::signal_aggregator::PriceData;
+use crate::TradeSignalWithRisk;
+
+/// 成交方向
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// 成交原因
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillReason {
+    Entry,      // 按信号开仓
+    StopLoss,   // 触发止损
+    TakeProfit, // 触发止盈
+}
+
+/// 一笔模拟成交
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub timestamp: NaiveDateTime,
+    pub side: Side,
+    pub price: f64,
+    pub quantity: f64,
+    pub reason: FillReason,
+}
+
+// 当前持仓，quantity 为正表示多头，为负表示空头
+#[derive(Debug, Clone)]
+struct OpenPosition {
+    quantity: f64,
+    stop_loss: f64,
+    take_profit: f64,
+}
+
+/// 模拟盘执行器：接收带风控参数的信号，用之后的K线模拟开平仓成交，
+/// 维护持仓和现金余额并打印每笔成交，不连接任何券商
+pub struct PaperExecutor {
+    cash: f64,
+    position: Option<OpenPosition>,
+    fills: Vec<Fill>,
+    last_bar: Option<NaiveDateTime>, // 已经处理过的最后一根K线时间
+}
+
+impl PaperExecutor {
+    pub fn new(cash: f64) -> Self {
+        PaperExecutor {
+            cash,
+            position: None,
+            fills: Vec::new(),
+            last_bar: None,
+        }
+    }
+
+    /// 处理一个信号：空仓时按信号的入场价开仓，然后用 price_data 中信号之后的K线检查止损止盈
+    /// 已有持仓时忽略新的开仓信号，Hold 信号只推进K线
+    pub fn on_signal(&mut self, signal: &TradeSignalWithRisk, price_data: &PriceData) {
+        if self.position.is_none() {
+            match signal {
+                TradeSignalWithRisk::Buy {
+                    entry_price,
+                    stop_loss,
+                    take_profit,
+                    quantity,
+                    generated_at,
+                    ..
+                } => self.open(
+                    Side::Buy,
+                    *entry_price,
+                    *quantity,
+                    *stop_loss,
+                    *take_profit,
+                    *generated_at,
+                ),
+                TradeSignalWithRisk::Sell {
+                    entry_price,
+                    stop_loss,
+                    take_profit,
+                    quantity,
+                    generated_at,
+                    ..
+                } => self.open(
+                    Side::Sell,
+                    *entry_price,
+                    *quantity,
+                    *stop_loss,
+                    *take_profit,
+                    *generated_at,
+                ),
+                TradeSignalWithRisk::Hold => {}
+            }
+        }
+        self.on_bars(price_data);
+    }
+
+    /// 用尚未处理过的K线检查持仓的止损和止盈
+    /// 同一根K线同时触及止损和止盈时，保守地按止损成交
+    pub fn on_bars(&mut self, price_data: &PriceData) {
+        for (i, timestamp) in price_data.timestamps.iter().enumerate() {
+            if self.last_bar.is_some_and(|last| *timestamp <= last) {
+                continue;
+            }
+            self.last_bar = Some(*timestamp);
+
+            let Some(position) = self.position.clone() else {
+                continue;
+            };
+            let (high, low) = (price_data.highs[i], price_data.lows[i]);
+            let is_long = position.quantity > 0.0;
+            let (stopped, took_profit) = if is_long {
+                (low <= position.stop_loss, high >= position.take_profit)
+            } else {
+                (high >= position.stop_loss, low <= position.take_profit)
+            };
+
+            if stopped {
+                self.close(position.stop_loss, *timestamp, FillReason::StopLoss);
+            } else if took_profit {
+                self.close(position.take_profit, *timestamp, FillReason::TakeProfit);
+            }
+        }
+    }
+
+    pub fn cash(&self) -> f64 {
+        self.cash
+    }
+
+    /// 当前持仓数量，多头为正，空头为负，空仓为 0
+    pub fn position(&self) -> f64 {
+        self.position.as_ref().map_or(0.0, |p| p.quantity)
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    // 开仓：买入减少现金，卖空增加现金
+    fn open(
+        &mut self,
+        side: Side,
+        price: f64,
+        quantity: f64,
+        stop_loss: f64,
+        take_profit: f64,
+        timestamp: NaiveDateTime,
+    ) {
+        if quantity <= 0.0 {
+            return;
+        }
+        let signed = match side {
+            Side::Buy => quantity,
+            Side::Sell => -quantity,
+        };
+        self.cash -= signed * price;
+        self.position = Some(OpenPosition {
+            quantity: signed,
+            stop_loss,
+            take_profit,
+        });
+        // 开仓K线本身不再用于检查止损止盈
+        self.last_bar = Some(self.last_bar.map_or(timestamp, |last| last.max(timestamp)));
+        self.record(Fill {
+            timestamp,
+            side,
+            price,
+            quantity,
+            reason: FillReason::Entry,
+        });
+    }
+
+    // 平掉全部持仓
+    fn close(&mut self, price: f64, timestamp: NaiveDateTime, reason: FillReason) {
+        let Some(position) = self.position.take() else {
+            return;
+        };
+        self.cash += position.quantity * price;
+        let side = if position.quantity > 0.0 {
+            Side::Sell
+        } else {
+            Side::Buy
+        };
+        self.record(Fill {
+            timestamp,
+            side,
+            price,
+            quantity: position.quantity.abs(),
+            reason,
+        });
+    }
+
+    fn record(&mut self, fill: Fill) {
+        println!(
+            "📝 PAPER {:?} {} @ {:.2} ({:?}) at {} | cash={:.2} position={}",
+            fill.side,
+            fill.quantity,
+            fill.price,
+            fill.reason,
+            fill.timestamp,
+            self.cash,
+            self.position()
+        );
+        self.fills.push(fill);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn start() -> NaiveDateTime {
+        NaiveDateTime::parse_from_str("2024-01-02 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    // 从 start() 开始每5分钟一根K线
+    fn bars(highs: &[f64], lows: &[f64]) -> PriceData {
+        let closes: Vec<f64> = highs.iter().zip(lows).map(|(h, l)| (h + l) / 2.0).collect();
+        PriceData {
+            timestamps: (0..highs.len())
+                .map(|i| start() + Duration::minutes(5 * i as i64))
+                .collect(),
+            prices: closes.clone(),
+            highs: highs.to_vec(),
+            lows: lows.to_vec(),
+            closes,
+        }
+    }
+
+    fn buy(
+        entry_price: f64,
+        stop_loss: f64,
+        take_profit: f64,
+        quantity: f64,
+    ) -> TradeSignalWithRisk {
+        TradeSignalWithRisk::Buy {
+            entry_price,
+            stop_loss,
+            take_profit,
+            quantity,
+            generated_at: start(),
+            valid_for: Duration::minutes(15),
+        }
+    }
+
+    #[test]
+    fn test_buy_then_stop_out() {
+        let mut executor = PaperExecutor::new(10_000.0);
+        // 第0根K线为开仓K线，即使最低价低于止损也不检查；第2根K线跌破止损95
+        let data = bars(&[101.0, 100.0, 97.0, 120.0], &[90.0, 98.0, 94.0, 96.0]);
+        executor.on_signal(&buy(100.0, 95.0, 110.0, 10.0), &data);
+
+        assert_eq!(executor.fills().len(), 2);
+        assert_eq!(executor.fills()[1].reason, FillReason::StopLoss);
+        assert_eq!(executor.fills()[1].price, 95.0);
+        assert_eq!(executor.position(), 0.0);
+        // 100买入10股，95止损：亏损50
+        assert_eq!(executor.cash(), 10_000.0 - 50.0);
+    }
+
+    #[test]
+    fn test_take_profit_and_ignore_signal_while_in_position() {
+        let mut executor = PaperExecutor::new(10_000.0);
+        let first = bars(&[101.0, 105.0], &[99.0, 101.0]);
+        executor.on_signal(&buy(100.0, 95.0, 110.0, 10.0), &first);
+        assert_eq!(executor.position(), 10.0);
+        assert_eq!(executor.cash(), 9_000.0);
+
+        // 持仓期间的新信号被忽略，后续K线触及止盈
+        let second = bars(&[101.0, 105.0, 111.0], &[99.0, 101.0, 104.0]);
+        executor.on_signal(&buy(105.0, 100.0, 120.0, 5.0), &second);
+        assert_eq!(executor.fills().len(), 2);
+        assert_eq!(executor.fills()[1].reason, FillReason::TakeProfit);
+        assert_eq!(executor.cash(), 10_100.0);
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+
+use chrono::NaiveDateTime;
+
+use crate::signal_aggregator::PriceData;
+
+/// 按时间戳对齐时缺失K线的处理方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillMethod {
+    /// 在两个序列时间戳的并集上对齐，缺失的K线用上一根K线的收盘价补齐（开头无法补齐的部分丢弃）
+    ForwardFill,
+    /// 只保留两个序列都有数据的时间戳
+    DropMissing,
+}
+
+/// 按时间戳对齐两个序列，返回等长且时间戳完全相同的两个序列
+/// 输入的每个序列需要按时间升序排列，同一时间戳只保留第一根K线
+pub fn align_by_timestamp(
+    a: &PriceData,
+    b: &PriceData,
+    fill: FillMethod,
+) -> (PriceData, PriceData) {
+    let index_a = timestamp_index(a);
+    let index_b = timestamp_index(b);
+    let union: BTreeSet<NaiveDateTime> = index_a.keys().chain(index_b.keys()).copied().collect();
+
+    let mut aligned_a = empty_series();
+    let mut aligned_b = empty_series();
+    // 上一根真实存在的K线下标，用于前向填充
+    let (mut last_a, mut last_b): (Option<usize>, Option<usize>) = (None, None);
+
+    for timestamp in union {
+        let (bar_a, bar_b) = (
+            index_a.get(&timestamp).copied(),
+            index_b.get(&timestamp).copied(),
+        );
+        last_a = bar_a.or(last_a);
+        last_b = bar_b.or(last_b);
+
+        match fill {
+            FillMethod::DropMissing => {
+                if let (Some(i), Some(j)) = (bar_a, bar_b) {
+                    push_bar(&mut aligned_a, a, i, timestamp);
+                    push_bar(&mut aligned_b, b, j, timestamp);
+                }
+            }
+            FillMethod::ForwardFill => {
+                // 任意一个序列还没有出现过K线时，无法向前填充
+                if let (Some(i), Some(j)) = (last_a, last_b) {
+                    push_filled(&mut aligned_a, a, i, bar_a.is_some(), timestamp);
+                    push_filled(&mut aligned_b, b, j, bar_b.is_some(), timestamp);
+                }
+            }
+        }
+    }
+    (aligned_a, aligned_b)
+}
+
+/// 检测跳空：收盘价相对上一根K线的变化幅度超过 threshold_pct（如 0.3 表示30%）的K线下标
+/// 拆股或错误报价会产生这种单根K线的巨大跳变，使指标失真
+pub fn detect_jumps(price_data: &PriceData, threshold_pct: f64) -> Vec<usize> {
+    price_data
+        .closes
+        .windows(2)
+        .enumerate()
+        .filter(|(_, w)| w[0] != 0.0 && ((w[1] - w[0]) / w[0]).abs() > threshold_pct)
+        .map(|(i, _)| i + 1)
+        .collect()
+}
+
+/// 向后复权：对每个跳空K线，把它之前的所有价格乘以 跳空后收盘价/跳空前收盘价，
+/// 使跳空前后的价格连续（适用于拆股，错误报价应直接剔除）
+pub fn back_adjust(price_data: &PriceData, jumps: &[usize]) -> PriceData {
+    let mut adjusted = price_data.clone();
+    for &jump in jumps {
+        if jump == 0 || jump >= price_data.closes.len() || price_data.closes[jump - 1] == 0.0 {
+            continue;
+        }
+        let ratio = price_data.closes[jump] / price_data.closes[jump - 1];
+        for series in [
+            &mut adjusted.prices,
+            &mut adjusted.highs,
+            &mut adjusted.lows,
+            &mut adjusted.closes,
+        ] {
+            series[..jump].iter_mut().for_each(|p| *p *= ratio);
+        }
+    }
+    adjusted
+}
+
+// 时间戳到K线下标的映射
+fn timestamp_index(series: &PriceData) -> HashMap<NaiveDateTime, usize> {
+    let mut index = HashMap::new();
+    for (i, timestamp) in series.timestamps.iter().enumerate() {
+        index.entry(*timestamp).or_insert(i);
+    }
+    index
+}
+
+fn empty_series() -> PriceData {
+    PriceData {
+        timestamps: Vec::new(),
+        prices: Vec::new(),
+        highs: Vec::new(),
+        lows: Vec::new(),
+        closes: Vec::new(),
+    }
+}
+
+// 复制 src 的第 i 根K线
+fn push_bar(dst: &mut PriceData, src: &PriceData, i: usize, timestamp: NaiveDateTime) {
+    dst.timestamps.push(timestamp);
+    dst.prices.push(src.prices[i]);
+    dst.highs.push(src.highs[i]);
+    dst.lows.push(src.lows[i]);
+    dst.closes.push(src.closes[i]);
+}
+
+// 存在真实K线时直接复制，否则用第 i 根K线的收盘价生成一根平的K线
+fn push_filled(
+    dst: &mut PriceData,
+    src: &PriceData,
+    i: usize,
+    present: bool,
+    timestamp: NaiveDateTime,
+) {
+    if present {
+        push_bar(dst, src, i, timestamp);
+    } else {
+        let close = src.closes[i];
+        dst.timestamps.push(timestamp);
+        dst.prices.push(close);
+        dst.highs.push(close);
+        dst.lows.push(close);
+        dst.closes.push(close);
+    }
+}
+
+/// 相对强弱：将标的的价格序列逐点除以基准（如 SPY）的价格序列，得到相对强弱曲线
+/// 两个序列按下标对齐，长度不一致或基准价格为0时返回错误，时间戳沿用标的的时间戳
+pub fn relative_strength(
+    symbol: &PriceData,
+    benchmark: &PriceData,
+) -> Result<PriceData, Box<dyn Error>> {
+    Ok(PriceData {
+        timestamps: symbol.timestamps.clone(),
+        prices: ratio_series(&symbol.prices, &benchmark.prices)?,
+        highs: ratio_series(&symbol.highs, &benchmark.highs)?,
+        lows: ratio_series(&symbol.lows, &benchmark.lows)?,
+        closes: ratio_series(&symbol.closes, &benchmark.closes)?,
+    })
+}
+
+// 计算两个等长序列的逐点比值
+fn ratio_series(values: &[f64], base: &[f64]) -> Result<Vec<f64>, Box<dyn Error>> {
+    if values.len() != base.len() {
+        return Err(format!(
+            "series length mismatch: symbol has {} bars, benchmark has {}",
+            values.len(),
+            base.len()
+        )
+        .into());
+    }
+
+    values
+        .iter()
+        .zip(base)
+        .enumerate()
+        .map(|(i, (v, b))| {
+            if *b == 0.0 {
+                Err(format!("benchmark price is zero at bar {}", i).into())
+            } else {
+                Ok(v / b)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(values: Vec<f64>) -> PriceData {
+        PriceData {
+            timestamps: Vec::new(),
+            prices: values.clone(),
+            highs: values.clone(),
+            lows: values.clone(),
+            closes: values,
+        }
+    }
+
+    #[test]
+    fn test_relative_strength_rising_ratio() {
+        // 标的每根K线上涨10%，基准每根K线上涨5%，相对强弱应单调上升
+        let symbol = series((0..10).map(|i| 100.0 * 1.10_f64.powi(i)).collect());
+        let benchmark = series((0..10).map(|i| 50.0 * 1.05_f64.powi(i)).collect());
+
+        let rs = relative_strength(&symbol, &benchmark).unwrap();
+
+        assert_eq!(rs.closes.len(), 10);
+        assert!((rs.closes[0] - 2.0).abs() < 1e-9);
+        assert!(rs.closes.windows(2).all(|w| w[1] > w[0]));
+        let expected_last = 2.0 * (1.10_f64 / 1.05).powi(9);
+        assert!((rs.closes[9] - expected_last).abs() < 1e-9);
+    }
+
+    fn timed(minutes: &[i64], closes: &[f64]) -> PriceData {
+        let start =
+            NaiveDateTime::parse_from_str("2024-01-02 09:30:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let mut data = series(closes.to_vec());
+        data.timestamps = minutes
+            .iter()
+            .map(|m| start + chrono::Duration::minutes(*m))
+            .collect();
+        data
+    }
+
+    #[test]
+    fn test_align_by_timestamp() {
+        // a 缺少第10分钟，b 缺少第0和第15分钟
+        let a = timed(&[0, 5, 15], &[1.0, 2.0, 4.0]);
+        let b = timed(&[5, 10, 20], &[20.0, 30.0, 50.0]);
+
+        let (fa, fb) = align_by_timestamp(&a, &b, FillMethod::ForwardFill);
+        // 并集为 0,5,10,15,20；第0分钟 b 还没有数据，无法填充
+        assert_eq!(fa.timestamps.len(), 4);
+        assert_eq!(fa.timestamps, fb.timestamps);
+        assert_eq!(fa.closes, vec![2.0, 2.0, 4.0, 4.0]);
+        assert_eq!(fb.closes, vec![20.0, 30.0, 30.0, 50.0]);
+
+        let (da, db) = align_by_timestamp(&a, &b, FillMethod::DropMissing);
+        assert_eq!(da.closes, vec![2.0]);
+        assert_eq!(db.closes, vec![20.0]);
+        assert_eq!(da.timestamps, db.timestamps);
+
+        // 对齐后可以按下标计算相对强弱
+        assert!(relative_strength(&fa, &fb).is_ok());
+    }
+
+    #[test]
+    fn test_detect_and_back_adjust_split() {
+        // 第3根K线发生 2:1 拆股，价格从 102 变为 51.5
+        let data = series(vec![100.0, 101.0, 102.0, 51.5, 52.0, 51.0]);
+
+        let jumps = detect_jumps(&data, 0.3);
+        assert_eq!(jumps, vec![3]);
+        // 普通波动不会被标记
+        assert!(detect_jumps(&data, 0.6).is_empty());
+
+        let adjusted = back_adjust(&data, &jumps);
+        let ratio = 51.5 / 102.0;
+        assert!((adjusted.closes[0] - 100.0 * ratio).abs() < 1e-9);
+        assert!((adjusted.highs[2] - 51.5).abs() < 1e-9);
+        // 跳空之后的价格不变，复权后不再有跳空
+        assert_eq!(adjusted.closes[3..], data.closes[3..]);
+        assert!(detect_jumps(&adjusted, 0.3).is_empty());
+    }
+
+    #[test]
+    fn test_relative_strength_length_mismatch() {
+        let symbol = series(vec![1.0, 2.0, 3.0]);
+        let benchmark = series(vec![1.0, 2.0]);
+        assert!(relative_strength(&symbol, &benchmark).is_err());
+    }
+}
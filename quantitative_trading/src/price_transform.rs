@@ -0,0 +1,164 @@
+use crate::signal_aggregator::PriceData;
+
+/// 价格变换：在计算指标之前对价格数据做预处理（取对数、收益率、标准化等）
+pub trait PriceTransform {
+    fn transform(&self, price_data: &PriceData) -> PriceData;
+}
+
+// 对价格数据的每个序列分别应用同一个变换，时间戳保持不变
+fn map_series(price_data: &PriceData, f: impl Fn(&[f64]) -> Vec<f64>) -> PriceData {
+    PriceData {
+        timestamps: price_data.timestamps.clone(),
+        prices: f(&price_data.prices),
+        highs: f(&price_data.highs),
+        lows: f(&price_data.lows),
+        closes: f(&price_data.closes),
+    }
+}
+
+/// 对数变换：ln(price)
+pub struct LogTransform;
+
+impl PriceTransform for LogTransform {
+    fn transform(&self, price_data: &PriceData) -> PriceData {
+        map_series(price_data, |series| series.iter().map(|p| p.ln()).collect())
+    }
+}
+
+/// 百分比收益率变换：(p[t] - p[t-1]) / p[t-1]，输出比输入少一个数据点
+pub struct ReturnsTransform;
+
+impl PriceTransform for ReturnsTransform {
+    fn transform(&self, price_data: &PriceData) -> PriceData {
+        let mut returns = map_series(price_data, |series| {
+            series.windows(2).map(|w| (w[1] - w[0]) / w[0]).collect()
+        });
+        // 第一根K线没有收益率，对应的时间戳也一并去掉
+        returns.timestamps = price_data.timestamps.iter().skip(1).copied().collect();
+        returns
+    }
+}
+
+/// 计算序列的对数收益率：ln(p[t] / p[t-1])，输出比输入少一个数据点
+pub fn log_returns(series: &[f64]) -> Vec<f64> {
+    series.windows(2).map(|w| (w[1] / w[0]).ln()).collect()
+}
+
+/// Z-Score标准化：(p - 均值) / 标准差
+/// 均值和标准差取自收盘价，所有序列使用同一组参数，从而保持高低收之间的相对关系
+pub struct ZScoreTransform;
+
+impl PriceTransform for ZScoreTransform {
+    fn transform(&self, price_data: &PriceData) -> PriceData {
+        let closes = &price_data.closes;
+        if closes.is_empty() {
+            return map_series(price_data, |series| series.to_vec());
+        }
+        let mean = closes.iter().sum::<f64>() / closes.len() as f64;
+        let variance = closes.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / closes.len() as f64;
+        let std_dev = variance.sqrt();
+        // 序列为常数时标准差为0，只做去均值处理
+        let scale = if std_dev == 0.0 { 1.0 } else { std_dev };
+        map_series(price_data, |series| {
+            series.iter().map(|p| (p - mean) / scale).collect()
+        })
+    }
+}
+
+/// 变换流水线：按添加顺序依次应用多个变换
+#[derive(Default)]
+pub struct TransformPipeline {
+    transforms: Vec<Box<dyn PriceTransform>>,
+}
+
+impl TransformPipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn then(mut self, transform: impl PriceTransform + 'static) -> Self {
+        self.transforms.push(Box::new(transform));
+        self
+    }
+}
+
+impl PriceTransform for TransformPipeline {
+    fn transform(&self, price_data: &PriceData) -> PriceData {
+        let mut current = map_series(price_data, |series| series.to_vec());
+        for transform in &self.transforms {
+            current = transform.transform(&current);
+        }
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal_aggregator::generate_trading_signals;
+
+    fn sample_data(len: usize) -> PriceData {
+        let closes: Vec<f64> = (0..len)
+            .map(|i| 100.0 + (i as f64 * 0.7).sin() * 5.0 + i as f64)
+            .collect();
+        PriceData {
+            timestamps: Vec::new(),
+            prices: closes.clone(),
+            highs: closes.iter().map(|c| c + 1.0).collect(),
+            lows: closes.iter().map(|c| c - 1.0).collect(),
+            closes,
+        }
+    }
+
+    #[test]
+    fn test_log_transform_values() {
+        let data = PriceData {
+            timestamps: Vec::new(),
+            prices: vec![1.0, std::f64::consts::E, 100.0],
+            highs: vec![1.0, std::f64::consts::E, 100.0],
+            lows: vec![1.0, std::f64::consts::E, 100.0],
+            closes: vec![1.0, std::f64::consts::E, 100.0],
+        };
+        let transformed = LogTransform.transform(&data);
+        assert!((transformed.closes[0] - 0.0).abs() < 1e-12);
+        assert!((transformed.closes[1] - 1.0).abs() < 1e-12);
+        assert!((transformed.closes[2] - 100.0_f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_returns_transform_drops_first_bar() {
+        let data = PriceData {
+            timestamps: Vec::new(),
+            prices: vec![100.0, 110.0, 99.0],
+            highs: vec![100.0, 110.0, 99.0],
+            lows: vec![100.0, 110.0, 99.0],
+            closes: vec![100.0, 110.0, 99.0],
+        };
+        let transformed = ReturnsTransform.transform(&data);
+        assert_eq!(transformed.closes.len(), 2);
+        assert!((transformed.closes[0] - 0.1).abs() < 1e-12);
+        assert!((transformed.closes[1] + 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_zscore_transform_has_zero_mean() {
+        let transformed = ZScoreTransform.transform(&sample_data(30));
+        let mean = transformed.closes.iter().sum::<f64>() / transformed.closes.len() as f64;
+        assert!(mean.abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_indicators_run_on_transformed_series() {
+        let pipeline = TransformPipeline::new()
+            .then(LogTransform)
+            .then(ZScoreTransform);
+        let transformed = pipeline.transform(&sample_data(40));
+        assert_eq!(transformed.closes.len(), 40);
+
+        let signals = generate_trading_signals(&transformed);
+        assert_eq!(signals.len(), 5);
+        assert!(signals
+            .values()
+            .all(|s| s.buy_strength.is_finite() && s.sell_strength.is_finite()));
+    }
+}
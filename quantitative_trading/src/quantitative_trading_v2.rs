@@ -1,4 +1,4 @@
-use reqwest;
+use chrono::{Duration, Local, NaiveDateTime};
 use serde::Deserialize;
 use serde_json::Value;
 use signal_aggregator::{execute_trading_strategy, PriceData};
@@ -6,6 +6,13 @@ use std::error::Error;
 use ta::indicators::SimpleMovingAverage;
 use ta::Next;
 
+pub mod backtest;
+pub mod chart;
+pub mod hook;
+pub mod incremental;
+pub mod paper;
+pub mod price_series;
+pub mod price_transform;
 pub mod signal_aggregator;
 
 // Alpha Vantage数据结构
@@ -29,29 +36,107 @@ pub enum TradeSignal {
     Buy,
     Sell,
     Hold,
+    Conflict, // 买卖信号都很强且难分高下
 }
 
-enum TradeSignalWithRisk {
+// 带风控参数的交易信号，generated_at 为信号生成时所在K线的时间，valid_for 为信号的有效期
+pub enum TradeSignalWithRisk {
     Buy {
         entry_price: f64,
         stop_loss: f64,
         take_profit: f64,
         quantity: f64,
+        generated_at: NaiveDateTime,
+        valid_for: Duration,
     },
     Sell {
         entry_price: f64,
         stop_loss: f64,
         take_profit: f64,
         quantity: f64,
+        generated_at: NaiveDateTime,
+        valid_for: Duration,
     },
     Hold,
 }
 
+impl TradeSignalWithRisk {
+    // 判断信号在 now 时刻是否已经过期，过期的信号不应再被执行
+    // Hold 信号没有可执行的动作，永远不会过期
+    fn is_expired(&self, now: NaiveDateTime) -> bool {
+        match self {
+            TradeSignalWithRisk::Buy {
+                generated_at,
+                valid_for,
+                ..
+            }
+            | TradeSignalWithRisk::Sell {
+                generated_at,
+                valid_for,
+                ..
+            } => now > *generated_at + *valid_for,
+            TradeSignalWithRisk::Hold => false,
+        }
+    }
+
+    // 序列化为 TradingView 告警桥接使用的单行格式：SYMBOL,SIDE,PRICE,SL,TP,TS
+    // Hold 信号没有需要发送的告警，返回 None
+    fn to_tv_line(&self, symbol: &str) -> Option<String> {
+        let (side, entry_price, stop_loss, take_profit, generated_at) = match self {
+            TradeSignalWithRisk::Buy {
+                entry_price,
+                stop_loss,
+                take_profit,
+                generated_at,
+                ..
+            } => ("BUY", entry_price, stop_loss, take_profit, generated_at),
+            TradeSignalWithRisk::Sell {
+                entry_price,
+                stop_loss,
+                take_profit,
+                generated_at,
+                ..
+            } => ("SELL", entry_price, stop_loss, take_profit, generated_at),
+            TradeSignalWithRisk::Hold => return None,
+        };
+        Some(format!(
+            "{},{},{:.2},{:.2},{:.2},{}",
+            symbol,
+            side,
+            entry_price,
+            stop_loss,
+            take_profit,
+            generated_at.format("%Y-%m-%dT%H:%M:%S")
+        ))
+    }
+}
+
+// 信号输出格式
+#[derive(Debug, PartialEq)]
+enum OutputFormat {
+    Human, // 带表情符号的可读输出
+    Tv,    // TradingView 告警桥接的单行格式
+}
+
+// 解析 --format human|tv，默认为 human
+fn parse_output_format(args: &[String]) -> Result<OutputFormat, Box<dyn Error>> {
+    let Some(pos) = args.iter().position(|arg| arg == "--format") else {
+        return Ok(OutputFormat::Human);
+    };
+    match args.get(pos + 1).map(String::as_str) {
+        Some("human") => Ok(OutputFormat::Human),
+        Some("tv") => Ok(OutputFormat::Tv),
+        Some(other) => Err(format!("unknown --format: {} (expected human or tv)", other).into()),
+        None => Err("--format requires a value".into()),
+    }
+}
+
 struct RiskManager {
     total_capital: f64,
     risk_per_trade: f64,
     take_profit_pct: f64,
     atr_period: usize,
+    signal_validity: Duration, // 信号有效期
 }
 
 impl RiskManager {
@@ -61,6 +146,7 @@ impl RiskManager {
             risk_per_trade: 0.01,
             take_profit_pct: 0.03,
             atr_period: 14,
+            signal_validity: Duration::minutes(15), // 5分钟K线，默认三根K线内有效
         }
     }
 
@@ -83,6 +169,21 @@ impl RiskManager {
 #[tokio::main]
 // 异步主函数，返回一个Result类型，其中Ok为空元组，Err为Box<dyn Error>动态错误类型
 async fn main() -> Result<(), Box<dyn Error>> {
+    // --chart：在终端打印收盘价火花线和买卖信号标记
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let chart = args.iter().any(|arg| arg == "--chart");
+    // --format tv：按 TradingView 告警桥接的单行格式输出信号
+    let format = parse_output_format(&args)?;
+    // --on-signal <command>：产生买卖信号时在后台执行该命令，信号详情以JSON传入
+    let on_signal = match args.iter().position(|arg| arg == "--on-signal") {
+        Some(pos) => Some(
+            args.get(pos + 1)
+                .ok_or("--on-signal requires a command")?
+                .clone(),
+        ),
+        None => None,
+    };
+
     // 创建一个策略配置实例，包含API密钥、股票符号、短期窗口和长期窗口
     let config = StrategyConfig {
         api_key: "XTUOEZ3P3FCS956P".to_string(), // API密钥，用于访问市场数据
@@ -98,17 +199,46 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     let atr = calculate_atr(&price_data, risk_manager.atr_period);
 
+    if chart {
+        chart::print_chart(&price_data);
+    }
+
     // 生成交易信号，传入价格数据、短期窗口和长期窗口
     let signal = execute_trading_strategy(&price_data);
     let signal_with_risk_manager =
         calulate_signal_with_risk_manager(&signal, &risk_manager, atr, &price_data);
 
+    // 信号超过有效期时（如收盘后运行）提示不要执行，提示写到 stderr，不混入 tv 格式的输出
+    // 注意：Alpha Vantage 返回的是美东时间，这里用本地时间近似判断
+    let expired = signal_with_risk_manager.is_expired(Local::now().naive_local());
+    if expired {
+        eprintln!("⚪ Signal expired, do not execute");
+    } else if let Some(command) = &on_signal {
+        hook::notify_signal(
+            &hook::CommandHook::new(command),
+            &config.symbol,
+            &signal_with_risk_manager,
+        );
+    }
+
+    // 过期的信号不输出告警行，避免桥接程序照常下单
+    if format == OutputFormat::Tv {
+        if expired {
+            return Ok(());
+        }
+        if let Some(line) = signal_with_risk_manager.to_tv_line(&config.symbol) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
     match signal_with_risk_manager {
         TradeSignalWithRisk::Buy {
             entry_price,
             stop_loss,
             take_profit,
             quantity,
+            ..
         } => {
             println!(
                 "🟢 BUY: Price={:.2} Qty={} SL={:.2} TP={:.2}",
@@ -120,6 +250,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
             stop_loss,
             take_profit,
             quantity,
+            ..
         } => {
             println!(
                 "🔴 SELL: Price={:.2} Qty={} SL={:.2} TP={:.2}",
@@ -180,6 +311,13 @@ fn calulate_signal_with_risk_manager(
     atr: Vec<f64>,
     price_data: &PriceData,
 ) -> TradeSignalWithRisk {
+    // 信号生成时间取最后一根K线的时间
+    let generated_at = price_data
+        .timestamps
+        .last()
+        .copied()
+        .unwrap_or_else(|| Local::now().naive_local());
+
     match signal {
         TradeSignal::Buy => {
             let entry_price = price_data.closes.last().unwrap();
@@ -190,12 +328,14 @@ fn calulate_signal_with_risk_manager(
 
             let take_profit = entry_price * (1.0 + risk_manager.take_profit_pct);
 
-            return TradeSignalWithRisk::Buy {
+            TradeSignalWithRisk::Buy {
                 entry_price: *entry_price,
                 stop_loss,
                 take_profit,
                 quantity,
-            };
+                generated_at,
+                valid_for: risk_manager.signal_validity,
+            }
         }
         TradeSignal::Sell => {
             let entry_price = price_data.closes.last().unwrap();
@@ -205,16 +345,17 @@ fn calulate_signal_with_risk_manager(
             let quantity = risk_manager.calculate_position_size(*entry_price, *current_atr);
             let take_profit = entry_price * (1.0 - risk_manager.take_profit_pct);
 
-            return TradeSignalWithRisk::Sell {
+            TradeSignalWithRisk::Sell {
                 entry_price: *entry_price,
                 stop_loss,
                 take_profit,
                 quantity,
-            };
-        }
-        TradeSignal::Hold => {
-            return TradeSignalWithRisk::Hold;
+                generated_at,
+                valid_for: risk_manager.signal_validity,
+            }
         }
+        // 信号冲突时不开仓
+        TradeSignal::Hold | TradeSignal::Conflict => TradeSignalWithRisk::Hold,
     }
 }
 
@@ -266,41 +407,35 @@ async fn fetch_market_data_v2(config: &StrategyConfig) -> Result<PriceData, Box<
         .json::<AlphaVantageResponse>()
         .await?;
 
-    // 初始化存储价格相关数据的向量
-    let mut prices = Vec::new();
-    let mut highs = Vec::new();
-    let mut lows = Vec::new();
-    let mut closes = Vec::new();
+    // 先收集(时间, 开, 高, 低, 收)，按时间排序后再拆分为各个序列
+    let mut bars: Vec<(NaiveDateTime, f64, f64, f64, f64)> = Vec::new();
 
     // 检查响应中是否包含时间序列数据
     if let Some(time_series) = response.time_series {
         // 遍历时间序列数据
-        for (_, v) in time_series.as_object().unwrap() {
+        for (time, v) in time_series.as_object().unwrap() {
+            // 解析K线时间，格式如 "2024-01-02 16:00:00"
+            let timestamp = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S")?;
             // 从每个数据点中提取开盘价、最高价、最低价和收盘价，并解析为f64
             let open = v["1. open"].as_str().unwrap().parse::<f64>()?;
             let high = v["2. high"].as_str().unwrap().parse::<f64>()?;
             let low = v["3. low"].as_str().unwrap().parse::<f64>()?;
             let close = v["4. close"].as_str().unwrap().parse::<f64>()?;
 
-            prices.push(open);
-            highs.push(high);
-            lows.push(low);
-            closes.push(close);
+            bars.push((timestamp, open, high, low, close));
         }
     }
 
-    // 确保数据按时间升序排列（API可能返回降序数据）
-    prices.reverse();
-    highs.reverse();
-    lows.reverse();
-    closes.reverse();
+    // 确保数据按时间升序排列（不依赖API或JSON对象的键顺序）
+    bars.sort_by_key(|bar| bar.0);
 
     // 将采集到的数据封装到PriceData结构体中返回
     Ok(PriceData {
-        prices,
-        highs,
-        lows,
-        closes,
+        timestamps: bars.iter().map(|bar| bar.0).collect(),
+        prices: bars.iter().map(|bar| bar.1).collect(),
+        highs: bars.iter().map(|bar| bar.2).collect(),
+        lows: bars.iter().map(|bar| bar.3).collect(),
+        closes: bars.iter().map(|bar| bar.4).collect(),
     })
 }
 
@@ -355,6 +490,64 @@ fn _generate_signal(prices: &[f64], short_window: usize, long_window: usize) ->
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_signal_expires_after_validity_window() {
+        let generated_at =
+            NaiveDateTime::parse_from_str("2024-01-02 10:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let price_data = PriceData {
+            timestamps: vec![generated_at - Duration::minutes(5), generated_at],
+            prices: vec![100.0, 101.0],
+            highs: vec![101.0, 102.0],
+            lows: vec![99.0, 100.0],
+            closes: vec![100.5, 101.5],
+        };
+        let risk_manager = RiskManager::new(100000.0);
+        let window = risk_manager.signal_validity;
+
+        let signal = calulate_signal_with_risk_manager(
+            &TradeSignal::Buy,
+            &risk_manager,
+            vec![1.0],
+            &price_data,
+        );
+
+        assert!(!signal.is_expired(generated_at));
+        assert!(!signal.is_expired(generated_at + window));
+        assert!(signal.is_expired(generated_at + window + Duration::seconds(1)));
+        assert!(!TradeSignalWithRisk::Hold.is_expired(generated_at + window * 10));
+    }
+
+    #[test]
+    fn test_tv_line_format() {
+        let generated_at =
+            NaiveDateTime::parse_from_str("2024-01-02 10:05:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let signal = TradeSignalWithRisk::Buy {
+            entry_price: 101.5,
+            stop_loss: 99.25,
+            take_profit: 104.545,
+            quantity: 10.0,
+            generated_at,
+            valid_for: Duration::minutes(15),
+        };
+        assert_eq!(
+            signal.to_tv_line("MSFT").unwrap(),
+            "MSFT,BUY,101.50,99.25,104.55,2024-01-02T10:05:00"
+        );
+        assert_eq!(TradeSignalWithRisk::Hold.to_tv_line("MSFT"), None);
+
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_output_format(&args(&[])).unwrap(),
+            OutputFormat::Human
+        );
+        assert_eq!(
+            parse_output_format(&args(&["--chart", "--format", "tv"])).unwrap(),
+            OutputFormat::Tv
+        );
+        assert!(parse_output_format(&args(&["--format", "json"])).is_err());
+        assert!(parse_output_format(&args(&["--format"])).is_err());
+    }
+
     #[test]
     fn test_generate_signal_short_window_greater_than_long_window() {
         let prices = vec![10.0, 20.0, 15.0, 30.0, 25.0];
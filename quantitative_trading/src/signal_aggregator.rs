@@ -1,5 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
+use chrono::NaiveDateTime;
+
+use crate::price_transform::{log_returns, PriceTransform};
 use crate::TradeSignal;
 
 #[derive(Debug, Clone)]
@@ -9,15 +12,37 @@ pub struct SignalStrength {
 }
 
 pub struct SignalAggregator {
-    indicators: HashMap<String, f64>, // 指标权重
-    threshold: f64,                   // 信号阈值
+    indicators: HashMap<String, f64>,    // 指标权重
+    threshold: f64,                      // 信号阈值
+    returns_indicators: HashSet<String>, // 基于对数收益率而不是价格计算的指标
+    warmup_mode: WarmupMode,             // 预热期内未就绪指标的处理方式
+    confidence_floor: f64,               // 买卖得分都低于该值时不给出判断
+    conflict_margin: Option<f64>,        // 买卖得分都超过阈值且差距小于该值时视为冲突
+}
+
+/// 预热期内数据不足、尚未就绪的指标如何参与综合评分
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WarmupMode {
+    /// 未就绪的指标按零强度计入（默认）
+    CountAsZero,
+    /// 未就绪的指标不参与计算，按已就绪指标的权重重新归一化
+    Renormalize,
+}
+
+/// 市场状态：趋势市或震荡市
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Regime {
+    Trending,
+    Ranging,
 }
 
+#[derive(Clone)]
 pub struct PriceData {
-    pub prices: Vec<f64>, // 价格数据
-    pub highs: Vec<f64>,  // 最高价数据
-    pub lows: Vec<f64>,   //  最低价数据
-    pub closes: Vec<f64>, //    收盘价数据
+    pub timestamps: Vec<NaiveDateTime>, // 每根K线的时间
+    pub prices: Vec<f64>,               // 价格数据
+    pub highs: Vec<f64>,                // 最高价数据
+    pub lows: Vec<f64>,                 //  最低价数据
+    pub closes: Vec<f64>,               //    收盘价数据
 }
 
 impl SignalAggregator {
@@ -32,33 +57,169 @@ impl SignalAggregator {
         Self {
             indicators,
             threshold,
+            returns_indicators: HashSet::new(),
+            warmup_mode: WarmupMode::CountAsZero,
+            confidence_floor: 0.0,
+            conflict_margin: None,
         }
     }
 
-    pub fn generate_composite_signal(
-        &self,
-        signals: &HashMap<String, SignalStrength>,
-    ) -> TradeSignal {
+    /// 按市场状态创建聚合器：趋势市侧重MACD和均线交叉等趋势指标，
+    /// 震荡市侧重RSI、布林带和KDJ等均值回归指标
+    pub fn for_regime(regime: Regime, threshold: f64) -> Self {
+        let weights: [(&str, f64); 5] = match regime {
+            Regime::Trending => [
+                ("MACD", 0.35),
+                ("MA_CROSS", 0.3),
+                ("KDJ", 0.15),
+                ("RSI", 0.1),
+                ("BB", 0.1),
+            ],
+            Regime::Ranging => [
+                ("RSI", 0.3),
+                ("BB", 0.3),
+                ("KDJ", 0.2),
+                ("MACD", 0.1),
+                ("MA_CROSS", 0.1),
+            ],
+        };
+
+        let mut aggregator = Self::new(threshold);
+        aggregator.indicators = weights
+            .iter()
+            .map(|(name, weight)| (name.to_string(), *weight))
+            .collect();
+        aggregator
+    }
+
+    /// 让指定指标基于对数收益率而不是价格计算，以获得更平稳的输入
+    /// 目前只有基于价格的 RSI 和 BB 支持该选项，KDJ 等基于高低收的指标始终使用原始价格
+    pub fn with_returns_input(mut self, indicator: &str) -> Self {
+        self.returns_indicators.insert(indicator.to_string());
+        self
+    }
+
+    /// 设置预热期内未就绪指标的处理方式
+    pub fn with_warmup_mode(mut self, mode: WarmupMode) -> Self {
+        self.warmup_mode = mode;
+        self
+    }
+
+    /// 设置置信下限：买入和卖出得分都低于该值时，
+    /// generate_composite_signal_or_abstain 返回 None 而不是 Hold
+    pub fn with_confidence_floor(mut self, floor: f64) -> Self {
+        self.confidence_floor = floor;
+        self
+    }
+
+    /// 设置冲突区间：买卖得分都超过阈值且两者相差小于 margin 时返回 TradeSignal::Conflict，
+    /// 未设置时总是选择得分更高的一方
+    pub fn with_conflict_margin(mut self, margin: f64) -> Self {
+        self.conflict_margin = Some(margin);
+        self
+    }
+
+    /// 按照聚合器的配置计算各个指标的信号强度
+    /// Renormalize 模式下，数据不足的指标不会出现在结果中
+    pub fn generate_signals(&self, price_data: &PriceData) -> HashMap<String, SignalStrength> {
+        let mut signals = generate_trading_signals(price_data);
+
+        if !self.returns_indicators.is_empty() {
+            let returns = log_returns(&price_data.prices);
+            if self.returns_indicators.contains("RSI") {
+                signals.insert("RSI".to_string(), rsi_signal(&returns));
+            }
+            if self.returns_indicators.contains("BB") {
+                signals.insert("BB".to_string(), bollinger_signal(&returns));
+            }
+        }
+
+        if self.warmup_mode == WarmupMode::Renormalize {
+            let bars = price_data.prices.len();
+            signals.retain(|indicator, _| {
+                // 基于收益率的指标比价格少一个数据点
+                let available = if self.returns_indicators.contains(indicator) {
+                    bars.saturating_sub(1)
+                } else {
+                    bars
+                };
+                available >= warmup_bars(indicator)
+            });
+        }
+        signals
+    }
+
+    /// 计算加权后的 (买入得分, 卖出得分)
+    /// Renormalize 模式下只在已就绪的指标之间分配权重，全部就绪时与 CountAsZero 结果一致
+    pub fn composite_scores(&self, signals: &HashMap<String, SignalStrength>) -> (f64, f64) {
         let mut total_buy = 0.0;
         let mut total_sell = 0.0;
+        let mut total_weight = 0.0;
+        let mut ready_weight = 0.0;
 
         for (indicator, weight) in &self.indicators {
+            total_weight += weight;
             if let Some(signal) = signals.get(indicator) {
                 total_buy += signal.buy_strength * weight;
                 total_sell += signal.sell_strength * weight;
+                ready_weight += weight;
             }
         }
 
-        if total_buy > self.threshold {
-            TradeSignal::Buy
-        } else if total_sell > self.threshold {
-            TradeSignal::Sell
+        if self.warmup_mode == WarmupMode::Renormalize && ready_weight > 0.0 {
+            let scale = total_weight / ready_weight;
+            total_buy *= scale;
+            total_sell *= scale;
+        }
+        (total_buy, total_sell)
+    }
+
+    pub fn generate_composite_signal(
+        &self,
+        signals: &HashMap<String, SignalStrength>,
+    ) -> TradeSignal {
+        let (total_buy, total_sell) = self.composite_scores(signals);
+
+        match (total_buy > self.threshold, total_sell > self.threshold) {
+            // 买卖双方都超过阈值时，差距过小视为冲突，否则选择得分更高的一方
+            (true, true) => match self.conflict_margin {
+                Some(margin) if (total_buy - total_sell).abs() < margin => TradeSignal::Conflict,
+                _ if total_sell > total_buy => TradeSignal::Sell,
+                _ => TradeSignal::Buy,
+            },
+            (true, false) => TradeSignal::Buy,
+            (false, true) => TradeSignal::Sell,
+            (false, false) => TradeSignal::Hold,
+        }
+    }
+
+    /// 与 generate_composite_signal 相同，但买卖得分都低于置信下限时返回 None，
+    /// 用来区分“有把握的观望”（Some(Hold)）和“没有判断”（None）
+    pub fn generate_composite_signal_or_abstain(
+        &self,
+        signals: &HashMap<String, SignalStrength>,
+    ) -> Option<TradeSignal> {
+        let (total_buy, total_sell) = self.composite_scores(signals);
+        if total_buy < self.confidence_floor && total_sell < self.confidence_floor {
+            None
         } else {
-            TradeSignal::Hold
+            Some(self.generate_composite_signal(signals))
         }
     }
 }
 
+/// 各指标产生有效信号所需的最少K线数量，未知指标返回 0
+pub fn warmup_bars(indicator: &str) -> usize {
+    match indicator {
+        "MACD" => 26,     // 长期EMA窗口
+        "RSI" => 14 + 1,  // RSI周期加1个用于计算变化
+        "BB" => 20,       // 布林带周期
+        "KDJ" => 9,       // RSV周期
+        "MA_CROSS" => 21, // 长期均线窗口加1个用于判断交叉
+        _ => 0,
+    }
+}
+
 // 交易信号生成器
 pub fn generate_trading_signals(price_data: &PriceData) -> HashMap<String, SignalStrength> {
     let mut signals = HashMap::new();
@@ -122,6 +283,11 @@ fn calculate_macd_signal(price_data: &PriceData) -> SignalStrength {
     // 计算MACD直方图，即MACD线减去信号线
     let macd_histogram = macd_line - signal_line;
 
+    macd_strength(macd_histogram)
+}
+
+// 由MACD直方图得到信号强度，批量计算和增量聚合器共用
+pub(crate) fn macd_strength(macd_histogram: f64) -> SignalStrength {
     // 如果MACD直方图大于0，则返回买入强度为MACD直方图值，卖出强度为0的信号强度
     if macd_histogram > 0.0 {
         SignalStrength {
@@ -153,25 +319,20 @@ fn calculate_ema(prices: &[f64], window: usize) -> f64 {
     ema
 }
 
-// 定义一个函数，用于计算相对强弱指数（RSI）信号
-pub fn calculate_rsi_signal(price_data: &PriceData) -> SignalStrength {
-    // 设置RSI的周期为14
-    let rsi_period = 14;
-    // 如果价格数据中的价格数量小于RSI周期加1，则无法计算变化率，返回无信号的SignalStrength
-    if price_data.prices.len() < rsi_period + 1 {
+/// 计算最近 period 根K线的相对强弱指数（RSI），数据不足时返回 None
+pub fn calculate_rsi(prices: &[f64], period: usize) -> Option<f64> {
+    // 如果价格数量小于周期加1，则无法计算变化率
+    if prices.len() < period + 1 {
         // +1 是为了计算变化率
-        return SignalStrength {
-            buy_strength: 0.0,
-            sell_strength: 0.0,
-        };
+        return None;
     }
     // 初始化收益和亏损为0.0
     let mut gains = 0.0; // 收益
     let mut losses = 0.0; // 亏损
-                          // 遍历价格数据中最后RSI周期内的每一天
-    for i in price_data.prices.len() - rsi_period..price_data.prices.len() {
+                          // 遍历最后RSI周期内的每一天
+    for i in prices.len() - period..prices.len() {
         // 计算每一天的价格变化
-        let change = price_data.prices[i] - price_data.prices[i - 1];
+        let change = prices[i] - prices[i - 1];
         // 如果价格变化为正，则累加到收益中
         if change > 0.0 {
             gains += change;
@@ -181,13 +342,73 @@ pub fn calculate_rsi_signal(price_data: &PriceData) -> SignalStrength {
         }
     }
     // 计算平均收益和平均亏损
-    let avg_gain = gains / rsi_period as f64;
-    let avg_loss = losses / rsi_period as f64;
+    let avg_gain = gains / period as f64;
+    let avg_loss = losses / period as f64;
     // 根据平均收益和平均亏损计算RSI值
-    let rsi = if avg_loss == 0.0 {
-        100.0
+    if avg_loss == 0.0 {
+        Some(100.0)
     } else {
-        100.0 - 100.0 / (1.0 + avg_gain / avg_loss)
+        Some(100.0 - 100.0 / (1.0 + avg_gain / avg_loss))
+    }
+}
+
+/// calculate_rsi 的流式版本：逐个读取价格，惰性地输出每根K线上的RSI
+/// 只保留最近 period 个价格变化，前 period 根K线（预热期）输出 None
+/// 第 i 个输出与 calculate_rsi(&prices[..=i], period) 相同
+pub fn rsi_iter(
+    prices: impl Iterator<Item = f64>,
+    period: usize,
+) -> impl Iterator<Item = Option<f64>> {
+    let mut changes: VecDeque<f64> = VecDeque::with_capacity(period + 1);
+    let mut prev: Option<f64> = None;
+    prices.map(move |price| {
+        if let Some(prev) = prev {
+            changes.push_back(price - prev);
+            if changes.len() > period {
+                changes.pop_front();
+            }
+        }
+        prev = Some(price);
+        if changes.len() < period || period == 0 {
+            return None;
+        }
+        // 与 calculate_rsi 按相同顺序累加，保证结果完全一致
+        let (mut gains, mut losses) = (0.0, 0.0);
+        for &change in &changes {
+            if change > 0.0 {
+                gains += change;
+            } else {
+                losses += -change;
+            }
+        }
+        let avg_gain = gains / period as f64;
+        let avg_loss = losses / period as f64;
+        if avg_loss == 0.0 {
+            Some(100.0)
+        } else {
+            Some(100.0 - 100.0 / (1.0 + avg_gain / avg_loss))
+        }
+    })
+}
+
+// 定义一个函数，用于计算相对强弱指数（RSI）信号
+pub fn calculate_rsi_signal(price_data: &PriceData) -> SignalStrength {
+    rsi_signal(&price_data.prices)
+}
+
+// 根据给定序列（价格或收益率）计算RSI信号
+fn rsi_signal(series: &[f64]) -> SignalStrength {
+    // 设置RSI的周期为14
+    let rsi_period = 14;
+    // 数据不足时返回无信号的SignalStrength
+    let rsi = match calculate_rsi(series, rsi_period) {
+        Some(rsi) => rsi,
+        None => {
+            return SignalStrength {
+                buy_strength: 0.0,
+                sell_strength: 0.0,
+            }
+        }
     };
 
     // 根据RSI值判断信号强度
@@ -214,17 +435,22 @@ pub fn calculate_rsi_signal(price_data: &PriceData) -> SignalStrength {
 
 // 布林带信号，计算20SMA和标准差，当最新价格触及上下轨时给出信号
 pub fn calculate_bollinger_signal(price_data: &PriceData) -> SignalStrength {
+    bollinger_signal(&price_data.prices)
+}
+
+// 根据给定序列（价格或收益率）计算布林带信号
+fn bollinger_signal(series: &[f64]) -> SignalStrength {
     // 设置布林带的周期为20
     let period = 20;
-    // 如果价格数据中的价格数量小于周期，则返回一个买入和卖出强度都为0的信号强度
-    if price_data.prices.len() < period {
+    // 如果序列中的数据数量小于周期，则返回一个买入和卖出强度都为0的信号强度
+    if series.len() < period {
         return SignalStrength {
             buy_strength: 0.0,
             sell_strength: 0.0,
         };
     }
-    // 获取最近20个价格数据的切片
-    let slice = &price_data.prices[price_data.prices.len() - period..];
+    // 获取最近20个数据的切片
+    let slice = &series[series.len() - period..];
     // 计算这20个价格数据的简单移动平均数（SMA）
     let sma = slice.iter().sum::<f64>() / period as f64;
     // 计算这20个价格数据的方差
@@ -235,8 +461,8 @@ pub fn calculate_bollinger_signal(price_data: &PriceData) -> SignalStrength {
     let upper = sma + 2.0 * std_dev;
     // 计算布林带的下轨
     let lower = sma - 2.0 * std_dev;
-    // 获取最后一个价格
-    let last_price = *price_data.prices.last().unwrap();
+    // 获取最后一个数据
+    let last_price = *series.last().unwrap();
 
     // 如果最后一个价格小于等于下轨，则计算买入强度，卖出强度为0
     if last_price <= lower {
@@ -296,7 +522,11 @@ pub fn calculate_kdj_signal(price_data: &PriceData) -> SignalStrength {
     let k = rsv;
     let d = rsv;
     let j = 3.0 * k - 2.0 * d; // 实际上 j == rsv
+    kdj_strength(j)
+}
 
+// 由J值得到信号强度，J值低于20超卖买入，高于80超买卖出，批量计算和增量聚合器共用
+pub(crate) fn kdj_strength(j: f64) -> SignalStrength {
     if j < 20.0 {
         SignalStrength {
             buy_strength: (20.0 - j) / 20.0,
@@ -375,10 +605,78 @@ pub fn calculate_ma_cross_signal(price_data: &PriceData) -> SignalStrength {
     }
 }
 
+// 均线在回看期内移动超过多少倍ATR视为趋势市
+const TREND_ATR_MULTIPLE: f64 = 1.0;
+
+/// 根据长期均线的斜率判断市场状态
+/// 比较当前的 ma_period 均线与 ma_period / 2 根K线之前的均线，
+/// 用同期的平均真实波幅（ATR）归一化，移动幅度超过 TREND_ATR_MULTIPLE 倍ATR为趋势市，否则为震荡市
+/// 数据不足时按震荡市处理
+pub fn market_regime(price_data: &PriceData, ma_period: usize) -> Regime {
+    let closes = &price_data.closes;
+    let lookback = (ma_period / 2).max(1);
+    if ma_period == 0
+        || closes.len() < ma_period + lookback + 1
+        || price_data.highs.len() != closes.len()
+        || price_data.lows.len() != closes.len()
+    {
+        return Regime::Ranging;
+    }
+
+    let n = closes.len();
+    let sma = |end: usize| closes[end - ma_period..end].iter().sum::<f64>() / ma_period as f64;
+    let ma_now = sma(n);
+    let ma_prev = sma(n - lookback);
+
+    // 最近 ma_period 根K线的平均真实波幅
+    let atr = (n - ma_period..n)
+        .map(|i| {
+            let high = price_data.highs[i];
+            let low = price_data.lows[i];
+            let prev_close = closes[i - 1];
+            (high - low)
+                .max((high - prev_close).abs())
+                .max((low - prev_close).abs())
+        })
+        .sum::<f64>()
+        / ma_period as f64;
+
+    let ma_move = (ma_now - ma_prev).abs();
+    if atr == 0.0 {
+        return if ma_move > 0.0 {
+            Regime::Trending
+        } else {
+            Regime::Ranging
+        };
+    }
+
+    if ma_move / atr > TREND_ATR_MULTIPLE {
+        Regime::Trending
+    } else {
+        Regime::Ranging
+    }
+}
+
+// 判断市场状态使用的长期均线周期
+const REGIME_MA_PERIOD: usize = 50;
+
 // 使用示例
 pub fn execute_trading_strategy(price_data: &PriceData) -> TradeSignal {
-    let aggregator = SignalAggregator::new(0.6);
-    let signals = generate_trading_signals(price_data);
+    execute_trading_strategy_with_transform(price_data, None)
+}
+
+/// 与 execute_trading_strategy 相同，但可以在计算指标之前先对价格数据做变换
+pub fn execute_trading_strategy_with_transform(
+    price_data: &PriceData,
+    transform: Option<&dyn PriceTransform>,
+) -> TradeSignal {
+    let transformed = transform.map(|transform| transform.transform(price_data));
+    let data = transformed.as_ref().unwrap_or(price_data);
+
+    // 按市场状态调整各指标的权重
+    let regime = market_regime(data, REGIME_MA_PERIOD);
+    let aggregator = SignalAggregator::for_regime(regime, 0.6);
+    let signals = aggregator.generate_signals(data);
     aggregator.generate_composite_signal(&signals)
 }
 
@@ -412,4 +710,207 @@ mod tests {
         let signal = aggregator.generate_composite_signal(&signals);
         assert_eq!(signal, TradeSignal::Buy);
     }
+
+    fn trending_data() -> PriceData {
+        // 稳步上涨但涨幅逐渐放缓的序列：价格上RSI接近100，收益率上RSI偏低
+        let prices: Vec<f64> = (0..40).map(|i| 100.0 + (i as f64).sqrt() * 10.0).collect();
+        PriceData {
+            timestamps: Vec::new(),
+            prices: prices.clone(),
+            highs: prices.iter().map(|p| p + 1.0).collect(),
+            lows: prices.iter().map(|p| p - 1.0).collect(),
+            closes: prices,
+        }
+    }
+
+    #[test]
+    fn test_rsi_on_returns_differs_from_rsi_on_prices() {
+        let data = trending_data();
+        let rsi_on_prices = calculate_rsi(&data.prices, 14).unwrap();
+        let rsi_on_returns = calculate_rsi(&log_returns(&data.prices), 14).unwrap();
+        assert!((rsi_on_prices - 100.0).abs() < 1e-9);
+        assert!(rsi_on_returns < 30.0);
+
+        let on_prices = SignalAggregator::new(0.6).generate_signals(&data);
+        let on_returns = SignalAggregator::new(0.6)
+            .with_returns_input("RSI")
+            .generate_signals(&data);
+        assert!(on_prices["RSI"].sell_strength > 0.0);
+        assert!(on_returns["RSI"].buy_strength > 0.0);
+        // KDJ 不受收益率选项影响
+        assert_eq!(
+            on_prices["KDJ"].sell_strength,
+            on_returns["KDJ"].sell_strength
+        );
+    }
+
+    #[test]
+    fn test_rsi_iter_matches_batch() {
+        let prices: Vec<f64> = (0..80)
+            .map(|i| 100.0 + (i as f64 * 0.45).sin() * 6.0 + (i % 7) as f64 * 0.3)
+            .collect();
+        let streamed: Vec<Option<f64>> = rsi_iter(prices.iter().copied(), 14).collect();
+        assert_eq!(streamed.len(), prices.len());
+        for (i, value) in streamed.iter().enumerate() {
+            assert_eq!(
+                *value,
+                calculate_rsi(&prices[..=i], 14),
+                "mismatch at bar {}",
+                i
+            );
+        }
+        // 预热期输出 None
+        assert!(streamed[..14].iter().all(Option::is_none));
+        assert!(streamed[14..].iter().all(Option::is_some));
+
+        // 可用于无限序列，只取需要的部分
+        let rising = rsi_iter((0..).map(|i| i as f64), 3).nth(10);
+        assert_eq!(rising, Some(Some(100.0)));
+    }
+
+    fn series(closes: Vec<f64>) -> PriceData {
+        PriceData {
+            timestamps: Vec::new(),
+            prices: closes.clone(),
+            highs: closes.iter().map(|c| c + 0.5).collect(),
+            lows: closes.iter().map(|c| c - 0.5).collect(),
+            closes,
+        }
+    }
+
+    fn uniform_signals(buy_strength: f64) -> HashMap<String, SignalStrength> {
+        ["MACD", "RSI", "BB", "KDJ", "MA_CROSS"]
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    SignalStrength {
+                        buy_strength,
+                        sell_strength: 0.0,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn conflicting_signals(
+        buy_strength: f64,
+        sell_strength: f64,
+    ) -> HashMap<String, SignalStrength> {
+        let mut signals = uniform_signals(buy_strength);
+        for signal in signals.values_mut() {
+            signal.sell_strength = sell_strength;
+        }
+        signals
+    }
+
+    #[test]
+    fn test_composite_signal_picks_stronger_side() {
+        let aggregator = SignalAggregator::new(0.6);
+        // 卖出 > 买入 > 阈值时应当卖出，而不是因为判断顺序选择买入
+        assert_eq!(
+            aggregator.generate_composite_signal(&conflicting_signals(0.7, 0.9)),
+            TradeSignal::Sell
+        );
+        assert_eq!(
+            aggregator.generate_composite_signal(&conflicting_signals(0.9, 0.7)),
+            TradeSignal::Buy
+        );
+    }
+
+    #[test]
+    fn test_composite_signal_conflict_on_near_tie() {
+        let aggregator = SignalAggregator::new(0.6).with_conflict_margin(0.05);
+        assert_eq!(
+            aggregator.generate_composite_signal(&conflicting_signals(0.8, 0.82)),
+            TradeSignal::Conflict
+        );
+        assert_eq!(
+            aggregator.generate_composite_signal(&conflicting_signals(0.7, 0.9)),
+            TradeSignal::Sell
+        );
+        // 只有一方超过阈值时不会冲突
+        assert_eq!(
+            aggregator.generate_composite_signal(&conflicting_signals(0.62, 0.58)),
+            TradeSignal::Buy
+        );
+    }
+
+    #[test]
+    fn test_composite_signal_abstains_below_confidence_floor() {
+        let aggregator = SignalAggregator::new(0.6).with_confidence_floor(0.2);
+        // 买入得分0.1，低于置信下限：没有判断
+        assert_eq!(
+            aggregator.generate_composite_signal_or_abstain(&uniform_signals(0.1)),
+            None
+        );
+        // 买入得分0.4，高于置信下限但低于动作阈值：有把握的观望
+        assert_eq!(
+            aggregator.generate_composite_signal_or_abstain(&uniform_signals(0.4)),
+            Some(TradeSignal::Hold)
+        );
+        assert_eq!(
+            aggregator.generate_composite_signal_or_abstain(&uniform_signals(0.9)),
+            Some(TradeSignal::Buy)
+        );
+        // 原有接口不受影响
+        assert_eq!(
+            aggregator.generate_composite_signal(&uniform_signals(0.1)),
+            TradeSignal::Hold
+        );
+    }
+
+    #[test]
+    fn test_warmup_renormalize_excludes_unready_indicators() {
+        // 22根K线：MACD（需要26根）尚未就绪，其余指标已就绪
+        let data = series((0..22).map(|i| 100.0 - i as f64 * 0.5).collect());
+        let zero = SignalAggregator::new(0.6);
+        let renormalize = SignalAggregator::new(0.6).with_warmup_mode(WarmupMode::Renormalize);
+
+        let zero_signals = zero.generate_signals(&data);
+        let renormalized_signals = renormalize.generate_signals(&data);
+        assert!(zero_signals.contains_key("MACD"));
+        assert!(!renormalized_signals.contains_key("MACD"));
+
+        let (zero_buy, zero_sell) = zero.composite_scores(&zero_signals);
+        let (buy, sell) = renormalize.composite_scores(&renormalized_signals);
+        assert!(zero_buy > 0.0);
+        // 已就绪指标的权重合计为0.7，重新归一化后得分放大为 1/0.7 倍
+        assert!((buy - zero_buy / 0.7).abs() < 1e-9);
+        assert!((sell - zero_sell / 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_warmup_modes_agree_once_all_ready() {
+        let data = series((0..60).map(|i| 100.0 + (i as f64 * 0.3).sin()).collect());
+        let zero = SignalAggregator::new(0.6);
+        let renormalize = SignalAggregator::new(0.6).with_warmup_mode(WarmupMode::Renormalize);
+        let (zero_buy, zero_sell) = zero.composite_scores(&zero.generate_signals(&data));
+        let (buy, sell) = renormalize.composite_scores(&renormalize.generate_signals(&data));
+        assert!((buy - zero_buy).abs() < 1e-9);
+        assert!((sell - zero_sell).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_market_regime_trending() {
+        let data = series((0..120).map(|i| 100.0 + i as f64).collect());
+        assert_eq!(market_regime(&data, 50), Regime::Trending);
+    }
+
+    #[test]
+    fn test_market_regime_ranging() {
+        // 周期为10根K线的正弦震荡，50均线几乎是水平的
+        let data = series(
+            (0..120)
+                .map(|i| 100.0 + 5.0 * (i as f64 * std::f64::consts::PI / 5.0).sin())
+                .collect(),
+        );
+        assert_eq!(market_regime(&data, 50), Regime::Ranging);
+    }
+
+    #[test]
+    fn test_market_regime_insufficient_data() {
+        let data = series((0..10).map(|i| 100.0 + i as f64).collect());
+        assert_eq!(market_regime(&data, 50), Regime::Ranging);
+    }
 }
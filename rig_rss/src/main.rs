@@ -1,21 +1,37 @@
-use rig::providers::openai::Client;
+use chrono::{DateTime, TimeDelta, Utc};
+use regex::Regex;
+use rig::embeddings::distance::VectorDistance;
+use rig::embeddings::EmbeddingModel;
+use rig::providers::openai::{self, Client};
+use rss::{Channel, Item};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
-use reqwest;
-use rss::Channel;
-use tokio::time::{self, Duration};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::error::Error;
-use regex::Regex;
+use std::fs;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tokio::time::{self, Duration};
 
 #[derive(Debug, Deserialize, Serialize, JsonSchema)]
 struct SummarizedRssItem {
     title: String,
     link: String,
-    #[schemars(with = "String")]
-    pub_date: DateTime<Utc>,
+    // 订阅源中没有发布日期时为 None，避免模型编造日期
+    #[schemars(with = "Option<String>")]
+    pub_date: Option<DateTime<Utc>>,
     summary: String,
     relevance_score: f32,
+    // 解释模式下模型给出的一句话打分理由，未开启时为空
+    #[serde(default)]
+    score_reason: String,
+    // 是否为本轮新出现的条目，不参与模型输出的 schema
+    #[serde(skip)]
+    is_new: bool,
+    // 与用户兴趣描述的向量余弦相似度，仅在开启向量重排时计算
+    #[serde(skip)]
+    interest_similarity: Option<f32>,
 }
 
 #[derive(Debug, Deserialize, JsonSchema, Serialize)]
@@ -26,12 +42,437 @@ struct RssSummary {
     overall_summary: String,
 }
 
+// 命令行选项
+#[derive(Debug)]
+struct Options {
+    diff: bool,                           // 是否标记本轮相对上一轮新增的条目
+    audit_dir: Option<PathBuf>,           // 保存每轮提示文本和模型输出的审计目录
+    interest: Option<String>,             // 用户兴趣描述，设置后按向量相似度重新排序
+    embedding_weight: f32,                // 向量相似度在综合得分中所占的权重（0.0 ~ 1.0）
+    max_body_bytes: usize,                // 订阅源响应体的最大字节数
+    site: Option<String>,                 // 网站首页地址，设置后自动发现其订阅源
+    feeds: Vec<String>,                   // 要轮询的订阅源地址，为空时使用默认订阅源
+    interval: u64,                        // 全局轮询间隔（秒）
+    feed_intervals: HashMap<String, u64>, // 单个订阅源的轮询间隔（秒），覆盖全局间隔
+    since: Option<DateTime<Utc>>,         // 只摘要发布时间晚于该时刻的条目
+    since_file: Option<PathBuf>,          // 保存每个订阅源已见过的最新发布时间，下次启动时继续使用
+    max_llm_calls: Option<usize>,         // 本进程最多调用模型的次数
+    max_tokens: Option<usize>,            // 本进程最多发送给模型的估算 token 数
+    stale_after: TimeDelta,               // 最新条目早于该时长时认为订阅源可能已停更
+    explain: bool,                        // 是否让模型为每个条目给出打分理由
+    config: Option<PathBuf>,              // TOML 配置文件路径
+    keywords: Vec<String>,                // 用于给条目打标签的关键词
+    require_keyword: bool,                // 是否丢弃没有命中任何关键词的条目
+}
+
+// 默认30天没有新条目就提示订阅源可能已停更
+const DEFAULT_STALE_DAYS: i64 = 30;
+
+// 默认的订阅源和全局轮询间隔（1小时）
+const DEFAULT_FEED_URL: &str = "https://news.ycombinator.com/rss";
+const DEFAULT_INTERVAL_SECS: u64 = 3600;
+
+// 默认的摘要模型、API 地址和保存 API key 的环境变量
+const DEFAULT_MODEL: &str = "gpt-4o-mini-2024-07-18";
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+const DEFAULT_API_KEY_ENV: &str = "OPENAI_API_KEY";
+
+// TOML 配置文件，例如：
+// [openai]
+// api_key_env = "AZURE_OPENAI_KEY"
+// base_url = "https://my-proxy.example/v1"
+// model = "gpt-4o-mini"
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    openai: OpenAiConfig,
+}
+
+// 配置文件中的 OpenAI 设置，未设置的项使用默认值
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct OpenAiConfig {
+    api_key_env: Option<String>, // 保存 API key 的环境变量名
+    base_url: Option<String>,    // API 地址，可指向代理、Azure 或其他兼容 OpenAI 的服务
+    model: Option<String>,       // 摘要使用的模型
+}
+
+// 合并配置文件和环境变量之后的 OpenAI 设置
+#[derive(Debug, Clone, PartialEq)]
+struct OpenAiSettings {
+    api_key: String,
+    base_url: String,
+    model: String,
+}
+
+impl OpenAiSettings {
+    // 环境变量 OPENAI_BASE_URL、OPENAI_MODEL 优先于配置文件，API key 从配置的环境变量中读取
+    fn resolve(
+        config: &OpenAiConfig,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let key_env = config.api_key_env.as_deref().unwrap_or(DEFAULT_API_KEY_ENV);
+        let api_key = env(key_env)
+            .filter(|key| !key.trim().is_empty())
+            .ok_or_else(|| {
+                format!(
+                    "OpenAI API key not found: environment variable {} is not set",
+                    key_env
+                )
+            })?;
+        let base_url = env("OPENAI_BASE_URL")
+            .or_else(|| config.base_url.clone())
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        let model = env("OPENAI_MODEL")
+            .or_else(|| config.model.clone())
+            .unwrap_or_else(|| DEFAULT_MODEL.to_string());
+        Ok(OpenAiSettings {
+            api_key,
+            base_url,
+            model,
+        })
+    }
+
+    fn client(&self) -> Client {
+        Client::from_url(&self.api_key, &self.base_url)
+    }
+}
+
+// 读取 TOML 配置文件
+fn load_config(path: &Path) -> Result<Config, Box<dyn Error>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| format!("failed to read config {}: {}", path.display(), e))?;
+    Ok(toml::from_str(&content)?)
+}
+
+// 解析正整数参数（秒数、天数等）
+fn parse_positive(flag: &str, value: &str) -> Result<u64, String> {
+    match value.parse::<u64>() {
+        Ok(secs) if secs > 0 => Ok(secs),
+        _ => Err(format!("invalid {}: {}", flag, value)),
+    }
+}
+
+// 默认的向量相似度权重：与模型给出的相关性得分各占一半
+const DEFAULT_EMBEDDING_WEIGHT: f32 = 0.5;
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            diff: false,
+            audit_dir: None,
+            interest: None,
+            embedding_weight: DEFAULT_EMBEDDING_WEIGHT,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            site: None,
+            feeds: Vec::new(),
+            interval: DEFAULT_INTERVAL_SECS,
+            feed_intervals: HashMap::new(),
+            since: None,
+            since_file: None,
+            max_llm_calls: None,
+            max_tokens: None,
+            stale_after: TimeDelta::days(DEFAULT_STALE_DAYS),
+            explain: false,
+            config: None,
+            keywords: Vec::new(),
+            require_keyword: false,
+        }
+    }
+}
+
+impl Options {
+    // 是否按发布时间过滤条目并记录已见过的最新时间
+    fn tracks_since(&self) -> bool {
+        self.since.is_some() || self.since_file.is_some()
+    }
+
+    // 某个订阅源的轮询间隔，未单独配置时使用全局间隔
+    fn interval_for(&self, feed_url: &str) -> Duration {
+        let secs = self
+            .feed_intervals
+            .get(feed_url)
+            .copied()
+            .unwrap_or(self.interval);
+        Duration::from_secs(secs)
+    }
+
+    // 解析命令行参数（不包含程序名）
+    fn parse<I: Iterator<Item = String>>(mut args: I) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--diff" => options.diff = true,
+                "--explain" => options.explain = true,
+                "--keyword" => {
+                    let keyword = args.next().ok_or("--keyword requires a value")?;
+                    options.keywords.push(keyword);
+                }
+                "--require-keyword" => options.require_keyword = true,
+                "--config" => {
+                    let path = args.next().ok_or("--config requires a path")?;
+                    options.config = Some(PathBuf::from(path));
+                }
+                "--audit-dir" => {
+                    let dir = args.next().ok_or("--audit-dir requires a path")?;
+                    options.audit_dir = Some(PathBuf::from(dir));
+                }
+                "--interest" => {
+                    let query = args.next().ok_or("--interest requires a query")?;
+                    options.interest = Some(query);
+                }
+                "--embedding-weight" => {
+                    let weight = args.next().ok_or("--embedding-weight requires a value")?;
+                    let weight: f32 = weight
+                        .parse()
+                        .map_err(|_| format!("invalid --embedding-weight: {}", weight))?;
+                    if !(0.0..=1.0).contains(&weight) {
+                        return Err("--embedding-weight must be between 0.0 and 1.0".to_string());
+                    }
+                    options.embedding_weight = weight;
+                }
+                "--max-body-bytes" => {
+                    let bytes = args.next().ok_or("--max-body-bytes requires a value")?;
+                    options.max_body_bytes = bytes
+                        .parse()
+                        .map_err(|_| format!("invalid --max-body-bytes: {}", bytes))?;
+                }
+                "--site" => {
+                    let url = args.next().ok_or("--site requires a URL")?;
+                    options.site = Some(url);
+                }
+                "--feed" => {
+                    let url = args.next().ok_or("--feed requires a URL")?;
+                    options.feeds.push(url);
+                }
+                "--interval" => {
+                    let secs = args.next().ok_or("--interval requires a value")?;
+                    options.interval = parse_positive("--interval", &secs)?;
+                }
+                "--feed-interval" => {
+                    // 格式为 <url>=<秒数>，URL 中可能包含 '='，所以按最后一个 '=' 拆分
+                    let spec = args.next().ok_or("--feed-interval requires <url>=<secs>")?;
+                    let (url, secs) = spec
+                        .rsplit_once('=')
+                        .ok_or_else(|| format!("invalid --feed-interval: {}", spec))?;
+                    let secs = parse_positive("--feed-interval", secs)?;
+                    options.feed_intervals.insert(url.to_string(), secs);
+                }
+                "--since" => {
+                    let since = args.next().ok_or("--since requires an RFC3339 timestamp")?;
+                    let since = DateTime::parse_from_rfc3339(&since)
+                        .map_err(|_| format!("invalid --since: {}", since))?;
+                    options.since = Some(since.with_timezone(&Utc));
+                }
+                "--since-file" => {
+                    let path = args.next().ok_or("--since-file requires a path")?;
+                    options.since_file = Some(PathBuf::from(path));
+                }
+                "--max-llm-calls" => {
+                    let calls = args.next().ok_or("--max-llm-calls requires a value")?;
+                    options.max_llm_calls = Some(
+                        calls
+                            .parse()
+                            .map_err(|_| format!("invalid --max-llm-calls: {}", calls))?,
+                    );
+                }
+                "--max-tokens" => {
+                    let tokens = args.next().ok_or("--max-tokens requires a value")?;
+                    options.max_tokens = Some(
+                        tokens
+                            .parse()
+                            .map_err(|_| format!("invalid --max-tokens: {}", tokens))?,
+                    );
+                }
+                "--stale-days" => {
+                    let days = args.next().ok_or("--stale-days requires a value")?;
+                    let parsed = parse_positive("--stale-days", &days)?;
+                    // 超出 TimeDelta 范围的天数报告为参数错误，而不是 panic
+                    options.stale_after = i64::try_from(parsed)
+                        .ok()
+                        .and_then(TimeDelta::try_days)
+                        .ok_or_else(|| format!("invalid --stale-days: {}", days))?;
+                }
+                other => return Err(format!("unknown argument: {}", other)),
+            }
+        }
+        if options.require_keyword && options.keywords.is_empty() {
+            return Err("--require-keyword requires at least one --keyword".to_string());
+        }
+        Ok(options)
+    }
+}
+
+// 整个进程的模型调用预算，跨轮次累计，防止失控的循环产生高额费用
+#[derive(Debug, Default)]
+struct LlmBudget {
+    max_calls: Option<usize>,  // 最多调用次数，None 表示不限
+    max_tokens: Option<usize>, // 最多发送的估算 token 数，None 表示不限
+    calls: usize,              // 已经调用的次数
+    tokens: usize,             // 已经发送的估算 token 数
+}
+
+impl LlmBudget {
+    fn new(max_calls: Option<usize>, max_tokens: Option<usize>) -> Self {
+        LlmBudget {
+            max_calls,
+            max_tokens,
+            ..Default::default()
+        }
+    }
+
+    // 预算足够时记下本次消耗并返回 true，否则不做任何记录并返回 false
+    fn try_spend(&mut self, tokens: usize) -> bool {
+        let calls_ok = self.max_calls.is_none_or(|max| self.calls < max);
+        let tokens_ok = self
+            .max_tokens
+            .is_none_or(|max| self.tokens + tokens <= max);
+        if calls_ok && tokens_ok {
+            self.calls += 1;
+            self.tokens += tokens;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// 粗略估算文本的 token 数：英文平均每个 token 约4个字符
+// 只统计发送的提示文本，模型输出的 token 不在估算范围内
+fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+// 在预算允许时调用 summarize，预算用完时返回 None 且不调用
+async fn summarize_with_budget<F, Fut>(
+    budget: &mut LlmBudget,
+    formatted_rss: &str,
+    summarize: F,
+) -> Option<Result<RssSummary, Box<dyn Error>>>
+where
+    F: FnOnce(String) -> Fut,
+    Fut: Future<Output = Result<RssSummary, Box<dyn Error>>>,
+{
+    if !budget.try_spend(estimate_tokens(formatted_rss)) {
+        return None;
+    }
+    Some(summarize(formatted_rss.to_string()).await)
+}
+
+// 按各自的间隔调度多个订阅源的最小堆，时间用相对启动时刻的偏移量表示，
+// 不依赖真实时钟，便于测试
+struct FeedScheduler {
+    queue: BinaryHeap<Reverse<(Duration, usize)>>, // (下次轮询时间, 订阅源下标)
+    intervals: Vec<Duration>,                      // 每个订阅源的轮询间隔
+}
+
+impl FeedScheduler {
+    // 所有订阅源在启动时立即轮询一次
+    fn new(intervals: Vec<Duration>) -> Self {
+        let queue = (0..intervals.len())
+            .map(|index| Reverse((Duration::ZERO, index)))
+            .collect();
+        FeedScheduler { queue, intervals }
+    }
+
+    // 最近一次需要轮询的时间
+    fn next_due(&self) -> Option<Duration> {
+        self.queue.peek().map(|Reverse((due, _))| *due)
+    }
+
+    // 取出在 now 之前到期的订阅源，并按各自的间隔安排下一次轮询
+    // 时钟跳过了多个间隔时跳过错过的轮询，每个订阅源每次最多返回一次
+    fn pop_due(&mut self, now: Duration) -> Vec<usize> {
+        let mut due_feeds = Vec::new();
+        while let Some(Reverse((due, index))) = self.queue.peek().copied() {
+            if due > now {
+                break;
+            }
+            self.queue.pop();
+            let next = next_poll_after(due, self.intervals[index], now);
+            self.queue.push(Reverse((next, index)));
+            due_feeds.push(index);
+        }
+        due_feeds
+    }
+}
+
+// 在 due 到期的订阅源的下一次轮询时间：保持原来的节奏，取 due 之后第一个晚于 now 的间隔点
+fn next_poll_after(due: Duration, interval: Duration, now: Duration) -> Duration {
+    let next = due + interval;
+    if next > now {
+        return next;
+    }
+    let missed = (now - due).as_nanos() / interval.as_nanos();
+    due + interval * (missed + 1) as u32
+}
+
+// 根据上一轮出现过的链接标记本轮新增的条目，返回新增条目的数量
+fn mark_new_items(summary: &mut RssSummary, previous_links: &HashSet<String>) -> usize {
+    let mut new_count = 0;
+    for item in summary.items.iter_mut() {
+        item.is_new = !previous_links.contains(&item.link);
+        if item.is_new {
+            new_count += 1;
+        }
+    }
+    new_count
+}
+
+// 使用向量相似度按用户兴趣重新排序摘要条目
+// 综合得分 = (1 - weight) * 模型相关性得分 + weight * 余弦相似度，结果写回 relevance_score
+async fn rerank_by_interest<M: EmbeddingModel>(
+    model: &M,
+    summary: &mut RssSummary,
+    interest: &str,
+    weight: f32,
+) -> Result<(), Box<dyn Error>> {
+    if summary.items.is_empty() {
+        return Ok(());
+    }
+
+    let interest_embedding = model.embed_text(interest).await?;
+    let texts: Vec<String> = summary
+        .items
+        .iter()
+        .map(|item| format!("{}. {}", item.title, item.summary))
+        .collect();
+    let item_embeddings = model.embed_texts(texts).await?;
+
+    for (item, embedding) in summary.items.iter_mut().zip(item_embeddings.iter()) {
+        let similarity = embedding.cosine_similarity(&interest_embedding, false) as f32;
+        // 零向量会得到 NaN，按不相关处理
+        let similarity = if similarity.is_nan() { 0.0 } else { similarity };
+        item.interest_similarity = Some(similarity);
+        item.relevance_score = (1.0 - weight) * item.relevance_score + weight * similarity;
+    }
+
+    summary
+        .items
+        .sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    Ok(())
+}
+
+// 格式化发布日期，未知日期显示为 "unknown date"
+fn format_pub_date(pub_date: Option<DateTime<Utc>>) -> String {
+    match pub_date {
+        Some(date) => date.to_string(),
+        None => "unknown date".to_string(),
+    }
+}
+
 // 定义一个函数，用于美化打印RSS摘要信息
-fn pretty_print_summary(summary: &RssSummary) {
+// show_new 为 true 时会打印新增条目数量，并在新增条目前加上 [NEW] 标记
+fn pretty_print_summary(summary: &RssSummary, show_new: bool) {
     // 打印RSS摘要的标题
     println!("RSS Feed Summary:");
     // 打印总项目数
     println!("Total Items: {}", summary.total_count);
+    // 打印本轮新增的项目数
+    if show_new {
+        let new_count = summary.items.iter().filter(|item| item.is_new).count();
+        println!("{} new items", new_count);
+    }
     // 打印提取时间
     println!("Extraction Time: {}", summary.extraction_time);
     // 打印顶级项目列表的标题
@@ -39,15 +480,27 @@ fn pretty_print_summary(summary: &RssSummary) {
     // 遍历摘要中的项目列表
     for (i, item) in summary.items.iter().enumerate() {
         // 打印项目编号和标题
-        println!("{}. {}", i + 1, item.title);
+        if show_new && item.is_new {
+            println!("{}. [NEW] {}", i + 1, item.title);
+        } else {
+            println!("{}. {}", i + 1, item.title);
+        }
         // 打印项目的链接
         println!("   Link: {}", item.link);
         // 打印项目的发布日期
-        println!("   Published: {}", item.pub_date);
+        println!("   Published: {}", format_pub_date(item.pub_date));
         // 打印项目的摘要
         println!("   Summary: {}", item.summary);
         // 打印项目的相关性得分，保留两位小数
         println!("   Relevance Score: {:.2}", item.relevance_score);
+        // 解释模式下打印打分理由
+        if !item.score_reason.is_empty() {
+            println!("   Score Reason: {}", item.score_reason);
+        }
+        // 开启向量重排时打印与兴趣描述的相似度
+        if let Some(similarity) = item.interest_similarity {
+            println!("   Interest Similarity: {:.2}", similarity);
+        }
         // 打印空行以分隔不同项目
         println!();
     }
@@ -55,20 +508,94 @@ fn pretty_print_summary(summary: &RssSummary) {
     println!("Overall Summary: {}", summary.overall_summary);
 }
 
+// 订阅源响应体的默认大小上限（5 MB），防止异常的订阅源耗尽内存
+const DEFAULT_MAX_BODY_BYTES: usize = 5 * 1024 * 1024;
+
 // 异步函数，用于从给定的URL获取RSS订阅源
-async fn fetch_rss_feed(url: &str) -> Result<Channel, Box<dyn Error>> {
+// max_body_bytes 是允许下载的最大响应体字节数，超过时中止下载并返回错误
+async fn fetch_rss_feed(url: &str, max_body_bytes: usize) -> Result<Channel, Box<dyn Error>> {
     // 使用reqwest库发送HTTP GET请求到指定的URL
-    // await关键字用于等待异步操作的完成
     // ?操作符用于传播错误，如果请求失败，将返回错误
-    let response = reqwest::get(url).await?.text().await?;
-    // 尝试将响应文本解析为Channel类型
-    // parse方法用于将字符串解析为特定的数据结构
+    let response = reqwest::get(url).await?;
+    // 以流的方式读取响应体，边读边检查大小
+    let body = read_body_limited(response, max_body_bytes).await?;
+    // 尝试将响应内容解析为Channel类型
     // ?操作符用于传播错误，如果解析失败，将返回错误
-    let channel = response.parse::<Channel>()?;
+    let channel = Channel::read_from(&body[..])?;
     // 如果一切顺利，返回解析后的Channel对象
     Ok(channel)
 }
 
+// 分块读取响应体，累计大小超过 max_body_bytes 时立即返回错误
+async fn read_body_limited(
+    mut response: reqwest::Response,
+    max_body_bytes: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let limit_error = || format!("feed body exceeds the limit of {} bytes", max_body_bytes);
+
+    // 服务器声明的长度已经超过上限时无需下载
+    if let Some(length) = response.content_length() {
+        if length > max_body_bytes as u64 {
+            return Err(limit_error().into());
+        }
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if body.len() + chunk.len() > max_body_bytes {
+            return Err(limit_error().into());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+// 从HTML中提取 <link rel="alternate"> 声明的RSS/Atom订阅源地址
+// 相对地址按 base_url 解析为绝对地址，重复的地址只保留一次
+fn extract_feed_links(html: &str, base_url: &str) -> Vec<String> {
+    let re_link = Regex::new(r"(?is)<link\b[^>]*>").unwrap();
+    let re_attr = Regex::new(r#"(?is)\b(rel|type|href)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+    let base = reqwest::Url::parse(base_url).ok();
+
+    let mut feeds = Vec::new();
+    for tag in re_link.find_iter(html) {
+        let (mut rel, mut kind, mut href) = (String::new(), String::new(), None);
+        for attr in re_attr.captures_iter(tag.as_str()) {
+            let value = attr
+                .get(2)
+                .or_else(|| attr.get(3))
+                .map_or("", |m| m.as_str());
+            match attr[1].to_ascii_lowercase().as_str() {
+                "rel" => rel = value.to_ascii_lowercase(),
+                "type" => kind = value.trim().to_ascii_lowercase(),
+                _ => href = Some(value.trim().to_string()),
+            }
+        }
+
+        let is_alternate = rel.split_whitespace().any(|r| r == "alternate");
+        let is_feed = kind == "application/rss+xml" || kind == "application/atom+xml";
+        if let (true, true, Some(href)) = (is_alternate, is_feed, href) {
+            // 能解析成绝对地址就使用绝对地址，否则保留原始的 href
+            let url = match &base {
+                Some(base) => base.join(&href).map(|u| u.to_string()).unwrap_or(href),
+                None => href,
+            };
+            if !feeds.contains(&url) {
+                feeds.push(url);
+            }
+        }
+    }
+    feeds
+}
+
+// 下载网站首页并返回其中声明的候选订阅源地址
+async fn discover_feed(url: &str, max_body_bytes: usize) -> Result<Vec<String>, Box<dyn Error>> {
+    let response = reqwest::get(url).await?;
+    let body = read_body_limited(response, max_body_bytes).await?;
+    let html = String::from_utf8_lossy(&body);
+    Ok(extract_feed_links(&html, url))
+}
+
 // 定义一个名为 sanitize_string 的函数，接受一个字符串切片作为输入，返回一个字符串
 fn sanitize_string(input: &str) -> String {
     // 将输入字符串转换为可变的字符串类型
@@ -83,73 +610,1052 @@ fn sanitize_string(input: &str) -> String {
     sanitized
 }
 
-// 异步函数，用于从RSS频道中提取摘要
-async fn summarize_rss_feed(channel: Channel) -> Result<RssSummary, Box<dyn Error>> {
-    // 创建一个OpenAI客户端
-    let openai_client = Client::from_env();
+// 解析条目的发布时间，RSS 使用 RFC2822，部分订阅源使用 RFC3339
+fn parse_pub_date(raw: &str) -> Option<DateTime<Utc>> {
+    let raw = raw.trim();
+    DateTime::parse_from_rfc2822(raw)
+        .or_else(|_| DateTime::parse_from_rfc3339(raw))
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
 
-    // 创建一个提取器，指定模型和前导文本
-    let extractor = openai_client
-        .extractor::<RssSummary>("gpt-4o-mini-2024-07-18")
-        .preamble("You are an AI assistant specialized in summarizing RSS feeds. \
-                   Your task is to analyze the RSS items, extract the most relevant information, \
-                   and provide concise summaries. For each item, provide a brief summary and a \
-                   relevance score from 0.0 to 1.0. Also, provide an overall summary of the feed.")
-        .build();
+// 只保留发布时间晚于 since 的条目，没有可解析日期的条目无法判断新旧，一律保留
+fn items_since(items: &[Item], since: Option<DateTime<Utc>>) -> Vec<Item> {
+    items
+        .iter()
+        .filter(
+            |item| match (since, item.pub_date().and_then(parse_pub_date)) {
+                (Some(since), Some(date)) => date > since,
+                _ => true,
+            },
+        )
+        .cloned()
+        .collect()
+}
 
-    // 创建一个包含所有摘要的向量
-    let rss_items = channel.items();
-    let mut formatted_rss = String::new();
+// 条目中最新的发布时间
+fn newest_pub_date(items: &[Item]) -> Option<DateTime<Utc>> {
+    items
+        .iter()
+        .filter_map(|item| item.pub_date().and_then(parse_pub_date))
+        .max()
+}
 
-    // 创建一个包含所有摘要的向量
+// 订阅源最新条目早于 stale_after 时返回提示信息；没有可解析日期的订阅源无法判断，不提示
+fn staleness_warning(
+    rss_url: &str,
+    items: &[Item],
+    now: DateTime<Utc>,
+    stale_after: TimeDelta,
+) -> Option<String> {
+    let newest = newest_pub_date(items)?;
+    if now - newest > stale_after {
+        Some(format!(
+            "Feed {} looks stale: newest item is from {} ({} days ago)",
+            rss_url,
+            newest.format("%Y-%m-%d"),
+            (now - newest).num_days()
+        ))
+    } else {
+        None
+    }
+}
+
+// 读取保存的每个订阅源的最新发布时间，文件不存在时视为空
+fn load_since_state(path: &Path) -> Result<HashMap<String, DateTime<Utc>>, Box<dyn Error>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+// 保存每个订阅源的最新发布时间
+fn save_since_state(
+    path: &Path,
+    state: &HashMap<String, DateTime<Utc>>,
+) -> Result<(), Box<dyn Error>> {
+    fs::write(path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+// 清洗后的RSS条目，各字段已去除HTML标签和特殊字符
+#[derive(Debug, Clone)]
+struct CleanItem {
+    title: String,
+    link: String,
+    pub_date: String, // 没有发布日期时为 "unknown"
+    description: String,
+    tags: Vec<String>, // 标题或描述中命中的关键词
+}
+
+// 清洗RSS条目
+fn clean_items(rss_items: &[Item]) -> Vec<CleanItem> {
+    // 用于去除HTML标签和CDATA片段的正则表达式
     let re_html = Regex::new(r"(?i)<[^>]*>").unwrap();
     let re_cdata = Regex::new(r"(?i)<!\[CDATA\[.*?\]\]>").unwrap();
 
-    for (i, item) in rss_items.iter().enumerate() {
-        let title = item.title().unwrap_or("").to_string();
-        let link = item.link().unwrap_or("").to_string();
-        let pub_date = item.pub_date().unwrap_or("").to_string();
-        let description = item.description().unwrap_or("").to_string();
+    rss_items
+        .iter()
+        .map(|item| {
+            // 没有发布日期时明确告诉模型日期未知
+            let pub_date = match item.pub_date() {
+                Some(date) if !date.trim().is_empty() => date,
+                _ => "unknown",
+            };
+            let description = item.description().unwrap_or("");
+            // 提取摘要
+            let clean_description = re_html
+                .replace_all(&re_cdata.replace_all(description, ""), "")
+                .to_string();
+
+            CleanItem {
+                title: sanitize_string(item.title().unwrap_or("")),
+                link: sanitize_string(item.link().unwrap_or("")),
+                pub_date: sanitize_string(pub_date),
+                description: sanitize_string(&clean_description),
+                tags: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+// 给每个条目标记标题或描述中出现的关键词：不区分大小写，且必须是完整的词（"rust" 不匹配 "trust"）
+fn tag_items(items: &mut [CleanItem], keywords: &[String]) {
+    // 关键词两侧必须是字符串边界或非单词字符，这样 "c++" 这类关键词也能匹配
+    let patterns: Vec<(&String, Regex)> = keywords
+        .iter()
+        .map(|keyword| {
+            let pattern = format!(r"(?i)(?:^|\W){}(?:$|\W)", regex::escape(keyword));
+            (keyword, Regex::new(&pattern).unwrap())
+        })
+        .collect();
 
-        // 提取摘要
-        let clean_description = re_html.replace_all(&re_cdata.replace_all(&description, ""), "").to_string();
-        let sanitized_description = sanitize_string(&clean_description);
+    for item in items.iter_mut() {
+        item.tags = patterns
+            .iter()
+            .filter(|(_, re)| re.is_match(&item.title) || re.is_match(&item.description))
+            .map(|(keyword, _)| keyword.to_string())
+            .collect();
+    }
+}
 
+// 将清洗后的条目格式化为发送给模型的提示文本，命中关键词时一并告诉模型
+fn format_clean_items(items: &[CleanItem]) -> String {
+    let mut formatted_rss = String::new();
+    for (i, item) in items.iter().enumerate() {
         formatted_rss.push_str(&format!(
-            "{}. Title: {}\nLink: {}\nDate: {}\nDescription: {}\n\n",
+            "{}. Title: {}\nLink: {}\nDate: {}\nDescription: {}\n",
             i + 1,
-            sanitize_string(&title),
-            sanitize_string(&link),
-            sanitize_string(&pub_date),
-            sanitized_description
+            item.title,
+            item.link,
+            item.pub_date,
+            item.description
         ));
+        if !item.tags.is_empty() {
+            formatted_rss.push_str(&format!("Keywords: {}\n", item.tags.join(", ")));
+        }
+        formatted_rss.push('\n');
     }
+    formatted_rss
+}
+
+// 摘要提取器的前导文本，只有开启解释模式时才要求模型给出打分理由，以节省 token
+fn summary_preamble(explain: bool) -> String {
+    let mut preamble = String::from(
+        "You are an AI assistant specialized in summarizing RSS feeds. \
+         Your task is to analyze the RSS items, extract the most relevant information, \
+         and provide concise summaries. For each item, provide a brief summary and a \
+         relevance score from 0.0 to 1.0. Also, provide an overall summary of the feed. \
+         If an item's date is unknown, set its pub_date to null instead of guessing.",
+    );
+    if explain {
+        preamble.push_str(
+            " For each item, also set score_reason to a one-line justification of its relevance score.",
+        );
+    } else {
+        preamble.push_str(" Leave score_reason empty.");
+    }
+    preamble
+}
+
+// 异步函数，用于从格式化后的RSS条目中提取摘要
+async fn summarize_rss_feed(
+    openai_client: &Client,
+    model: &str,
+    formatted_rss: &str,
+    explain: bool,
+) -> Result<RssSummary, Box<dyn Error>> {
+    // 创建一个提取器，指定模型和前导文本
+    let extractor = openai_client
+        .extractor::<RssSummary>(model)
+        .preamble(&summary_preamble(explain))
+        .build();
 
     println!("Extracting summary from the RSS feed...\n");
 
-    let rss_summary = extractor.extract(&formatted_rss).await?;
+    let rss_summary = extractor.extract(formatted_rss).await?;
 
     Ok(rss_summary)
 }
 
+// 将订阅源URL转换为可用作文件名的片段
+fn feed_file_stem(feed_url: &str) -> String {
+    let stem = feed_url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://");
+    stem.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
+
+// 将本轮的提示文本和模型返回的摘要写入审计目录，文件名按UTC时间戳和订阅源命名
+fn write_audit_files(
+    audit_dir: &Path,
+    feed_url: &str,
+    timestamp: DateTime<Utc>,
+    prompt: &str,
+    summary: &RssSummary,
+) -> Result<(PathBuf, PathBuf), Box<dyn Error>> {
+    fs::create_dir_all(audit_dir)?;
+
+    let base_name = format!(
+        "{}_{}",
+        timestamp.format("%Y%m%dT%H%M%SZ"),
+        feed_file_stem(feed_url)
+    );
+    let prompt_path = audit_dir.join(format!("{}_prompt.txt", base_name));
+    let summary_path = audit_dir.join(format!("{}_summary.json", base_name));
+
+    fs::write(&prompt_path, prompt)?;
+    fs::write(&summary_path, serde_json::to_string_pretty(summary)?)?;
+
+    Ok((prompt_path, summary_path))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let rss_url = "https://news.ycombinator.com/rss";
-    let mut interval = time::interval(Duration::from_secs(3600)); // 1 hour interval
-
-    loop {
-        interval.tick().await;
-        
-        match fetch_rss_feed(rss_url).await {
-            Ok(channel) => {
-                match summarize_rss_feed(channel).await {
-                    Ok(rss_summary) => {
-                        pretty_print_summary(&rss_summary);
+    let options = Options::parse(std::env::args().skip(1))?;
+    let config = match &options.config {
+        Some(path) => load_config(path)?,
+        None => Config::default(),
+    };
+    let openai = OpenAiSettings::resolve(&config.openai, |name| std::env::var(name).ok())?;
+    let mut feeds = options.feeds.clone();
+    // 给出的是网站首页时，使用自动发现的第一个订阅源
+    if let Some(site) = &options.site {
+        let discovered = discover_feed(site, options.max_body_bytes).await?;
+        println!("Discovered {} feed(s) on {}", discovered.len(), site);
+        let feed = discovered
+            .into_iter()
+            .next()
+            .ok_or_else(|| format!("no RSS or Atom feed found on {}", site))?;
+        feeds.push(feed);
+    }
+    if feeds.is_empty() {
+        feeds.push(DEFAULT_FEED_URL.to_string());
+    }
+
+    let intervals = feeds.iter().map(|url| options.interval_for(url)).collect();
+    let mut scheduler = FeedScheduler::new(intervals);
+    // 每个订阅源上一轮摘要中出现过的链接，用于判断哪些条目是新增的
+    let mut previous_links: Vec<HashSet<String>> = vec![HashSet::new(); feeds.len()];
+    // 每个订阅源已经见过的最新发布时间
+    let mut seen_until = match &options.since_file {
+        Some(path) => load_since_state(path)?,
+        None => HashMap::new(),
+    };
+    let mut budget = LlmBudget::new(options.max_llm_calls, options.max_tokens);
+    let start = time::Instant::now();
+
+    while let Some(due) = scheduler.next_due() {
+        time::sleep_until(start + due).await;
+
+        for index in scheduler.pop_due(start.elapsed()) {
+            process_feed(
+                &feeds[index],
+                &options,
+                &mut previous_links[index],
+                &mut seen_until,
+                &mut budget,
+                &openai,
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+// 条目中出现的全部链接
+fn item_links(items: &[Item]) -> HashSet<String> {
+    items
+        .iter()
+        .filter_map(|item| item.link().map(|link| link.to_string()))
+        .collect()
+}
+
+// 记录订阅源已经处理到的最新发布时间，设置了 --since-file 时同时保存到文件
+fn record_since(
+    rss_url: &str,
+    since: Option<DateTime<Utc>>,
+    items: &[Item],
+    options: &Options,
+    seen_until: &mut HashMap<String, DateTime<Utc>>,
+) {
+    if !options.tracks_since() {
+        return;
+    }
+    if let Some(newest) = since.max(newest_pub_date(items)) {
+        seen_until.insert(rss_url.to_string(), newest);
+    }
+    if let Some(path) = &options.since_file {
+        if let Err(e) = save_since_state(path, seen_until) {
+            eprintln!("Error saving since state: {}", e);
+        }
+    }
+}
+
+// 完成一个订阅源的一轮处理：下载、摘要、重排、审计和打印，错误只打印不中断
+async fn process_feed(
+    rss_url: &str,
+    options: &Options,
+    previous_links: &mut HashSet<String>,
+    seen_until: &mut HashMap<String, DateTime<Utc>>,
+    budget: &mut LlmBudget,
+    openai: &OpenAiSettings,
+) {
+    match fetch_rss_feed(rss_url, options.max_body_bytes).await {
+        Ok(channel) => {
+            if let Some(warning) =
+                staleness_warning(rss_url, channel.items(), Utc::now(), options.stale_after)
+            {
+                eprintln!("⚠️ {}", warning);
+            }
+
+            // 截止时间取 --since 与已保存的最新发布时间中较晚的一个
+            let since = options.since.max(seen_until.get(rss_url).copied());
+            let items = items_since(channel.items(), since);
+            if items.is_empty() {
+                println!("No items newer than the last run in {}", rss_url);
+                return;
+            }
+
+            let mut cleaned = clean_items(&items);
+            if !options.keywords.is_empty() {
+                tag_items(&mut cleaned, &options.keywords);
+                // 在调用模型之前丢弃不相关的条目以节省 token
+                if options.require_keyword {
+                    cleaned.retain(|item| !item.tags.is_empty());
+                    if cleaned.is_empty() {
+                        println!("No items matching the keywords in {}", rss_url);
+                        // 这些条目已经处理过，下一轮不应再被当作新条目
+                        record_since(rss_url, since, &items, options, seen_until);
+                        if options.diff {
+                            *previous_links = item_links(&items);
+                        }
+                        return;
                     }
-                    Err(e) => eprintln!("Error summarizing RSS feed: {}", e),
                 }
             }
-            Err(e) => eprintln!("Error fetching RSS feed: {}", e),
+            let formatted_rss = format_clean_items(&cleaned);
+            let explain = options.explain;
+            let (client, model) = (openai.client(), openai.model.clone());
+            let summarized = summarize_with_budget(budget, &formatted_rss, |prompt| async move {
+                summarize_rss_feed(&client, &model, &prompt, explain).await
+            })
+            .await;
+            let Some(summarized) = summarized else {
+                // 预算用完后只继续下载和去重，不再调用模型
+                eprintln!("LLM budget exhausted, skipping summary for {}", rss_url);
+                if options.diff {
+                    *previous_links = item_links(&items);
+                }
+                return;
+            };
+            match summarized {
+                Ok(mut rss_summary) => {
+                    record_since(rss_url, since, &items, options, seen_until);
+                    if let Some(interest) = &options.interest {
+                        let embedding_model = openai
+                            .client()
+                            .embedding_model(openai::TEXT_EMBEDDING_3_SMALL);
+                        if let Err(e) = rerank_by_interest(
+                            &embedding_model,
+                            &mut rss_summary,
+                            interest,
+                            options.embedding_weight,
+                        )
+                        .await
+                        {
+                            eprintln!("Error re-ranking by interest: {}", e);
+                        }
+                    }
+                    if let Some(audit_dir) = &options.audit_dir {
+                        if let Err(e) = write_audit_files(
+                            audit_dir,
+                            rss_url,
+                            Utc::now(),
+                            &formatted_rss,
+                            &rss_summary,
+                        ) {
+                            eprintln!("Error writing audit files: {}", e);
+                        }
+                    }
+                    if options.diff {
+                        mark_new_items(&mut rss_summary, previous_links);
+                        *previous_links = rss_summary
+                            .items
+                            .iter()
+                            .map(|item| item.link.clone())
+                            .collect();
+                    }
+                    pretty_print_summary(&rss_summary, options.diff);
+                }
+                Err(e) => eprintln!("Error summarizing RSS feed {}: {}", rss_url, e),
+            }
+        }
+        Err(e) => eprintln!("Error fetching RSS feed {}: {}", rss_url, e),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(link: &str) -> SummarizedRssItem {
+        SummarizedRssItem {
+            title: format!("Title for {}", link),
+            link: link.to_string(),
+            pub_date: Some(Utc::now()),
+            summary: String::new(),
+            relevance_score: 0.5,
+            score_reason: String::new(),
+            is_new: false,
+            interest_similarity: None,
+        }
+    }
+
+    // 测试用的向量模型：按关键词出现次数生成向量，不访问网络
+    #[derive(Clone)]
+    struct KeywordEmbedder;
+
+    impl EmbeddingModel for KeywordEmbedder {
+        const MAX_DOCUMENTS: usize = 16;
+
+        fn ndims(&self) -> usize {
+            2
+        }
+
+        async fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> Result<Vec<rig::embeddings::Embedding>, rig::embeddings::EmbeddingError> {
+            Ok(texts
+                .into_iter()
+                .map(|text| {
+                    let lower = text.to_lowercase();
+                    rig::embeddings::Embedding {
+                        vec: vec![
+                            lower.matches("rust").count() as f64,
+                            lower.matches("python").count() as f64,
+                        ],
+                        document: text,
+                    }
+                })
+                .collect())
+        }
+    }
+
+    fn summary(links: &[&str]) -> RssSummary {
+        RssSummary {
+            items: links.iter().map(|link| item(link)).collect(),
+            total_count: links.len(),
+            extraction_time: Utc::now().to_rfc3339(),
+            overall_summary: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_mark_new_items_across_cycles() {
+        let mut previous_links = HashSet::new();
+
+        // 第一轮：所有条目都是新的
+        let mut first = summary(&["https://a", "https://b"]);
+        assert_eq!(mark_new_items(&mut first, &previous_links), 2);
+        previous_links = first.items.iter().map(|item| item.link.clone()).collect();
+
+        // 第二轮：b 是沿用的条目，只有 c 是新增的
+        let mut second = summary(&["https://b", "https://c"]);
+        assert_eq!(mark_new_items(&mut second, &previous_links), 1);
+        assert!(!second.items[0].is_new);
+        assert!(second.items[1].is_new);
+    }
+
+    #[test]
+    fn test_write_audit_files() {
+        let audit_dir = std::env::temp_dir().join(format!("rig_rss_audit_{}", std::process::id()));
+        let timestamp = DateTime::parse_from_rfc3339("2024-05-01T12:30:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let prompt = "1. Title: Hello\nLink: https://a\n";
+        let rss_summary = summary(&["https://a"]);
+
+        let (prompt_path, summary_path) = write_audit_files(
+            &audit_dir,
+            "https://news.ycombinator.com/rss",
+            timestamp,
+            prompt,
+            &rss_summary,
+        )
+        .unwrap();
+
+        assert_eq!(
+            prompt_path.file_name().unwrap(),
+            "20240501T123000Z_news_ycombinator_com_rss_prompt.txt"
+        );
+        assert_eq!(fs::read_to_string(&prompt_path).unwrap(), prompt);
+        let written: RssSummary =
+            serde_json::from_str(&fs::read_to_string(&summary_path).unwrap()).unwrap();
+        assert_eq!(written.items.len(), 1);
+        assert_eq!(written.items[0].link, "https://a");
+
+        fs::remove_dir_all(&audit_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rerank_by_interest_prefers_closer_items() {
+        let mut rss_summary = summary(&["https://python", "https://rust"]);
+        rss_summary.items[0].title = "Python 3.13 released".to_string();
+        rss_summary.items[0].relevance_score = 0.9;
+        rss_summary.items[1].title = "Rust 1.80 released".to_string();
+        rss_summary.items[1].relevance_score = 0.3;
+
+        rerank_by_interest(&KeywordEmbedder, &mut rss_summary, "rust", 1.0)
+            .await
+            .unwrap();
+
+        assert_eq!(rss_summary.items[0].link, "https://rust");
+        assert!((rss_summary.items[0].interest_similarity.unwrap() - 1.0).abs() < 1e-6);
+        assert_eq!(rss_summary.items[1].interest_similarity, Some(0.0));
+    }
+
+    #[test]
+    fn test_item_without_pub_date() {
+        let channel = rss::ChannelBuilder::default()
+            .title("Test feed")
+            .items(vec![rss::ItemBuilder::default()
+                .title(Some("No date".to_string()))
+                .link(Some("https://a".to_string()))
+                .build()])
+            .build();
+        let formatted = format_clean_items(&clean_items(channel.items()));
+        assert!(formatted.contains("Date: unknown\n"));
+
+        // 模型对未知日期返回 null 时可以正常解析，并显示为 unknown date
+        let parsed: SummarizedRssItem = serde_json::from_str(
+            r#"{"title":"No date","link":"https://a","pub_date":null,"summary":"s","relevance_score":0.4}"#,
+        )
+        .unwrap();
+        assert_eq!(parsed.pub_date, None);
+        assert_eq!(format_pub_date(parsed.pub_date), "unknown date");
+        // 未开启解释模式时模型不返回打分理由，默认为空
+        assert_eq!(parsed.score_reason, "");
+    }
+
+    #[test]
+    fn test_explain_mode_populates_score_reasons() {
+        assert!(summary_preamble(true).contains("one-line justification"));
+        assert!(!summary_preamble(false).contains("one-line justification"));
+
+        let summary: RssSummary = serde_json::from_str(
+            r#"{"items":[
+                {"title":"Rust 2.0","link":"https://a","pub_date":null,"summary":"s",
+                 "relevance_score":0.9,"score_reason":"Major release of a core tool"},
+                {"title":"Cat pictures","link":"https://b","pub_date":null,"summary":"s",
+                 "relevance_score":0.1,"score_reason":"Off-topic"}
+            ],"total_count":2,"extraction_time":"2024-01-02T10:00:00Z","overall_summary":"o"}"#,
+        )
+        .unwrap();
+        let reasons: Vec<&str> = summary
+            .items
+            .iter()
+            .map(|i| i.score_reason.as_str())
+            .collect();
+        assert_eq!(reasons, vec!["Major release of a core tool", "Off-topic"]);
+
+        let options = Options::parse(vec!["--explain".to_string()].into_iter()).unwrap();
+        assert!(options.explain);
+        assert!(!Options::default().explain);
+    }
+
+    fn dated_item(title: &str, pub_date: Option<&str>) -> Item {
+        rss::ItemBuilder::default()
+            .title(Some(title.to_string()))
+            .pub_date(pub_date.map(|d| d.to_string()))
+            .build()
+    }
+
+    #[test]
+    fn test_items_since_keeps_newer_and_undated_items() {
+        let items = vec![
+            dated_item("old", Some("Mon, 01 Jan 2024 08:00:00 GMT")),
+            dated_item("new", Some("Wed, 03 Jan 2024 08:00:00 GMT")),
+            dated_item("rfc3339", Some("2024-01-04T08:00:00Z")),
+            dated_item("undated", None),
+            dated_item("garbage", Some("yesterday")),
+        ];
+        let since = DateTime::parse_from_rfc3339("2024-01-02T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        let kept = items_since(&items, Some(since));
+        let titles: Vec<&str> = kept.iter().filter_map(|item| item.title()).collect();
+        assert_eq!(titles, vec!["new", "rfc3339", "undated", "garbage"]);
+
+        let formatted = format_clean_items(&clean_items(&kept));
+        assert!(!formatted.contains("Title: old"));
+        assert!(formatted.contains("Title: new"));
+
+        assert_eq!(items_since(&items, None).len(), items.len());
+        assert_eq!(
+            newest_pub_date(&items),
+            Some(
+                DateTime::parse_from_rfc3339("2024-01-04T08:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+    }
+
+    #[test]
+    fn test_staleness_warning() {
+        let now = DateTime::parse_from_rfc3339("2024-03-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let old_feed = vec![
+            dated_item("older", Some("Mon, 01 Jan 2024 08:00:00 GMT")),
+            dated_item("newest", Some("Wed, 10 Jan 2024 08:00:00 GMT")),
+        ];
+        let warning = staleness_warning("https://a/rss", &old_feed, now, TimeDelta::days(30))
+            .expect("stale feed should warn");
+        assert!(warning.contains("https://a/rss"));
+        assert!(warning.contains("2024-01-10"));
+
+        // 阈值放宽后不再提示
+        assert!(staleness_warning("https://a/rss", &old_feed, now, TimeDelta::days(60)).is_none());
+        // 没有日期的订阅源无法判断
+        let undated = vec![dated_item("undated", None)];
+        assert!(staleness_warning("https://a/rss", &undated, now, TimeDelta::days(1)).is_none());
+    }
+
+    #[test]
+    fn test_since_state_round_trip() {
+        let dir = std::env::temp_dir().join(format!("rig_rss_since_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("since.json");
+        assert!(load_since_state(&path).unwrap().is_empty());
+
+        let mut state = HashMap::new();
+        state.insert("https://a/rss".to_string(), Utc::now());
+        save_since_state(&path, &state).unwrap();
+        assert_eq!(load_since_state(&path).unwrap(), state);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 启动一个只处理一次请求的本地HTTP服务器，返回其URL
+    async fn serve_once(body: Vec<u8>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = socket.read(&mut request).await;
+            // 不发送 Content-Length，强制客户端按流读取直到连接关闭
+            let header =
+                "HTTP/1.1 200 OK\r\nContent-Type: application/rss+xml\r\nConnection: close\r\n\r\n";
+            let _ = socket.write_all(header.as_bytes()).await;
+            let _ = socket.write_all(&body).await;
+            let _ = socket.shutdown().await;
+        });
+        format!("http://{}/rss", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rss_feed_rejects_oversized_body() {
+        let url = serve_once(vec![b'a'; 64 * 1024]).await;
+        let err = fetch_rss_feed(&url, 1024).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds the limit of 1024 bytes"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_rss_feed_within_limit() {
+        let feed = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>T</title><link>https://a</link><description>D</description><item><title>One</title></item></channel></rss>"#;
+        let url = serve_once(feed.as_bytes().to_vec()).await;
+        let channel = fetch_rss_feed(&url, DEFAULT_MAX_BODY_BYTES).await.unwrap();
+        assert_eq!(channel.items().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_keyword_filtered_round_records_seen_items() {
+        let feed = r#"<?xml version="1.0"?><rss version="2.0"><channel><title>T</title><link>https://a</link><description>D</description><item><title>Cooking</title><link>https://a/1</link><pubDate>Wed, 03 Jan 2024 08:00:00 GMT</pubDate></item></channel></rss>"#;
+        let url = serve_once(feed.as_bytes().to_vec()).await;
+        let args = [
+            "--keyword",
+            "rust",
+            "--require-keyword",
+            "--diff",
+            "--since",
+            "2024-01-01T00:00:00Z",
+        ];
+        let options = Options::parse(args.iter().map(|arg| arg.to_string())).unwrap();
+        let openai = OpenAiSettings {
+            api_key: "sk-test".to_string(),
+            base_url: "http://127.0.0.1:9/v1".to_string(),
+            model: DEFAULT_MODEL.to_string(),
+        };
+        let mut previous_links = HashSet::new();
+        let mut seen_until = HashMap::new();
+        let mut budget = LlmBudget::new(None, None);
+        process_feed(
+            &url,
+            &options,
+            &mut previous_links,
+            &mut seen_until,
+            &mut budget,
+            &openai,
+        )
+        .await;
+
+        // 全部条目被关键词过滤掉时，仍然记录已见过的链接和发布时间
+        assert_eq!(previous_links, HashSet::from(["https://a/1".to_string()]));
+        assert_eq!(
+            seen_until.get(&url).copied(),
+            Some(
+                DateTime::parse_from_rfc3339("2024-01-03T08:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc)
+            )
+        );
+    }
+
+    #[test]
+    fn test_extract_feed_links() {
+        let html = r#"<html><head>
+            <link rel="stylesheet" href="/style.css">
+            <link rel="alternate" type="application/rss+xml" title="RSS" href="/feed.xml">
+            <LINK REL='alternate' TYPE='application/atom+xml' HREF='https://example.com/atom.xml' />
+            <link rel="alternate" type="text/html" href="/fr/">
+        </head><body></body></html>"#;
+        let feeds = extract_feed_links(html, "https://example.com/blog/");
+        assert_eq!(
+            feeds,
+            vec![
+                "https://example.com/feed.xml".to_string(),
+                "https://example.com/atom.xml".to_string(),
+            ]
+        );
+        assert!(extract_feed_links("<html></html>", "https://example.com/").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_discover_feed() {
+        let html = br#"<link rel="alternate" type="application/rss+xml" href="feed.xml">"#;
+        let url = serve_once(html.to_vec()).await;
+        let feeds = discover_feed(&url, DEFAULT_MAX_BODY_BYTES).await.unwrap();
+        assert_eq!(feeds, vec![url.replace("/rss", "/feed.xml")]);
+    }
+
+    #[test]
+    fn test_scheduler_polls_feeds_at_their_own_rates() {
+        let mut scheduler =
+            FeedScheduler::new(vec![Duration::from_secs(10), Duration::from_secs(60)]);
+        let mut polls = [0, 0];
+
+        // 模拟时钟：每次直接跳到下一个到期时间
+        while let Some(due) = scheduler.next_due() {
+            if due > Duration::from_secs(120) {
+                break;
+            }
+            for index in scheduler.pop_due(due) {
+                polls[index] += 1;
+            }
         }
+        // 快的订阅源在 0,10,...,120 共13次，慢的在 0,60,120 共3次
+        assert_eq!(polls, [13, 3]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_scheduler_pop_due_only_returns_due_feeds() {
+        let mut scheduler =
+            FeedScheduler::new(vec![Duration::from_secs(5), Duration::from_secs(30)]);
+        assert_eq!(scheduler.pop_due(Duration::ZERO), vec![0, 1]);
+        assert!(scheduler.pop_due(Duration::from_secs(4)).is_empty());
+        assert_eq!(scheduler.pop_due(Duration::from_secs(5)), vec![0]);
+        assert_eq!(scheduler.next_due(), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn test_scheduler_skips_missed_polls_after_clock_jump() {
+        let mut scheduler =
+            FeedScheduler::new(vec![Duration::from_secs(10), Duration::from_secs(60)]);
+        assert_eq!(scheduler.pop_due(Duration::ZERO), vec![0, 1]);
+        // 时钟一次跳过了快订阅源的十几个间隔，每个订阅源也只返回一次
+        assert_eq!(scheduler.pop_due(Duration::from_secs(125)), vec![0, 1]);
+        // 下一次轮询保持原来的节奏，安排在 now 之后的第一个间隔点
+        assert_eq!(scheduler.next_due(), Some(Duration::from_secs(130)));
+        assert_eq!(scheduler.pop_due(Duration::from_secs(130)), vec![0]);
+        assert_eq!(scheduler.pop_due(Duration::from_secs(185)), vec![0, 1]);
+        assert_eq!(scheduler.next_due(), Some(Duration::from_secs(190)));
+    }
+
+    #[tokio::test]
+    async fn test_budget_stops_calling_summarizer() {
+        let mut budget = LlmBudget::new(Some(2), None);
+        let mut invocations = 0;
+        for cycle in 0..5 {
+            let result = summarize_with_budget(&mut budget, "prompt", |_| {
+                invocations += 1;
+                async { Ok(summary(&[])) }
+            })
+            .await;
+            assert_eq!(result.is_some(), cycle < 2);
+        }
+        // 两次之后不再调用模型
+        assert_eq!(invocations, 2);
+        assert_eq!(budget.calls, 2);
+    }
+
+    #[test]
+    fn test_token_budget() {
+        let mut budget = LlmBudget::new(None, Some(10));
+        // "a" * 20 约5个 token
+        let prompt = "a".repeat(20);
+        assert!(budget.try_spend(estimate_tokens(&prompt)));
+        assert!(budget.try_spend(estimate_tokens(&prompt)));
+        assert!(!budget.try_spend(estimate_tokens(&prompt)));
+        assert_eq!(budget.tokens, 10);
+        assert!(LlmBudget::default().try_spend(1_000_000));
+    }
+
+    #[test]
+    fn test_parse_diff_option() {
+        let options = Options::parse(vec!["--diff".to_string()].into_iter()).unwrap();
+        assert!(options.diff);
+        assert!(Options::parse(vec!["--bogus".to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_audit_dir_option() {
+        let options =
+            Options::parse(vec!["--audit-dir".to_string(), "audit".to_string()].into_iter())
+                .unwrap();
+        assert_eq!(options.audit_dir, Some(PathBuf::from("audit")));
+        assert!(Options::parse(vec!["--audit-dir".to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_parse_interest_option() {
+        let options = Options::parse(
+            vec!["--interest".to_string(), "rust compilers".to_string()].into_iter(),
+        )
+        .unwrap();
+        assert_eq!(options.interest.as_deref(), Some("rust compilers"));
+        assert_eq!(options.embedding_weight, DEFAULT_EMBEDDING_WEIGHT);
+        assert!(Options::parse(
+            vec!["--embedding-weight".to_string(), "1.5".to_string()].into_iter()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_site_option() {
+        let options = Options::parse(
+            vec!["--site".to_string(), "https://example.com".to_string()].into_iter(),
+        )
+        .unwrap();
+        assert_eq!(options.site.as_deref(), Some("https://example.com"));
+    }
+
+    #[test]
+    fn test_parse_feed_interval_option() {
+        let options = Options::parse(
+            vec![
+                "--feed".to_string(),
+                "https://a.example/rss".to_string(),
+                "--interval".to_string(),
+                "600".to_string(),
+                "--feed-interval".to_string(),
+                "https://b.example/feed?id=1=86400".to_string(),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(options.feeds, vec!["https://a.example/rss".to_string()]);
+        assert_eq!(
+            options.interval_for("https://a.example/rss"),
+            Duration::from_secs(600)
+        );
+        assert_eq!(
+            options.interval_for("https://b.example/feed?id=1"),
+            Duration::from_secs(86400)
+        );
+        assert!(
+            Options::parse(vec!["--interval".to_string(), "0".to_string()].into_iter()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_since_option() {
+        let options = Options::parse(
+            vec![
+                "--since".to_string(),
+                "2024-01-02T00:00:00+08:00".to_string(),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(
+            options.since.map(|since| since.to_rfc3339()),
+            Some("2024-01-01T16:00:00+00:00".to_string())
+        );
+        assert!(options.tracks_since());
+        assert!(
+            Options::parse(vec!["--since".to_string(), "soon".to_string()].into_iter()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_budget_options() {
+        let options = Options::parse(
+            vec![
+                "--max-llm-calls".to_string(),
+                "3".to_string(),
+                "--max-tokens".to_string(),
+                "50000".to_string(),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(options.max_llm_calls, Some(3));
+        assert_eq!(options.max_tokens, Some(50000));
+    }
+
+    #[test]
+    fn test_parse_stale_days_option() {
+        let options = Options::parse(std::iter::empty()).unwrap();
+        assert_eq!(options.stale_after, TimeDelta::days(DEFAULT_STALE_DAYS));
+
+        let options =
+            Options::parse(vec!["--stale-days".to_string(), "7".to_string()].into_iter()).unwrap();
+        assert_eq!(options.stale_after, TimeDelta::days(7));
+        for days in ["18446744073709551615", "9223372036854775807"] {
+            assert_eq!(
+                Options::parse(vec!["--stale-days".to_string(), days.to_string()].into_iter())
+                    .unwrap_err(),
+                format!("invalid --stale-days: {}", days)
+            );
+        }
+    }
+
+    #[test]
+    fn test_openai_settings_from_config() {
+        let dir = std::env::temp_dir().join(format!("rig_rss_config_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(
+            &path,
+            "[openai]\napi_key_env = \"MY_KEY\"\nbase_url = \"https://proxy.example/v1\"\nmodel = \"gpt-4o\"\n",
+        )
+        .unwrap();
+        let config = load_config(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let env = |name: &str| (name == "MY_KEY").then(|| "sk-test".to_string());
+        let settings = OpenAiSettings::resolve(&config.openai, env).unwrap();
+        assert_eq!(
+            settings,
+            OpenAiSettings {
+                api_key: "sk-test".to_string(),
+                base_url: "https://proxy.example/v1".to_string(),
+                model: "gpt-4o".to_string(),
+            }
+        );
+
+        // 环境变量优先于配置文件
+        let env = |name: &str| match name {
+            "MY_KEY" => Some("sk-test".to_string()),
+            "OPENAI_MODEL" => Some("gpt-4o-mini".to_string()),
+            _ => None,
+        };
+        let settings = OpenAiSettings::resolve(&config.openai, env).unwrap();
+        assert_eq!(settings.model, "gpt-4o-mini");
+        assert_eq!(settings.base_url, "https://proxy.example/v1");
+
+        // 未提供配置文件时使用默认值
+        let env = |name: &str| (name == DEFAULT_API_KEY_ENV).then(|| "sk".to_string());
+        let settings = OpenAiSettings::resolve(&Config::default().openai, env).unwrap();
+        assert_eq!(settings.base_url, DEFAULT_BASE_URL);
+        assert_eq!(settings.model, DEFAULT_MODEL);
+    }
+
+    #[test]
+    fn test_tag_and_filter_items_by_keyword() {
+        let items = vec![
+            rss::ItemBuilder::default()
+                .title(Some("Rust 1.80 released".to_string()))
+                .description(Some("<p>New <b>WASM</b> targets</p>".to_string()))
+                .build(),
+            rss::ItemBuilder::default()
+                .title(Some("In code we trust".to_string()))
+                .description(Some("An essay".to_string()))
+                .build(),
+            rss::ItemBuilder::default()
+                .title(Some("Why I still write C++".to_string()))
+                .build(),
+        ];
+        let keywords = vec!["rust".to_string(), "wasm".to_string(), "C++".to_string()];
+
+        let mut cleaned = clean_items(&items);
+        tag_items(&mut cleaned, &keywords);
+        // 不区分大小写，描述中的HTML已被去除；"trust" 不算命中 "rust"
+        assert_eq!(
+            cleaned[0].tags,
+            vec!["rust".to_string(), "wasm".to_string()]
+        );
+        assert!(cleaned[1].tags.is_empty());
+        assert_eq!(cleaned[2].tags, vec!["C++".to_string()]);
+        assert!(format_clean_items(&cleaned).contains("Keywords: rust, wasm\n"));
+
+        cleaned.retain(|item| !item.tags.is_empty());
+        let titles: Vec<&str> = cleaned.iter().map(|item| item.title.as_str()).collect();
+        assert_eq!(titles, vec!["Rust 1.80 released", "Why I still write C++"]);
+
+        let options = Options::parse(
+            vec![
+                "--keyword".to_string(),
+                "rust".to_string(),
+                "--require-keyword".to_string(),
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+        assert_eq!(options.keywords, vec!["rust".to_string()]);
+        assert!(options.require_keyword);
+        assert!(Options::parse(vec!["--require-keyword".to_string()].into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_openai_settings_missing_key() {
+        let config: Config = toml::from_str("[openai]\napi_key_env = \"MY_KEY\"\n").unwrap();
+        let err = OpenAiSettings::resolve(&config.openai, |_| None).unwrap_err();
+        assert_eq!(
+            err,
+            "OpenAI API key not found: environment variable MY_KEY is not set"
+        );
+
+        assert!(load_config(Path::new("/nonexistent/rig_rss.toml")).is_err());
+        assert!(toml::from_str::<Config>("[openai]\nmodel = 3\n").is_err());
+    }
+}